@@ -1,19 +1,44 @@
+#[cfg(feature = "gui")]
 use shaderc::{Compiler, ShaderKind};
+#[cfg(feature = "gui")]
 use std::{
-	fs::{create_dir, File},
+	env,
+	fs::File,
 	io::prelude::*,
-	path::Path,
+	path::{Path, PathBuf},
 };
 
 fn main() {
-	create_dir("build").ok();
-	build_shader("src/gfx/shaders/shader.vert", "build/shader.vert.spv", ShaderKind::Vertex);
-	build_shader("src/gfx/shaders/shader.frag", "build/shader.frag.spv", ShaderKind::Fragment);
+	// a build without the game has no renderer, so no shaders
+	#[cfg(feature = "gui")]
+	build_shaders();
 }
 
-fn build_shader(input: &str, output: &str, kind: ShaderKind) {
+#[cfg(feature = "gui")]
+fn build_shaders() {
+	let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+	build_shader("src/gfx/shaders/shader.vert", &out_dir.join("shader.vert.spv"), ShaderKind::Vertex);
+	build_shader("src/gfx/shaders/shader.frag", &out_dir.join("shader.frag.spv"), ShaderKind::Fragment);
+	build_shader("src/gfx/shaders/gui.vert", &out_dir.join("gui.vert.spv"), ShaderKind::Vertex);
+	build_shader("src/gfx/shaders/gui.frag", &out_dir.join("gui.frag.spv"), ShaderKind::Fragment);
+	build_shader("src/gfx/shaders/mesh.vert", &out_dir.join("mesh.vert.spv"), ShaderKind::Vertex);
+	build_shader("src/gfx/shaders/mesh.frag", &out_dir.join("mesh.frag.spv"), ShaderKind::Fragment);
+	build_shader("src/gfx/shaders/lines.vert", &out_dir.join("lines.vert.spv"), ShaderKind::Vertex);
+	build_shader("src/gfx/shaders/lines.frag", &out_dir.join("lines.frag.spv"), ShaderKind::Fragment);
+	build_shader("src/gfx/shaders/temporal.frag", &out_dir.join("temporal.frag.spv"), ShaderKind::Fragment);
+	build_shader("src/gfx/shaders/volume.vert", &out_dir.join("volume.vert.spv"), ShaderKind::Vertex);
+	build_shader("src/gfx/shaders/cull.comp", &out_dir.join("cull.comp.spv"), ShaderKind::Compute);
+	build_shader("src/gfx/shaders/mips.comp", &out_dir.join("mips.comp.spv"), ShaderKind::Compute);
+	build_shader("src/gfx/shaders/edit.comp", &out_dir.join("edit.comp.spv"), ShaderKind::Compute);
+	build_shader("src/gfx/shaders/egui.vert", &out_dir.join("egui.vert.spv"), ShaderKind::Vertex);
+	build_shader("src/gfx/shaders/egui.frag", &out_dir.join("egui.frag.spv"), ShaderKind::Fragment);
+}
+
+#[cfg(feature = "gui")]
+fn build_shader(input: &str, output: &Path, kind: ShaderKind) {
+	println!("cargo:rerun-if-changed={}", input);
+
 	let input = Path::new(input);
-	let output = Path::new(output);
 
 	let mut file = File::open(input).unwrap();
 	let mut source = String::new();
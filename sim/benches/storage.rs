@@ -0,0 +1,58 @@
+//! Times chunk encoding and decoding. Run with `cargo bench -p sim`.
+//!
+//! There's no benchmark harness on stable, so this is a plain binary that runs each case for a while and prints the
+//! average.
+
+use nalgebra::Vector3;
+use sim::world::{
+	generate::{NoiseTerrain, TerrainGenerator},
+	storage::{decode, decode_into, encode},
+	Chunk, CHUNK_VOLUME,
+};
+use std::{
+	mem, ptr,
+	time::{Duration, Instant},
+};
+
+/// How long each case runs for.
+const DURATION: Duration = Duration::from_secs(2);
+
+fn main() {
+	let terrain = NoiseTerrain::default();
+	let chunks = [
+		("empty", Chunk::new()),
+		// the surface crosses this one
+		("surface", terrain.generate(Vector3::zeros())),
+		// and caves cross this one
+		("caves", terrain.generate(Vector3::new(0, 0, -2))),
+	];
+
+	let mut out = vec![0; 2 * CHUNK_VOLUME];
+	for (name, chunk) in &chunks {
+		let bytes = encode(chunk);
+		println!("{}: {} bytes, {:.1}x smaller", name, bytes.len(), (2 * CHUNK_VOLUME) as f64 / bytes.len() as f64);
+		bench(&format!("{} encode", name), || encode(chunk));
+		bench(&format!("{} decode", name), || decode(&bytes).unwrap());
+		bench(&format!("{} decode_into", name), || decode_into(&bytes[..], &mut out).unwrap());
+	}
+}
+
+/// Runs `f` until `DURATION` is up and prints how long it took on average.
+fn bench<T>(name: &str, mut f: impl FnMut() -> T) {
+	let start = Instant::now();
+	let mut iters = 0u32;
+	while start.elapsed() < DURATION {
+		black_box(f());
+		iters += 1;
+	}
+	println!("  {:<24} {:>10.1?}/iter", name, start.elapsed() / iters);
+}
+
+/// Hides `x` from the optimizer, so the work that made it isn't thrown away.
+fn black_box<T>(x: T) -> T {
+	unsafe {
+		let ret = ptr::read_volatile(&x);
+		mem::forget(x);
+		ret
+	}
+}
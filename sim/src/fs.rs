@@ -0,0 +1,14 @@
+use crate::threads::{JobHandle, JobKind, JOBS};
+use std::{fs, io, path::Path};
+
+pub fn read_to_string<P: AsRef<Path> + Send + 'static>(path: P) -> JobHandle<io::Result<String>> {
+	JOBS.spawn(JobKind::Io, move || fs::read_to_string(path))
+}
+
+pub fn read_bytes<P: AsRef<Path> + Send + 'static>(path: P) -> JobHandle<io::Result<Vec<u8>>> {
+	JOBS.spawn(JobKind::Io, move || fs::read(path))
+}
+
+pub fn write<P: AsRef<Path> + Send + 'static>(path: P, contents: Vec<u8>) -> JobHandle<io::Result<()>> {
+	JOBS.spawn(JobKind::Io, move || fs::write(path, contents))
+}
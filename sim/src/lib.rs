@@ -0,0 +1,7 @@
+//! The world and everything needed to simulate it, without graphics, windows or input, so a server can build it
+//! alone.
+
+pub mod fs;
+pub mod math;
+pub mod threads;
+pub mod world;
@@ -0,0 +1,346 @@
+//! Geometry used by culling, picking, and physics.
+//!
+//! The world is Z-up and cameras look along +Y before rotation. Eye space follows the GL convention the projection
+//! uses: +X right, +Y up, looking down -Z.
+
+use nalgebra::{Matrix4, Point3, UnitQuaternion, Vector2, Vector3, Vector4};
+
+pub fn lerp(a: f32, b: f32, t: f32) -> f32 {
+	a + (b - a) * t
+}
+
+/// Component-wise maximum.
+pub fn v3max(a: Vector3<f32>, b: Vector3<f32>) -> Vector3<f32> {
+	a.zip_map(&b, f32::max)
+}
+
+/// Component-wise minimum.
+pub fn v3min(a: Vector3<f32>, b: Vector3<f32>) -> Vector3<f32> {
+	a.zip_map(&b, f32::min)
+}
+
+/// Packs a perspective projection into the only entries that aren't constant: the x and y scales, then the z scale and
+/// offset.
+///
+/// `fovy` is the vertical field of view in radians and `aspect` is width over height.
+pub fn perspective(aspect: f32, fovy: f32, near: f32, far: f32) -> Vector4<f32> {
+	let f = 1.0 / (fovy / 2.0).tan();
+	Vector4::new(f / aspect, f, -(far + near) / (far - near), -2.0 * far * near / (far - near))
+}
+
+/// The full matrix for a projection packed by `perspective`.
+pub fn perspective_matrix(proj: Vector4<f32>) -> Matrix4<f32> {
+	#[rustfmt::skip]
+	let mat = Matrix4::new(
+		proj.x, 0.0, 0.0, 0.0,
+		0.0, proj.y, 0.0, 0.0,
+		0.0, 0.0, proj.z, proj.w,
+		0.0, 0.0, -1.0, 0.0,
+	);
+	mat
+}
+
+/// Transforms world space into eye space for a camera at `pos` with rotation `rot`.
+pub fn view_matrix(pos: Vector3<f32>, rot: UnitQuaternion<f32>) -> Matrix4<f32> {
+	// world forward (+Y) becomes -Z and world up (+Z) becomes +Y
+	#[rustfmt::skip]
+	let axes = Matrix4::new(
+		1.0, 0.0, 0.0, 0.0,
+		0.0, 0.0, 1.0, 0.0,
+		0.0, -1.0, 0.0, 0.0,
+		0.0, 0.0, 0.0, 1.0,
+	);
+	axes * rot.inverse().to_homogeneous() * Matrix4::new_translation(&-pos)
+}
+
+/// An axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+	pub min: Vector3<f32>,
+	pub max: Vector3<f32>,
+}
+impl Aabb {
+	pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Self {
+		Self { min, max }
+	}
+
+	pub fn from_center(center: Vector3<f32>, half_extents: Vector3<f32>) -> Self {
+		Self { min: center - half_extents, max: center + half_extents }
+	}
+
+	pub fn center(&self) -> Vector3<f32> {
+		(self.min + self.max) / 2.0
+	}
+
+	pub fn half_extents(&self) -> Vector3<f32> {
+		(self.max - self.min) / 2.0
+	}
+
+	pub fn contains(&self, point: Vector3<f32>) -> bool {
+		(0..3).all(|i| point[i] >= self.min[i] && point[i] <= self.max[i])
+	}
+
+	pub fn intersects(&self, other: &Aabb) -> bool {
+		(0..3).all(|i| self.min[i] <= other.max[i] && self.max[i] >= other.min[i])
+	}
+
+	/// The smallest box containing both boxes.
+	pub fn union(&self, other: &Aabb) -> Self {
+		Self { min: v3min(self.min, other.min), max: v3max(self.max, other.max) }
+	}
+
+	/// The point in the box closest to `point`.
+	pub fn closest_point(&self, point: Vector3<f32>) -> Vector3<f32> {
+		v3min(v3max(point, self.min), self.max)
+	}
+
+	/// The signed distance from `point` to the box's surface, negative inside.
+	pub fn distance(&self, point: Vector3<f32>) -> f32 {
+		let q = (point - self.center()).abs() - self.half_extents();
+		v3max(q, Vector3::zeros()).norm() + q.max().min(0.0)
+	}
+
+	/// The box's bounds in normalized device coordinates after `view_proj`, or `None` if part of it is behind the
+	/// camera, where projecting the corners doesn't bound it.
+	pub fn project(&self, view_proj: &Matrix4<f32>) -> Option<(Vector2<f32>, Vector2<f32>)> {
+		let mut min = Vector2::repeat(f32::INFINITY);
+		let mut max = Vector2::repeat(f32::NEG_INFINITY);
+		for i in 0..8 {
+			let corner = Vector3::new(
+				if i & 1 == 0 { self.min.x } else { self.max.x },
+				if i & 2 == 0 { self.min.y } else { self.max.y },
+				if i & 4 == 0 { self.min.z } else { self.max.z },
+			);
+			let clip = view_proj * Point3::from(corner).to_homogeneous();
+			if clip.w <= 0.0 {
+				return None;
+			}
+			let ndc = clip.xy() / clip.w;
+			min = min.zip_map(&ndc, f32::min);
+			max = max.zip_map(&ndc, f32::max);
+		}
+		Some((min, max))
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+	pub origin: Vector3<f32>,
+	/// Normalized, so distances along the ray are in world units.
+	pub dir: Vector3<f32>,
+}
+impl Ray {
+	pub fn new(origin: Vector3<f32>, dir: Vector3<f32>) -> Self {
+		Self { origin, dir: dir.normalize() }
+	}
+
+	pub fn at(&self, t: f32) -> Vector3<f32> {
+		self.origin + self.dir * t
+	}
+
+	/// Returns the distances at which the ray enters and leaves `aabb`, if it hits it in front of the origin. The entry
+	/// distance is negative if the origin is inside the box.
+	pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<(f32, f32)> {
+		let inv = self.dir.map(|x| 1.0 / x);
+		let t0 = (aabb.min - self.origin).component_mul(&inv);
+		let t1 = (aabb.max - self.origin).component_mul(&inv);
+		let near = v3min(t0, t1).max();
+		let far = v3max(t0, t1).min();
+		if near <= far && far >= 0.0 {
+			Some((near, far))
+		} else {
+			None
+		}
+	}
+
+	/// Returns the distance to `plane`, if the ray hits it in front of the origin.
+	pub fn intersect_plane(&self, plane: &Plane) -> Option<f32> {
+		let denom = plane.normal.dot(&self.dir);
+		if denom.abs() < f32::EPSILON {
+			return None;
+		}
+		let t = -plane.signed_distance(self.origin) / denom;
+		if t >= 0.0 {
+			Some(t)
+		} else {
+			None
+		}
+	}
+
+	/// Returns the distance to the first hit on the sphere, if the ray hits it in front of the origin.
+	pub fn intersect_sphere(&self, center: Vector3<f32>, radius: f32) -> Option<f32> {
+		let to_origin = self.origin - center;
+		let b = to_origin.dot(&self.dir);
+		let c = to_origin.norm_squared() - radius * radius;
+		let discriminant = b * b - c;
+		if discriminant < 0.0 {
+			return None;
+		}
+
+		let sqrt = discriminant.sqrt();
+		let t = if -b - sqrt >= 0.0 { -b - sqrt } else { -b + sqrt };
+		if t >= 0.0 {
+			Some(t)
+		} else {
+			None
+		}
+	}
+}
+
+/// The points where `normal.dot(point) + d == 0`. The normal points toward the positive side.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Plane {
+	pub normal: Vector3<f32>,
+	pub d: f32,
+}
+impl Plane {
+	pub fn new(normal: Vector3<f32>, d: f32) -> Self {
+		Self { normal, d }
+	}
+
+	pub fn from_point_normal(point: Vector3<f32>, normal: Vector3<f32>) -> Self {
+		let normal = normal.normalize();
+		Self { normal, d: -normal.dot(&point) }
+	}
+
+	/// Scales the plane so the normal has unit length, making `signed_distance` return world units.
+	pub fn normalize(&self) -> Self {
+		let len = self.normal.norm();
+		Self { normal: self.normal / len, d: self.d / len }
+	}
+
+	pub fn signed_distance(&self, point: Vector3<f32>) -> f32 {
+		self.normal.dot(&point) + self.d
+	}
+}
+
+/// The volume a camera can see, as six inward-facing planes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+	/// Left, right, bottom, top, near, far.
+	pub planes: [Plane; 6],
+}
+impl Frustum {
+	/// Extracts the planes from a combined projection and view matrix, so they're in world space.
+	pub fn from_matrix(m: &Matrix4<f32>) -> Self {
+		let row = |i: usize| Vector4::new(m[(i, 0)], m[(i, 1)], m[(i, 2)], m[(i, 3)]);
+		let plane = |v: Vector4<f32>| Plane::new(v.xyz(), v.w).normalize();
+		let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+		Self { planes: [plane(w + x), plane(w - x), plane(w + y), plane(w - y), plane(w + z), plane(w - z)] }
+	}
+
+	pub fn contains(&self, point: Vector3<f32>) -> bool {
+		self.planes.iter().all(|plane| plane.signed_distance(point) >= 0.0)
+	}
+
+	pub fn intersects_sphere(&self, center: Vector3<f32>, radius: f32) -> bool {
+		self.planes.iter().all(|plane| plane.signed_distance(center) >= -radius)
+	}
+
+	/// Conservative: boxes near the frustum's corners may be reported as intersecting when they don't.
+	pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+		self.planes.iter().all(|plane| {
+			// the corner furthest along the plane's normal
+			let corner = Vector3::new(
+				if plane.normal.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+				if plane.normal.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+				if plane.normal.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+			);
+			plane.signed_distance(corner) >= 0.0
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn unit_box() -> Aabb {
+		Aabb::new(Vector3::zeros(), Vector3::repeat(1.0))
+	}
+
+	/// A camera at the origin looking along +Y, with a square 90 degree view from 0.1 to 100.
+	fn view_proj() -> Matrix4<f32> {
+		let proj = perspective_matrix(perspective(1.0, std::f32::consts::FRAC_PI_2, 0.1, 100.0));
+		proj * view_matrix(Vector3::zeros(), UnitQuaternion::identity())
+	}
+
+	#[test]
+	fn aabb_distance() {
+		let aabb = unit_box();
+		assert_eq!(aabb.distance(Vector3::repeat(0.5)), -0.5);
+		assert_eq!(aabb.distance(Vector3::new(3.0, 0.5, 0.5)), 2.0);
+		// off a corner, the nearest point is the corner itself
+		assert!((aabb.distance(Vector3::new(2.0, 2.0, 1.0)) - 2f32.sqrt()).abs() < 1e-6);
+	}
+
+	#[test]
+	fn aabb_union_and_intersects() {
+		let other = Aabb::new(Vector3::repeat(0.5), Vector3::repeat(2.0));
+		assert!(unit_box().intersects(&other));
+		assert_eq!(unit_box().union(&other), Aabb::new(Vector3::zeros(), Vector3::repeat(2.0)));
+		let far = Aabb::new(Vector3::repeat(3.0), Vector3::repeat(4.0));
+		assert!(!unit_box().intersects(&far));
+	}
+
+	#[test]
+	fn ray_aabb() {
+		let ray = Ray::new(Vector3::new(-1.0, 0.5, 0.5), Vector3::x());
+		assert_eq!(ray.intersect_aabb(&unit_box()), Some((1.0, 2.0)));
+		// from inside, entering behind the origin
+		let inside = Ray::new(Vector3::repeat(0.5), Vector3::x());
+		assert_eq!(inside.intersect_aabb(&unit_box()), Some((-0.5, 0.5)));
+		// pointing away, and passing beside it
+		assert_eq!(Ray::new(Vector3::new(-1.0, 0.5, 0.5), -Vector3::x()).intersect_aabb(&unit_box()), None);
+		assert_eq!(Ray::new(Vector3::new(-1.0, 2.0, 0.5), Vector3::x()).intersect_aabb(&unit_box()), None);
+	}
+
+	#[test]
+	fn ray_plane() {
+		let floor = Plane::from_point_normal(Vector3::new(0.0, 0.0, 2.0), Vector3::z());
+		assert_eq!(Ray::new(Vector3::new(1.0, 1.0, 5.0), -Vector3::z()).intersect_plane(&floor), Some(3.0));
+		assert_eq!(Ray::new(Vector3::new(1.0, 1.0, 5.0), Vector3::z()).intersect_plane(&floor), None);
+		assert_eq!(Ray::new(Vector3::new(1.0, 1.0, 5.0), Vector3::x()).intersect_plane(&floor), None);
+	}
+
+	#[test]
+	fn ray_sphere() {
+		let center = Vector3::new(0.0, 5.0, 0.0);
+		assert_eq!(Ray::new(Vector3::zeros(), Vector3::y()).intersect_sphere(center, 1.0), Some(4.0));
+		// from inside, the exit
+		assert_eq!(Ray::new(center, Vector3::y()).intersect_sphere(center, 1.0), Some(1.0));
+		assert_eq!(Ray::new(Vector3::zeros(), Vector3::x()).intersect_sphere(center, 1.0), None);
+		assert_eq!(Ray::new(Vector3::zeros(), -Vector3::y()).intersect_sphere(center, 1.0), None);
+	}
+
+	#[test]
+	fn view_looks_along_y() {
+		let eye = view_matrix(Vector3::new(1.0, 2.0, 3.0), UnitQuaternion::identity())
+			* Point3::new(1.0, 7.0, 4.0).to_homogeneous();
+		// 5 ahead is -Z in eye space, and world up is eye up
+		assert_eq!(eye, Vector4::new(0.0, 1.0, -5.0, 1.0));
+	}
+
+	#[test]
+	fn frustum_culls() {
+		let frustum = Frustum::from_matrix(&view_proj());
+		assert!(frustum.contains(Vector3::new(0.0, 10.0, 0.0)));
+		assert!(!frustum.contains(Vector3::new(0.0, -10.0, 0.0)));
+		assert!(!frustum.contains(Vector3::new(0.0, 200.0, 0.0)));
+		assert!(frustum.intersects_sphere(Vector3::new(11.0, 10.0, 0.0), 1.0));
+		assert!(!frustum.intersects_sphere(Vector3::new(20.0, 10.0, 0.0), 1.0));
+		assert!(frustum.intersects_aabb(&Aabb::from_center(Vector3::new(0.0, 10.0, 0.0), Vector3::repeat(1.0))));
+		assert!(!frustum.intersects_aabb(&Aabb::from_center(Vector3::new(0.0, -10.0, 0.0), Vector3::repeat(1.0))));
+	}
+
+	#[test]
+	fn project_bounds_the_box() {
+		let aabb = Aabb::from_center(Vector3::new(0.0, 10.0, 0.0), Vector3::repeat(1.0));
+		let (min, max) = aabb.project(&view_proj()).unwrap();
+		// the near face is 9 away and reaches 1 to each side
+		assert!((max.x - 1.0 / 9.0).abs() < 1e-5 && (min.x + 1.0 / 9.0).abs() < 1e-5);
+		assert!((max.y - 1.0 / 9.0).abs() < 1e-5 && (min.y + 1.0 / 9.0).abs() < 1e-5);
+		// part of it is behind the camera
+		assert_eq!(Aabb::from_center(Vector3::zeros(), Vector3::repeat(1.0)).project(&view_proj()), None);
+	}
+}
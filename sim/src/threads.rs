@@ -0,0 +1,256 @@
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
+use futures::{
+	channel::oneshot,
+	executor::block_on,
+	task::{waker_ref, ArcWake},
+};
+use lazy_static::lazy_static;
+use std::{
+	cell::RefCell,
+	future::Future,
+	iter::repeat_with,
+	pin::Pin,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		mpsc, Arc, Condvar, Mutex,
+	},
+	task::{Context, Poll},
+	thread,
+	time::Duration,
+};
+
+lazy_static! {
+	/// One worker per core, minus the main thread.
+	pub static ref JOBS: JobSystem = JobSystem::new(num_cpus::get().saturating_sub(1).max(1));
+}
+
+/// Which queue a job goes into. Workers always prefer frame jobs, and background jobs never occupy every worker, so
+/// long-running work can't delay a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobKind {
+	/// Short work the current frame waits on, like recording secondary command buffers.
+	Frame,
+	/// Work that can span many frames, like generating chunks or decoding assets.
+	Background,
+	/// Blocking file access. These run one at a time on a thread of their own, in the order they were spawned, so the
+	/// workers never wait on the disk and a read always sees earlier writes.
+	Io,
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A pool of worker threads that share jobs by work stealing, plus a thread for IO jobs.
+///
+/// Frame jobs spawned from a worker go into that worker's own queue, and idle workers steal from each other, so jobs
+/// that fan out stay on the thread that spawned them until someone else is free.
+pub struct JobSystem {
+	shared: Arc<Shared>,
+}
+impl JobSystem {
+	pub fn new(threads: usize) -> Self {
+		let workers = (0..threads).map(|_| Worker::new_fifo()).collect::<Vec<_>>();
+		let (io, io_jobs) = mpsc::channel::<Job>();
+		let shared = Arc::new(Shared {
+			frame: Injector::new(),
+			background: Injector::new(),
+			stealers: workers.iter().map(Worker::stealer).collect(),
+			// leave at least one worker free for frame jobs
+			max_background: threads.saturating_sub(1).max(1),
+			running_background: AtomicUsize::new(0),
+			pending_frame: AtomicUsize::new(0),
+			sleep: Mutex::new(()),
+			wake: Condvar::new(),
+			io: Mutex::new(io),
+		});
+
+		for (i, worker) in workers.into_iter().enumerate() {
+			let shared = shared.clone();
+			thread::Builder::new().name(format!("job worker {}", i)).spawn(move || run_worker(shared, worker)).unwrap();
+		}
+		thread::Builder::new().name("io".to_string()).spawn(move || io_jobs.into_iter().for_each(|job| job())).unwrap();
+
+		Self { shared }
+	}
+
+	/// Queues `job` and returns a handle that resolves to its result.
+	pub fn spawn<T: Send + 'static>(&self, kind: JobKind, job: impl FnOnce() -> T + Send + 'static) -> JobHandle<T> {
+		let (send, recv) = oneshot::channel();
+		let job = move || {
+			// the caller may have dropped the handle
+			let _ = send.send(job());
+		};
+		push(&self.shared, kind, Box::new(job));
+		JobHandle(recv)
+	}
+
+	/// Runs `future` as a series of jobs of `kind`, one each time it's woken, so it only holds a thread while it has
+	/// something to do. `wait_frame` doesn't wait for frame tasks that are waiting to be woken.
+	pub fn spawn_async<T: Send + 'static>(
+		&self,
+		kind: JobKind,
+		future: impl Future<Output = T> + Send + 'static,
+	) -> JobHandle<T> {
+		let (send, recv) = oneshot::channel();
+		let future = async move {
+			let _ = send.send(future.await);
+		};
+		let task = Arc::new(Task { shared: self.shared.clone(), kind, future: Mutex::new(Some(Box::pin(future))) });
+		ArcWake::wake(task);
+		JobHandle(recv)
+	}
+
+	/// Blocks until every frame job has finished, running queued frame jobs on this thread while it waits.
+	pub fn wait_frame(&self) {
+		while self.shared.pending_frame.load(Ordering::SeqCst) > 0 {
+			match self.shared.find_frame_job(None) {
+				Some(job) => job(),
+				None => thread::yield_now(),
+			}
+		}
+	}
+}
+
+/// The result of a job. Await it, or call `wait` to block.
+pub struct JobHandle<T>(oneshot::Receiver<T>);
+impl<T> JobHandle<T> {
+	pub fn wait(self) -> T {
+		block_on(self)
+	}
+
+	/// Takes the result if the job has finished, without blocking.
+	pub fn try_take(&mut self) -> Option<T> {
+		self.0.try_recv().expect("job panicked")
+	}
+
+	/// Lets the job finish without waiting for it. Dropping the handle does the same, but this says it's on purpose.
+	pub fn forget(self) {}
+}
+impl<T> Future for JobHandle<T> {
+	type Output = T;
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		Pin::new(&mut self.0).poll(cx).map(|res| res.expect("job panicked"))
+	}
+}
+
+/// A future spawned by `spawn_async`.
+struct Task {
+	shared: Arc<Shared>,
+	kind: JobKind,
+	/// `None` once it's finished. The lock is held while polling, so a wake during a poll queues a job that polls
+	/// again after this one is done.
+	future: Mutex<Option<Pin<Box<dyn Future<Output = ()> + Send>>>>,
+}
+impl Task {
+	fn poll(self: Arc<Self>) {
+		let mut future = self.future.lock().unwrap();
+		if let Some(inner) = &mut *future {
+			let waker = waker_ref(&self);
+			if inner.as_mut().poll(&mut Context::from_waker(&waker)).is_ready() {
+				*future = None;
+			}
+		}
+	}
+}
+impl ArcWake for Task {
+	fn wake_by_ref(task: &Arc<Self>) {
+		let job = task.clone();
+		push(&task.shared, task.kind, Box::new(move || job.poll()));
+	}
+}
+
+struct Shared {
+	frame: Injector<Job>,
+	background: Injector<Job>,
+	stealers: Vec<Stealer<Job>>,
+	max_background: usize,
+	running_background: AtomicUsize,
+	pending_frame: AtomicUsize,
+	sleep: Mutex<()>,
+	wake: Condvar,
+	io: Mutex<mpsc::Sender<Job>>,
+}
+impl Shared {
+	fn find_frame_job(&self, local: Option<&Worker<Job>>) -> Option<Job> {
+		if let Some(job) = local.and_then(Worker::pop) {
+			return Some(job);
+		}
+
+		repeat_with(|| {
+			let injected = match local {
+				Some(local) => self.frame.steal_batch_and_pop(local),
+				None => self.frame.steal(),
+			};
+			injected.or_else(|| self.stealers.iter().map(Stealer::steal).collect())
+		})
+		.find(|steal| !steal.is_retry())
+		.and_then(Steal::success)
+	}
+
+	fn find_background_job(&self) -> Option<Job> {
+		let running = self.running_background.fetch_add(1, Ordering::SeqCst);
+		if running < self.max_background {
+			let job = repeat_with(|| self.background.steal()).find(|steal| !steal.is_retry()).and_then(Steal::success);
+			if let Some(job) = job {
+				return Some(job);
+			}
+		}
+		self.running_background.fetch_sub(1, Ordering::SeqCst);
+		None
+	}
+}
+
+fn push(shared: &Arc<Shared>, kind: JobKind, job: Job) {
+	match kind {
+		JobKind::Frame => {
+			shared.pending_frame.fetch_add(1, Ordering::SeqCst);
+			let pending = shared.clone();
+			let job: Job = Box::new(move || {
+				job();
+				pending.pending_frame.fetch_sub(1, Ordering::SeqCst);
+			});
+			let job = LOCAL_WORKER.with(|local| match &*local.borrow() {
+				Some(worker) => {
+					worker.push(job);
+					None
+				},
+				None => Some(job),
+			});
+			if let Some(job) = job {
+				shared.frame.push(job);
+			}
+		},
+		JobKind::Background => shared.background.push(job),
+		JobKind::Io => {
+			shared.io.lock().unwrap().send(job).unwrap();
+			return;
+		},
+	}
+	shared.wake.notify_one();
+}
+
+thread_local! {
+	static LOCAL_WORKER: RefCell<Option<Worker<Job>>> = RefCell::new(None);
+}
+
+fn run_worker(shared: Arc<Shared>, worker: Worker<Job>) {
+	LOCAL_WORKER.with(|local| *local.borrow_mut() = Some(worker));
+
+	loop {
+		let job = LOCAL_WORKER.with(|local| shared.find_frame_job(local.borrow().as_ref()));
+		if let Some(job) = job {
+			job();
+			continue;
+		}
+
+		if let Some(job) = shared.find_background_job() {
+			job();
+			shared.running_background.fetch_sub(1, Ordering::SeqCst);
+			continue;
+		}
+
+		// the timeout covers jobs pushed between the searches above and this wait
+		let sleep = shared.sleep.lock().unwrap();
+		let _ = shared.wake.wait_timeout(sleep, Duration::from_millis(10)).unwrap();
+	}
+}
@@ -0,0 +1,805 @@
+//! The CPU copy of the signed distance field, for physics and picking. Nothing here depends on `gfx`, so the server
+//! can simulate the world without a GPU.
+
+pub mod edit;
+pub mod generate;
+pub mod mesher;
+pub mod storage;
+pub mod stream;
+
+use crate::{
+	math::{lerp, Aabb, Ray},
+	threads::JobHandle,
+};
+use edit::{Edit, Material, Shape};
+use nalgebra::Vector3;
+use std::{
+	collections::{HashMap, HashSet},
+	io,
+};
+use storage::ChunkStorage;
+
+const CHUNK_SHIFT: i32 = 5;
+/// The number of voxels along each edge of a chunk.
+pub const CHUNK_SIZE: usize = 1 << CHUNK_SHIFT;
+const CHUNK_MASK: i32 = CHUNK_SIZE as i32 - 1;
+/// The number of voxels in a chunk.
+pub const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+const BRICK_SHIFT: i32 = 3;
+/// Chunks track which cubes of this many voxels per edge are empty, so marching can skip them.
+pub const BRICK_SIZE: usize = 1 << BRICK_SHIFT;
+const BRICKS_PER_EDGE: usize = CHUNK_SIZE / BRICK_SIZE;
+/// How many levels of min-filtered mips the GPU copy has above the voxels, each half the size of the one below.
+/// Marching skips the coarsest empty texel it's in, so the last level's 16 voxel blocks are the biggest steps inside a
+/// chunk.
+pub const MIP_LEVELS: i32 = 4;
+/// The raw value of a voxel at least `EMPTY_DISTANCE` from any surface.
+const EMPTY_VOXEL: i8 = 127;
+
+/// The distance returned for voxels in chunks that aren't loaded, so unloaded space reads as empty.
+pub const EMPTY_DISTANCE: f32 = 1.0;
+/// How close a sweep has to get to a surface to count as touching it.
+const SWEEP_EPSILON: f32 = 0.01;
+
+/// Where a sphere sweep stopped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SweepHit {
+	/// How far the sphere moved along the ray.
+	pub distance: f32,
+	/// The sphere's center when it touched the surface.
+	pub pos: Vector3<f32>,
+	/// The surface's normal at the contact.
+	pub normal: Vector3<f32>,
+}
+
+/// Where a ray hit the surface.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+	/// How far along the ray the surface is.
+	pub distance: f32,
+	pub pos: Vector3<f32>,
+	pub normal: Vector3<f32>,
+	/// The key of the chunk containing `pos`.
+	pub chunk: Vector3<i32>,
+}
+impl RayHit {
+	/// The voxel just behind the surface, which removing a block should target.
+	pub fn voxel(&self) -> Vector3<i32> {
+		(self.pos - self.normal * 0.5).map(|x| x.round() as i32)
+	}
+
+	/// The voxel just in front of the surface, where a placed block should go.
+	pub fn adjacent(&self) -> Vector3<i32> {
+		(self.pos + self.normal * 0.5).map(|x| x.round() as i32)
+	}
+}
+
+/// A cube of voxels holding distances in the same format as the GPU volume (`R8_SNORM`), and a material for each.
+pub struct Chunk {
+	data: Box<[i8]>,
+	mats: Box<[Material]>,
+	/// How many voxels in each brick are closer than `EMPTY_DISTANCE` to a surface, which is a min-filtered mip of the
+	/// field: a brick with none can't contain a surface.
+	occupied: [u16; BRICKS_PER_EDGE * BRICKS_PER_EDGE * BRICKS_PER_EDGE],
+}
+impl Chunk {
+	pub fn new() -> Self {
+		Self::from_raw(vec![EMPTY_VOXEL; CHUNK_VOLUME].into_boxed_slice(), vec![0; CHUNK_VOLUME].into_boxed_slice())
+	}
+
+	fn from_raw(data: Box<[i8]>, mats: Box<[Material]>) -> Self {
+		let mut occupied = [0; BRICKS_PER_EDGE * BRICKS_PER_EDGE * BRICKS_PER_EDGE];
+		for (i, _) in data.iter().enumerate().filter(|(_, &x)| x != EMPTY_VOXEL) {
+			occupied[brick_index(unindex(i))] += 1;
+		}
+		Self { data, mats, occupied }
+	}
+
+	pub fn get(&self, local: Vector3<usize>) -> f32 {
+		decode(self.data[index(local)])
+	}
+
+	pub fn set(&mut self, local: Vector3<usize>, distance: f32) {
+		let value = (distance.clamp(-1.0, 1.0) * 127.0).round() as i8;
+		let old = std::mem::replace(&mut self.data[index(local)], value);
+		match (old == EMPTY_VOXEL, value == EMPTY_VOXEL) {
+			(true, false) => self.occupied[brick_index(local)] += 1,
+			(false, true) => self.occupied[brick_index(local)] -= 1,
+			_ => (),
+		}
+	}
+
+	pub fn material(&self, local: Vector3<usize>) -> Material {
+		self.mats[index(local)]
+	}
+
+	pub fn set_material(&mut self, local: Vector3<usize>, material: Material) {
+		self.mats[index(local)] = material;
+	}
+
+	/// Whether every voxel is empty, so the chunk reads the same as an unloaded one.
+	pub fn is_empty(&self) -> bool {
+		self.occupied.iter().all(|&x| x == 0)
+	}
+
+	/// Whether every voxel is empty in the cube `1 << level` voxels wide that contains `local`, and is aligned to its
+	/// size like a texel of mip level `level`. Cubes of whole bricks are read from their counts.
+	fn is_block_empty(&self, level: i32, local: Vector3<usize>) -> bool {
+		let size = 1 << level;
+		let min = local.map(|x| x / size * size);
+		let step = if size >= BRICK_SIZE { BRICK_SIZE } else { 1 };
+		for z in (0..size).step_by(step) {
+			for y in (0..size).step_by(step) {
+				for x in (0..size).step_by(step) {
+					let pos = min + Vector3::new(x, y, z);
+					let empty = if step == BRICK_SIZE {
+						self.occupied[brick_index(pos)] == 0
+					} else {
+						self.data[index(pos)] == EMPTY_VOXEL
+					};
+					if !empty {
+						return false;
+					}
+				}
+			}
+		}
+		true
+	}
+
+	/// The raw voxels, x-major then y then z, for uploading to the GPU.
+	pub fn data(&self) -> &[i8] {
+		&self.data
+	}
+
+	/// The raw materials, in the same order as `data`.
+	pub fn materials(&self) -> &[Material] {
+		&self.mats
+	}
+
+	/// The voxels and materials at a lower level of detail, in the same order and encoding as `data` and `materials`.
+	/// Each level halves the resolution. A voxel is the minimum of the cube of voxels under it, so surfaces only grow,
+	/// and it takes the material of the voxel it came from.
+	pub fn downsample(&self, lod: u32) -> (Vec<i8>, Vec<Material>) {
+		let (size, scale) = (CHUNK_SIZE >> lod, 1 << lod);
+		let mut data = Vec::with_capacity(size * size * size);
+		let mut mats = Vec::with_capacity(size * size * size);
+		for z in 0..size {
+			for y in 0..size {
+				for x in 0..size {
+					let min = Vector3::new(x, y, z) * scale;
+					let mut nearest = index(min);
+					for dz in 0..scale {
+						for dy in 0..scale {
+							for dx in 0..scale {
+								let i = index(min + Vector3::new(dx, dy, dz));
+								if self.data[i] < self.data[nearest] {
+									nearest = i;
+								}
+							}
+						}
+					}
+					data.push(self.data[nearest]);
+					mats.push(self.mats[nearest]);
+				}
+			}
+		}
+		(data, mats)
+	}
+}
+impl Default for Chunk {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// How much of a chunk a copy of the world has to keep, since empty chunks read the same as unloaded ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Residency {
+	Unloaded,
+	/// Loaded, but every voxel is empty.
+	Empty,
+	/// Loaded, with voxels near a surface.
+	Occupied,
+}
+
+pub struct World {
+	chunks: HashMap<Vector3<i32>, Chunk>,
+	/// Chunks edited since they were last saved.
+	dirty: HashSet<Vector3<i32>>,
+	/// Chunks whose distances changed since the last `take_changed`.
+	changed: HashSet<Vector3<i32>>,
+	edits: Vec<Edit>,
+	/// Edits waiting for `take_deferred`, or `None` if `edit` applies them right away. See `defer_edits`.
+	deferred: Option<Vec<Edit>>,
+}
+impl World {
+	pub fn new() -> Self {
+		Self { chunks: HashMap::new(), dirty: HashSet::new(), changed: HashSet::new(), edits: vec![], deferred: None }
+	}
+
+	pub fn chunk(&self, key: Vector3<i32>) -> Option<&Chunk> {
+		self.chunks.get(&key)
+	}
+
+	pub fn insert_chunk(&mut self, key: Vector3<i32>, chunk: Chunk) -> Option<Chunk> {
+		self.chunks.insert(key, chunk)
+	}
+
+	pub fn remove_chunk(&mut self, key: Vector3<i32>) -> Option<Chunk> {
+		self.chunks.remove(&key)
+	}
+
+	pub fn residency(&self, key: Vector3<i32>) -> Residency {
+		match self.chunks.get(&key) {
+			None => Residency::Unloaded,
+			Some(chunk) if chunk.is_empty() => Residency::Empty,
+			Some(_) => Residency::Occupied,
+		}
+	}
+
+	/// The keys of every loaded chunk.
+	pub fn chunk_keys(&self) -> impl Iterator<Item = Vector3<i32>> + '_ {
+		self.chunks.keys().copied()
+	}
+
+	/// Adds a one-voxel cube centered on `voxel`.
+	pub fn set_block(&mut self, voxel: Vector3<i32>, material: Material) {
+		self.edit(Edit::Add(block(voxel), material));
+	}
+
+	/// Carves out a one-voxel cube centered on `voxel`.
+	pub fn remove_block(&mut self, voxel: Vector3<i32>) {
+		self.edit(Edit::Subtract(block(voxel)));
+	}
+
+	/// Changes the material of a single voxel.
+	pub fn set_material(&mut self, voxel: Vector3<i32>, material: Material) {
+		self.edit(Edit::Paint(block(voxel), material));
+	}
+
+	/// The material of a single voxel, or `None` if its chunk isn't loaded.
+	pub fn material(&self, voxel: Vector3<i32>) -> Option<Material> {
+		let (key, local) = split(voxel);
+		self.chunks.get(&key).map(|chunk| chunk.material(local))
+	}
+
+	/// Applies `edit` to every loaded voxel it touches, and queues it for GPU copies of the field. While edits are
+	/// deferred, it only queues `edit` for `take_deferred`, and the world doesn't change until it comes back through
+	/// `apply_edit`.
+	pub fn edit(&mut self, edit: Edit) {
+		match &mut self.deferred {
+			Some(deferred) => deferred.push(edit),
+			None => self.apply_edit(edit),
+		}
+	}
+
+	/// Applies `edit` like `edit` does, even while edits are deferred.
+	pub fn apply_edit(&mut self, edit: Edit) {
+		let bounds = edit.bounds();
+		let min = bounds.min.map(|x| x.floor() as i32);
+		let max = bounds.max.map(|x| x.ceil() as i32);
+		self.edit_voxels(&edit, min, max);
+		self.edits.push(edit);
+	}
+
+	/// From now on, `edit` holds edits for `take_deferred` instead of applying them. It's for a client whose server
+	/// decides the order of everyone's edits, since overlapping edits applied in a different order than the other
+	/// clients' would leave their worlds different.
+	pub fn defer_edits(&mut self) {
+		if self.deferred.is_none() {
+			self.deferred = Some(vec![]);
+		}
+	}
+
+	/// Returns the edits deferred since the last call.
+	pub fn take_deferred(&mut self) -> Vec<Edit> {
+		self.deferred.as_mut().map(std::mem::take).unwrap_or_default()
+	}
+
+	/// Applies `edit` to the chunk at `key` alone, without queuing it like `edit` does, for catching up a chunk that
+	/// loaded after the edit was made. The GPU copies get the chunk with the edit already in it.
+	pub fn replay_edit(&mut self, key: Vector3<i32>, edit: Edit) {
+		let bounds = edit.bounds();
+		let origin = key * CHUNK_SIZE as i32;
+		let last = origin + Vector3::repeat(CHUNK_MASK);
+		let min = bounds.min.map(|x| x.floor() as i32).zip_map(&origin, |a, b| a.max(b));
+		let max = bounds.max.map(|x| x.ceil() as i32).zip_map(&last, |a, b| a.min(b));
+		self.edit_voxels(&edit, min, max);
+	}
+
+	/// Applies `edit` to the loaded voxels from `min` to `max` inclusive.
+	fn edit_voxels(&mut self, edit: &Edit, min: Vector3<i32>, max: Vector3<i32>) {
+		for z in min.z..=max.z {
+			for y in min.y..=max.y {
+				for x in min.x..=max.x {
+					let voxel = Vector3::new(x, y, z);
+					let (key, local) = split(voxel);
+					let chunk = match self.chunks.get_mut(&key) {
+						Some(chunk) => chunk,
+						None => continue,
+					};
+
+					let old = chunk.get(local);
+					let (new, material) = edit.apply(voxel.map(|x| x as f32), old);
+					if new != old {
+						chunk.set(local, new);
+						self.changed.insert(key);
+					}
+					if let Some(material) = material {
+						chunk.set_material(local, material);
+					}
+					if new != old || material.is_some() {
+						self.dirty.insert(key);
+					}
+				}
+			}
+		}
+	}
+
+	/// Returns the edits applied since the last call, for replaying on GPU copies of the field and sending to a server.
+	/// They pile up until something takes them.
+	pub fn take_edits(&mut self) -> Vec<Edit> {
+		std::mem::take(&mut self.edits)
+	}
+
+	/// Returns the chunks whose distances were edited since the last call, for rebuilding anything made from them.
+	pub fn take_changed(&mut self) -> HashSet<Vector3<i32>> {
+		std::mem::take(&mut self.changed)
+	}
+
+	/// Copies the distances in the cube `size` voxels across from `min`, in the same order and encoding as
+	/// `Chunk::data`, for working on them off the main thread. Voxels in unloaded chunks are empty.
+	pub fn copy_voxels(&self, min: Vector3<i32>, size: usize) -> Vec<i8> {
+		let mut voxels = Vec::with_capacity(size * size * size);
+		// runs of voxels share a chunk, so this saves most of the lookups
+		let mut last: Option<(Vector3<i32>, Option<&Chunk>)> = None;
+		for z in 0..size {
+			for y in 0..size {
+				for x in 0..size {
+					let (key, local) = split(min + Vector3::new(x, y, z).map(|x| x as i32));
+					let chunk = match last {
+						Some((last_key, chunk)) if last_key == key => chunk,
+						_ => {
+							let chunk = self.chunks.get(&key);
+							last = Some((key, chunk));
+							chunk
+						},
+					};
+					voxels.push(chunk.map_or(EMPTY_VOXEL, |chunk| chunk.data[index(local)]));
+				}
+			}
+		}
+		voxels
+	}
+
+	/// Loads a saved chunk if it isn't loaded already. Returns whether the chunk is loaded afterward; it won't be if it
+	/// was never saved.
+	pub async fn load_chunk(&mut self, storage: &ChunkStorage, key: Vector3<i32>) -> io::Result<bool> {
+		if self.chunks.contains_key(&key) {
+			return Ok(true);
+		}
+		match storage.load(key).await? {
+			Some(chunk) => {
+				self.chunks.insert(key, chunk);
+				Ok(true)
+			},
+			None => Ok(false),
+		}
+	}
+
+	/// Saves the chunk if it was edited since it was last saved.
+	pub fn save_chunk(&mut self, storage: &ChunkStorage, key: Vector3<i32>) -> Option<JobHandle<io::Result<()>>> {
+		match self.chunks.get(&key) {
+			Some(chunk) if self.dirty.remove(&key) => Some(storage.save(key, chunk)),
+			_ => None,
+		}
+	}
+
+	/// Saves every chunk edited since the last save.
+	pub fn save_dirty(&mut self, storage: &ChunkStorage) -> Vec<JobHandle<io::Result<()>>> {
+		let chunks = &self.chunks;
+		self.dirty.drain().filter_map(|key| chunks.get(&key).map(|chunk| storage.save(key, chunk))).collect()
+	}
+
+	/// The distance stored in a single voxel.
+	pub fn sample_exact(&self, voxel: Vector3<i32>) -> f32 {
+		let (key, local) = split(voxel);
+		self.chunks.get(&key).map(|chunk| chunk.get(local)).unwrap_or(EMPTY_DISTANCE)
+	}
+
+	/// The trilinearly interpolated distance at `pos`, in voxels. For many samples in a row, use `sampler` instead.
+	pub fn sample(&self, pos: Vector3<f32>) -> f32 {
+		self.sampler().sample(pos)
+	}
+
+	/// The direction the distance increases fastest at `pos`, which is the surface normal near surfaces. Returns +Z
+	/// where the field is flat.
+	pub fn normal(&self, pos: Vector3<f32>) -> Vector3<f32> {
+		let (x, y, z) = (Vector3::x() * 0.5, Vector3::y() * 0.5, Vector3::z() * 0.5);
+		let mut samples = [0.0; 6];
+		self.sampler().sample_batch(&[pos + x, pos - x, pos + y, pos - y, pos + z, pos - z], &mut samples);
+		let gradient = Vector3::new(samples[0] - samples[1], samples[2] - samples[3], samples[4] - samples[5]);
+		gradient.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::z)
+	}
+
+	/// Moves a sphere along `ray` until it touches a surface or travels `max_distance`.
+	///
+	/// Distances are clamped to one voxel, so `radius` must be less than one. Surfaces the sphere is moving along or
+	/// away from don't stop it, so a sphere resting on the ground can still roll across it.
+	pub fn sphere_sweep(&self, ray: &Ray, radius: f32, max_distance: f32) -> Option<SweepHit> {
+		let mut sampler = self.sampler();
+		let mut distance = 0.0;
+		loop {
+			if let Some(exit) = self.skip_empty(ray, distance) {
+				distance = exit.min(max_distance);
+			}
+			let pos = ray.at(distance);
+			let gap = sampler.sample(pos) - radius;
+			if gap <= SWEEP_EPSILON {
+				let normal = self.normal(pos);
+				if ray.dir.dot(&normal) < 0.0 {
+					return Some(SweepHit { distance, pos, normal });
+				}
+			}
+			if distance >= max_distance {
+				return None;
+			}
+			distance = (distance + gap.max(SWEEP_EPSILON)).min(max_distance);
+		}
+	}
+
+	/// Marches `ray` through the distance field to the first surface within `max_distance`. Unloaded chunks count as
+	/// empty space.
+	pub fn raycast(&self, ray: &Ray, max_distance: f32) -> Option<RayHit> {
+		let mut sampler = self.sampler();
+		let mut distance = 0.0;
+		loop {
+			if let Some(exit) = self.skip_empty(ray, distance) {
+				distance = exit.min(max_distance);
+			}
+			let pos = ray.at(distance);
+			let gap = sampler.sample(pos);
+			if gap <= SWEEP_EPSILON {
+				return Some(RayHit { distance, pos, normal: self.normal(pos), chunk: chunk_key(pos) });
+			}
+			if distance >= max_distance {
+				return None;
+			}
+			distance = (distance + gap).min(max_distance);
+		}
+	}
+
+	/// A sampler that remembers the last chunk it read from, for callers that sample many nearby points.
+	pub fn sampler(&self) -> Sampler<'_> {
+		Sampler { world: self, last: None }
+	}
+
+	/// If the ray is in a region where every sample reads `EMPTY_DISTANCE`, returns the distance at which it leaves
+	/// that region. Marching one voxel at a time through it would find nothing, so it can jump straight there.
+	///
+	/// The regions are unloaded or empty chunks, and the coarsest empty texel of the mips the volume shader marches
+	/// with, so both skip the same space.
+	pub fn skip_empty(&self, ray: &Ray, distance: f32) -> Option<f32> {
+		let (key, local) = split(ray.at(distance).map(|x| x.floor() as i32));
+		let (min, size) = match self.chunks.get(&key) {
+			Some(chunk) if !chunk.is_empty() => {
+				let level = (1..=MIP_LEVELS).rev().find(|&level| chunk.is_block_empty(level, local))?;
+				let block = local.map(|x| (x >> level << level) as i32);
+				(key.map(|x| x << CHUNK_SHIFT) + block, 1 << level)
+			},
+			// unloaded chunks read as empty too
+			_ => (key.map(|x| x << CHUNK_SHIFT), CHUNK_SIZE),
+		};
+
+		// samples interpolate between neighboring voxels, so they only stay in the region up to its last voxel
+		let min = min.map(|x| x as f32);
+		let region = Aabb::new(min, min + Vector3::repeat(size as f32 - 1.0));
+		let (_, exit) = ray.intersect_aabb(&region)?;
+		if exit.is_finite() && exit > distance {
+			Some(exit)
+		} else {
+			None
+		}
+	}
+}
+impl Default for World {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// Samples a `World`, fetching each 2x2x2 neighborhood in one pass.
+pub struct Sampler<'a> {
+	world: &'a World,
+	last: Option<(Vector3<i32>, Option<&'a Chunk>)>,
+}
+impl<'a> Sampler<'a> {
+	pub fn sample(&mut self, pos: Vector3<f32>) -> f32 {
+		let floor = pos.map(f32::floor);
+		let t = pos - floor;
+		let corners = self.neighborhood(floor.map(|x| x as i32));
+
+		// interpolate along x, then y, then z
+		let x00 = lerp(corners[0], corners[1], t.x);
+		let x10 = lerp(corners[2], corners[3], t.x);
+		let x01 = lerp(corners[4], corners[5], t.x);
+		let x11 = lerp(corners[6], corners[7], t.x);
+		lerp(lerp(x00, x10, t.y), lerp(x01, x11, t.y), t.z)
+	}
+
+	/// Samples every position in `positions`, writing the distances to `out`. The neighborhoods are fetched one at a
+	/// time, but they're interpolated `LANES` at once, which the compiler turns into SIMD.
+	pub fn sample_batch(&mut self, positions: &[Vector3<f32>], out: &mut [f32]) {
+		assert_eq!(positions.len(), out.len());
+		for (positions, out) in positions.chunks(LANES).zip(out.chunks_mut(LANES)) {
+			// each corner and axis is a row of lanes, so every lerp below works on a whole row
+			let mut corners = [[0.0; LANES]; 8];
+			let mut t = [[0.0; LANES]; 3];
+			for (lane, pos) in positions.iter().enumerate() {
+				let floor = pos.map(f32::floor);
+				for (corner, &distance) in corners.iter_mut().zip(&self.neighborhood(floor.map(|x| x as i32))) {
+					corner[lane] = distance;
+				}
+				for (axis, t) in t.iter_mut().enumerate() {
+					t[lane] = pos[axis] - floor[axis];
+				}
+			}
+
+			let x00 = lerp_lanes(&corners[0], &corners[1], &t[0]);
+			let x10 = lerp_lanes(&corners[2], &corners[3], &t[0]);
+			let x01 = lerp_lanes(&corners[4], &corners[5], &t[0]);
+			let x11 = lerp_lanes(&corners[6], &corners[7], &t[0]);
+			let distances = lerp_lanes(&lerp_lanes(&x00, &x10, &t[1]), &lerp_lanes(&x01, &x11, &t[1]), &t[2]);
+			out.copy_from_slice(&distances[..out.len()]);
+		}
+	}
+
+	/// Returns the eight voxels from `min` to `min + 1`, ordered by x, then y, then z.
+	fn neighborhood(&mut self, min: Vector3<i32>) -> [f32; 8] {
+		let (key, local) = split(min);
+
+		// fast path: the whole neighborhood is in one chunk, so it's eight reads at fixed strides
+		if local.iter().all(|&x| x < CHUNK_SIZE - 1) {
+			return match self.chunk(key) {
+				Some(chunk) => {
+					let base = index(local);
+					let mut out = [0.0; 8];
+					for (i, out) in out.iter_mut().enumerate() {
+						*out = decode(chunk.data[base + CORNER_OFFSETS[i]]);
+					}
+					out
+				},
+				None => [EMPTY_DISTANCE; 8],
+			};
+		}
+
+		let mut out = [0.0; 8];
+		for (i, out) in out.iter_mut().enumerate() {
+			let voxel = min + Vector3::new(i as i32 & 1, (i as i32 >> 1) & 1, i as i32 >> 2);
+			let (key, local) = split(voxel);
+			*out = self.chunk(key).map(|chunk| chunk.get(local)).unwrap_or(EMPTY_DISTANCE);
+		}
+		out
+	}
+
+	fn chunk(&mut self, key: Vector3<i32>) -> Option<&'a Chunk> {
+		match self.last {
+			Some((last, chunk)) if last == key => chunk,
+			_ => {
+				let chunk = self.world.chunks.get(&key);
+				self.last = Some((key, chunk));
+				chunk
+			},
+		}
+	}
+}
+
+/// How many samples `Sampler::sample_batch` interpolates at once. Four fit in an SSE or NEON register.
+const LANES: usize = 4;
+
+/// `lerp` for each lane.
+fn lerp_lanes(a: &[f32; LANES], b: &[f32; LANES], t: &[f32; LANES]) -> [f32; LANES] {
+	let mut out = [0.0; LANES];
+	for (((out, &a), &b), &t) in out.iter_mut().zip(a).zip(b).zip(t) {
+		*out = lerp(a, b, t);
+	}
+	out
+}
+
+/// Offsets into a chunk's data for each corner of a neighborhood, in the order `Sampler::neighborhood` returns them.
+const CORNER_OFFSETS: [usize; 8] = [
+	0,
+	1,
+	CHUNK_SIZE,
+	CHUNK_SIZE + 1,
+	CHUNK_SIZE * CHUNK_SIZE,
+	CHUNK_SIZE * CHUNK_SIZE + 1,
+	CHUNK_SIZE * CHUNK_SIZE + CHUNK_SIZE,
+	CHUNK_SIZE * CHUNK_SIZE + CHUNK_SIZE + 1,
+];
+
+/// The cube that `World::set_block` fills.
+fn block(voxel: Vector3<i32>) -> Shape {
+	Shape::Box(Aabb::from_center(voxel.map(|x| x as f32), Vector3::repeat(0.5)))
+}
+
+/// The key of the chunk containing `pos`.
+pub fn chunk_key(pos: Vector3<f32>) -> Vector3<i32> {
+	split(pos.map(|x| x.floor() as i32)).0
+}
+
+/// The space covered by the chunk with the given key. Voxels are sampled at integer positions, so each one covers half
+/// a voxel on either side.
+pub fn chunk_bounds(key: Vector3<i32>) -> Aabb {
+	let min = key.map(|x| (x << CHUNK_SHIFT) as f32 - 0.5);
+	Aabb::new(min, min + Vector3::repeat(CHUNK_SIZE as f32))
+}
+
+/// Splits a voxel position into its chunk's key and its position within the chunk.
+fn split(voxel: Vector3<i32>) -> (Vector3<i32>, Vector3<usize>) {
+	(voxel.map(|x| x >> CHUNK_SHIFT), voxel.map(|x| (x & CHUNK_MASK) as usize))
+}
+
+fn index(local: Vector3<usize>) -> usize {
+	local.x + local.y * CHUNK_SIZE + local.z * CHUNK_SIZE * CHUNK_SIZE
+}
+
+fn unindex(i: usize) -> Vector3<usize> {
+	Vector3::new(i % CHUNK_SIZE, i / CHUNK_SIZE % CHUNK_SIZE, i / (CHUNK_SIZE * CHUNK_SIZE))
+}
+
+/// The index of the brick containing a voxel, in `Chunk::occupied`.
+fn brick_index(local: Vector3<usize>) -> usize {
+	let brick = local.map(|x| x >> BRICK_SHIFT);
+	brick.x + brick.y * BRICKS_PER_EDGE + brick.z * BRICKS_PER_EDGE * BRICKS_PER_EDGE
+}
+
+fn decode(value: i8) -> f32 {
+	value as f32 / 127.0
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Two chunks side by side along X, filled up to z = 4.5 so the surface lies between voxel centers.
+	fn floor() -> World {
+		let mut world = World::new();
+		world.insert_chunk(Vector3::new(0, 0, 0), Chunk::new());
+		world.insert_chunk(Vector3::new(1, 0, 0), Chunk::new());
+		let floor = Aabb::new(Vector3::new(-1.0, -1.0, -1.0), Vector3::new(64.0, 32.0, 4.5));
+		world.edit(Edit::Add(Shape::Box(floor), 2));
+		world
+	}
+
+	#[test]
+	fn replayed_edits_match_loaded_ones() {
+		let edit = Edit::Subtract(Shape::Sphere { center: Vector3::new(32.0, 8.0, 4.0), radius: 6.0 });
+		let mut loaded = floor();
+		loaded.edit(edit);
+
+		// the second chunk loads after the edit, and catches up on it
+		let mut late = floor();
+		let chunk = late.remove_chunk(Vector3::new(1, 0, 0)).unwrap();
+		late.edit(edit);
+		late.take_edits();
+		late.insert_chunk(Vector3::new(1, 0, 0), chunk);
+		late.replay_edit(Vector3::new(1, 0, 0), edit);
+		assert!(late.take_edits().is_empty());
+		for &key in &[Vector3::new(0, 0, 0), Vector3::new(1, 0, 0)] {
+			assert_eq!(late.chunk(key).unwrap().data(), loaded.chunk(key).unwrap().data());
+		}
+	}
+
+	#[test]
+	fn deferred_edits_wait_to_be_applied() {
+		let mut world = floor();
+		world.take_edits();
+		world.defer_edits();
+		let voxel = Vector3::new(10.0, 10.0, 4.0);
+		world.remove_block(voxel.map(|x| x as i32));
+		assert!(world.sample(voxel) < 0.0);
+		assert!(world.take_edits().is_empty());
+
+		let deferred = world.take_deferred();
+		assert_eq!(deferred.len(), 1);
+		assert!(world.take_deferred().is_empty());
+		world.apply_edit(deferred[0]);
+		assert!(world.sample(voxel) > 0.0);
+		assert_eq!(world.take_edits(), deferred);
+	}
+
+	#[test]
+	fn raycast_hits_the_floor() {
+		let world = floor();
+		let hit = world.raycast(&Ray::new(Vector3::new(10.0, 10.0, 20.0), -Vector3::z()), 64.0).unwrap();
+		assert!((hit.distance - 15.5).abs() < 0.05, "hit at {}", hit.distance);
+		assert!(hit.normal.z > 0.99);
+		assert_eq!(hit.voxel(), Vector3::new(10, 10, 4));
+		assert_eq!(hit.adjacent(), Vector3::new(10, 10, 5));
+		assert_eq!(hit.chunk, Vector3::zeros());
+		assert_eq!(world.material(hit.voxel()), Some(2));
+	}
+
+	#[test]
+	fn raycast_crosses_chunks() {
+		let world = floor();
+		let dir = Vector3::new(1.0, 0.0, -0.25).normalize();
+		let hit = world.raycast(&Ray::new(Vector3::new(20.0, 10.0, 12.0), dir), 64.0).unwrap();
+		assert!((hit.pos.z - 4.5).abs() < 0.05);
+		assert_eq!(hit.chunk, Vector3::new(1, 0, 0));
+	}
+
+	#[test]
+	fn raycast_misses() {
+		let world = floor();
+		// pointing up, and too short to reach the floor
+		assert_eq!(world.raycast(&Ray::new(Vector3::new(10.0, 10.0, 20.0), Vector3::z()), 64.0), None);
+		assert_eq!(world.raycast(&Ray::new(Vector3::new(10.0, 10.0, 20.0), -Vector3::z()), 8.0), None);
+		// unloaded chunks are empty
+		assert_eq!(world.raycast(&Ray::new(Vector3::new(10.0, 40.0, 20.0), -Vector3::z()), 64.0), None);
+	}
+
+	#[test]
+	fn removed_blocks_let_rays_through() {
+		let mut world = floor();
+		world.remove_block(Vector3::new(10, 10, 4));
+		let hit = world.raycast(&Ray::new(Vector3::new(10.0, 10.0, 20.0), -Vector3::z()), 64.0).unwrap();
+		assert_eq!(hit.voxel(), Vector3::new(10, 10, 3));
+	}
+
+	#[test]
+	fn skip_empty_leaves_the_coarsest_empty_block() {
+		let world = floor();
+		let down = |z| world.skip_empty(&Ray::new(Vector3::new(10.5, 10.5, z), -Vector3::z()), 0.0);
+		// a 16 voxel block, then a brick, then a pair of voxels just above the floor
+		assert_eq!(down(20.0), Some(4.0));
+		assert_eq!(down(10.0), Some(2.0));
+		assert_eq!(down(7.0), Some(1.0));
+		// next to the surface, nothing around is empty
+		assert_eq!(down(5.0), None);
+	}
+
+	#[test]
+	fn downsample_keeps_the_nearest_voxel() {
+		let mut chunk = Chunk::new();
+		chunk.set(Vector3::new(5, 6, 7), 0.5);
+		chunk.set(Vector3::new(4, 7, 6), -0.5);
+		chunk.set_material(Vector3::new(4, 7, 6), 3);
+		let (data, mats) = chunk.downsample(1);
+		assert_eq!(data.len(), CHUNK_VOLUME / 8);
+		let size = CHUNK_SIZE / 2;
+		let i = 2 + 3 * size + 3 * size * size;
+		assert_eq!((decode(data[i]), mats[i]), (chunk.get(Vector3::new(4, 7, 6)), 3));
+		assert!(data.iter().enumerate().all(|(j, &x)| j == i || x == EMPTY_VOXEL));
+
+		let (data, _) = chunk.downsample(2);
+		assert_eq!(data.iter().filter(|&&x| x != EMPTY_VOXEL).count(), 1);
+	}
+
+	#[test]
+	fn residency_follows_edits() {
+		let mut world = floor();
+		assert_eq!(world.residency(Vector3::new(0, 0, 0)), Residency::Occupied);
+		assert_eq!(world.residency(Vector3::new(0, 0, 1)), Residency::Unloaded);
+		world.insert_chunk(Vector3::new(0, 0, 1), Chunk::new());
+		assert_eq!(world.residency(Vector3::new(0, 0, 1)), Residency::Empty);
+
+		// with a margin, since voxels just outside the removed space would still be near its surface
+		let all = Aabb::new(Vector3::new(-4.0, -4.0, -4.0), Vector3::new(68.0, 36.0, 8.0));
+		world.edit(Edit::Subtract(Shape::Box(all)));
+		assert_eq!(world.residency(Vector3::new(0, 0, 0)), Residency::Empty);
+	}
+
+	#[test]
+	fn sample_batch_matches_sample() {
+		let world = floor();
+		// enough for a partial last batch, and spread over both chunks, their seam and unloaded space
+		let positions = (0..11).map(|i| Vector3::new(i as f32 * 6.3 - 1.7, 9.2, i as f32 * 0.9)).collect::<Vec<_>>();
+		let mut batch = vec![0.0; positions.len()];
+		world.sampler().sample_batch(&positions, &mut batch);
+		for (pos, batch) in positions.iter().zip(batch) {
+			assert_eq!(batch, world.sample(*pos), "at {:?}", pos);
+		}
+	}
+}
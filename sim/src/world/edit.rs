@@ -0,0 +1,113 @@
+//! Constructive solid geometry edits to the distance field and its materials.
+
+use crate::math::Aabb;
+use nalgebra::Vector3;
+
+/// Identifies what a voxel is made of. 0 is the default material.
+pub type Material = u8;
+
+/// A region of space. Positions and sizes are in voxels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+	Box(Aabb),
+	Sphere { center: Vector3<f32>, radius: f32 },
+}
+impl Shape {
+	/// The signed distance from `pos` to the shape's surface, negative inside.
+	pub fn distance(&self, pos: Vector3<f32>) -> f32 {
+		match *self {
+			Shape::Box(aabb) => aabb.distance(pos),
+			Shape::Sphere { center, radius } => (pos - center).norm() - radius,
+		}
+	}
+
+	pub fn bounds(&self) -> Aabb {
+		match *self {
+			Shape::Box(aabb) => aabb,
+			Shape::Sphere { center, radius } => Aabb::from_center(center, Vector3::repeat(radius)),
+		}
+	}
+}
+
+/// A change to the world's shape or materials.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edit {
+	/// Fills the shape with a material.
+	Add(Shape, Material),
+	Subtract(Shape),
+	/// Changes the material of voxels inside the shape without changing the surface.
+	Paint(Shape, Material),
+}
+impl Edit {
+	pub fn shape(&self) -> Shape {
+		match *self {
+			Edit::Add(shape, _) | Edit::Subtract(shape) | Edit::Paint(shape, _) => shape,
+		}
+	}
+
+	/// The region the edit can change. Distances are clamped to one voxel, so this is the shape grown by one.
+	pub fn bounds(&self) -> Aabb {
+		let shape = self.shape().bounds();
+		Aabb::new(shape.min - Vector3::repeat(1.0), shape.max + Vector3::repeat(1.0))
+	}
+
+	/// Combines the distance already at `pos` with the edit's shape. Also returns the voxel's new material, if it
+	/// changes.
+	pub fn apply(&self, pos: Vector3<f32>, distance: f32) -> (f32, Option<Material>) {
+		let shape = self.shape().distance(pos);
+		match *self {
+			// the voxel takes the new material if the new shape is now its nearest surface
+			Edit::Add(_, material) if shape < distance => (shape, Some(material)),
+			Edit::Add(..) => (distance, None),
+			Edit::Subtract(_) => (distance.max(-shape), None),
+			Edit::Paint(_, material) if shape <= 0.0 => (distance, Some(material)),
+			Edit::Paint(..) => (distance, None),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn sphere() -> Shape {
+		Shape::Sphere { center: Vector3::zeros(), radius: 2.0 }
+	}
+
+	#[test]
+	fn add_takes_the_nearer_surface() {
+		let edit = Edit::Add(sphere(), 5);
+		// inside the sphere, where it's now the nearest surface
+		assert_eq!(edit.apply(Vector3::new(1.0, 0.0, 0.0), 1.0), (-1.0, Some(5)));
+		// outside it, next to a surface that's closer
+		assert_eq!(edit.apply(Vector3::new(3.0, 0.0, 0.0), 0.5), (0.5, None));
+	}
+
+	#[test]
+	fn subtract_carves_without_changing_materials() {
+		let edit = Edit::Subtract(sphere());
+		assert_eq!(edit.apply(Vector3::new(1.0, 0.0, 0.0), -1.0), (1.0, None));
+		assert_eq!(edit.apply(Vector3::new(4.0, 0.0, 0.0), -1.0), (-1.0, None));
+	}
+
+	#[test]
+	fn paint_keeps_the_surface() {
+		let edit = Edit::Paint(sphere(), 9);
+		assert_eq!(edit.apply(Vector3::new(0.0, 1.0, 0.0), -0.25), (-0.25, Some(9)));
+		assert_eq!(edit.apply(Vector3::new(0.0, 3.0, 0.0), -0.25), (-0.25, None));
+	}
+
+	#[test]
+	fn bounds_cover_the_clamped_distance() {
+		let bounds = Edit::Subtract(sphere()).bounds();
+		assert_eq!(bounds.min, Vector3::repeat(-3.0));
+		assert_eq!(bounds.max, Vector3::repeat(3.0));
+	}
+
+	#[test]
+	fn box_distance() {
+		let shape = Shape::Box(Aabb::new(Vector3::zeros(), Vector3::repeat(2.0)));
+		assert_eq!(shape.distance(Vector3::new(1.0, 1.0, 1.0)), -1.0);
+		assert_eq!(shape.distance(Vector3::new(1.0, 1.0, 5.0)), 3.0);
+	}
+}
@@ -0,0 +1,229 @@
+//! Procedural terrain. Generators fill whole chunks on worker threads, and `GenerationQueue` feeds the results into
+//! the world.
+
+use crate::{
+	math::lerp,
+	threads::{JobHandle, JobKind, JOBS},
+	world::{storage::ChunkStorage, Chunk, World, CHUNK_SIZE},
+};
+use nalgebra::{Vector2, Vector3};
+use std::{collections::HashMap, sync::Arc};
+
+/// Produces the initial contents of chunks. Implement this for each kind of world.
+pub trait TerrainGenerator: Send + Sync {
+	/// Fills the chunk at `key`. Called from worker threads, so it shouldn't touch anything but `self`.
+	fn generate(&self, key: Vector3<i32>) -> Chunk;
+}
+
+/// Solid ground below a fixed height.
+pub struct FlatTerrain {
+	pub height: f32,
+}
+impl TerrainGenerator for FlatTerrain {
+	fn generate(&self, key: Vector3<i32>) -> Chunk {
+		fill(key, |pos| pos.z - self.height)
+	}
+}
+
+/// Rolling hills from layered noise, with caves carved out beneath them.
+pub struct NoiseTerrain {
+	pub seed: u32,
+	/// The average ground height, in voxels.
+	pub base_height: f32,
+	/// How far hills rise above and valleys sink below `base_height`.
+	pub amplitude: f32,
+	/// The width of the largest hills, in voxels.
+	pub wavelength: f32,
+	/// Higher values make fewer, thinner caves. Noise ranges from about -1 to 1, so 1 or more disables caves.
+	pub cave_threshold: f32,
+	pub cave_wavelength: f32,
+}
+impl Default for NoiseTerrain {
+	fn default() -> Self {
+		Self {
+			seed: 0,
+			base_height: 0.0,
+			amplitude: 24.0,
+			wavelength: 128.0,
+			cave_threshold: 0.35,
+			cave_wavelength: 24.0,
+		}
+	}
+}
+impl NoiseTerrain {
+	fn height(&self, pos: Vector2<f32>) -> f32 {
+		let pos = Vector3::new(pos.x, pos.y, 0.0) / self.wavelength;
+		self.base_height + fbm(self.seed, pos, 4) * self.amplitude
+	}
+}
+impl TerrainGenerator for NoiseTerrain {
+	fn generate(&self, key: Vector3<i32>) -> Chunk {
+		// heights only depend on x and y, so compute each column once
+		let origin = key * CHUNK_SIZE as i32;
+		let mut heights = vec![0.0; CHUNK_SIZE * CHUNK_SIZE];
+		for y in 0..CHUNK_SIZE {
+			for x in 0..CHUNK_SIZE {
+				let pos = Vector2::new((origin.x + x as i32) as f32, (origin.y + y as i32) as f32);
+				heights[x + y * CHUNK_SIZE] = self.height(pos);
+			}
+		}
+
+		let mut chunk = Chunk::new();
+		for z in 0..CHUNK_SIZE {
+			for y in 0..CHUNK_SIZE {
+				for x in 0..CHUNK_SIZE {
+					let pos = (origin + Vector3::new(x, y, z).map(|x| x as i32)).map(|x| x as f32);
+					let ground = pos.z - heights[x + y * CHUNK_SIZE];
+					// skip the cave noise where the distance gets clamped away anyway
+					let distance = if ground < -1.0 {
+						let cave = noise(self.seed.wrapping_add(1), pos / self.cave_wavelength);
+						ground.max((cave - self.cave_threshold) * self.cave_wavelength)
+					} else {
+						ground
+					};
+					chunk.set(Vector3::new(x, y, z), distance);
+				}
+			}
+		}
+		chunk
+	}
+}
+
+/// Generates chunks in the background and inserts them into a `World` when they're done.
+pub struct GenerationQueue {
+	generator: Arc<dyn TerrainGenerator>,
+	storage: Option<Arc<ChunkStorage>>,
+	pending: HashMap<Vector3<i32>, JobHandle<Chunk>>,
+}
+impl GenerationQueue {
+	pub fn new(generator: Arc<dyn TerrainGenerator>) -> Self {
+		Self { generator, storage: None, pending: HashMap::new() }
+	}
+
+	/// Loads chunks from `storage` when they've been saved, and only generates the rest.
+	pub fn with_storage(mut self, storage: Arc<ChunkStorage>) -> Self {
+		self.storage = Some(storage);
+		self
+	}
+
+	/// Starts generating the chunk at `key`, unless it's already in progress.
+	pub fn request(&mut self, key: Vector3<i32>) {
+		if self.pending.contains_key(&key) {
+			return;
+		}
+
+		let generator = self.generator.clone();
+		let storage = self.storage.clone();
+		// a task rather than a job, so the worker is free while the saved chunk is read
+		let job = JOBS.spawn_async(JobKind::Background, async move {
+			if let Some(storage) = storage {
+				match storage.load(key).await {
+					Ok(Some(chunk)) => return chunk,
+					Ok(None) => (),
+					Err(err) => log::warn!("failed to load chunk {:?}, generating it instead: {}", key, err),
+				}
+			}
+			generator.generate(key)
+		});
+		self.pending.insert(key, job);
+	}
+
+	pub fn is_pending(&self, key: Vector3<i32>) -> bool {
+		self.pending.contains_key(&key)
+	}
+
+	/// Whether every requested chunk has been inserted.
+	pub fn is_idle(&self) -> bool {
+		self.pending.is_empty()
+	}
+
+	/// Stops waiting for a chunk that's no longer needed. It still finishes generating, but is thrown away.
+	pub fn cancel(&mut self, key: Vector3<i32>) {
+		self.pending.remove(&key);
+	}
+
+	/// Inserts every finished chunk into `world`, and returns their keys.
+	pub fn poll(&mut self, world: &mut World) -> Vec<Vector3<i32>> {
+		let mut finished = vec![];
+		self.pending.retain(|&key, job| match job.try_take() {
+			Some(chunk) => {
+				world.insert_chunk(key, chunk);
+				finished.push(key);
+				false
+			},
+			None => true,
+		});
+		finished
+	}
+}
+
+/// Calls `distance` with the world position of each voxel in a chunk.
+fn fill(key: Vector3<i32>, distance: impl Fn(Vector3<f32>) -> f32) -> Chunk {
+	let origin = key * CHUNK_SIZE as i32;
+	let mut chunk = Chunk::new();
+	for z in 0..CHUNK_SIZE {
+		for y in 0..CHUNK_SIZE {
+			for x in 0..CHUNK_SIZE {
+				let local = Vector3::new(x, y, z);
+				chunk.set(local, distance((origin + local.map(|x| x as i32)).map(|x| x as f32)));
+			}
+		}
+	}
+	chunk
+}
+
+/// Sums octaves of `noise`, each twice the frequency and half the amplitude of the last.
+fn fbm(seed: u32, pos: Vector3<f32>, octaves: u32) -> f32 {
+	let mut sum = 0.0;
+	let mut amplitude = 1.0;
+	let mut total = 0.0;
+	for octave in 0..octaves {
+		sum += noise(seed.wrapping_add(octave), pos * (1 << octave) as f32) * amplitude;
+		total += amplitude;
+		amplitude /= 2.0;
+	}
+	sum / total
+}
+
+/// Gradient noise, roughly from -1 to 1, with features about one unit apart.
+fn noise(seed: u32, pos: Vector3<f32>) -> f32 {
+	let cell = pos.map(f32::floor);
+	let t = pos - cell;
+	let cell = cell.map(|x| x as i32);
+	let fade = t.map(|t| t * t * t * (t * (t * 6.0 - 15.0) + 10.0));
+
+	let mut corners = [0.0; 8];
+	for (i, corner) in corners.iter_mut().enumerate() {
+		let offset = Vector3::new(i as i32 & 1, (i as i32 >> 1) & 1, i as i32 >> 2);
+		*corner = gradient(seed, cell + offset).dot(&(t - offset.map(|x| x as f32)));
+	}
+
+	let x00 = lerp(corners[0], corners[1], fade.x);
+	let x10 = lerp(corners[2], corners[3], fade.x);
+	let x01 = lerp(corners[4], corners[5], fade.x);
+	let x11 = lerp(corners[6], corners[7], fade.x);
+	lerp(lerp(x00, x10, fade.y), lerp(x01, x11, fade.y), fade.z)
+}
+
+/// One of the 12 edge directions of a cube, picked by hashing `cell`.
+fn gradient(seed: u32, cell: Vector3<i32>) -> Vector3<f32> {
+	let mut hash = seed ^ 0x9e37_79b9;
+	for &x in cell.iter() {
+		hash = (hash ^ x as u32).wrapping_mul(0x85eb_ca6b);
+		hash ^= hash >> 13;
+	}
+	match hash % 12 {
+		0 => Vector3::new(1.0, 1.0, 0.0),
+		1 => Vector3::new(-1.0, 1.0, 0.0),
+		2 => Vector3::new(1.0, -1.0, 0.0),
+		3 => Vector3::new(-1.0, -1.0, 0.0),
+		4 => Vector3::new(1.0, 0.0, 1.0),
+		5 => Vector3::new(-1.0, 0.0, 1.0),
+		6 => Vector3::new(1.0, 0.0, -1.0),
+		7 => Vector3::new(-1.0, 0.0, -1.0),
+		8 => Vector3::new(0.0, 1.0, 1.0),
+		9 => Vector3::new(0.0, -1.0, 1.0),
+		10 => Vector3::new(0.0, 1.0, -1.0),
+		_ => Vector3::new(0.0, -1.0, -1.0),
+	}
+}
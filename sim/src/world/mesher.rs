@@ -0,0 +1,116 @@
+//! Triangle meshes of the distance field's surface, built a chunk at a time with surface nets, for drawing the world
+//! without raymarching it.
+
+use super::{decode, CHUNK_SHIFT, CHUNK_SIZE};
+use nalgebra::Vector3;
+
+/// How many voxels across `surface_nets` reads: the chunk, and one more on each side so its surface meets its
+/// neighbors'.
+pub const PADDED_SIZE: usize = CHUNK_SIZE + 2;
+
+/// The cells between voxels that `surface_nets` can place vertices in, across each axis.
+const CELLS: usize = PADDED_SIZE - 1;
+
+/// The corners of a cell, as offsets from its lowest voxel. Bit `i` of the index is the offset on axis `i`.
+const CORNERS: [[usize; 3]; 8] =
+	[[0, 0, 0], [1, 0, 0], [0, 1, 0], [1, 1, 0], [0, 0, 1], [1, 0, 1], [0, 1, 1], [1, 1, 1]];
+
+/// The cell edges, as pairs of indices into `CORNERS`.
+const EDGES: [(usize, usize); 12] =
+	[(0, 1), (2, 3), (4, 5), (6, 7), (0, 2), (1, 3), (4, 6), (5, 7), (0, 4), (1, 5), (2, 6), (3, 7)];
+
+/// An indexed triangle list in world space, wound counter-clockwise when viewed from outside like `Mesh`.
+#[derive(Clone, Debug, Default)]
+pub struct SurfaceMesh {
+	pub positions: Vec<Vector3<f32>>,
+	pub normals: Vec<Vector3<f32>>,
+	pub indices: Vec<u32>,
+}
+
+/// Meshes the surface in chunk `key`. `voxels` are `PADDED_SIZE` across, starting one voxel below the chunk's first on
+/// each axis, as `World::copy_voxels` gives them.
+pub fn surface_nets(key: Vector3<i32>, voxels: &[i8]) -> SurfaceMesh {
+	assert_eq!(voxels.len(), PADDED_SIZE * PADDED_SIZE * PADDED_SIZE);
+	let sample = |p: [usize; 3]| decode(voxels[p[0] + p[1] * PADDED_SIZE + p[2] * PADDED_SIZE * PADDED_SIZE]);
+	// the position of the padding's first voxel
+	let origin = key.map(|x| ((x << CHUNK_SHIFT) - 1) as f32);
+
+	// one vertex in each cell the surface passes through, at the average of where it crosses the cell's edges
+	let mut mesh = SurfaceMesh::default();
+	let mut cell_verts = vec![u32::MAX; CELLS * CELLS * CELLS];
+	let cell_index = |c: [usize; 3]| c[0] + c[1] * CELLS + c[2] * CELLS * CELLS;
+	for z in 0..CELLS {
+		for y in 0..CELLS {
+			for x in 0..CELLS {
+				let mut corners = [0.0; 8];
+				for (corner, offset) in corners.iter_mut().zip(&CORNERS) {
+					*corner = sample([x + offset[0], y + offset[1], z + offset[2]]);
+				}
+				let inside = corners.iter().filter(|&&d| d < 0.0).count();
+				if inside == 0 || inside == 8 {
+					continue;
+				}
+
+				let mut sum = Vector3::zeros();
+				let mut crossings = 0;
+				for &(a, b) in &EDGES {
+					let (da, db) = (corners[a], corners[b]);
+					if (da < 0.0) != (db < 0.0) {
+						let t = da / (da - db);
+						let corner = |i: usize| Vector3::from(CORNERS[i]).map(|x| x as f32);
+						sum += corner(a) + (corner(b) - corner(a)) * t;
+						crossings += 1;
+					}
+				}
+				let pos = origin + Vector3::new(x, y, z).map(|x| x as f32) + sum / crossings as f32;
+
+				// the gradient across the cell, which points out of the surface
+				let gradient = |axis: usize| {
+					let bit = 1 << axis;
+					(0..8).map(|i| if i & bit != 0 { corners[i] } else { -corners[i] }).sum::<f32>()
+				};
+				let normal = Vector3::new(gradient(0), gradient(1), gradient(2));
+				let normal = normal.try_normalize(1e-6).unwrap_or_else(Vector3::z);
+
+				cell_verts[cell_index([x, y, z])] = mesh.positions.len() as u32;
+				mesh.positions.push(pos);
+				mesh.normals.push(normal);
+			}
+		}
+	}
+
+	// a quad across each edge the surface crosses, joining the four cells around it. the chunk owns the edges that
+	// start at its own voxels, so neighboring chunks never both make the same quad
+	for z in 1..=CHUNK_SIZE {
+		for y in 1..=CHUNK_SIZE {
+			for x in 1..=CHUNK_SIZE {
+				let p = [x, y, z];
+				let d = sample(p);
+				for axis in 0..3 {
+					let mut next = p;
+					next[axis] += 1;
+					if (d < 0.0) == (sample(next) < 0.0) {
+						continue;
+					}
+
+					// the other two axes, in the order that makes them a right-handed frame with `axis`
+					let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+					let cell = |du: usize, dv: usize| {
+						let mut c = p;
+						c[u] -= 1 - du;
+						c[v] -= 1 - dv;
+						cell_verts[cell_index(c)]
+					};
+					let quad = [cell(0, 0), cell(1, 0), cell(1, 1), cell(0, 1)];
+					// counter-clockwise seen from along `axis`, which is the outside if `p` is inside
+					if d < 0.0 {
+						mesh.indices.extend_from_slice(&[quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+					} else {
+						mesh.indices.extend_from_slice(&[quad[0], quad[2], quad[1], quad[0], quad[3], quad[2]]);
+					}
+				}
+			}
+		}
+	}
+	mesh
+}
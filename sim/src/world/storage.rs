@@ -0,0 +1,208 @@
+//! Saves chunks to disk so edits survive restarts.
+//!
+//! Each chunk is a file named after its key. Most of a chunk is empty space or solid ground with the same distance,
+//! so the voxels are run-length encoded. `decode_into` streams a chunk out of any reader without buffering it, so a
+//! chunk can go from a file or socket straight into mapped memory.
+
+use crate::{
+	fs,
+	threads::JobHandle,
+	world::{Chunk, CHUNK_VOLUME},
+};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use nalgebra::Vector3;
+use std::{
+	io::{self, Read},
+	path::PathBuf,
+};
+
+const MAGIC: &[u8; 4] = b"SDFC";
+const VERSION: u8 = 2;
+
+/// A directory of saved chunks.
+pub struct ChunkStorage {
+	dir: PathBuf,
+}
+impl ChunkStorage {
+	/// Creates the directory if it doesn't exist.
+	pub fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+		let dir = dir.into();
+		std::fs::create_dir_all(&dir)?;
+		Ok(Self { dir })
+	}
+
+	/// Reads a saved chunk, or returns `None` if the chunk has never been saved.
+	pub async fn load(&self, key: Vector3<i32>) -> io::Result<Option<Chunk>> {
+		match fs::read_bytes(self.path(key)).await {
+			Ok(bytes) => decode(&bytes).map(Some),
+			Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+			Err(err) => Err(err),
+		}
+	}
+
+	pub fn save(&self, key: Vector3<i32>, chunk: &Chunk) -> JobHandle<io::Result<()>> {
+		fs::write(self.path(key), encode(chunk))
+	}
+
+	fn path(&self, key: Vector3<i32>) -> PathBuf {
+		self.dir.join(format!("{}_{}_{}.chunk", key.x, key.y, key.z))
+	}
+}
+
+pub fn encode(chunk: &Chunk) -> Vec<u8> {
+	let mut out = MAGIC.to_vec();
+	out.push(VERSION);
+	write_runs(&mut out, chunk.data.iter().map(|&x| x as u8));
+	write_runs(&mut out, chunk.mats.iter().copied());
+	out
+}
+
+pub fn decode(mut bytes: &[u8]) -> io::Result<Chunk> {
+	let version = read_header(&mut bytes)?;
+	let mut data = vec![0; CHUNK_VOLUME];
+	read_runs(&mut bytes, &mut data)?;
+	let mut mats = vec![0; CHUNK_VOLUME];
+	// version 1 predates materials
+	if version >= 2 {
+		read_runs(&mut bytes, &mut mats)?;
+	}
+	Ok(Chunk::from_raw(data.into_iter().map(|x| x as i8).collect(), mats.into()))
+}
+
+/// Decodes a chunk into `out`, which is `2 * CHUNK_VOLUME` bytes: the distances as bytes, then the materials, the
+/// layout the renderer stages chunks in. Runs are expanded as they're read and nothing else is written, so `out` can
+/// be mapped memory.
+pub fn decode_into(mut reader: impl Read, out: &mut [u8]) -> io::Result<()> {
+	assert_eq!(out.len(), 2 * CHUNK_VOLUME);
+	let version = read_header(&mut reader)?;
+	let (data, mats) = out.split_at_mut(CHUNK_VOLUME);
+	read_runs(&mut reader, data)?;
+	if version >= 2 {
+		read_runs(&mut reader, mats)?;
+	} else {
+		mats.iter_mut().for_each(|x| *x = 0);
+	}
+	Ok(())
+}
+
+/// Checks the magic number and returns the version.
+fn read_header(reader: &mut impl Read) -> io::Result<u8> {
+	let mut header = [0; 5];
+	reader.read_exact(&mut header).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not a chunk file"))?;
+	if &header[..4] != MAGIC {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, "not a chunk file"));
+	}
+	let version = header[4];
+	if version == 0 || version > VERSION {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unsupported chunk version {}", version)));
+	}
+	Ok(version)
+}
+
+/// Run-length encodes one byte per voxel.
+fn write_runs(out: &mut Vec<u8>, values: impl Iterator<Item = u8>) {
+	let mut values = values.peekable();
+	while let Some(value) = values.next() {
+		let mut run = 1u16;
+		while run < u16::MAX && values.peek() == Some(&value) {
+			values.next();
+			run += 1;
+		}
+		out.write_u16::<LittleEndian>(run).unwrap();
+		out.write_u8(value).unwrap();
+	}
+}
+
+/// Fills `out` with runs from `reader`. Errors if the runs don't add up to exactly its length.
+fn read_runs(reader: &mut impl Read, out: &mut [u8]) -> io::Result<()> {
+	let mut filled = 0;
+	while filled < out.len() {
+		let run = reader.read_u16::<LittleEndian>()? as usize;
+		let value = reader.read_u8()?;
+		if filled + run > out.len() {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "chunk has too many voxels"));
+		}
+		out[filled..filled + run].iter_mut().for_each(|x| *x = value);
+		filled += run;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		math::Aabb,
+		world::{
+			edit::{Edit, Shape},
+			World,
+		},
+	};
+
+	/// A chunk with a surface and two materials, so both streams have more than one run.
+	fn edited_chunk() -> Chunk {
+		let mut world = World::new();
+		world.insert_chunk(Vector3::zeros(), Chunk::new());
+		let floor = Aabb::new(Vector3::repeat(-1.0), Vector3::new(32.0, 32.0, 6.5));
+		world.edit(Edit::Add(Shape::Box(floor), 3));
+		world.edit(Edit::Subtract(Shape::Sphere { center: Vector3::new(12.0, 20.0, 6.0), radius: 4.0 }));
+		world.remove_chunk(Vector3::zeros()).unwrap()
+	}
+
+	#[test]
+	fn round_trip() {
+		let chunk = edited_chunk();
+		let decoded = decode(&encode(&chunk)).unwrap();
+		assert_eq!(decoded.data, chunk.data);
+		assert_eq!(decoded.mats, chunk.mats);
+		assert_eq!(decoded.occupied, chunk.occupied);
+	}
+
+	#[test]
+	fn decode_into_matches_decode() {
+		let chunk = edited_chunk();
+		// garbage, to check every byte is written
+		let mut out = vec![0xaa; 2 * CHUNK_VOLUME];
+		decode_into(&encode(&chunk)[..], &mut out).unwrap();
+		assert!(out[..CHUNK_VOLUME].iter().zip(chunk.data.iter()).all(|(&a, &b)| a == b as u8));
+		assert_eq!(&out[CHUNK_VOLUME..], &*chunk.mats);
+	}
+
+	#[test]
+	fn empty_chunk_is_small() {
+		// a run per stream, plus the header
+		assert_eq!(encode(&Chunk::new()).len(), 5 + 3 + 3);
+	}
+
+	#[test]
+	fn runs_longer_than_u16_are_split() {
+		let mut out = vec![];
+		write_runs(&mut out, vec![7; 70_000].into_iter());
+		assert_eq!(out, [0xff, 0xff, 7, 0x71, 0x11, 7]);
+	}
+
+	#[test]
+	fn version_1_has_no_materials() {
+		let mut bytes = MAGIC.to_vec();
+		bytes.push(1);
+		write_runs(&mut bytes, Chunk::new().data.iter().map(|&x| x as u8));
+		let chunk = decode(&bytes).unwrap();
+		assert!(chunk.mats.iter().all(|&x| x == 0));
+		let mut out = vec![0xaa; 2 * CHUNK_VOLUME];
+		decode_into(&bytes[..], &mut out).unwrap();
+		assert!(out[CHUNK_VOLUME..].iter().all(|&x| x == 0));
+	}
+
+	#[test]
+	fn rejects_bad_data() {
+		assert!(decode(b"nope").is_err());
+		assert!(decode(b"SDFC\x03").is_err());
+		// truncated after the header
+		assert!(decode(b"SDFC\x02").is_err());
+
+		let mut too_long = MAGIC.to_vec();
+		too_long.push(VERSION);
+		write_runs(&mut too_long, vec![0; CHUNK_VOLUME + 1].into_iter());
+		assert!(decode(&too_long).is_err());
+	}
+}
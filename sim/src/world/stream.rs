@@ -0,0 +1,120 @@
+//! Keeps the chunks around the camera loaded, and unloads the rest. It also picks the level of detail to draw each one
+//! with, which drops in rings around the camera.
+
+use crate::world::{chunk_key, generate::GenerationQueue, storage::ChunkStorage, World};
+use nalgebra::Vector3;
+use std::{collections::HashSet, sync::Arc};
+
+/// Chunks up to this many from the camera's in each direction are drawn at full resolution, and each ring this many
+/// wide after that is drawn at the next level of detail.
+pub const LOD_RING: i32 = 2;
+/// The lowest level of detail, where each level halves the resolution of the one before.
+pub const MAX_LOD: u32 = 2;
+
+/// What changed in the world during a `ChunkStreamer::update`, so renderers can update only those chunks.
+#[derive(Clone, Debug, Default)]
+pub struct StreamChanges {
+	pub loaded: Vec<Vector3<i32>>,
+	pub unloaded: Vec<Vector3<i32>>,
+}
+
+pub struct ChunkStreamer {
+	queue: GenerationQueue,
+	storage: Option<Arc<ChunkStorage>>,
+	/// How many chunks to keep loaded in each direction from the camera's chunk.
+	radius: i32,
+	center: Option<Vector3<i32>>,
+	/// Chunks that are loaded or being loaded.
+	wanted: HashSet<Vector3<i32>>,
+}
+impl ChunkStreamer {
+	pub fn new(queue: GenerationQueue, storage: Option<Arc<ChunkStorage>>, radius: i32) -> Self {
+		Self { queue, storage, radius, center: None, wanted: HashSet::new() }
+	}
+
+	/// Requests chunks that came into range and unloads ones that left it, saving any edits first. Chunks load in the
+	/// background, so they show up in later updates.
+	pub fn update(&mut self, world: &mut World, camera_pos: Vector3<f32>) -> StreamChanges {
+		let mut changes = StreamChanges::default();
+
+		let center = chunk_key(camera_pos);
+		if self.center != Some(center) {
+			self.center = Some(center);
+			self.retarget(world, center, &mut changes);
+		}
+
+		changes.loaded = self.queue.poll(world);
+		changes
+	}
+
+	/// The level of detail to draw the chunk at `key` with. It changes as the camera moves, so chunks are promoted as
+	/// it gets closer and demoted as it leaves.
+	pub fn lod(&self, key: Vector3<i32>) -> u32 {
+		self.center.map_or(0, |center| lod(key, center))
+	}
+
+	/// Whether chunks in range are still loading. Once it's false, `update` has inserted all of them.
+	pub fn is_loading(&self) -> bool {
+		!self.queue.is_idle()
+	}
+
+	fn retarget(&mut self, world: &mut World, center: Vector3<i32>, changes: &mut StreamChanges) {
+		let radius = self.radius;
+		let in_range = |key: &Vector3<i32>| (key - center).iter().all(|x| x.abs() <= radius);
+
+		let far = self.wanted.iter().filter(|key| !in_range(key)).copied().collect::<Vec<_>>();
+		for key in far {
+			self.wanted.remove(&key);
+			self.queue.cancel(key);
+			if let Some(storage) = &self.storage {
+				if let Some(save) = world.save_chunk(storage, key) {
+					save.forget();
+				}
+			}
+			if world.remove_chunk(key).is_some() {
+				changes.unloaded.push(key);
+			}
+		}
+
+		// request the nearest chunks first, since the job system runs them in order
+		let mut near = vec![];
+		for z in -radius..=radius {
+			for y in -radius..=radius {
+				for x in -radius..=radius {
+					let key = center + Vector3::new(x, y, z);
+					if self.wanted.contains(&key) {
+						continue;
+					}
+					if world.chunk(key).is_some() {
+						self.wanted.insert(key);
+					} else {
+						near.push(key);
+					}
+				}
+			}
+		}
+		near.sort_by_key(|key| (key - center).iter().map(|x| x * x).sum::<i32>());
+		for key in near {
+			self.wanted.insert(key);
+			self.queue.request(key);
+		}
+	}
+}
+
+/// The level of detail of the chunk at `key` when the camera is in the chunk at `center`. See `LOD_RING`.
+pub fn lod(key: Vector3<i32>, center: Vector3<i32>) -> u32 {
+	let distance = (key - center).abs().max();
+	(((distance - 1).max(0) / LOD_RING) as u32).min(MAX_LOD)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn lod_drops_in_rings() {
+		let center = Vector3::new(10, -3, 4);
+		let lods = (0..8).map(|x| lod(center + Vector3::new(x, 1, -x / 2), center)).collect::<Vec<_>>();
+		assert_eq!(lods, [0, 0, 0, 1, 1, 2, 2, 2]);
+	}
+}
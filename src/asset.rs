@@ -0,0 +1,264 @@
+//! Loading authored content from disk. Files are read on the IO thread, so loading doesn't stall the frame.
+
+pub mod ktx2;
+
+use crate::gfx::{
+	image::Mips,
+	mesh::{Material, Mesh, MeshVertex, Model},
+	texture::TextureHandle,
+	Gfx,
+};
+use ash::vk;
+use gltf::{buffer, image::Source, mesh::Mode, Gltf, Node};
+use image::ImageError;
+use nalgebra::{Matrix3, Matrix4, Point3, Vector2, Vector3, Vector4, U3};
+use sim::fs;
+use std::{
+	collections::HashMap,
+	error::Error,
+	f32::consts::FRAC_PI_2,
+	fmt, io,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+/// Why an asset couldn't be loaded.
+#[derive(Debug)]
+pub enum AssetError {
+	Io(io::Error),
+	Gltf(gltf::Error),
+	Image(ImageError),
+	Ktx2(&'static str),
+	/// A buffer refers to the binary chunk of a .glb, but the file doesn't have one.
+	MissingBlob,
+	/// Only files next to the asset and data embedded in a .glb are supported.
+	UnsupportedUri(String),
+}
+impl fmt::Display for AssetError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Io(err) => write!(f, "failed to read asset: {}", err),
+			Self::Gltf(err) => write!(f, "invalid glTF: {}", err),
+			Self::Image(err) => write!(f, "failed to decode image: {}", err),
+			Self::Ktx2(reason) => write!(f, "invalid KTX2: {}", reason),
+			Self::MissingBlob => write!(f, "the glTF has no binary chunk"),
+			Self::UnsupportedUri(uri) => write!(f, "unsupported URI {}", uri),
+		}
+	}
+}
+impl Error for AssetError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			Self::Io(err) => Some(err),
+			Self::Gltf(err) => Some(err),
+			Self::Image(err) => Some(err),
+			Self::Ktx2(_) | Self::MissingBlob | Self::UnsupportedUri(_) => None,
+		}
+	}
+}
+impl From<io::Error> for AssetError {
+	fn from(err: io::Error) -> Self {
+		Self::Io(err)
+	}
+}
+impl From<gltf::Error> for AssetError {
+	fn from(err: gltf::Error) -> Self {
+		Self::Gltf(err)
+	}
+}
+impl From<ImageError> for AssetError {
+	fn from(err: ImageError) -> Self {
+		Self::Image(err)
+	}
+}
+
+/// Loads the triangles of a glTF 2.0 file's default scene, with each node's transform baked into its vertices. Each
+/// primitive becomes a mesh with its material's base color and base color texture. Textures are uploaded before this
+/// returns, so it blocks for a moment once the file is read.
+pub async fn load_gltf(gfx: &Gfx, path: impl Into<PathBuf>) -> Result<Arc<Model>, AssetError> {
+	let path = path.into();
+	let base = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+	let Gltf { document, mut blob } = Gltf::from_slice(&fs::read_bytes(path).await?)?;
+
+	let mut buffers = Vec::with_capacity(document.buffers().len());
+	for buffer in document.buffers() {
+		let data = match buffer.source() {
+			buffer::Source::Bin => blob.take().ok_or(AssetError::MissingBlob)?,
+			buffer::Source::Uri(uri) => read_relative(&base, uri).await?,
+		};
+		buffers.push(data);
+	}
+
+	let mut textures = HashMap::new();
+	for material in document.materials() {
+		let image = match material.pbr_metallic_roughness().base_color_texture() {
+			Some(info) => info.texture().source(),
+			None => continue,
+		};
+		if textures.contains_key(&image.index()) {
+			continue;
+		}
+		let encoded = match image.source() {
+			Source::View { view, .. } => {
+				buffers[view.buffer().index()][view.offset()..view.offset() + view.length()].to_vec()
+			},
+			Source::Uri { uri, .. } => read_relative(&base, uri).await?,
+		};
+		textures.insert(image.index(), decode_texture(gfx, &encoded)?);
+	}
+
+	let scene = match document.default_scene().or_else(|| document.scenes().next()) {
+		Some(scene) => scene,
+		None => return Ok(Arc::new(Model { meshes: vec![] })),
+	};
+	let mut meshes = vec![];
+	// glTF is Y-up and the world is Z-up
+	let root = Matrix4::from_axis_angle(&Vector3::x_axis(), FRAC_PI_2);
+	let mut nodes = scene.nodes().map(|node| (node, root)).collect::<Vec<_>>();
+	while let Some((node, parent)) = nodes.pop() {
+		let transform = parent * Matrix4::from(node.transform().matrix());
+		nodes.extend(node.children().map(|child| (child, transform)));
+		load_node(gfx, &node, &transform, &buffers, &textures, &mut meshes);
+	}
+	Ok(Arc::new(Model { meshes }))
+}
+
+fn load_node(
+	gfx: &Gfx,
+	node: &Node,
+	transform: &Matrix4<f32>,
+	buffers: &[Vec<u8>],
+	textures: &HashMap<usize, TextureHandle>,
+	meshes: &mut Vec<Arc<Mesh>>,
+) {
+	let mesh = match node.mesh() {
+		Some(mesh) => mesh,
+		None => return,
+	};
+	// the inverse transpose keeps normals perpendicular under non-uniform scaling
+	let normal_transform = transform
+		.fixed_slice::<U3, U3>(0, 0)
+		.into_owned()
+		.try_inverse()
+		.map(|inverse| inverse.transpose())
+		.unwrap_or_else(Matrix3::identity);
+
+	for primitive in mesh.primitives() {
+		if primitive.mode() != Mode::Triangles {
+			log::warn!("skipping a {:?} primitive in mesh {}", primitive.mode(), mesh.index());
+			continue;
+		}
+		let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+		let positions = match reader.read_positions() {
+			Some(positions) => positions.map(Vector3::from).collect::<Vec<_>>(),
+			None => continue,
+		};
+		let normals = reader.read_normals().map(|normals| normals.map(Vector3::from).collect::<Vec<_>>());
+		let uvs = reader.read_tex_coords(0).map(|uvs| uvs.into_f32().map(Vector2::from).collect::<Vec<_>>());
+		let mut indices = match reader.read_indices() {
+			Some(indices) => indices.into_u32().collect(),
+			None => (0..positions.len() as u32).collect::<Vec<_>>(),
+		};
+		indices.truncate(indices.len() / 3 * 3);
+		if indices.is_empty() {
+			continue;
+		}
+
+		let vert = |i: usize, normal: Vector3<f32>| MeshVertex {
+			pos: transform.transform_point(&Point3::from(positions[i])).coords,
+			normal: (normal_transform * normal).normalize(),
+			uv: uvs.as_ref().map_or(Vector2::zeros(), |uvs| uvs[i]),
+		};
+		let verts = match &normals {
+			Some(normals) => (0..positions.len()).map(|i| vert(i, normals[i])).collect::<Vec<_>>(),
+			// without normals each triangle is shaded flat, so it needs vertices of its own
+			None => {
+				let mut verts = Vec::with_capacity(indices.len());
+				for triangle in indices.chunks_exact(3) {
+					let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+					let flat = (positions[b] - positions[a])
+						.cross(&(positions[c] - positions[a]))
+						.try_normalize(0.0)
+						.unwrap_or_else(Vector3::z);
+					verts.extend([a, b, c].iter().map(|&i| vert(i, flat)));
+				}
+				indices = (0..verts.len() as u32).collect();
+				verts
+			},
+		};
+
+		let pbr = primitive.material().pbr_metallic_roughness();
+		let material = Material {
+			color: Vector4::from(pbr.base_color_factor()),
+			texture: pbr.base_color_texture().and_then(|info| textures.get(&info.texture().source().index()).copied()),
+		};
+		meshes.push(Mesh::new(gfx, &verts, &indices, material));
+	}
+}
+
+/// Reads a file referenced by a relative URI. Embedded base64 data isn't supported; use a .glb instead.
+async fn read_relative(base: &Path, uri: &str) -> Result<Vec<u8>, AssetError> {
+	if uri.contains(':') {
+		return Err(AssetError::UnsupportedUri(uri.to_string()));
+	}
+	Ok(fs::read_bytes(base.join(uri)).await?)
+}
+
+/// Where the texture manifest is, relative to the working directory.
+pub const TEXTURE_MANIFEST_PATH: &str = "assets/textures.cfg";
+
+/// Loads every texture listed in a manifest. Each line looks like `stone = "blocks/stone.ktx2"`, with paths relative to
+/// the manifest, and `#` starts a comment. Bad lines and textures that fail to load are logged and skipped.
+pub async fn load_texture_manifest(
+	gfx: &Gfx,
+	path: impl Into<PathBuf>,
+) -> Result<HashMap<String, TextureHandle>, AssetError> {
+	let path = path.into();
+	let base = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+	let manifest = fs::read_to_string(path.clone()).await?;
+
+	let mut textures = HashMap::new();
+	for (i, line) in manifest.lines().enumerate() {
+		let line = line.split('#').next().unwrap().trim();
+		if line.is_empty() {
+			continue;
+		}
+
+		let mut parts = line.splitn(2, '=');
+		let (name, file) = match (parts.next(), parts.next()) {
+			(Some(name), Some(file)) => (name.trim(), file.trim().trim_matches('"')),
+			_ => {
+				log::warn!("{}:{}: expected `name = \"path\"`", path.display(), i + 1);
+				continue;
+			},
+		};
+		match load_texture(gfx, base.join(file)).await {
+			Ok(texture) => {
+				textures.insert(name.to_string(), texture);
+			},
+			Err(err) => log::warn!("{}:{}: failed to load {}: {}", path.display(), i + 1, file, err),
+		}
+	}
+	Ok(textures)
+}
+
+/// Loads a texture from a KTX2 file, with its format and mip chain as stored, or otherwise from a PNG or JPEG.
+pub async fn load_texture(gfx: &Gfx, path: impl Into<PathBuf>) -> Result<TextureHandle, AssetError> {
+	let path = path.into();
+	let ktx2 = path.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("ktx2"));
+	let data = fs::read_bytes(path).await?;
+	if !ktx2 {
+		return decode_texture(gfx, &data);
+	}
+
+	let texture = ktx2::parse(&data)?;
+	let mips = if texture.generate_mips { Mips::Generate } else { Mips::Provided(texture.levels.len() as _) };
+	Ok(gfx.load_texture(texture.format, texture.extent, mips, &texture.levels.concat()))
+}
+
+/// Decodes a PNG or JPEG and uploads it as an sRGB texture with generated mips.
+fn decode_texture(gfx: &Gfx, encoded: &[u8]) -> Result<TextureHandle, AssetError> {
+	let image = image::load_from_memory(encoded)?.to_rgba8();
+	let extent = vk::Extent2D { width: image.width(), height: image.height() };
+	Ok(gfx.load_texture(vk::Format::R8G8B8A8_SRGB, extent, Mips::Generate, &image.into_raw()))
+}
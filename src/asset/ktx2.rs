@@ -0,0 +1,86 @@
+//! The KTX2 container, which holds textures in their GPU format, mip chain included, so they can be copied straight
+//! into an image.
+
+use crate::asset::AssetError;
+use ash::vk;
+use byteorder::{ByteOrder, LittleEndian};
+use vulkan::image::block_size;
+
+const IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+/// The identifier, the header and the index, which are followed by the level index.
+const LEVEL_INDEX_OFFSET: usize = 80;
+
+pub struct Ktx2<'a> {
+	pub format: vk::Format,
+	pub extent: vk::Extent2D,
+	/// The base level first.
+	pub levels: Vec<&'a [u8]>,
+	/// The file only has the base level, and asks for the rest to be generated.
+	pub generate_mips: bool,
+}
+
+/// Parses a 2D texture with no supercompression, in a format `block_size` knows.
+pub fn parse(data: &[u8]) -> Result<Ktx2, AssetError> {
+	if data.len() < LEVEL_INDEX_OFFSET || data[..12] != IDENTIFIER {
+		return Err(AssetError::Ktx2("not a KTX2 file"));
+	}
+	let header = |i: usize| LittleEndian::read_u32(&data[12 + i * 4..]);
+	let format = vk::Format::from_raw(header(0) as _);
+	let extent = vk::Extent2D { width: header(2), height: header(3) };
+	let (depth, layers, faces, level_count, supercompression) = (header(4), header(5), header(6), header(7), header(8));
+
+	let (block, bytes) = block_size(format).ok_or(AssetError::Ktx2("unsupported format"))?;
+	if depth > 0 || layers > 1 || faces != 1 {
+		return Err(AssetError::Ktx2("only 2D textures are supported"));
+	}
+	if supercompression != 0 {
+		return Err(AssetError::Ktx2("supercompression isn't supported"));
+	}
+
+	// 0 asks the loader to generate the mips, but there's still the base level
+	let generate_mips = level_count == 0;
+	let level_count = level_count.max(1) as usize;
+	if data.len() < LEVEL_INDEX_OFFSET + level_count * 24 {
+		return Err(AssetError::Ktx2("truncated level index"));
+	}
+	let mut levels = Vec::with_capacity(level_count);
+	for level in 0..level_count {
+		let entry = &data[LEVEL_INDEX_OFFSET + level * 24..];
+		let offset = LittleEndian::read_u64(entry) as usize;
+		let length = LittleEndian::read_u64(&entry[8..]) as usize;
+
+		let blocks = |x: u32| ((x >> level).max(1) + block - 1) / block;
+		let expected = (blocks(extent.width) * blocks(extent.height) * bytes) as usize;
+		if length != expected {
+			return Err(AssetError::Ktx2("a level has the wrong size"));
+		}
+		levels.push(data.get(offset..offset + length).ok_or(AssetError::Ktx2("a level is past the end of the file"))?);
+	}
+
+	Ok(Ktx2 { format, extent, levels, generate_mips })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_the_logo() {
+		let data = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/textures/logo.ktx2")).unwrap();
+		let texture = parse(&data).unwrap();
+		assert_eq!(texture.format, vk::Format::R8G8B8A8_SRGB);
+		assert_eq!((texture.extent.width, texture.extent.height), (256, 64));
+		assert!(!texture.generate_mips);
+		let sizes = texture.levels.iter().map(|level| level.len()).collect::<Vec<_>>();
+		assert_eq!(sizes, [65536, 16384, 4096, 1024, 256, 64, 16, 8, 4]);
+	}
+
+	#[test]
+	fn rejects_other_files() {
+		assert!(parse(b"\x89PNG\r\n\x1a\n").is_err());
+		let mut data = IDENTIFIER.to_vec();
+		data.resize(LEVEL_INDEX_OFFSET, 0);
+		// a zero format isn't one `block_size` knows
+		assert!(parse(&data).is_err());
+	}
+}
@@ -0,0 +1,35 @@
+//! Sound effects, mixed by rodio on its own thread. Sounds are read on the IO thread and decoded in background jobs,
+//! so each one starts playing once it's loaded instead of holding up startup. Builds without the `audio` feature
+//! leave rodio out, and their `Audio` plays nothing.
+
+#[cfg(feature = "audio")]
+mod mixer;
+
+#[cfg(feature = "audio")]
+pub use mixer::Audio;
+
+#[cfg(not(feature = "audio"))]
+use crate::camera::Camera;
+#[cfg(not(feature = "audio"))]
+use nalgebra::Vector3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Sound {
+	BreakBlock,
+	PlaceBlock,
+	Footstep,
+}
+
+/// Stands in for the mixer when the `audio` feature is off.
+#[cfg(not(feature = "audio"))]
+pub struct Audio;
+#[cfg(not(feature = "audio"))]
+impl Audio {
+	pub fn new(_volume: f32) -> Self {
+		Self
+	}
+
+	pub fn update(&mut self, _camera: &Camera) {}
+
+	pub fn play_at(&self, _sound: Sound, _pos: Vector3<f32>) {}
+}
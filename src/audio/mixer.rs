@@ -0,0 +1,189 @@
+//! The real output, which needs rodio and a sound device.
+
+use super::Sound;
+use crate::camera::Camera;
+use nalgebra::{UnitQuaternion, Vector3};
+use rodio::{
+	buffer::SamplesBuffer,
+	decoder::DecoderError,
+	source::{ChannelVolume, Source},
+	Decoder, OutputStream, OutputStreamHandle,
+};
+use sim::{
+	fs,
+	threads::{JobHandle, JobKind, JOBS},
+};
+use std::{
+	collections::HashMap,
+	error::Error,
+	fmt,
+	io::{self, Cursor},
+	path::Path,
+};
+
+/// Where the sounds are, relative to the working directory.
+const SOUND_DIR: &str = "assets/sounds";
+/// Sounds closer than this play at full volume, in voxels.
+const REF_DISTANCE: f32 = 2.0;
+/// Sounds fade out completely at this distance, and further ones aren't played.
+const MAX_DISTANCE: f32 = 48.0;
+
+impl Sound {
+	const ALL: [Sound; 3] = [Sound::BreakBlock, Sound::PlaceBlock, Sound::Footstep];
+
+	fn file(self) -> &'static str {
+		match self {
+			Sound::BreakBlock => "break_block.ogg",
+			Sound::PlaceBlock => "place_block.ogg",
+			Sound::Footstep => "footstep.ogg",
+		}
+	}
+
+	/// How loud the sound is relative to the others, before attenuation.
+	fn volume(self) -> f32 {
+		match self {
+			Sound::BreakBlock | Sound::PlaceBlock => 1.0,
+			Sound::Footstep => 0.4,
+		}
+	}
+}
+
+/// Why a sound couldn't be loaded.
+#[derive(Debug)]
+pub enum AudioError {
+	Io(io::Error),
+	Decode(DecoderError),
+}
+impl fmt::Display for AudioError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Io(err) => write!(f, "failed to read sound: {}", err),
+			Self::Decode(err) => write!(f, "failed to decode sound: {}", err),
+		}
+	}
+}
+impl Error for AudioError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			Self::Io(err) => Some(err),
+			Self::Decode(err) => Some(err),
+		}
+	}
+}
+impl From<io::Error> for AudioError {
+	fn from(err: io::Error) -> Self {
+		Self::Io(err)
+	}
+}
+impl From<DecoderError> for AudioError {
+	fn from(err: DecoderError) -> Self {
+		Self::Decode(err)
+	}
+}
+
+/// A decoded sound, which can be played any number of times.
+struct Clip {
+	channels: u16,
+	sample_rate: u32,
+	samples: Vec<i16>,
+}
+
+pub struct Audio {
+	/// The stream has to be kept alive for the handle to work. `None` if there's no output device, in which case
+	/// nothing is loaded or played.
+	output: Option<(OutputStream, OutputStreamHandle)>,
+	clips: HashMap<Sound, Clip>,
+	loading: Vec<(Sound, JobHandle<Result<Clip, AudioError>>)>,
+	listener_pos: Vector3<f32>,
+	listener_rot: UnitQuaternion<f32>,
+	/// Multiplies the volume of every sound.
+	pub volume: f32,
+}
+impl Audio {
+	/// Opens the default output device and starts loading every sound.
+	pub fn new(volume: f32) -> Self {
+		let output = match OutputStream::try_default() {
+			Ok(output) => Some(output),
+			Err(err) => {
+				log::warn!("sound is disabled, since there's no output device: {}", err);
+				None
+			},
+		};
+		let loading = match output {
+			Some(_) => {
+				Sound::ALL.iter().map(|&sound| (sound, JOBS.spawn_async(JobKind::Background, load(sound)))).collect()
+			},
+			None => vec![],
+		};
+
+		Self {
+			output,
+			clips: HashMap::new(),
+			loading,
+			listener_pos: Vector3::zeros(),
+			listener_rot: UnitQuaternion::identity(),
+			volume,
+		}
+	}
+
+	/// Keeps the sounds that finished loading since the last call, and moves the listener to `camera`.
+	pub fn update(&mut self, camera: &Camera) {
+		let mut i = 0;
+		while i < self.loading.len() {
+			let result = match self.loading[i].1.try_take() {
+				Some(result) => result,
+				None => {
+					i += 1;
+					continue;
+				},
+			};
+			let (sound, _) = self.loading.swap_remove(i);
+			match result {
+				Ok(clip) => {
+					self.clips.insert(sound, clip);
+				},
+				Err(err) => log::warn!("failed to load {}: {}", sound.file(), err),
+			}
+		}
+
+		self.listener_pos = camera.pos;
+		self.listener_rot = camera.rot();
+	}
+
+	/// Plays `sound` as if it came from `pos`, quieter the further it is from the listener and panned toward the side
+	/// it's on. Sounds that haven't loaded yet are skipped.
+	pub fn play_at(&self, sound: Sound, pos: Vector3<f32>) {
+		let (handle, clip) = match (&self.output, self.clips.get(&sound)) {
+			(Some((_, handle)), Some(clip)) => (handle, clip),
+			_ => return,
+		};
+
+		let offset = pos - self.listener_pos;
+		let distance = offset.norm();
+		if distance >= MAX_DISTANCE {
+			return;
+		}
+		// inverse distance, faded to nothing at MAX_DISTANCE so sounds don't cut off abruptly
+		let falloff = REF_DISTANCE / distance.max(REF_DISTANCE) * (1.0 - distance / MAX_DISTANCE);
+		let gain = falloff * sound.volume() * self.volume;
+
+		// the camera's local +X is to the right, so this is -1 for fully left and 1 for fully right
+		let pan = (self.listener_rot.inverse() * offset).try_normalize(std::f32::EPSILON).map_or(0.0, |dir| dir.x);
+		let left = gain * ((1.0 - pan) / 2.0).sqrt();
+		let right = gain * ((1.0 + pan) / 2.0).sqrt();
+
+		let source = SamplesBuffer::new(clip.channels, clip.sample_rate, clip.samples.clone());
+		if let Err(err) = handle.play_raw(ChannelVolume::new(source, vec![left, right]).convert_samples()) {
+			log::warn!("failed to play {:?}: {}", sound, err);
+		}
+	}
+}
+
+/// Reads and decodes a sound. It runs as a background task, which doesn't hold a worker while the file is read.
+async fn load(sound: Sound) -> Result<Clip, AudioError> {
+	let data = fs::read_bytes(Path::new(SOUND_DIR).join(sound.file())).await?;
+	let decoder = Decoder::new(Cursor::new(data))?;
+	let channels = decoder.channels();
+	let sample_rate = decoder.sample_rate();
+	Ok(Clip { channels, sample_rate, samples: decoder.collect() })
+}
@@ -0,0 +1,124 @@
+//! The player's viewpoint. Cameras look along +Y before rotation, with +Z up, matching the shaders.
+
+use crate::{
+	input::{Action, Input},
+	settings::Settings,
+};
+use nalgebra::{Matrix4, UnitQuaternion, Vector2, Vector3, Vector4};
+use sim::math::{lerp, perspective, perspective_matrix, view_matrix};
+use std::f32::consts::FRAC_PI_2;
+
+/// The near and far clip distances, in voxels.
+const NEAR: f32 = 0.5;
+const FAR: f32 = 500.0;
+/// How much each `FlyFaster` or `FlySlower` multiplies or divides the flying speed by.
+const SPEED_STEP: f32 = 1.25;
+/// The range of flying speeds, in voxels per second.
+const MIN_SPEED: f32 = 1.0;
+const MAX_SPEED: f32 = 512.0;
+/// How much faster flying is while `Sprint` is held.
+const SPRINT: f32 = 4.0;
+
+#[derive(Clone)]
+pub struct Camera {
+	pub pos: Vector3<f32>,
+	/// Rotation around +Z, in radians. 0 looks along +Y.
+	pub yaw: f32,
+	/// Rotation above the horizon, in radians.
+	pub pitch: f32,
+	/// Voxels per second while flying, not counting sprinting.
+	pub speed: f32,
+	/// Vertical field of view, in radians.
+	pub fov: f32,
+	/// Radians turned per pixel of mouse motion.
+	pub sensitivity: f32,
+	/// Radians turned per second with the look actions pushed all the way.
+	pub stick_sensitivity: f32,
+}
+impl Camera {
+	pub fn new(pos: Vector3<f32>, settings: &Settings) -> Self {
+		Self {
+			pos,
+			yaw: 0.0,
+			pitch: 0.0,
+			speed: 16.0,
+			fov: settings.fov.to_radians(),
+			sensitivity: settings.mouse_sensitivity,
+			stick_sensitivity: settings.gamepad_sensitivity,
+		}
+	}
+
+	/// The camera `t` of the way from this one to `next`, for drawing between simulation ticks. Everything but the
+	/// position and orientation comes from `next`.
+	pub fn interpolate(&self, next: &Camera, t: f32) -> Camera {
+		Camera {
+			pos: self.pos.lerp(&next.pos, t),
+			// yaw isn't wrapped, so this never goes the long way around
+			yaw: lerp(self.yaw, next.yaw, t),
+			pitch: lerp(self.pitch, next.pitch, t),
+			..next.clone()
+		}
+	}
+
+	/// The projection for a framebuffer with the given aspect ratio, packed by `perspective`. Pass the current aspect
+	/// every frame, so resizing the window doesn't stretch the image.
+	pub fn projection(&self, aspect: f32) -> Vector4<f32> {
+		perspective(aspect, self.fov, NEAR, FAR)
+	}
+
+	/// Transforms world space into clip space. The shaders get it, and its inverse for casting view rays, every frame.
+	pub fn view_projection(&self, aspect: f32) -> Matrix4<f32> {
+		perspective_matrix(self.projection(aspect)) * view_matrix(self.pos, self.rot())
+	}
+
+	pub fn rot(&self) -> UnitQuaternion<f32> {
+		UnitQuaternion::from_axis_angle(&Vector3::z_axis(), self.yaw)
+			* UnitQuaternion::from_axis_angle(&Vector3::x_axis(), self.pitch)
+	}
+
+	pub fn forward(&self) -> Vector3<f32> {
+		self.rot() * Vector3::y()
+	}
+
+	/// Turns with the mouse and the look actions.
+	pub fn look(&mut self, input: &Input, delta: f32) {
+		let mouse = input.mouse_delta().map(|x| x as f32) * self.sensitivity;
+		let turn = self.stick_sensitivity * delta;
+		let stick =
+			Vector2::new(input.axis(Action::LookLeft, Action::LookRight), input.axis(Action::LookDown, Action::LookUp));
+		self.yaw -= mouse.x + stick.x * turn;
+		self.pitch = (self.pitch - mouse.y + stick.y * turn).max(-FRAC_PI_2).min(FRAC_PI_2);
+	}
+
+	/// The speed `fly` moves at with the actions held in `input`, in voxels per second.
+	pub fn fly_speed(&self, input: &Input) -> f32 {
+		if input.pressed(Action::Sprint) {
+			self.speed * SPRINT
+		} else {
+			self.speed
+		}
+	}
+
+	/// Turns with the mouse and flies with the movement actions, ignoring collisions. The speed actions change `speed`.
+	pub fn fly(&mut self, input: &Input, delta: f32) {
+		self.look(input, delta);
+		if input.just_pressed(Action::FlyFaster) {
+			self.speed = (self.speed * SPEED_STEP).min(MAX_SPEED);
+		}
+		if input.just_pressed(Action::FlySlower) {
+			self.speed = (self.speed / SPEED_STEP).max(MIN_SPEED);
+		}
+
+		let local = Vector3::new(
+			input.axis(Action::MoveLeft, Action::MoveRight),
+			input.axis(Action::MoveBack, Action::MoveForward),
+			0.0,
+		);
+		let vertical = input.axis(Action::MoveDown, Action::MoveUp);
+		let horizontal = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), self.yaw) * local;
+		let dir = horizontal + Vector3::new(0.0, 0.0, vertical);
+		if dir != Vector3::zeros() {
+			self.pos += dir.normalize() * self.fly_speed(input) * delta;
+		}
+	}
+}
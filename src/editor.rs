@@ -0,0 +1,153 @@
+//! A mode for prototyping levels. The camera flies free of physics, chunk boundaries are drawn, and edits use a brush
+//! that can be resized and reshaped instead of single blocks.
+
+use crate::{
+	camera::Camera,
+	gfx::lines::DebugLines,
+	input::{Action, Input},
+};
+use nalgebra::{Vector3, Vector4};
+use sim::{
+	math::{Aabb, Ray},
+	world::{
+		chunk_bounds, chunk_key,
+		edit::{Edit, Shape},
+		RayHit, World,
+	},
+};
+
+/// How far away the brush reaches, which is further than the player can edit.
+const REACH: f32 = 64.0;
+const MAX_BRUSH_SIZE: u32 = 32;
+/// How many chunks around the camera's have their boundaries drawn, in each direction.
+const BOUNDARY_RANGE: i32 = 1;
+const BOUNDARY_COLOR: [f32; 4] = [1.0, 0.8, 0.2, 0.6];
+const BRUSH_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.9];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BrushShape {
+	Cube,
+	Sphere,
+}
+impl BrushShape {
+	fn next(self) -> Self {
+		match self {
+			BrushShape::Cube => BrushShape::Sphere,
+			BrushShape::Sphere => BrushShape::Cube,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Brush {
+	pub shape: BrushShape,
+	/// The width of the shape in voxels. A size 1 cube is the same as a single block.
+	pub size: u32,
+}
+impl Brush {
+	/// The brush's shape centered on `center`.
+	pub fn at(&self, center: Vector3<f32>) -> Shape {
+		let radius = self.size as f32 / 2.0;
+		match self.shape {
+			BrushShape::Cube => Shape::Box(Aabb::from_center(center, Vector3::repeat(radius))),
+			BrushShape::Sphere => Shape::Sphere { center, radius },
+		}
+	}
+
+	/// Where to center the brush so it digs into the surface at `hit`, or sits on top of it if `add` is true. Larger
+	/// brushes are moved along the normal so their near side lines up with the surface either way.
+	fn center(&self, hit: &RayHit, add: bool) -> Vector3<f32> {
+		let shift = (self.size as f32 - 1.0) / 2.0;
+		if add {
+			hit.adjacent().map(|x| x as f32) + hit.normal * shift
+		} else {
+			hit.voxel().map(|x| x as f32) - hit.normal * shift
+		}
+	}
+}
+impl Default for Brush {
+	fn default() -> Self {
+		Self { shape: BrushShape::Cube, size: 1 }
+	}
+}
+
+pub struct Editor {
+	active: bool,
+	pub brush: Brush,
+}
+impl Editor {
+	pub fn new() -> Self {
+		Self { active: false, brush: Brush::default() }
+	}
+
+	pub fn active(&self) -> bool {
+		self.active
+	}
+
+	/// Handles the editor's actions. Returns true if the mode was toggled this frame, so the caller can move the player
+	/// to wherever the camera ended up.
+	pub fn update(&mut self, input: &Input) -> bool {
+		let toggled = input.just_pressed(Action::ToggleEditor);
+		if toggled {
+			self.active = !self.active;
+		}
+		if !self.active {
+			return toggled;
+		}
+
+		if input.just_pressed(Action::BrushBigger) {
+			self.brush.size = (self.brush.size + 1).min(MAX_BRUSH_SIZE);
+		}
+		if input.just_pressed(Action::BrushSmaller) {
+			self.brush.size = self.brush.size.saturating_sub(1).max(1);
+		}
+		if input.just_pressed(Action::CycleBrushShape) {
+			self.brush.shape = self.brush.shape.next();
+		}
+		toggled
+	}
+
+	/// Carves or adds the brush's shape where the camera is looking. Added shapes take the material of the surface
+	/// they're placed against.
+	pub fn edit(&self, world: &mut World, input: &Input, camera: &Camera) {
+		let remove = input.just_pressed(Action::RemoveBlock);
+		let place = input.just_pressed(Action::PlaceBlock);
+		if !remove && !place {
+			return;
+		}
+
+		let hit = match world.raycast(&Ray::new(camera.pos, camera.forward()), REACH) {
+			Some(hit) => hit,
+			None => return,
+		};
+		let shape = self.brush.at(self.brush.center(&hit, place));
+		if remove {
+			world.edit(Edit::Subtract(shape));
+		} else {
+			let material = world.material(hit.voxel()).unwrap_or(0);
+			world.edit(Edit::Add(shape, material));
+		}
+	}
+
+	/// Adds the boundaries of the loaded chunks near the camera, and an outline of what the brush would remove.
+	pub fn draw(&self, world: &World, camera: &Camera, lines: &mut DebugLines) {
+		let around = chunk_key(camera.pos);
+		let range = -BOUNDARY_RANGE..=BOUNDARY_RANGE;
+		for key in world.chunk_keys() {
+			let offset = key - around;
+			if offset.iter().all(|x| range.contains(x)) {
+				lines.aabb(&chunk_bounds(key), Vector4::from(BOUNDARY_COLOR));
+			}
+		}
+
+		let hit = match world.raycast(&Ray::new(camera.pos, camera.forward()), REACH) {
+			Some(hit) => hit,
+			None => return,
+		};
+		let color = Vector4::from(BRUSH_COLOR);
+		match self.brush.at(self.brush.center(&hit, false)) {
+			Shape::Box(aabb) => lines.aabb(&aabb, color),
+			Shape::Sphere { center, radius } => lines.sphere(center, radius, color),
+		}
+	}
+}
@@ -0,0 +1,527 @@
+//! The game in a window, which is everything the binary does but `--server`.
+
+#[cfg(feature = "net")]
+use crate::net::client::{Client, PLAYER_MODEL_PATH};
+use crate::{
+	asset,
+	audio::{Audio, Sound},
+	camera::Camera,
+	editor::Editor,
+	gfx::{
+		gui::{crosshair::CrosshairElement, Color, GuiEvent},
+		offscreen::Offscreen,
+		volume::WorldVolumes,
+		window::{Window, WindowMode},
+		Gfx,
+	},
+	hud::PerfHud,
+	input::{Action, Bindings, Input},
+	lighting::DayCycle,
+	menu::{self, AppState, Menus},
+	player::Player,
+	replay::{Playback, Recorder},
+	scene::Scene,
+	settings::Settings,
+	timestep::FixedTimestep,
+};
+use futures::executor::block_on;
+use sim::{
+	math::Ray,
+	world::{
+		edit::Material,
+		generate::{GenerationQueue, NoiseTerrain},
+		storage::ChunkStorage,
+		stream::ChunkStreamer,
+		World,
+	},
+};
+use std::{
+	collections::HashMap,
+	sync::Arc,
+	thread,
+	time::{Duration, Instant},
+};
+use vulkan::{Extent2D, Offset2D};
+use winit::{
+	event::{ElementState, Event, MouseButton, WindowEvent},
+	event_loop::{ControlFlow, EventLoop},
+};
+
+/// How far away blocks can be edited from.
+const REACH: f32 = 8.0;
+/// How many materials painting cycles through, which is how many colors the volume shader has.
+const PAINT_MATERIALS: Material = 4;
+/// Seconds per in-game day.
+const DAY_LENGTH: f32 = 20.0 * 60.0;
+/// Seconds per simulation tick.
+const TICK: f32 = 1.0 / 60.0;
+const SPAWN: [f32; 3] = [0.0, 0.0, 32.0];
+/// Where chunks are saved, relative to the working directory.
+const WORLD_DIR: &str = "world";
+
+/// Runs whichever of the game's modes `mode` names, with the rest of the command line in `args`.
+pub async fn run(mode: Option<String>, mut args: impl Iterator<Item = String>) {
+	let mut settings = Settings::load().await;
+	let gfx = match Gfx::new(settings.adapter, settings.validation).await {
+		Ok(gfx) => gfx,
+		Err(err) => {
+			log::error!("{}", err);
+			return;
+		},
+	};
+
+	// `--render <path>` saves one frame from the spawn point without opening a window
+	if mode.as_deref() == Some("--render") {
+		match args.next() {
+			Some(path) => render_headless(gfx, &settings, &path),
+			None => log::error!("--render needs a path to save the frame to"),
+		}
+		return;
+	}
+
+	// `--connect <host:port>` joins a multiplayer game
+	#[cfg(feature = "net")]
+	let mut client = match mode.as_deref() {
+		Some("--connect") => {
+			let addr = match args.next() {
+				Some(addr) => addr,
+				None => {
+					log::error!("--connect needs the server's address");
+					return;
+				},
+			};
+			let model = match asset::load_gltf(&gfx, PLAYER_MODEL_PATH).await {
+				Ok(model) => Some(model),
+				Err(err) => {
+					log::warn!("failed to load {}: {}", PLAYER_MODEL_PATH, err);
+					None
+				},
+			};
+			match Client::connect(&gfx, &addr, model) {
+				Ok(client) => Some(client),
+				Err(err) => {
+					log::error!("failed to connect to {}: {}", addr, err);
+					return;
+				},
+			}
+		},
+		_ => None,
+	};
+
+	// `--record <path>` saves the input of every frame when the game closes, and `--replay <path>` plays it back in
+	// place of the real input
+	let mut recorder = None;
+	let mut playback = None;
+	match mode.as_deref() {
+		Some("--record") => match args.next() {
+			Some(path) => recorder = Some(Recorder::new(path)),
+			None => {
+				log::error!("--record needs a path to save the replay to");
+				return;
+			},
+		},
+		Some("--replay") => {
+			let path = match args.next() {
+				Some(path) => path,
+				None => {
+					log::error!("--replay needs the path of a replay");
+					return;
+				},
+			};
+			match Playback::load(path.clone()).await {
+				Ok(loaded) => playback = Some(loaded),
+				Err(err) => {
+					log::error!("failed to load {}: {}", path, err);
+					return;
+				},
+			}
+		},
+		_ => (),
+	}
+
+	let event_loop = EventLoop::new();
+	let mut window = match Window::new(gfx.clone(), &event_loop, settings.display.clone(), settings.render.clone()) {
+		Ok(window) => window,
+		Err(err) => {
+			log::error!("{}", err);
+			return;
+		},
+	};
+	let mut input = Input::new(Bindings::load().await, settings.gamepad_dead_zone);
+	// the same events as `input`, but cleared after each tick rather than each frame, so every press reaches exactly
+	// one tick however many run in a frame
+	let mut tick_input = Input::without_gamepads(input.bindings.clone(), settings.gamepad_dead_zone);
+	let mut hud = PerfHud::new(&mut window.gui);
+	window.gui.push(Arc::new(CrosshairElement::new(16, 2, Color::new(255, 255, 255, 200))));
+	let textures = match asset::load_texture_manifest(&gfx, asset::TEXTURE_MANIFEST_PATH).await {
+		Ok(textures) => textures,
+		Err(err) => {
+			log::warn!("failed to load {}: {}", asset::TEXTURE_MANIFEST_PATH, err);
+			HashMap::new()
+		},
+	};
+	let mut menus = Menus::new(&settings, textures.get("logo").copied());
+	let mut audio = Audio::new(settings.volume);
+
+	// a server's world is made from its edit log, so the local save is neither loaded nor overwritten
+	#[cfg(feature = "net")]
+	let multiplayer = client.is_some();
+	#[cfg(not(feature = "net"))]
+	let multiplayer = false;
+	let storage = if multiplayer {
+		None
+	} else {
+		match ChunkStorage::new(WORLD_DIR) {
+			Ok(storage) => Some(Arc::new(storage)),
+			Err(err) => {
+				log::error!("failed to open {}: {}", WORLD_DIR, err);
+				return;
+			},
+		}
+	};
+	let mut queue = GenerationQueue::new(Arc::new(NoiseTerrain::default()));
+	if let Some(storage) = &storage {
+		queue = queue.with_storage(storage.clone());
+	}
+	let mut streamer = ChunkStreamer::new(queue, storage.clone(), settings.render_distance);
+	let mut world = World::new();
+	if multiplayer {
+		world.defer_edits();
+	}
+	let mut volumes = WorldVolumes::new(gfx.clone(), settings.render_distance, window.frames().count());
+	// only other players are in the scene so far
+	#[cfg(feature = "net")]
+	let mut scene = Scene::new();
+	#[cfg(not(feature = "net"))]
+	let scene = Scene::new();
+	let mut day = DayCycle::new(DAY_LENGTH);
+	let mut camera = Camera::new(SPAWN.into(), &settings);
+	// where the camera was before the last tick, for drawing between ticks
+	let mut last_camera = camera.clone();
+	let mut player = Player::new(camera.pos);
+	let mut noclip = false;
+	let mut editor = Editor::new();
+
+	let mut timestep = FixedTimestep::new(TICK);
+	let mut last_frame = Instant::now();
+
+	event_loop.run(move |event, _window, control| {
+		*control = ControlFlow::Poll;
+
+		match event {
+			Event::WindowEvent { window_id, event } if window_id == window.id() => {
+				// a replay is the only input while it plays
+				if playback.is_none() {
+					input.window_event(&event);
+				}
+				match event {
+					WindowEvent::CloseRequested => *control = ControlFlow::Exit,
+					WindowEvent::Focused(false) if menus.state() == AppState::Playing && playback.is_none() => {
+						set_app_state(&mut menus, &mut window, &mut input, AppState::Paused)
+					},
+					WindowEvent::CursorMoved { position, .. } => {
+						let position = position.to_physical(window.hidpi_factor());
+						let position = Offset2D { x: position.x as _, y: position.y as _ };
+						#[cfg(feature = "debug-overlay")]
+						window.debug_overlay.cursor_moved(position, window.hidpi_factor() as _);
+						window.gui.cursor_moved(position);
+					},
+					WindowEvent::CursorLeft { .. } => {
+						#[cfg(feature = "debug-overlay")]
+						window.debug_overlay.cursor_left();
+						window.gui.cursor_left();
+					},
+					WindowEvent::HiDpiFactorChanged(_) => window.hidpi_factor_changed(),
+					WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+						let pressed = state == ElementState::Pressed;
+						#[cfg(feature = "debug-overlay")]
+						{
+							window.debug_overlay.mouse_input(pressed);
+							if pressed && window.debug_overlay.wants_pointer() {
+								return;
+							}
+						}
+						window.gui.mouse_input(pressed);
+					},
+					_ => (),
+				}
+			},
+			Event::DeviceEvent { event, .. } if playback.is_none() => input.device_event(&event),
+			Event::EventsCleared => {
+				let delta = match &mut playback {
+					// the recorded delta rather than the real one, so the simulation takes exactly the same steps
+					Some(playback) => match playback.next_frame(&mut input) {
+						Some(delta) => delta,
+						None => {
+							log::warn!("the replay finished");
+							*control = ControlFlow::Exit;
+							return;
+						},
+					},
+					None => {
+						input.poll_gamepads();
+						last_frame.elapsed().as_secs_f32()
+					},
+				};
+				if let Some(recorder) = &mut recorder {
+					recorder.record_frame(delta, input.frame_events());
+				}
+				let now = Instant::now();
+
+				if input.just_pressed(Action::Menu) {
+					let state = menus.state().back();
+					set_app_state(&mut menus, &mut window, &mut input, state);
+				}
+				let playing = menus.state() == AppState::Playing;
+				if playing && input.just_pressed(Action::ToggleCursor) {
+					let grab = !input.cursor_grabbed();
+					input.set_cursor_grab(window.winit_window(), grab);
+				}
+				if input.just_pressed(Action::ToggleFullscreen) {
+					let mode = match window.display().mode {
+						WindowMode::Windowed => WindowMode::Borderless,
+						_ => WindowMode::Windowed,
+					};
+					set_window_mode(&mut window, &mut settings, mode);
+					menus.update_settings(&settings);
+				}
+				let toggles = [Action::ToggleWireframe, Action::ToggleStepHeatmap, Action::ToggleMeshedWorld];
+				if toggles.iter().any(|&action| input.just_pressed(action)) {
+					let mut view = window.debug_view();
+					view.wireframe ^= input.just_pressed(Action::ToggleWireframe);
+					view.step_heatmap ^= input.just_pressed(Action::ToggleStepHeatmap);
+					view.meshed_world ^= input.just_pressed(Action::ToggleMeshedWorld);
+					window.set_debug_view(view);
+				}
+				if input.just_pressed(Action::TogglePerfHud) {
+					let visible = !hud.visible();
+					hud.set_visible(&mut window.gui, visible);
+				}
+				#[cfg(feature = "debug-overlay")]
+				{
+					if input.just_pressed(Action::ToggleDebugOverlay) {
+						window.debug_overlay.visible = !window.debug_overlay.visible;
+					}
+				}
+
+				let update = gfx.stats().cpu_scope("update");
+				for &event in input.frame_events() {
+					tick_input.apply(event);
+				}
+				// the menus freeze the world, though chunks keep streaming in behind them
+				if playing {
+					let tick = timestep.tick();
+					for _ in 0..timestep.advance(delta) {
+						last_camera = camera.clone();
+						if tick_input.just_pressed(Action::ToggleNoclip) {
+							noclip = !noclip;
+							player = Player::new(camera.pos);
+						}
+						if editor.update(&tick_input) {
+							player = Player::new(camera.pos);
+						}
+						if noclip || editor.active() {
+							camera.fly(&tick_input, tick);
+						} else {
+							player.update(&world, &tick_input, &mut camera, tick);
+						}
+						if player.take_footstep() {
+							audio.play_at(Sound::Footstep, player.feet());
+						}
+						if input.cursor_grabbed() {
+							if editor.active() {
+								editor.edit(&mut world, &tick_input, &camera);
+							} else {
+								edit_blocks(&mut world, &tick_input, &camera, &audio);
+							}
+						}
+						day.update(tick);
+						tick_input.end_frame();
+					}
+					let flying = noclip || editor.active();
+					hud.set_fly_speed(if flying { Some(camera.fly_speed(&input)) } else { None });
+				} else {
+					// the world doesn't see anything pressed in the menus
+					tick_input.end_frame();
+				}
+				#[cfg(feature = "net")]
+				{
+					if let Some(client) = &mut client {
+						client.update(&mut world, &camera, &mut scene);
+					}
+				}
+				// in multiplayer, these are the server's log entries the client just applied
+				let edits = world.take_edits();
+				let changes = streamer.update(&mut world, camera.pos);
+				#[cfg(feature = "net")]
+				{
+					if let Some(client) = &client {
+						client.catch_up(&mut world, &changes.loaded);
+					}
+				}
+				gfx.stats().add_chunks_generated(changes.loaded.len() as _);
+				let changed = world.take_changed();
+				let meshed = window.debug_view().meshed_world;
+				window.terrain.update(&world, &changed, meshed);
+				volumes.update(&world, &edits, |key| streamer.lod(key));
+				hud.record(gfx.frame_stats());
+				window.gui.tick(now - last_frame);
+				#[cfg(feature = "debug-overlay")]
+				window.update_debug_overlay();
+				last_frame = now;
+
+				let events = window.gui.drain_events().collect::<Vec<_>>();
+				for event in events {
+					match event {
+						GuiEvent::Clicked(menu::RESUME) => {
+							set_app_state(&mut menus, &mut window, &mut input, AppState::Playing)
+						},
+						GuiEvent::Clicked(menu::SETTINGS) => {
+							set_app_state(&mut menus, &mut window, &mut input, AppState::Menu)
+						},
+						GuiEvent::Clicked(menu::QUIT) => *control = ControlFlow::Exit,
+						GuiEvent::Clicked(menu::DISPLAY_MODE) => {
+							let mode = match window.display().mode {
+								WindowMode::Windowed => WindowMode::Borderless,
+								WindowMode::Borderless => WindowMode::Exclusive,
+								WindowMode::Exclusive => WindowMode::Windowed,
+							};
+							set_window_mode(&mut window, &mut settings, mode);
+							menus.update_settings(&settings);
+						},
+						GuiEvent::Clicked(menu::FOV) => {
+							settings.fov = menu::next_fov(settings.fov);
+							camera.fov = settings.fov.to_radians();
+							settings.save().forget();
+							menus.update_settings(&settings);
+						},
+						GuiEvent::Clicked(menu::RENDER_SCALE) => {
+							window.set_render_scale(menu::next_render_scale(settings.render.render_scale));
+							settings.render.render_scale = window.render_scale();
+							settings.save().forget();
+							menus.update_settings(&settings);
+						},
+						GuiEvent::Clicked(menu::PRESENT_MODE) => {
+							settings.render.present_mode = menu::next_present_mode(settings.render.present_mode);
+							window.set_present_mode(settings.render.present_mode);
+							settings.save().forget();
+							menus.update_settings(&settings);
+						},
+						GuiEvent::Clicked(menu::BACK) => {
+							set_app_state(&mut menus, &mut window, &mut input, AppState::Paused)
+						},
+						GuiEvent::Clicked(id) => log::info!("clicked {}", id),
+					}
+				}
+				let view = last_camera.interpolate(&camera, timestep.alpha());
+				audio.update(&view);
+				if editor.active() {
+					editor.draw(&world, &view, &mut window.lines);
+				}
+				drop(update);
+				window.draw(&view, &day.light(), &mut volumes, &scene);
+				gfx.stats().end_frame();
+				input.end_frame();
+			},
+			Event::LoopDestroyed => {
+				if let Some(recorder) = &recorder {
+					if let Err(err) = block_on(recorder.save()) {
+						log::error!("failed to save the replay: {}", err);
+					}
+				}
+				if let Some(storage) = &storage {
+					for save in world.save_dirty(storage) {
+						if let Err(err) = block_on(save) {
+							log::error!("failed to save chunk: {}", err);
+						}
+					}
+				}
+			},
+			_ => (),
+		};
+	});
+}
+
+/// Renders the world around the spawn point at the window's resolution, once every chunk in range has loaded.
+fn render_headless(gfx: Arc<Gfx>, settings: &Settings, path: &str) {
+	let (width, height) = settings.display.resolution;
+	let mut offscreen = match Offscreen::new(gfx.clone(), Extent2D { width, height }, &settings.render) {
+		Ok(offscreen) => offscreen,
+		Err(err) => {
+			log::error!("{}", err);
+			return;
+		},
+	};
+
+	let storage = match ChunkStorage::new(WORLD_DIR) {
+		Ok(storage) => Arc::new(storage),
+		Err(err) => {
+			log::error!("failed to open {}: {}", WORLD_DIR, err);
+			return;
+		},
+	};
+	let queue = GenerationQueue::new(Arc::new(NoiseTerrain::default())).with_storage(storage.clone());
+	let mut streamer = ChunkStreamer::new(queue, Some(storage), settings.render_distance);
+	let mut world = World::new();
+	let camera = Camera::new(SPAWN.into(), settings);
+	streamer.update(&mut world, camera.pos);
+	while streamer.is_loading() {
+		thread::sleep(Duration::from_millis(10));
+		streamer.update(&mut world, camera.pos);
+	}
+
+	// every chunk is uploaded with the one frame
+	let mut volumes = WorldVolumes::new(gfx, settings.render_distance, 1);
+	volumes.update(&world, &[], |key| streamer.lod(key));
+	while volumes.is_uploading() {
+		volumes.update(&world, &[], |key| streamer.lod(key));
+	}
+	let light = DayCycle::new(DAY_LENGTH).light();
+	let pixels = offscreen.render(&camera, &light, 0.0, &mut volumes, &Scene::new());
+	if let Err(err) = image::save_buffer(path, &pixels, width, height, image::ColorType::Rgba8) {
+		log::error!("failed to save {}: {}", path, err);
+	}
+}
+
+/// Switches between playing and the menus, grabbing the cursor only while playing.
+fn set_app_state(menus: &mut Menus, window: &mut Window, input: &mut Input, state: AppState) {
+	menus.set_state(&mut window.gui, state);
+	input.set_cursor_grab(window.winit_window(), state == AppState::Playing);
+}
+
+/// Switches the window to `mode` and saves it as the player's choice.
+fn set_window_mode(window: &mut Window, settings: &mut Settings, mode: WindowMode) {
+	let mut display = window.display().clone();
+	display.mode = mode;
+	window.set_display(display.clone());
+	settings.display = display;
+	settings.save().forget();
+}
+
+/// Removes or places the block under the crosshair, with a sound from where it was.
+fn edit_blocks(world: &mut World, input: &Input, camera: &Camera, audio: &Audio) {
+	let remove = input.just_pressed(Action::RemoveBlock);
+	let place = input.just_pressed(Action::PlaceBlock);
+	let paint = input.just_pressed(Action::PaintBlock);
+	if !remove && !place && !paint {
+		return;
+	}
+
+	let hit = match world.raycast(&Ray::new(camera.pos, camera.forward()), REACH) {
+		Some(hit) => hit,
+		None => return,
+	};
+	if remove {
+		world.remove_block(hit.voxel());
+		audio.play_at(Sound::BreakBlock, hit.voxel().map(|x| x as f32));
+	} else if paint {
+		let material = world.material(hit.voxel()).unwrap_or(0);
+		world.set_material(hit.voxel(), (material + 1) % PAINT_MATERIALS);
+		audio.play_at(Sound::PlaceBlock, hit.voxel().map(|x| x as f32));
+	} else {
+		// placed blocks match whatever they're placed against
+		let material = world.material(hit.voxel()).unwrap_or(0);
+		world.set_block(hit.adjacent(), material);
+		audio.play_at(Sound::PlaceBlock, hit.adjacent().map(|x| x as f32));
+	}
+}
@@ -1,40 +1,96 @@
+pub mod buffer;
+#[cfg(feature = "debug-overlay")]
+pub mod debug_overlay;
+pub mod error;
+pub mod fence;
+pub mod frame;
+pub mod graph;
 pub mod gui;
+pub mod image;
+pub mod lines;
+pub mod mesh;
+pub mod offscreen;
+pub mod profiler;
+#[cfg(test)]
+mod reference;
+pub mod renderer;
+pub mod shaders;
+pub mod stats;
+pub mod temporal;
+pub mod terrain;
+pub mod texture;
+pub mod upload;
+pub mod visibility;
 pub mod volume;
 pub mod window;
 
-use crate::fs::read_all_u32;
 use ash::vk;
+use error::GfxError;
+use image::Mips;
 use memoffset::offset_of;
-use nalgebra::Vector2;
+use nalgebra::{Vector2, Vector3, Vector4};
 #[cfg(debug_assertions)]
-use std::ffi::CString;
-use std::sync::Arc;
+use shaders::{Shader, ShaderWatcher};
+use sim::world::edit::{Edit, Shape};
+use stats::{FrameStats, StatsCollector};
+#[cfg(debug_assertions)]
+use std::ptr;
+use std::{
+	env,
+	ffi::{CStr, CString},
+	mem::size_of,
+	sync::{Arc, Mutex, RwLock},
+};
+use texture::{Texture, TextureHandle};
 use typenum::{B0, B1};
+use upload::{UploadHandle, UploadQueue};
 use vulkan::{
 	buffer::Buffer,
+	descriptor::DescriptorSetLayout,
 	device::{BufferUsageFlags, Device, Queue},
+	image::Image,
 	instance::{Instance, Version},
-	pipeline::{PipelineLayout, VertexDesc},
+	physical_device::PhysicalDevice,
+	pipeline::{PipelineLayout, PushConstantRange, VertexDesc},
 	shader::ShaderModule,
 	Vulkan,
 };
 
+/// The most push constant bytes every device supports. The volumes' compute passes share a layout with this much room.
+const MAX_PUSH_CONSTANTS: u32 = 128;
+
 pub struct Gfx {
 	instance: Arc<Instance>,
 	device: Arc<Device>,
 	queue: Arc<Queue>,
+	/// Has the frame uniforms in set 0, the world's volumes in set 1, and the volume shader's debug flags as a
+	/// fragment push constant.
 	layout: Arc<PipelineLayout>,
+	/// Set 0 of every pipeline, which holds the frame uniforms. Whatever draws frames owns the buffers.
+	frame_set_layout: Arc<DescriptorSetLayout>,
+	/// Set 1 of the volume pipelines, which holds the GPU copy of the world from a `WorldVolumes`.
+	volume_set_layout: Arc<DescriptorSetLayout>,
+	/// Has a `WorldVolumes` set in set 0 and each pass's parameters as compute push constants, for the compute passes
+	/// that fill in the volumes.
+	volume_compute_layout: Arc<PipelineLayout>,
 	triangle: Arc<Buffer<[TriangleVertex]>>,
-	vshader: Arc<ShaderModule>,
-	fshader: Arc<ShaderModule>,
+	shaders: RwLock<Arc<ShaderModules>>,
+	#[cfg(debug_assertions)]
+	shader_watcher: Mutex<ShaderWatcher>,
+	textures: Mutex<Vec<Arc<Texture>>>,
+	/// Created by the first renderer that needs it.
+	white: Mutex<Option<TextureHandle>>,
+	stats: StatsCollector,
+	uploads: UploadQueue,
 }
 impl Gfx {
-	pub async fn new() -> Arc<Self> {
-		// start reading files now to use later
-		let vert_spv = read_all_u32("build/shader.vert.spv");
-		let frag_spv = read_all_u32("build/shader.frag.spv");
-
-		let vulkan = Vulkan::new().unwrap();
+	/// `adapter` forces a physical device by its index in enumeration order, for when the automatic choice is wrong.
+	/// The `SPACE_THING_ADAPTER` environment variable takes precedence over it.
+	///
+	/// `validation` turns the validation layers on or off, and `None` only uses them in debug builds. The
+	/// `SPACE_THING_VALIDATION` environment variable takes precedence over it.
+	pub async fn new(adapter: Option<usize>, validation: Option<bool>) -> Result<Arc<Self>, GfxError> {
+		let vulkan = Vulkan::new()?;
 
 		let name = CString::new(env!("CARGO_PKG_NAME")).unwrap();
 		let version = Version::new(
@@ -42,10 +98,10 @@ impl Gfx {
 			env!("CARGO_PKG_VERSION_MINOR").parse().unwrap(),
 			env!("CARGO_PKG_VERSION_PATCH").parse().unwrap(),
 		);
-		let instance = Instance::new(vulkan, &name, version);
+		let instance = Instance::new(vulkan, &name, version, validation_enabled(validation));
 
-		let (device, mut queue) = {
-			let physical_device = instance.enumerate_physical_devices().next().unwrap();
+		let (device, mut queue, transfer_queue) = {
+			let physical_device = select_physical_device(&instance, adapter).ok_or(GfxError::NoSuitableDevice)?;
 
 			let queue_family = physical_device
 				.get_queue_family_properties()
@@ -54,11 +110,33 @@ impl Gfx {
 				.unwrap()
 				.family();
 
-			let (device, mut queues) = physical_device.create_device(vec![(queue_family, &[1.0][..])]);
-			(device, queues.next().unwrap())
+			// a family without graphics is usually backed by a DMA engine, and the ones without compute are the most
+			// likely to be
+			let transfer_family = physical_device
+				.get_queue_family_properties()
+				.filter(|props| props.queue_flags().transfer() && !props.queue_flags().graphics())
+				.min_by_key(|props| props.queue_flags().compute())
+				.map(|props| props.family());
+
+			let mut families = vec![(queue_family, &[1.0][..])];
+			families.extend(transfer_family.map(|family| (family, &[1.0][..])));
+			let (device, mut queues) = physical_device.create_device(families)?;
+			let queue = queues.next().unwrap();
+			(device, queue, queues.next())
 		};
 
-		let layout = device.create_pipeline_layout();
+		let frame_set_layout = frame::create_set_layout(&device);
+		let volume_set_layout = volume::create_set_layout(&device);
+		let push_constants = [PushConstantRange::builder()
+			.stage_flags(vk::ShaderStageFlags::FRAGMENT)
+			.size(size_of::<u32>() as _)
+			.build()];
+		let set_layouts = vec![frame_set_layout.clone(), volume_set_layout.clone()];
+		let layout = device.create_pipeline_layout(set_layouts, &push_constants);
+		let push_constants =
+			[PushConstantRange::builder().stage_flags(vk::ShaderStageFlags::COMPUTE).size(MAX_PUSH_CONSTANTS).build()];
+		let volume_compute_layout = device.create_pipeline_layout(vec![volume_set_layout.clone()], &push_constants);
+		let uploads = UploadQueue::new(&device, queue.clone(), transfer_queue);
 
 		let cmdpool = device.create_command_pool(queue.family(), true);
 
@@ -72,11 +150,235 @@ impl Gfx {
 			.create_buffer_slice(verts.len() as _, B0, BufferUsageFlags::TRANSFER_DST | BufferUsageFlags::VERTEX_BUFFER)
 			.copy_from_buffer(&mut queue, &cmdpool, triangle);
 		future.end().wait();
+		device.set_object_name(triangle.vk, "fullscreen triangle");
+
+		let shaders = unsafe {
+			ShaderModules {
+				#[cfg(debug_assertions)]
+				generation: 0,
+				vert: device.create_shader_module(&shaders::SHADER_VERT.load().await?),
+				frag: device.create_shader_module(&shaders::SHADER_FRAG.load().await?),
+				gui_vert: device.create_shader_module(&shaders::GUI_VERT.load().await?),
+				gui_frag: device.create_shader_module(&shaders::GUI_FRAG.load().await?),
+				mesh_vert: device.create_shader_module(&shaders::MESH_VERT.load().await?),
+				mesh_frag: device.create_shader_module(&shaders::MESH_FRAG.load().await?),
+				lines_vert: device.create_shader_module(&shaders::LINES_VERT.load().await?),
+				lines_frag: device.create_shader_module(&shaders::LINES_FRAG.load().await?),
+				temporal_frag: device.create_shader_module(&shaders::TEMPORAL_FRAG.load().await?),
+				volume_vert: device.create_shader_module(&shaders::VOLUME_VERT.load().await?),
+				cull_comp: device.create_shader_module(&shaders::CULL_COMP.load().await?),
+				mips_comp: device.create_shader_module(&shaders::MIPS_COMP.load().await?),
+				edit_comp: device.create_shader_module(&shaders::EDIT_COMP.load().await?),
+				#[cfg(feature = "debug-overlay")]
+				egui_vert: device.create_shader_module(&shaders::EGUI_VERT.load().await?),
+				#[cfg(feature = "debug-overlay")]
+				egui_frag: device.create_shader_module(&shaders::EGUI_FRAG.load().await?),
+			}
+		};
+
+		Ok(Arc::new(Self {
+			instance,
+			device,
+			queue,
+			layout,
+			frame_set_layout,
+			volume_set_layout,
+			volume_compute_layout,
+			triangle,
+			shaders: RwLock::new(Arc::new(shaders)),
+			#[cfg(debug_assertions)]
+			shader_watcher: Mutex::new(ShaderWatcher::new(ShaderModules::SOURCES)),
+			textures: Mutex::default(),
+			white: Mutex::default(),
+			stats: StatsCollector::new(),
+			uploads,
+		}))
+	}
+
+	/// Uploads a texture, blocking until the upload is complete.
+	pub fn load_texture(&self, format: vk::Format, extent: vk::Extent2D, mips: Mips, data: &[u8]) -> TextureHandle {
+		let (image, _) = self.upload_image(format, extent, mips, data);
+		let view = image.create_view();
+		self.uploads.finish();
+
+		let mut textures = self.textures.lock().unwrap();
+		textures.push(Arc::new(Texture { image, view }));
+		TextureHandle(textures.len() as u32 - 1)
+	}
+
+	/// A single opaque white texel, for drawing untextured things with pipelines that always sample a texture. Every
+	/// window shares the same one.
+	pub fn white_texture(&self) -> TextureHandle {
+		*self.white.lock().unwrap().get_or_insert_with(|| {
+			let extent = vk::Extent2D { width: 1, height: 1 };
+			self.load_texture(vk::Format::R8G8B8A8_SRGB, extent, Mips::Provided(1), &[255; 4])
+		})
+	}
+
+	/// Creates a sampled 2D image and queues an upload of `data` into it, which is submitted with the next batch.
+	pub fn upload_image(
+		&self,
+		format: vk::Format,
+		extent: vk::Extent2D,
+		mips: Mips,
+		data: &[u8],
+	) -> (Arc<Image>, UploadHandle) {
+		let (levels, generate) = match mips {
+			Mips::Provided(levels) => (levels, false),
+			Mips::Generate if image::can_generate_mips(self, format) => (image::full_mip_levels(extent), true),
+			Mips::Generate => {
+				log::warn!("can't generate mips for {:?} images, so they only have one level", format);
+				(1, false)
+			},
+		};
+		let init = image::create_image(self, format, extent, levels);
+		let image = init.image().clone();
+		let uploaded = if generate { 1 } else { levels };
+		self.stats.add_upload(data.len() as _);
+		let handle = self.uploads.push_image(data, init, uploaded);
+		(image, handle)
+	}
+
+	/// Batches CPU to GPU copies made through `upload_image`.
+	pub fn uploads(&self) -> &UploadQueue {
+		&self.uploads
+	}
+
+	pub fn texture(&self, handle: TextureHandle) -> Arc<Texture> {
+		self.textures.lock().unwrap()[handle.0 as usize].clone()
+	}
+
+	/// The highest sample count, up to `requested`, that the device supports for color attachments.
+	pub fn supported_samples(&self, requested: u32) -> vk::SampleCountFlags {
+		let supported = self.device.physical_device().get_properties().limits.framebuffer_color_sample_counts;
+		[64, 32, 16, 8, 4, 2]
+			.iter()
+			.map(|&count| vk::SampleCountFlags::from_raw(count))
+			.find(|&samples| samples.as_raw() <= requested && supported.contains(samples))
+			.unwrap_or(vk::SampleCountFlags::TYPE_1)
+	}
+
+	/// The current shader modules. Pipelines should be built from one snapshot so they don't mix old and new shaders.
+	fn shaders(&self) -> Arc<ShaderModules> {
+		self.shaders.read().unwrap().clone()
+	}
+
+	/// Recompiles any shader whose source has been saved since the last call. Every window polls this, so pipelines
+	/// should be rebuilt whenever `shader_generation` changes rather than only when the caller's poll found a change.
+	#[cfg(debug_assertions)]
+	pub fn reload_shaders(&self) {
+		let changed = self.shader_watcher.lock().unwrap().poll();
+		if changed.is_empty() {
+			return;
+		}
+
+		let mut shaders = (*self.shaders()).clone();
+		for (shader, code) in changed {
+			let module = unsafe { self.device.create_shader_module(&code) };
+			shaders.replace(shader, module);
+		}
+		shaders.generation += 1;
+		*self.shaders.write().unwrap() = Arc::new(shaders);
+	}
+
+	/// Counts how many times the shaders have been reloaded.
+	#[cfg(debug_assertions)]
+	pub fn shader_generation(&self) -> u64 {
+		self.shaders().generation
+	}
+
+	/// The most precise depth format the device can render to and sample, since the temporal pass reads the world's
+	/// depth.
+	pub fn depth_format(&self) -> vk::Format {
+		// Vulkan requires D16 to support both everywhere
+		let features = vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT | vk::FormatFeatureFlags::SAMPLED_IMAGE;
+		[vk::Format::D32_SFLOAT, vk::Format::X8_D24_UNORM_PACK32, vk::Format::D16_UNORM]
+			.iter()
+			.copied()
+			.find(|&format| {
+				let props = self.device.physical_device().get_format_properties(format);
+				props.optimal_tiling_features.contains(features)
+			})
+			.unwrap()
+	}
+
+	/// Collects counters and timings for the current frame.
+	pub fn stats(&self) -> &StatsCollector {
+		&self.stats
+	}
 
-		let vshader = unsafe { device.create_shader_module(&vert_spv.await.unwrap()) };
-		let fshader = unsafe { device.create_shader_module(&frag_spv.await.unwrap()) };
+	/// The stats of the last finished frame.
+	pub fn frame_stats(&self) -> FrameStats {
+		self.stats.last()
+	}
+}
+
+#[derive(Clone)]
+struct ShaderModules {
+	#[cfg(debug_assertions)]
+	generation: u64,
+	vert: Arc<ShaderModule>,
+	frag: Arc<ShaderModule>,
+	gui_vert: Arc<ShaderModule>,
+	gui_frag: Arc<ShaderModule>,
+	mesh_vert: Arc<ShaderModule>,
+	mesh_frag: Arc<ShaderModule>,
+	lines_vert: Arc<ShaderModule>,
+	lines_frag: Arc<ShaderModule>,
+	temporal_frag: Arc<ShaderModule>,
+	volume_vert: Arc<ShaderModule>,
+	cull_comp: Arc<ShaderModule>,
+	mips_comp: Arc<ShaderModule>,
+	edit_comp: Arc<ShaderModule>,
+	#[cfg(feature = "debug-overlay")]
+	egui_vert: Arc<ShaderModule>,
+	#[cfg(feature = "debug-overlay")]
+	egui_frag: Arc<ShaderModule>,
+}
+#[cfg(debug_assertions)]
+impl ShaderModules {
+	const SOURCES: &'static [&'static Shader] = &[
+		&shaders::SHADER_VERT,
+		&shaders::SHADER_FRAG,
+		&shaders::GUI_VERT,
+		&shaders::GUI_FRAG,
+		&shaders::MESH_VERT,
+		&shaders::MESH_FRAG,
+		&shaders::LINES_VERT,
+		&shaders::LINES_FRAG,
+		&shaders::TEMPORAL_FRAG,
+		&shaders::VOLUME_VERT,
+		&shaders::CULL_COMP,
+		&shaders::MIPS_COMP,
+		&shaders::EDIT_COMP,
+		#[cfg(feature = "debug-overlay")]
+		&shaders::EGUI_VERT,
+		#[cfg(feature = "debug-overlay")]
+		&shaders::EGUI_FRAG,
+	];
 
-		Arc::new(Self { instance, device, queue, layout, triangle, vshader, fshader })
+	fn replace(&mut self, shader: &'static Shader, module: Arc<ShaderModule>) {
+		let slots: Vec<(&Shader, &mut Arc<ShaderModule>)> = vec![
+			(&shaders::SHADER_VERT, &mut self.vert),
+			(&shaders::SHADER_FRAG, &mut self.frag),
+			(&shaders::GUI_VERT, &mut self.gui_vert),
+			(&shaders::GUI_FRAG, &mut self.gui_frag),
+			(&shaders::MESH_VERT, &mut self.mesh_vert),
+			(&shaders::MESH_FRAG, &mut self.mesh_frag),
+			(&shaders::LINES_VERT, &mut self.lines_vert),
+			(&shaders::LINES_FRAG, &mut self.lines_frag),
+			(&shaders::TEMPORAL_FRAG, &mut self.temporal_frag),
+			(&shaders::VOLUME_VERT, &mut self.volume_vert),
+			(&shaders::CULL_COMP, &mut self.cull_comp),
+			(&shaders::MIPS_COMP, &mut self.mips_comp),
+			(&shaders::EDIT_COMP, &mut self.edit_comp),
+			#[cfg(feature = "debug-overlay")]
+			(&shaders::EGUI_VERT, &mut self.egui_vert),
+			#[cfg(feature = "debug-overlay")]
+			(&shaders::EGUI_FRAG, &mut self.egui_frag),
+		];
+		let (_, slot) = slots.into_iter().find(|(source, _)| ptr::eq(*source, shader)).unwrap();
+		*slot = module;
 	}
 }
 
@@ -97,3 +399,130 @@ impl VertexDesc for TriangleVertex {
 		]
 	}
 }
+
+/// Laid out like the push constants in `mips.comp`.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct MipParams {
+	pub slot: u32,
+}
+
+/// Laid out like the push constants in `edit.comp`, which replays an edit on one chunk in the atlas.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct EditParams {
+	/// The chunk's first voxel.
+	pub chunk_min: Vector3<i32>,
+	pub slot: u32,
+	/// A box's min corner, or a sphere's center with its radius in w.
+	pub shape_a: Vector4<f32>,
+	/// A box's max corner.
+	pub shape_b: Vector4<f32>,
+	/// 0 to add the shape, 1 to subtract it, or 2 to paint it.
+	pub op: u32,
+	/// 0 for a box, or 1 for a sphere.
+	pub shape: u32,
+	/// The material added or painted.
+	pub material: u32,
+}
+impl EditParams {
+	pub fn new(edit: &Edit, slot: u32, chunk_min: Vector3<i32>) -> Self {
+		let (op, material) = match *edit {
+			Edit::Add(_, material) => (0, material),
+			Edit::Subtract(_) => (1, 0),
+			Edit::Paint(_, material) => (2, material),
+		};
+		let (shape_a, shape_b, shape) = match edit.shape() {
+			Shape::Box(aabb) => (aabb.min.push(0.0), aabb.max.push(0.0), 0),
+			Shape::Sphere { center, radius } => (center.push(radius), Vector4::zeros(), 1),
+		};
+		Self { chunk_min, slot, shape_a, shape_b, op, shape, material: material as u32 }
+	}
+}
+
+#[cfg(feature = "validation")]
+fn validation_enabled(validation: Option<bool>) -> bool {
+	let validation = match env::var("SPACE_THING_VALIDATION") {
+		Ok(var) => match var.as_str() {
+			"1" | "true" => Some(true),
+			"0" | "false" => Some(false),
+			_ => {
+				log::warn!("ignoring SPACE_THING_VALIDATION={}, which should be 1 or 0", var);
+				validation
+			},
+		},
+		Err(_) => validation,
+	};
+	validation.unwrap_or(cfg!(debug_assertions))
+}
+
+/// Builds without the `validation` feature never use the layers, so asking for them only gets a warning.
+#[cfg(not(feature = "validation"))]
+fn validation_enabled(validation: Option<bool>) -> bool {
+	let var = env::var("SPACE_THING_VALIDATION").unwrap_or_default();
+	if validation == Some(true) || var == "1" || var == "true" {
+		log::warn!("validation was requested, but this build doesn't have the validation feature");
+	}
+	false
+}
+
+/// Picks the physical device to render with. An override is used if it names a suitable device, and otherwise
+/// discrete GPUs win over integrated ones, then the device with the most local memory wins.
+fn select_physical_device(instance: &Arc<Instance>, adapter: Option<usize>) -> Option<PhysicalDevice> {
+	let adapter = match env::var("SPACE_THING_ADAPTER") {
+		Ok(var) => match var.parse() {
+			Ok(idx) => Some(idx),
+			Err(_) => {
+				log::warn!("ignoring SPACE_THING_ADAPTER={}, which isn't an adapter index", var);
+				adapter
+			},
+		},
+		Err(_) => adapter,
+	};
+
+	let devices = instance.enumerate_physical_devices().collect::<Vec<_>>();
+	for (i, device) in devices.iter().enumerate() {
+		log::info!("adapter {}: {} (score {:?})", i, device_name(device), device_score(device));
+	}
+
+	if let Some(idx) = adapter {
+		match devices.get(idx) {
+			Some(device) if device_score(device).is_some() => return Some(*device),
+			Some(device) => log::warn!("adapter {} ({}) can't render to a window", idx, device_name(device)),
+			None => log::warn!("adapter {} doesn't exist; there are {}", idx, devices.len()),
+		}
+	}
+
+	devices.into_iter().filter(|device| device_score(device).is_some()).max_by_key(device_score)
+}
+
+/// Ranks a device by its type and then its device local memory, or returns `None` if it can't be used at all.
+///
+/// Surface support can only be checked once there's a surface, so this only requires the swapchain extension, and
+/// `Window::new` checks the rest.
+fn device_score(device: &PhysicalDevice) -> Option<(u8, vk::DeviceSize)> {
+	let swapchain = CStr::from_bytes_with_nul(b"VK_KHR_swapchain\0").unwrap();
+	let graphics = device.get_queue_family_properties().any(|props| props.queue_flags().graphics());
+	if !graphics || !device.supports_extension(swapchain) {
+		return None;
+	}
+
+	let rank = match device.get_properties().device_type {
+		vk::PhysicalDeviceType::DISCRETE_GPU => 3,
+		vk::PhysicalDeviceType::INTEGRATED_GPU => 2,
+		vk::PhysicalDeviceType::VIRTUAL_GPU => 1,
+		_ => 0,
+	};
+	let memory = device.get_memory_properties();
+	let local = memory.memory_heaps[..memory.memory_heap_count as usize]
+		.iter()
+		.filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+		.map(|heap| heap.size)
+		.sum();
+	Some((rank, local))
+}
+
+fn device_name(device: &PhysicalDevice) -> String {
+	let props = device.get_properties();
+	unsafe { CStr::from_ptr(props.device_name.as_ptr()) }.to_string_lossy().into_owned()
+}
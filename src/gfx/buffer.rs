@@ -0,0 +1,132 @@
+//! Host visible memory for uploads. Ranges are suballocated from a few large buffers that stay mapped, instead of
+//! creating and destroying a buffer for every upload.
+
+use std::{
+	ops::Range,
+	sync::{Arc, Mutex},
+};
+use typenum::B1;
+use vulkan::{
+	buffer::Buffer,
+	device::{BufferUsageFlags, Device},
+};
+
+/// The size of each shared buffer. Uploads bigger than this get a buffer of their own.
+const BLOCK_SIZE: u64 = 16 << 20;
+/// Every range starts at a multiple of this, which covers the offset rules for copies into any image format.
+const ALIGNMENT: u64 = 256;
+
+pub struct StagingPool {
+	device: Arc<Device>,
+	blocks: Mutex<Vec<Block>>,
+}
+impl StagingPool {
+	pub(super) fn new(device: Arc<Device>) -> Self {
+		Self { device, blocks: Mutex::default() }
+	}
+
+	/// Copies `data` into a free range, creating another buffer if none of them has room.
+	pub fn push(&self, data: &[u8]) -> Staging {
+		self.push_with(data.len(), |dst| dst.copy_from_slice(data))
+	}
+
+	/// Like `push`, but `fill` writes the `len` bytes straight into the mapped range, so data that has to be decoded or
+	/// rearranged anyway doesn't need a buffer of its own first. `fill` must write every byte.
+	pub fn push_with(&self, len: usize, fill: impl FnOnce(&mut [u8])) -> Staging {
+		let len = len as u64;
+		let staging = if len > BLOCK_SIZE {
+			let buffer = self.device.create_buffer_slice(len as _, B1, BufferUsageFlags::TRANSFER_SRC).uninitialized();
+			Staging { buffer, offset: 0, len }
+		} else {
+			let mut blocks = self.blocks.lock().unwrap();
+			let found =
+				blocks.iter_mut().find_map(|block| block.alloc(len).map(|offset| (block.buffer.clone(), offset)));
+			let (buffer, offset) = found.unwrap_or_else(|| {
+				let mut block = Block::new(&self.device);
+				let offset = block.alloc(len).unwrap();
+				let buffer = block.buffer.clone();
+				blocks.push(block);
+				(buffer, offset)
+			});
+			Staging { buffer, offset, len }
+		};
+
+		// the range is only ours, so the GPU isn't reading it
+		unsafe { staging.buffer.write_with(staging.offset as _, staging.len as _, fill) };
+		staging
+	}
+
+	/// Returns `staging`'s range to the pool. The GPU must be done with it.
+	pub fn release(&self, staging: Staging) {
+		let mut blocks = self.blocks.lock().unwrap();
+		let idx = match blocks.iter().position(|block| Arc::ptr_eq(&block.buffer, &staging.buffer)) {
+			Some(idx) => idx,
+			// a dedicated buffer, which is freed when the last command buffer using it is
+			None => return,
+		};
+		blocks[idx].free(staging.offset..staging.offset + staging.len);
+		// one buffer is kept around for the next upload, but the rest only exist for bursts of loading
+		if idx > 0 && blocks[idx].is_empty() {
+			blocks.remove(idx);
+		}
+	}
+}
+
+/// A range of a staging buffer that holds data waiting to be copied to the GPU.
+pub struct Staging {
+	pub buffer: Arc<Buffer<[u8]>>,
+	/// Where the data starts, in bytes.
+	pub offset: u64,
+	pub len: u64,
+}
+
+struct Block {
+	buffer: Arc<Buffer<[u8]>>,
+	/// Unused ranges, sorted and never touching each other.
+	free: Vec<Range<u64>>,
+}
+impl Block {
+	fn new(device: &Arc<Device>) -> Self {
+		let buffer = device.create_buffer_slice(BLOCK_SIZE as _, B1, BufferUsageFlags::TRANSFER_SRC).uninitialized();
+		Self { buffer, free: vec![0..BLOCK_SIZE] }
+	}
+
+	/// Takes the first free range that fits `len` bytes, and returns its offset.
+	fn alloc(&mut self, len: u64) -> Option<u64> {
+		let (i, start) = self.free.iter().enumerate().find_map(|(i, range)| {
+			let start = (range.start + ALIGNMENT - 1) / ALIGNMENT * ALIGNMENT;
+			if start + len <= range.end {
+				Some((i, start))
+			} else {
+				None
+			}
+		})?;
+
+		// the padding before the range stays free, as does whatever's left after it
+		let range = self.free.remove(i);
+		if start + len < range.end {
+			self.free.insert(i, start + len..range.end);
+		}
+		if range.start < start {
+			self.free.insert(i, range.start..start);
+		}
+		Some(start)
+	}
+
+	fn free(&mut self, range: Range<u64>) {
+		let i = self.free.iter().position(|free| free.start > range.start).unwrap_or(self.free.len());
+		self.free.insert(i, range);
+
+		// merges with the neighbors on either side
+		if i + 1 < self.free.len() && self.free[i].end == self.free[i + 1].start {
+			self.free[i].end = self.free.remove(i + 1).end;
+		}
+		if i > 0 && self.free[i - 1].end == self.free[i].start {
+			self.free[i - 1].end = self.free.remove(i).end;
+		}
+	}
+
+	fn is_empty(&self) -> bool {
+		self.free.len() == 1 && self.free[0] == (0..BLOCK_SIZE)
+	}
+}
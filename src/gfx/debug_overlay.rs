@@ -0,0 +1,271 @@
+use crate::gfx::{
+	gui::render::{create_desc_pool, create_set_layout},
+	image::Mips,
+	stats::FrameStats,
+	window::viewport,
+	Gfx,
+};
+use ash::{version::DeviceV1_0, vk};
+use egui::{ClippedMesh, CtxRef, Event, PointerButton, RawInput};
+use memoffset::offset_of;
+use nalgebra::{Vector2, Vector4};
+use std::{iter::once, mem::size_of_val, sync::Arc, time::Instant};
+use typenum::B1;
+use vulkan::{
+	command::{CommandBuffer, CommandPool, InheritanceInfo},
+	descriptor::DescriptorSet,
+	device::BufferUsageFlags,
+	image::{Image, ImageView},
+	pipeline::{Pipeline, PipelineLayout, VertexDesc},
+	render_pass::RenderPass,
+	Extent2D, Offset2D, Rect2D,
+};
+
+/// Values shown in the debug window.
+pub struct DebugStats {
+	pub frame: FrameStats,
+	pub image_extent: Extent2D,
+	pub ui_scale: f32,
+}
+
+/// An egui context drawn over the game, for development tools.
+pub struct DebugOverlay {
+	gfx: Arc<Gfx>,
+	ctx: CtxRef,
+	input: RawInput,
+	cursor: Option<egui::Pos2>,
+	start: Instant,
+	pub visible: bool,
+	meshes: Vec<ClippedMesh>,
+	font_set: Arc<DescriptorSet>,
+	font: Option<(u64, Arc<Image>, Arc<ImageView>)>,
+	layout: Arc<PipelineLayout>,
+	pipeline: Arc<Pipeline>,
+}
+impl DebugOverlay {
+	pub fn new(gfx: Arc<Gfx>, render_pass: Arc<RenderPass>, samples: vk::SampleCountFlags) -> Self {
+		let set_layout = create_set_layout(&gfx);
+		let desc_pool = create_desc_pool(&gfx);
+		let font_set = desc_pool.allocate(set_layout.clone()).unwrap();
+		let layout = gfx.device.create_pipeline_layout(vec![set_layout], &[]);
+		let pipeline = create_pipeline(&gfx, layout.clone(), render_pass, samples);
+
+		Self {
+			gfx,
+			ctx: CtxRef::default(),
+			input: RawInput::default(),
+			cursor: None,
+			start: Instant::now(),
+			visible: false,
+			meshes: vec![],
+			font_set,
+			font: None,
+			layout,
+			pipeline,
+		}
+	}
+
+	/// Rebuilds the pipeline from the current shader modules, after they've been hot reloaded.
+	#[cfg(debug_assertions)]
+	pub fn reload_pipeline(&mut self, render_pass: Arc<RenderPass>, samples: vk::SampleCountFlags) {
+		self.pipeline = create_pipeline(&self.gfx, self.layout.clone(), render_pass, samples);
+	}
+
+	/// Whether egui is using the cursor, so clicks shouldn't reach the game.
+	pub fn wants_pointer(&self) -> bool {
+		self.visible && self.ctx.wants_pointer_input()
+	}
+
+	/// Takes a cursor position in physical pixels.
+	pub fn cursor_moved(&mut self, pos: Offset2D, hidpi_factor: f32) {
+		let pos = egui::pos2(pos.x as f32 / hidpi_factor, pos.y as f32 / hidpi_factor);
+		self.cursor = Some(pos);
+		self.input.events.push(Event::PointerMoved(pos));
+	}
+
+	pub fn cursor_left(&mut self) {
+		self.cursor = None;
+		self.input.events.push(Event::PointerGone);
+	}
+
+	pub fn mouse_input(&mut self, pressed: bool) {
+		if let Some(pos) = self.cursor {
+			let modifiers = Default::default();
+			self.input.events.push(Event::PointerButton { pos, button: PointerButton::Primary, pressed, modifiers });
+		}
+	}
+
+	/// Runs one egui frame, building the debug window from `stats`.
+	pub fn update(&mut self, image_extent: Extent2D, hidpi_factor: f32, stats: &mut DebugStats) {
+		let mut input = std::mem::take(&mut self.input);
+		if !self.visible {
+			return;
+		}
+
+		let size = egui::vec2(image_extent.width as f32, image_extent.height as f32) / hidpi_factor;
+		input.screen_rect = Some(egui::Rect::from_min_size(Default::default(), size));
+		input.pixels_per_point = Some(hidpi_factor);
+		input.time = Some(self.start.elapsed().as_secs_f64());
+
+		self.ctx.begin_frame(input);
+		egui::Window::new("Debug").show(&self.ctx, |ui| {
+			let frame = &stats.frame;
+			let frame_time = frame.frame_time.as_secs_f32();
+			ui.label(format!("frame: {:.2} ms ({:.0} fps)", frame_time * 1000.0, 1.0 / frame_time.max(0.0001)));
+			for (name, time) in &frame.cpu {
+				ui.label(format!("  cpu {}: {:.2} ms", name, time.as_secs_f32() * 1000.0));
+			}
+			for (name, time) in &frame.gpu {
+				ui.label(format!("  gpu {}: {:.2} ms", name, time.as_secs_f32() * 1000.0));
+			}
+			ui.label(format!("draws: {}, dispatches: {}", frame.draws, frame.dispatches));
+			ui.label(format!("uploaded: {:.1} KiB", frame.upload_bytes as f32 / 1024.0));
+			ui.label(format!("chunks generated: {}", frame.chunks_generated));
+			ui.label(format!("resolution: {}x{}", stats.image_extent.width, stats.image_extent.height));
+			ui.separator();
+			ui.add(egui::Slider::f32(&mut stats.ui_scale, 0.5..=3.0).text("ui scale"));
+		});
+		let (_output, shapes) = self.ctx.end_frame();
+		self.meshes = self.ctx.tessellate(shapes);
+	}
+
+	/// Records a secondary command buffer that draws the last frame's meshes. Returns `None` if the overlay is hidden.
+	pub fn record(
+		&mut self,
+		cmdpool: &Arc<CommandPool>,
+		inherit: InheritanceInfo,
+		image_extent: Extent2D,
+		hidpi_factor: f32,
+	) -> Option<Arc<CommandBuffer<B1>>> {
+		if !self.visible || self.meshes.is_empty() {
+			return None;
+		}
+		self.update_font();
+
+		// every mesh goes in the same buffers, and each draw offsets its indices by where its vertices start
+		let scale = Vector2::new(2.0 / image_extent.width as f32, 2.0 / image_extent.height as f32) * hidpi_factor;
+		let mut verts = vec![];
+		let mut indices = vec![];
+		let mut draws = vec![];
+		for ClippedMesh(clip, mesh) in &self.meshes {
+			let min = (clip.min.to_vec2() * hidpi_factor).max(egui::vec2(0.0, 0.0));
+			let max =
+				(clip.max.to_vec2() * hidpi_factor).min(egui::vec2(image_extent.width as _, image_extent.height as _));
+			if max.x <= min.x || max.y <= min.y || mesh.indices.is_empty() {
+				continue;
+			}
+			let scissor = Rect2D {
+				offset: Offset2D { x: min.x as _, y: min.y as _ },
+				extent: Extent2D { width: (max.x - min.x).ceil() as _, height: (max.y - min.y).ceil() as _ },
+			};
+
+			let base = verts.len() as i32;
+			let start = indices.len() as u32;
+			verts.extend(mesh.vertices.iter().map(|vert| EguiVertex {
+				pos: Vector2::new(vert.pos.x, vert.pos.y).component_mul(&scale) - Vector2::new(1.0, 1.0),
+				uv: Vector2::new(vert.uv.x, vert.uv.y),
+				color: Vector4::new(vert.color.r(), vert.color.g(), vert.color.b(), vert.color.a()),
+			}));
+			indices.extend_from_slice(&mesh.indices);
+			draws.push((scissor, start..indices.len() as u32, base));
+		}
+		if draws.is_empty() {
+			return None;
+		}
+
+		let device = &self.gfx.device;
+		let vert_buffer =
+			device.create_buffer_slice(verts.len(), B1, BufferUsageFlags::VERTEX_BUFFER).copy_from_slice(&verts);
+		let index_buffer =
+			device.create_buffer_slice(indices.len(), B1, BufferUsageFlags::INDEX_BUFFER).copy_from_slice(&indices);
+		self.gfx.stats().add_upload((size_of_val(&verts[..]) + size_of_val(&indices[..])) as _);
+		self.gfx.stats().add_draws(draws.len() as _);
+
+		let mut cmd = cmdpool
+			.record_secondary(true, false, Some(inherit))
+			.begin_label("debug overlay")
+			.bind_pipeline(self.pipeline.clone())
+			.set_viewport(0, &[viewport(image_extent)])
+			.bind_descriptor_sets(self.layout.clone(), 0, once(self.font_set.clone()))
+			.bind_vertex_buffer(0, vert_buffer, 0)
+			.bind_index_buffer(index_buffer, 0);
+		for (scissor, range, base) in draws {
+			cmd = cmd.set_scissor(0, &[scissor]).draw_indexed(range.end - range.start, 1, range.start, base, 0);
+		}
+		Some(cmd.end_label().build())
+	}
+
+	/// Uploads egui's font texture if it changed since the last frame.
+	fn update_font(&mut self) {
+		let texture = self.ctx.texture();
+		if self.font.as_ref().map(|font| font.0) == Some(texture.version) {
+			return;
+		}
+
+		let pixels = texture.pixels.iter().flat_map(|&a| vec![a, a, a, a]).collect::<Vec<_>>();
+		let extent = vk::Extent2D { width: texture.width as _, height: texture.height as _ };
+		let (image, _) = self.gfx.upload_image(vk::Format::R8G8B8A8_UNORM, extent, Mips::Provided(1), &pixels);
+		let view = image.create_view();
+		self.gfx.uploads().finish();
+
+		// the descriptor set may be in use by frames in flight. this only happens when egui adds glyphs, so stalling
+		// is fine for a debug tool
+		unsafe { self.gfx.device.vk.device_wait_idle() }.unwrap();
+		self.font_set.write().sampled_view(0, view.clone()).submit();
+		self.font = Some((texture.version, image, view));
+	}
+}
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct EguiVertex {
+	pos: Vector2<f32>,
+	uv: Vector2<f32>,
+	color: Vector4<u8>,
+}
+impl VertexDesc for EguiVertex {
+	fn attribute_descs() -> Vec<vk::VertexInputAttributeDescription> {
+		vec![
+			vk::VertexInputAttributeDescription::builder()
+				.binding(0)
+				.location(0)
+				.format(vk::Format::R32G32_SFLOAT)
+				.offset(offset_of!(Self, pos) as _)
+				.build(),
+			vk::VertexInputAttributeDescription::builder()
+				.binding(0)
+				.location(1)
+				.format(vk::Format::R32G32_SFLOAT)
+				.offset(offset_of!(Self, uv) as _)
+				.build(),
+			vk::VertexInputAttributeDescription::builder()
+				.binding(0)
+				.location(2)
+				.format(vk::Format::R8G8B8A8_UNORM)
+				.offset(offset_of!(Self, color) as _)
+				.build(),
+		]
+	}
+}
+
+fn create_pipeline(
+	gfx: &Gfx,
+	layout: Arc<PipelineLayout>,
+	render_pass: Arc<RenderPass>,
+	samples: vk::SampleCountFlags,
+) -> Arc<Pipeline> {
+	let shaders = gfx.shaders();
+	let pipeline = gfx
+		.device
+		.build_pipeline(layout, render_pass)
+		.vertex_shader(shaders.egui_vert.clone())
+		.fragment_shader(shaders.egui_frag.clone())
+		.vertex_input::<EguiVertex>()
+		.premultiplied_alpha_blending()
+		.cull_mode(vk::CullModeFlags::NONE)
+		.dynamic_viewport()
+		.samples(samples)
+		.build();
+	gfx.device.set_object_name(pipeline.vk, "debug overlay");
+	pipeline
+}
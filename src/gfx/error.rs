@@ -0,0 +1,59 @@
+use ash::vk;
+use std::{error::Error, fmt};
+use vulkan::LoadingError;
+use winit::error::OsError;
+
+/// Why the renderer or a window couldn't be created.
+#[derive(Debug)]
+pub enum GfxError {
+	/// The Vulkan loader isn't installed.
+	Loading(LoadingError),
+	ShaderLoad {
+		name: &'static str,
+		reason: String,
+	},
+	/// No device has a graphics queue and swapchain support.
+	NoSuitableDevice,
+	/// The device can't present to the window, the surface has no formats, or the driver doesn't support the window
+	/// system.
+	SurfaceUnsupported,
+	Window(OsError),
+	VkError(vk::Result),
+}
+impl fmt::Display for GfxError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Loading(err) => write!(f, "failed to load Vulkan: {}", err),
+			Self::ShaderLoad { name, reason } => write!(f, "failed to load shader {}: {}", name, reason),
+			Self::NoSuitableDevice => write!(f, "no GPU supports rendering to a window"),
+			Self::SurfaceUnsupported => write!(f, "the GPU can't present to this window"),
+			Self::Window(err) => write!(f, "failed to create the window: {}", err),
+			Self::VkError(err) => write!(f, "Vulkan error: {}", err),
+		}
+	}
+}
+impl Error for GfxError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			Self::Loading(err) => Some(err),
+			Self::Window(err) => Some(err),
+			Self::VkError(err) => Some(err),
+			_ => None,
+		}
+	}
+}
+impl From<LoadingError> for GfxError {
+	fn from(err: LoadingError) -> Self {
+		Self::Loading(err)
+	}
+}
+impl From<OsError> for GfxError {
+	fn from(err: OsError) -> Self {
+		Self::Window(err)
+	}
+}
+impl From<vk::Result> for GfxError {
+	fn from(err: vk::Result) -> Self {
+		Self::VkError(err)
+	}
+}
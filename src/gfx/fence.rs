@@ -0,0 +1,103 @@
+//! Futures for fences, so jobs can wait on the GPU without blocking a worker.
+
+use ash::vk;
+use lazy_static::lazy_static;
+use std::{
+	collections::HashMap,
+	future::Future,
+	pin::Pin,
+	sync::{mpsc, Arc, Mutex},
+	task::{Context, Poll, Waker},
+	thread,
+};
+use vulkan::sync::Fence;
+
+/// The longest the GPU-wait thread blocks on its fences before picking up new ones, in nanoseconds.
+const FENCE_WAIT_TIMEOUT: u64 = 5_000_000;
+
+lazy_static! {
+	pub static ref FENCES: FenceWaiter = FenceWaiter::new();
+}
+
+/// A thread that waits on fences for `FenceWait`s.
+pub struct FenceWaiter {
+	waits: Mutex<mpsc::Sender<(Arc<Fence>, Waker)>>,
+}
+impl FenceWaiter {
+	fn new() -> Self {
+		let (waits, recv) = mpsc::channel();
+		thread::Builder::new().name("gpu wait".to_string()).spawn(move || run_fence_waits(recv)).unwrap();
+		Self { waits: Mutex::new(waits) }
+	}
+
+	/// Resolves once `fence` is signalled. The GPU-wait thread checks every fence being waited on, so no worker blocks.
+	pub fn wait(&'static self, fence: Arc<Fence>) -> FenceWait {
+		FenceWait { waiter: self, fence, waker: None }
+	}
+}
+
+/// See `FenceWaiter::wait`.
+pub struct FenceWait {
+	waiter: &'static FenceWaiter,
+	fence: Arc<Fence>,
+	/// The waker last sent to the GPU-wait thread, if any.
+	waker: Option<Waker>,
+}
+impl Future for FenceWait {
+	type Output = ();
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		let this = self.get_mut();
+		if this.fence.is_signalled() {
+			return Poll::Ready(());
+		}
+		// the GPU-wait thread already has the fence, so it only needs to hear about a different waker
+		let registered = match &this.waker {
+			Some(waker) => waker.will_wake(cx.waker()),
+			None => false,
+		};
+		if !registered {
+			this.waker = Some(cx.waker().clone());
+			this.waiter.waits.lock().unwrap().send((this.fence.clone(), cx.waker().clone())).unwrap();
+		}
+		Poll::Pending
+	}
+}
+
+/// Wakes each fence's wakers once it's signalled. It sleeps until there's something to wait on, then blocks in the
+/// driver until any of its fences signal. Fences that arrive meanwhile wait for the timeout, since the driver's wait
+/// can't be interrupted.
+///
+/// Fences are kept by handle, so each is waited on once however many times it's sent. A fence sent again only adds
+/// its waker, if it isn't one that's already there.
+fn run_fence_waits(recv: mpsc::Receiver<(Arc<Fence>, Waker)>) {
+	let mut waiting = HashMap::<vk::Fence, (Arc<Fence>, Vec<Waker>)>::new();
+	let add = |waiting: &mut HashMap<_, (_, Vec<Waker>)>, (fence, waker): (Arc<Fence>, Waker)| {
+		let (_, wakers) = waiting.entry(fence.vk).or_insert_with(|| (fence, vec![]));
+		if !wakers.iter().any(|old| old.will_wake(&waker)) {
+			wakers.push(waker);
+		}
+	};
+	loop {
+		if waiting.is_empty() {
+			match recv.recv() {
+				Ok(wait) => add(&mut waiting, wait),
+				Err(_) => return,
+			}
+		}
+		for wait in recv.try_iter() {
+			add(&mut waiting, wait);
+		}
+
+		let fences = waiting.values().map(|(fence, _)| &**fence).collect::<Vec<_>>();
+		// checked even after a timeout, since fences from another device aren't part of the wait
+		Fence::wait_any(&fences, FENCE_WAIT_TIMEOUT);
+		waiting.retain(|_, (fence, wakers)| {
+			let signalled = fence.is_signalled();
+			if signalled {
+				wakers.drain(..).for_each(Waker::wake);
+			}
+			!signalled
+		});
+	}
+}
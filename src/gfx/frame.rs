@@ -0,0 +1,138 @@
+//! Data that changes once per frame, which every pipeline reads from a uniform buffer in descriptor set 0, and the
+//! bookkeeping for frames that are still in flight on the GPU.
+
+use crate::{camera::Camera, lighting::Light};
+use nalgebra::{Matrix4, Vector2, Vector3};
+use std::{mem, sync::Arc};
+use typenum::B1;
+use vulkan::{
+	buffer::Buffer,
+	descriptor::{
+		DescriptorPoolSize, DescriptorSet, DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorType,
+		ShaderStageFlags,
+	},
+	device::{BufferUsageFlags, Device},
+	sync::Fence,
+	Extent2D,
+};
+
+/// Laid out like the `Frame` block in the shaders, following std140.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct FrameUniforms {
+	/// Transforms world space into clip space.
+	pub view_proj: Matrix4<f32>,
+	/// Transforms clip space back into world space, so shaders can rebuild view rays.
+	pub inv_view_proj: Matrix4<f32>,
+	pub light: Light,
+	pub cam_pos: Vector3<f32>,
+	/// Seconds since the window was created.
+	pub time: f32,
+	/// The framebuffer size, in pixels.
+	pub resolution: Vector2<f32>,
+}
+impl FrameUniforms {
+	/// The camera's projection takes its aspect ratio from `extent`, so the image isn't stretched.
+	pub fn new(camera: &Camera, light: &Light, time: f32, extent: Extent2D) -> Self {
+		let view_proj = camera.view_projection(extent.width as f32 / extent.height as f32);
+		Self {
+			view_proj,
+			inv_view_proj: view_proj.try_inverse().unwrap_or_else(Matrix4::identity),
+			light: *light,
+			cam_pos: camera.pos,
+			time,
+			resolution: Vector2::new(extent.width as _, extent.height as _),
+		}
+	}
+}
+
+/// Which of the frames in flight is being recorded, and the fences that say when the GPU is done with the others.
+/// Anything the GPU reads while later frames are recorded, like command pools and uniform buffers, needs a copy for
+/// each frame, indexed by `current`.
+pub struct FramesInFlight {
+	fences: Vec<Option<Fence>>,
+	current: usize,
+}
+impl FramesInFlight {
+	/// `count` is raised to at least 1. More frames let the CPU get further ahead of the GPU, at the cost of latency.
+	pub fn new(count: usize) -> Self {
+		let count = count.max(1);
+		// the first `begin` wraps around to frame 0
+		Self { fences: (0..count).map(|_| None).collect(), current: count - 1 }
+	}
+
+	pub fn count(&self) -> usize {
+		self.fences.len()
+	}
+
+	/// The index of the frame being recorded.
+	pub fn current(&self) -> usize {
+		self.current
+	}
+
+	/// Creates a resource for each frame, in index order.
+	pub fn per_frame<T>(&self, create: impl FnMut(usize) -> T) -> Vec<T> {
+		(0..self.count()).map(create).collect()
+	}
+
+	/// Moves on to the next frame and waits until the GPU is done with its last submission, so its resources can be
+	/// reused. Returns its index.
+	pub fn begin(&mut self) -> usize {
+		self.current = (self.current + 1) % self.count();
+		if let Some(fence) = self.fences[self.current].take() {
+			fence.wait();
+		}
+		self.current
+	}
+
+	/// Sets the fence that signals once the GPU is done with the current frame.
+	pub fn submitted(&mut self, fence: Fence) {
+		self.fences[self.current] = Some(fence);
+	}
+}
+
+/// The layout of descriptor set 0, which holds the frame uniforms.
+pub(super) fn create_set_layout(device: &Arc<Device>) -> Arc<DescriptorSetLayout> {
+	let bindings = [DescriptorSetLayoutBinding::builder()
+		.binding(0)
+		.descriptor_type(DescriptorType::UNIFORM_BUFFER)
+		.descriptor_count(1)
+		.stage_flags(ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT)
+		.build()];
+	device.create_descriptor_set_layout(&bindings, vec![])
+}
+
+/// A uniform buffer and descriptor set for each frame in flight, so one frame's data can be written while the GPU
+/// still reads the last one's.
+pub struct FrameUniformRing {
+	slots: Vec<(Arc<Buffer<[FrameUniforms]>>, Arc<DescriptorSet>)>,
+}
+impl FrameUniformRing {
+	/// `set_layout` must come from `create_set_layout`.
+	pub(super) fn new(device: &Arc<Device>, set_layout: &Arc<DescriptorSetLayout>, frames: &FramesInFlight) -> Self {
+		let pool_sizes = [DescriptorPoolSize::builder()
+			.ty(DescriptorType::UNIFORM_BUFFER)
+			.descriptor_count(frames.count() as _)
+			.build()];
+		let pool = device.create_descriptor_pool(frames.count() as _, &pool_sizes);
+		let slots = frames.per_frame(|_| {
+			// every field is floats, so zeroes are valid, and they're overwritten before the first use anyway
+			let zeroed: FrameUniforms = unsafe { mem::zeroed() };
+			let buffer = device.create_buffer_slice(1, B1, BufferUsageFlags::UNIFORM_BUFFER).copy_from_slice(&[zeroed]);
+			device.set_object_name(buffer.vk, "frame uniforms");
+			let set = pool.allocate(set_layout.clone()).unwrap();
+			set.write().uniform_buffer(0, buffer.clone()).submit();
+			(buffer, set)
+		});
+		Self { slots }
+	}
+
+	/// Writes `uniforms` into the buffer for `frame`, and returns the set to bind it with.
+	///
+	/// The last frame recorded with the same index must have finished on the GPU.
+	pub unsafe fn write(&self, frame: usize, uniforms: &FrameUniforms) -> Arc<DescriptorSet> {
+		let (buffer, set) = &self.slots[frame];
+		buffer.write(&[*uniforms]);
+		set.clone()
+	}
+}
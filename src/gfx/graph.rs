@@ -0,0 +1,360 @@
+//! A frame's GPU work as passes that declare which images and buffers they use, and how. The graph orders the passes so
+//! everything a pass reads is written first, and records the barriers and layout transitions between them, so a pass
+//! only has to know what it does itself, not what ran before it.
+
+use ash::vk;
+use std::sync::Arc;
+use typenum::B0;
+use vulkan::{
+	buffer::BufferAbstract,
+	command::{BufferBarrier, CommandBufferBuilder, ImageBarrier},
+	image::ImageAbstract,
+};
+
+/// How a pass uses an image or buffer. Buffers ignore the layouts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Usage {
+	/// A swapchain image that was just acquired. Its contents are undefined, and it can't be touched until the acquire
+	/// semaphore is signalled, which submissions wait on at the color attachment output stage. Only for imports.
+	Acquired,
+	ColorAttachment,
+	DepthAttachment,
+	/// Read through a sampler in a shader.
+	Sampled,
+	/// A storage image or buffer that's only read.
+	StorageRead,
+	/// A storage image or buffer that's written, and maybe read too.
+	StorageWrite,
+	Uniform,
+	/// Vertex or index data.
+	Vertex,
+	/// Parameters for indirect draws or dispatches.
+	Indirect,
+	TransferSrc,
+	TransferDst,
+	/// Read by the CPU once the submission's fence is signalled.
+	HostRead,
+	Present,
+}
+impl Usage {
+	fn layout(self) -> vk::ImageLayout {
+		match self {
+			Usage::Acquired => vk::ImageLayout::UNDEFINED,
+			Usage::ColorAttachment => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+			Usage::DepthAttachment => vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+			Usage::Sampled => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+			Usage::StorageRead | Usage::StorageWrite | Usage::HostRead => vk::ImageLayout::GENERAL,
+			Usage::Uniform | Usage::Vertex | Usage::Indirect => vk::ImageLayout::UNDEFINED,
+			Usage::TransferSrc => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+			Usage::TransferDst => vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+			Usage::Present => vk::ImageLayout::PRESENT_SRC_KHR,
+		}
+	}
+
+	fn access(self) -> vk::AccessFlags {
+		match self {
+			Usage::Acquired | Usage::Present => vk::AccessFlags::empty(),
+			Usage::ColorAttachment => vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+			Usage::DepthAttachment => {
+				vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+			},
+			Usage::Sampled | Usage::StorageRead => vk::AccessFlags::SHADER_READ,
+			Usage::StorageWrite => vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+			Usage::Uniform => vk::AccessFlags::UNIFORM_READ,
+			Usage::Vertex => vk::AccessFlags::VERTEX_ATTRIBUTE_READ | vk::AccessFlags::INDEX_READ,
+			Usage::Indirect => vk::AccessFlags::INDIRECT_COMMAND_READ,
+			Usage::TransferSrc => vk::AccessFlags::TRANSFER_READ,
+			Usage::TransferDst => vk::AccessFlags::TRANSFER_WRITE,
+			Usage::HostRead => vk::AccessFlags::HOST_READ,
+		}
+	}
+
+	fn stages(self) -> vk::PipelineStageFlags {
+		let shaders = vk::PipelineStageFlags::VERTEX_SHADER
+			| vk::PipelineStageFlags::FRAGMENT_SHADER
+			| vk::PipelineStageFlags::COMPUTE_SHADER;
+		match self {
+			Usage::Acquired | Usage::ColorAttachment => vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+			Usage::DepthAttachment => {
+				vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+			},
+			Usage::Sampled | Usage::StorageRead | Usage::StorageWrite | Usage::Uniform => shaders,
+			Usage::Vertex => vk::PipelineStageFlags::VERTEX_INPUT,
+			Usage::Indirect => vk::PipelineStageFlags::DRAW_INDIRECT,
+			Usage::TransferSrc | Usage::TransferDst => vk::PipelineStageFlags::TRANSFER,
+			Usage::HostRead => vk::PipelineStageFlags::HOST,
+			Usage::Present => vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+		}
+	}
+
+	fn writes(self) -> bool {
+		matches!(self, Usage::ColorAttachment | Usage::DepthAttachment | Usage::StorageWrite | Usage::TransferDst)
+	}
+}
+
+/// An image added to a `RenderGraph`, for declaring how passes use it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageId(usize);
+
+/// A buffer added to a `RenderGraph`, for declaring how passes use it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferId(usize);
+
+/// One step of a `RenderGraph`: the commands it records, and the images and buffers they use.
+pub struct Pass<'a> {
+	name: &'static str,
+	uses: Vec<(usize, Usage)>,
+	record: Box<dyn FnOnce(CommandBufferBuilder<B0>) -> CommandBufferBuilder<B0> + 'a>,
+}
+impl<'a> Pass<'a> {
+	/// `record` gets the command buffer after the barriers for this pass, inside a debug label named `name`.
+	pub fn new(
+		name: &'static str,
+		record: impl FnOnce(CommandBufferBuilder<B0>) -> CommandBufferBuilder<B0> + 'a,
+	) -> Self {
+		Self { name, uses: vec![], record: Box::new(record) }
+	}
+
+	/// Each image should only be declared once per pass, with the usage that covers everything the pass does with it.
+	pub fn image(mut self, image: ImageId, usage: Usage) -> Self {
+		debug_assert!(self.uses.iter().all(|&(idx, _)| idx != image.0), "{} uses an image twice", self.name);
+		self.uses.push((image.0, usage));
+		self
+	}
+
+	/// Each buffer should only be declared once per pass, with the usage that covers everything the pass does with it.
+	pub fn buffer(mut self, buffer: BufferId, usage: Usage) -> Self {
+		debug_assert!(self.uses.iter().all(|&(idx, _)| idx != buffer.0), "{} uses a buffer twice", self.name);
+		self.uses.push((buffer.0, usage));
+		self
+	}
+}
+
+enum Resource {
+	Image(Arc<dyn ImageAbstract>),
+	Buffer(Arc<dyn BufferAbstract>),
+}
+
+struct Tracked {
+	resource: Resource,
+	state: State,
+	/// How it's used after the graph, if anything needs it ready.
+	export: Option<Usage>,
+}
+
+/// The passes for one command buffer. Build a new graph for every frame, since the passes borrow what they record.
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+	resources: Vec<Tracked>,
+	passes: Vec<Pass<'a>>,
+}
+impl<'a> RenderGraph<'a> {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// `last_use` is how the image was used before the graph, or `None` if nothing is still using it and its contents
+	/// can be discarded.
+	pub fn import_image(&mut self, image: Arc<dyn ImageAbstract>, last_use: Option<Usage>) -> ImageId {
+		let state = State::new(last_use, true);
+		self.resources.push(Tracked { resource: Resource::Image(image), state, export: None });
+		ImageId(self.resources.len() - 1)
+	}
+
+	/// Like `import_image`, but the contents are discarded even if `last_use` is known, like for an image that's
+	/// redrawn every frame. Whatever `last_use` was still finishes first.
+	pub fn import_discarded_image(&mut self, image: Arc<dyn ImageAbstract>, last_use: Option<Usage>) -> ImageId {
+		let mut state = State::new(last_use, true);
+		state.layout = vk::ImageLayout::UNDEFINED;
+		self.resources.push(Tracked { resource: Resource::Image(image), state, export: None });
+		ImageId(self.resources.len() - 1)
+	}
+
+	/// `last_use` is how the buffer was used before the graph, or `None` if nothing is still using it.
+	pub fn import_buffer(&mut self, buffer: Arc<dyn BufferAbstract>, last_use: Option<Usage>) -> BufferId {
+		let state = State::new(last_use, false);
+		self.resources.push(Tracked { resource: Resource::Buffer(buffer), state, export: None });
+		BufferId(self.resources.len() - 1)
+	}
+
+	/// Leaves the image ready for `usage` after the last pass, like `Present` for a swapchain image.
+	pub fn export_image(&mut self, image: ImageId, usage: Usage) {
+		self.resources[image.0].export = Some(usage);
+	}
+
+	/// Leaves the buffer ready for `usage` after the last pass, like `HostRead` for reading it back.
+	pub fn export_buffer(&mut self, buffer: BufferId, usage: Usage) {
+		self.resources[buffer.0].export = Some(usage);
+	}
+
+	pub fn add_pass(&mut self, pass: Pass<'a>) {
+		self.passes.push(pass);
+	}
+
+	/// Records every pass into `cmd`, each after the barriers it needs, then the barriers for the exports.
+	pub fn record(mut self, mut cmd: CommandBufferBuilder<B0>) -> CommandBufferBuilder<B0> {
+		let order = self.order();
+		let mut passes = self.passes.drain(..).map(Some).collect::<Vec<_>>();
+		for idx in order {
+			let pass = passes[idx].take().unwrap();
+			cmd = self.barriers(cmd, &pass.uses);
+			cmd = (pass.record)(cmd.begin_label(pass.name)).end_label();
+		}
+
+		let exports = self
+			.resources
+			.iter()
+			.enumerate()
+			.filter_map(|(idx, tracked)| tracked.export.map(|usage| (idx, usage)))
+			.collect::<Vec<_>>();
+		self.barriers(cmd, &exports)
+	}
+
+	/// The order to record the passes in. Every pass that writes a resource runs before every pass that only reads
+	/// it, and the writers run in the order they were added. Otherwise passes keep the order they were added in.
+	fn order(&self) -> Vec<usize> {
+		let mut after = vec![vec![]; self.passes.len()];
+		let mut waiting_on = vec![0; self.passes.len()];
+		for resource in 0..self.resources.len() {
+			let uses = self.passes.iter().enumerate().filter_map(|(idx, pass)| {
+				pass.uses.iter().find(|&&(other, _)| other == resource).map(|&(_, usage)| (idx, usage))
+			});
+			let (writers, readers): (Vec<_>, Vec<_>) = uses.partition(|(_, usage)| usage.writes());
+			for (i, &(writer, _)) in writers.iter().enumerate() {
+				let later = writers[i + 1..].iter().take(1).chain(&readers);
+				for &(pass, _) in later {
+					after[writer].push(pass);
+					waiting_on[pass] += 1;
+				}
+			}
+		}
+
+		let mut order = Vec::with_capacity(self.passes.len());
+		while order.len() < self.passes.len() {
+			let next = (0..self.passes.len()).find(|&idx| waiting_on[idx] == 0 && !order.contains(&idx));
+			let next = next.unwrap_or_else(|| panic!("render graph passes depend on each other in a cycle"));
+			order.push(next);
+			for &pass in &after[next] {
+				waiting_on[pass] -= 1;
+			}
+		}
+		order
+	}
+
+	/// Moves each resource in `uses` to its usage, with one barrier for all of them.
+	fn barriers(&mut self, cmd: CommandBufferBuilder<B0>, uses: &[(usize, Usage)]) -> CommandBufferBuilder<B0> {
+		let (mut src_stages, mut dst_stages) = (vk::PipelineStageFlags::empty(), vk::PipelineStageFlags::empty());
+		let (mut images, mut buffers) = (vec![], vec![]);
+		for &(idx, usage) in uses {
+			let tracked = &mut self.resources[idx];
+			let is_image = match tracked.resource {
+				Resource::Image(_) => true,
+				Resource::Buffer(_) => false,
+			};
+			let barrier = match tracked.state.transition(usage, is_image) {
+				Some(barrier) => barrier,
+				None => continue,
+			};
+			src_stages |= barrier.src_stages;
+			dst_stages |= usage.stages();
+			match &tracked.resource {
+				Resource::Image(image) => images.push(
+					ImageBarrier::new(image.clone(), barrier.old_layout, usage.layout())
+						.access(barrier.src_access, usage.access()),
+				),
+				Resource::Buffer(buffer) => {
+					buffers.push(BufferBarrier::new(buffer.clone(), barrier.src_access, usage.access()))
+				},
+			}
+		}
+
+		if images.is_empty() && buffers.is_empty() {
+			return cmd;
+		}
+		cmd.pipeline_barrier(src_stages, dst_stages, images, buffers)
+	}
+}
+
+/// What has to finish before a resource's next use.
+struct Barrier {
+	old_layout: vk::ImageLayout,
+	src_access: vk::AccessFlags,
+	src_stages: vk::PipelineStageFlags,
+}
+
+/// What the GPU has done with a resource as of some point in the graph.
+struct State {
+	layout: vk::ImageLayout,
+	/// The last write, or layout transition, and the stages it happened in.
+	write: Option<(vk::AccessFlags, vk::PipelineStageFlags)>,
+	/// The accesses and stages that already wait for `write`.
+	visible: (vk::AccessFlags, vk::PipelineStageFlags),
+	/// The stages that read it since `write`, which the next write has to wait for.
+	reads: vk::PipelineStageFlags,
+}
+impl State {
+	fn new(last_use: Option<Usage>, is_image: bool) -> Self {
+		let empty = (vk::AccessFlags::empty(), vk::PipelineStageFlags::empty());
+		let last_use = match last_use {
+			Some(last_use) => last_use,
+			None => return Self { layout: vk::ImageLayout::UNDEFINED, write: None, visible: empty, reads: empty.1 },
+		};
+		let layout = if is_image { last_use.layout() } else { vk::ImageLayout::UNDEFINED };
+		if last_use.writes() {
+			Self { layout, write: Some((last_use.access(), last_use.stages())), visible: empty, reads: empty.1 }
+		} else {
+			Self { layout, write: None, visible: empty, reads: last_use.stages() }
+		}
+	}
+
+	/// Moves to `usage`, returning what has to be waited for first if it isn't already.
+	fn transition(&mut self, usage: Usage, is_image: bool) -> Option<Barrier> {
+		let layout = if is_image { usage.layout() } else { self.layout };
+		assert!(!is_image || layout != vk::ImageLayout::UNDEFINED, "images can't be moved to {:?}", usage);
+		let (access, stages) = (usage.access(), usage.stages());
+
+		let relayout = layout != self.layout;
+		let hazard = match self.write {
+			// read after write, unless an earlier barrier already covered this kind of read
+			Some(_) if !usage.writes() => !(self.visible.0.contains(access) && self.visible.1.contains(stages)),
+			// write after write
+			Some(_) => true,
+			// write after read
+			None => usage.writes() && !self.reads.is_empty(),
+		};
+
+		if !relayout && !hazard {
+			if usage.writes() {
+				self.write = Some((access, stages));
+				self.visible = (vk::AccessFlags::empty(), vk::PipelineStageFlags::empty());
+			} else {
+				self.reads |= stages;
+			}
+			return None;
+		}
+
+		let (src_access, write_stages) =
+			self.write.unwrap_or((vk::AccessFlags::empty(), vk::PipelineStageFlags::empty()));
+		let mut src_stages = write_stages | self.reads;
+		if src_stages.is_empty() {
+			src_stages = vk::PipelineStageFlags::TOP_OF_PIPE;
+		}
+		let barrier = Barrier { old_layout: self.layout, src_access, src_stages };
+
+		if usage.writes() {
+			self.write = Some((access, stages));
+			self.visible = (vk::AccessFlags::empty(), vk::PipelineStageFlags::empty());
+			self.reads = vk::PipelineStageFlags::empty();
+		} else if relayout {
+			// the transition is a write that's only visible to this usage, and later reads have to wait for it too
+			self.write = Some((vk::AccessFlags::empty(), stages));
+			self.visible = (access, stages);
+			self.reads = stages;
+		} else {
+			self.visible = (self.visible.0 | access, self.visible.1 | stages);
+			self.reads |= stages;
+		}
+		self.layout = layout;
+		Some(barrier)
+	}
+}
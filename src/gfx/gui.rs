@@ -1,40 +1,611 @@
-use ash::vk;
-use nalgebra::Vector4;
-use std::{collections::HashMap, sync::Arc};
+pub mod anim;
+pub mod button;
+pub mod crosshair;
+pub mod font;
+pub mod image;
+pub mod layout;
+pub mod render;
+pub mod text;
 
-type Color = Vector4<u8>;
+use anim::{Animatable, AnimatedProperty, Tween};
+use layout::Layout;
+use nalgebra::{Vector2, Vector4};
+use render::GuiBatch;
+use std::{
+	cell::{Cell, RefCell},
+	collections::HashMap,
+	sync::Arc,
+	time::Duration,
+};
+use vulkan::{Extent2D, Offset2D, Rect2D};
 
-pub trait Node {}
+pub type Color = Vector4<u8>;
 
+pub trait Node {
+	/// Emits the node's own quads into `batch`, given the rect the layout pass assigned to it. Children are drawn
+	/// afterwards by the document.
+	fn draw(&self, rect: Rect2D, batch: &mut GuiBatch);
+
+	fn children(&self) -> &[Arc<dyn Node>] {
+		&[]
+	}
+
+	/// The width the node's content needs at the given UI scale, excluding padding, if it isn't set by its style.
+	fn content_width(&self, _scale: f32) -> Option<u32> {
+		None
+	}
+
+	/// The height the node's content needs when laid out with the given content width, excluding padding.
+	fn content_height(&self, _width: u32, _scale: f32) -> Option<u32> {
+		None
+	}
+
+	/// Reacts to a mouse input, given the rect the node was laid out in. Every node sees every event, so it can
+	/// notice the cursor leaving it.
+	fn pointer_event(&self, _rect: Rect2D, _event: PointerEvent, _events: &mut Vec<GuiEvent>) {}
+
+	/// Reports and clears whatever changed about the node since the last call.
+	fn take_change(&self) -> Change {
+		Change::None
+	}
+
+	fn style(&self) -> &Styles;
+}
+
+/// How much of the document has to be redone after a node changes. Ordered from least to most work.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+	None,
+	/// The node looks different but takes up the same space.
+	Redraw,
+	/// The node's size may have changed, so it and its siblings have to be laid out again.
+	Relayout,
+}
+
+/// Accumulates changes for nodes that mutate through a shared reference.
+#[derive(Debug)]
+pub struct ChangeFlag(Cell<Change>);
+impl ChangeFlag {
+	pub fn new() -> Self {
+		Self(Cell::new(Change::None))
+	}
+
+	pub fn set(&self, change: Change) {
+		self.0.set(self.0.get().max(change));
+	}
+
+	pub fn take(&self) -> Change {
+		self.0.replace(Change::None)
+	}
+}
+impl Default for ChangeFlag {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// A mouse input, in the document's pixel coordinates.
+#[derive(Clone, Copy, Debug)]
+pub enum PointerEvent {
+	Moved(Offset2D),
+	Exited,
+	Pressed(Offset2D),
+	Released(Offset2D),
+}
+
+/// Something a node wants the rest of the game to respond to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GuiEvent {
+	Clicked(&'static str),
+}
+
+/// A retained tree of nodes. Layouts and quads are cached per node and only redone for the parts that changed.
 pub struct Document {
 	body: Vec<Arc<dyn Node>>,
-	rect: vk::Rect2D,
+	style: Styles,
+	rect: Rect2D,
+	scale: f32,
+	layout: Vec<Layout>,
+	batch: GuiBatch,
+	dirty: bool,
+	tweens: Vec<(Arc<dyn Node>, Tween)>,
+	cursor: Option<Offset2D>,
+	events: Vec<GuiEvent>,
+}
+impl Document {
+	pub fn new(rect: Rect2D) -> Self {
+		Self {
+			body: vec![],
+			style: Styles::default(),
+			rect,
+			scale: 1.0,
+			layout: vec![],
+			batch: GuiBatch::new(rect.extent, 1.0),
+			dirty: true,
+			tweens: vec![],
+			cursor: None,
+			events: vec![],
+		}
+	}
+
+	pub fn push(&mut self, node: Arc<dyn Node>) {
+		self.body.push(node);
+		self.relayout();
+	}
+
+	/// Takes `node` out of the body, along with any tweens running on it.
+	pub fn remove(&mut self, node: &Arc<dyn Node>) {
+		let len = self.body.len();
+		self.body.retain(|other| !ptr_eq(other, node));
+		self.tweens.retain(|(other, _)| !ptr_eq(other, node));
+		if self.body.len() != len {
+			self.relayout();
+		}
+	}
+
+	pub fn rect(&self) -> Rect2D {
+		self.rect
+	}
+
+	pub fn set_rect(&mut self, rect: Rect2D) {
+		self.rect = rect;
+		self.relayout();
+	}
+
+	/// The factor pixel sizes in styles and font sizes are multiplied by.
+	pub fn scale(&self) -> f32 {
+		self.scale
+	}
+
+	pub fn set_scale(&mut self, scale: f32) {
+		if scale != self.scale {
+			self.scale = scale;
+			self.relayout();
+		}
+	}
+
+	/// The style of the document itself, which lays out the body like any other container.
+	pub fn style(&self) -> &Styles {
+		&self.style
+	}
+
+	pub fn set_style(&mut self, style: Styles) {
+		self.style = style;
+		self.relayout();
+	}
+
+	/// Starts animating one of `node`'s style properties, replacing any tween already running on that property.
+	pub fn animate(&mut self, node: Arc<dyn Node>, tween: Tween) {
+		self.tweens.retain(|(other, other_tween)| !ptr_eq(other, &node) || other_tween.property != tween.property);
+		self.tweens.push((node, tween));
+	}
+
+	/// Advances running tweens by the time since the last frame. Finished tweens leave their final value in place.
+	pub fn tick(&mut self, delta: Duration) {
+		for (node, tween) in &mut self.tweens {
+			let value = tween.tick(delta);
+			node.style().set_animated(tween.property, value);
+		}
+		self.tweens.retain(|(_, tween)| !tween.is_finished());
+	}
+
+	/// Applies node changes, then redraws whatever isn't cached. Returns whether the document's quads changed since
+	/// the last call.
+	pub fn update(&mut self, image_extent: Extent2D) -> bool {
+		let extent = self.batch.image_extent;
+		if image_extent.width != extent.width || image_extent.height != extent.height {
+			// quads are in normalized device coordinates, so they all move
+			self.relayout();
+			self.batch.image_extent = image_extent;
+		}
+
+		self.dirty |= update_tree(&self.body, &self.style, self.rect, self.scale, &mut self.layout);
+		if !self.dirty {
+			return false;
+		}
+		self.dirty = false;
+
+		let mut batch = GuiBatch::new(image_extent, self.scale);
+		draw_tree(&self.body, &mut self.layout, Vector2::zeros(), 1.0, &mut batch);
+		self.batch = batch;
+		true
+	}
+
+	/// The quads for the whole document as of the last `update`.
+	pub fn batch(&self) -> &GuiBatch {
+		&self.batch
+	}
+
+	pub fn cursor_moved(&mut self, pos: Offset2D) {
+		self.cursor = Some(pos);
+		self.pointer_event(PointerEvent::Moved(pos));
+	}
+
+	pub fn cursor_left(&mut self) {
+		self.cursor = None;
+		self.pointer_event(PointerEvent::Exited);
+	}
+
+	/// Handles the primary mouse button going down or up at the last known cursor position.
+	pub fn mouse_input(&mut self, pressed: bool) {
+		if let Some(pos) = self.cursor {
+			self.pointer_event(if pressed { PointerEvent::Pressed(pos) } else { PointerEvent::Released(pos) });
+		}
+	}
+
+	/// Returns the events raised by nodes since the last call.
+	pub fn drain_events(&mut self) -> impl Iterator<Item = GuiEvent> + '_ {
+		self.events.drain(..)
+	}
+
+	fn relayout(&mut self) {
+		self.layout = layout::layout_children(&self.body, &self.style, self.rect, self.scale);
+		self.dirty = true;
+	}
+
+	fn pointer_event(&mut self, event: PointerEvent) {
+		pointer_event_tree(&self.body, &self.layout, self.scale, Vector2::zeros(), event, &mut self.events);
+	}
 }
 
 pub struct DivElement {
 	children: Vec<Arc<dyn Node>>,
 	style: Styles,
 }
-impl Node for DivElement {}
+impl DivElement {
+	pub fn new(style: Styles) -> Self {
+		Self { children: vec![], style }
+	}
+
+	pub fn with_child(mut self, child: Arc<dyn Node>) -> Self {
+		self.children.push(child);
+		self
+	}
+}
+impl Node for DivElement {
+	fn draw(&self, rect: Rect2D, batch: &mut GuiBatch) {
+		if let Some(color) = self.style.background_color() {
+			batch.quad(rect, color);
+		}
+	}
+
+	fn children(&self) -> &[Arc<dyn Node>] {
+		&self.children
+	}
+
+	fn style(&self) -> &Styles {
+		&self.style
+	}
+}
+
+/// Whether `pos` lies inside `rect`.
+pub fn hit_test(rect: Rect2D, pos: Offset2D) -> bool {
+	pos.x >= rect.offset.x
+		&& pos.y >= rect.offset.y
+		&& pos.x < rect.offset.x + rect.extent.width as i32
+		&& pos.y < rect.offset.y + rect.extent.height as i32
+}
+
+/// Collects changes from `nodes` and their descendants. Nodes that need a redraw lose their cached quads, and
+/// siblings of nodes that need a relayout are laid out again inside the parent's existing rect.
+fn update_tree(nodes: &[Arc<dyn Node>], style: &Styles, rect: Rect2D, scale: f32, layouts: &mut Vec<Layout>) -> bool {
+	let mut changed = false;
+	let mut relayout = false;
+	for (node, layout) in nodes.iter().zip(layouts.iter_mut()) {
+		match node.take_change() {
+			Change::None => (),
+			Change::Redraw => {
+				layout.cache = None;
+				changed = true;
+			},
+			Change::Relayout => relayout = true,
+		}
+		// opacity and translation are inherited, so the whole subtree is redrawn
+		if node.style().take_animated_change() {
+			clear_caches(layout);
+			changed = true;
+		}
+		changed |= update_tree(node.children(), node.style(), layout.rect, scale, &mut layout.children);
+	}
+
+	if relayout {
+		*layouts = layout::layout_children(nodes, style, rect, scale);
+	}
+	changed || relayout
+}
+
+fn clear_caches(layout: &mut Layout) {
+	layout.cache = None;
+	for child in &mut layout.children {
+		clear_caches(child);
+	}
+}
 
-struct Styles {
+/// Appends each node's quads to `batch`, drawing those that aren't cached. `translate` and `opacity` are inherited
+/// from ancestors.
+fn draw_tree(
+	nodes: &[Arc<dyn Node>],
+	layout: &mut [Layout],
+	translate: Vector2<f32>,
+	opacity: f32,
+	batch: &mut GuiBatch,
+) {
+	for (node, layout) in nodes.iter().zip(layout) {
+		let style = node.style();
+		let translate = translate + style.translate() * batch.scale();
+		let opacity = opacity * style.opacity();
+
+		let rect = translate_rect(layout.rect, translate);
+		let cache = layout.cache.get_or_insert_with(|| {
+			let mut cache = GuiBatch::new(batch.image_extent, batch.scale());
+			cache.opacity = opacity;
+			node.draw(rect, &mut cache);
+			cache
+		});
+		batch.append(cache);
+		draw_tree(node.children(), &mut layout.children, translate, opacity, batch);
+	}
+}
+
+fn pointer_event_tree(
+	nodes: &[Arc<dyn Node>],
+	layout: &[Layout],
+	scale: f32,
+	translate: Vector2<f32>,
+	event: PointerEvent,
+	events: &mut Vec<GuiEvent>,
+) {
+	for (node, layout) in nodes.iter().zip(layout) {
+		let translate = translate + node.style().translate() * scale;
+		node.pointer_event(translate_rect(layout.rect, translate), event, events);
+		pointer_event_tree(node.children(), &layout.children, scale, translate, event, events);
+	}
+}
+
+fn translate_rect(rect: Rect2D, translate: Vector2<f32>) -> Rect2D {
+	let mut rect = rect;
+	rect.offset.x += translate.x.round() as i32;
+	rect.offset.y += translate.y.round() as i32;
+	rect
+}
+
+fn ptr_eq(a: &Arc<dyn Node>, b: &Arc<dyn Node>) -> bool {
+	&**a as *const dyn Node as *const () == &**b as *const dyn Node as *const ()
+}
+
+/// A length that is either absolute or relative to the size of the parent's content box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Size {
+	/// Pixels at a UI scale of 1.
+	Px(u32),
+	Percent(f32),
+}
+impl Size {
+	pub fn resolve(self, parent: u32, scale: f32) -> u32 {
+		match self {
+			Size::Px(px) => (px as f32 * scale).round() as u32,
+			Size::Percent(percent) => (parent as f32 * percent / 100.0) as u32,
+		}
+	}
+}
+impl From<u32> for Size {
+	fn from(px: u32) -> Self {
+		Size::Px(px)
+	}
+}
+
+/// Pixel widths for each side of a box, used for padding and margins.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Edges {
+	pub top: u32,
+	pub right: u32,
+	pub bottom: u32,
+	pub left: u32,
+}
+impl Edges {
+	pub fn all(px: u32) -> Self {
+		Self { top: px, right: px, bottom: px, left: px }
+	}
+
+	pub fn scaled(&self, scale: f32) -> Self {
+		let scale = |px: u32| (px as f32 * scale).round() as u32;
+		Self { top: scale(self.top), right: scale(self.right), bottom: scale(self.bottom), left: scale(self.left) }
+	}
+
+	pub fn horizontal(&self) -> u32 {
+		self.left + self.right
+	}
+
+	pub fn vertical(&self) -> u32 {
+		self.top + self.bottom
+	}
+
+	/// Returns `rect` with the edges removed from each side.
+	pub fn shrink(&self, rect: Rect2D) -> Rect2D {
+		let mut rect = rect;
+		rect.offset.x += self.left as i32;
+		rect.offset.y += self.top as i32;
+		rect.extent.width = rect.extent.width.saturating_sub(self.horizontal());
+		rect.extent.height = rect.extent.height.saturating_sub(self.vertical());
+		rect
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlexDirection {
+	Row,
+	Column,
+}
+
+/// Style properties of a node. Animated values are set through a shared reference by tweens and override the ones
+/// set directly.
+#[derive(Default)]
+pub struct Styles {
 	map: HashMap<StyleName, StyleValue>,
+	animated: RefCell<HashMap<StyleName, StyleValue>>,
+	animated_changed: Cell<bool>,
 }
 impl Styles {
-	fn background_color(&self) -> Option<Color> {
-		self.map.get(&StyleName::BackgroundColor).map(|x| unsafe { x.color })
+	pub fn background_color(&self) -> Option<Color> {
+		self.get(StyleName::BackgroundColor).map(|x| unsafe { x.color })
 	}
 
-	fn set_background_color(&mut self, color: Color) {
+	pub fn set_background_color(&mut self, color: Color) {
 		self.map.insert(StyleName::BackgroundColor, StyleValue { color });
 	}
+
+	pub fn width(&self) -> Option<Size> {
+		self.size(StyleName::Width)
+	}
+
+	pub fn set_width(&mut self, size: impl Into<Size>) {
+		self.map.insert(StyleName::Width, StyleValue { size: size.into() });
+	}
+
+	pub fn height(&self) -> Option<Size> {
+		self.size(StyleName::Height)
+	}
+
+	pub fn set_height(&mut self, size: impl Into<Size>) {
+		self.map.insert(StyleName::Height, StyleValue { size: size.into() });
+	}
+
+	pub fn min_width(&self) -> Option<Size> {
+		self.size(StyleName::MinWidth)
+	}
+
+	pub fn set_min_width(&mut self, size: impl Into<Size>) {
+		self.map.insert(StyleName::MinWidth, StyleValue { size: size.into() });
+	}
+
+	pub fn max_width(&self) -> Option<Size> {
+		self.size(StyleName::MaxWidth)
+	}
+
+	pub fn set_max_width(&mut self, size: impl Into<Size>) {
+		self.map.insert(StyleName::MaxWidth, StyleValue { size: size.into() });
+	}
+
+	pub fn min_height(&self) -> Option<Size> {
+		self.size(StyleName::MinHeight)
+	}
+
+	pub fn set_min_height(&mut self, size: impl Into<Size>) {
+		self.map.insert(StyleName::MinHeight, StyleValue { size: size.into() });
+	}
+
+	pub fn max_height(&self) -> Option<Size> {
+		self.size(StyleName::MaxHeight)
+	}
+
+	pub fn set_max_height(&mut self, size: impl Into<Size>) {
+		self.map.insert(StyleName::MaxHeight, StyleValue { size: size.into() });
+	}
+
+	pub fn padding(&self) -> Edges {
+		self.get(StyleName::Padding).map(|x| unsafe { x.edges }).unwrap_or_default()
+	}
+
+	pub fn set_padding(&mut self, edges: Edges) {
+		self.map.insert(StyleName::Padding, StyleValue { edges });
+	}
+
+	pub fn margin(&self) -> Edges {
+		self.get(StyleName::Margin).map(|x| unsafe { x.edges }).unwrap_or_default()
+	}
+
+	pub fn set_margin(&mut self, edges: Edges) {
+		self.map.insert(StyleName::Margin, StyleValue { edges });
+	}
+
+	/// The axis children are laid out along. Defaults to `Column`.
+	pub fn flex_direction(&self) -> FlexDirection {
+		self.get(StyleName::FlexDirection).map(|x| unsafe { x.direction }).unwrap_or(FlexDirection::Column)
+	}
+
+	pub fn set_flex_direction(&mut self, direction: FlexDirection) {
+		self.map.insert(StyleName::FlexDirection, StyleValue { direction });
+	}
+
+	/// How much of the parent's free space the node takes, relative to its siblings. Nodes with no size of their
+	/// own grow by 1 unless this is set.
+	pub fn flex_grow(&self) -> Option<f32> {
+		self.get(StyleName::FlexGrow).map(|x| unsafe { x.factor })
+	}
+
+	pub fn set_flex_grow(&mut self, factor: f32) {
+		self.map.insert(StyleName::FlexGrow, StyleValue { factor });
+	}
+
+	/// Multiplies the alpha of the node and its descendants. Defaults to 1.
+	pub fn opacity(&self) -> f32 {
+		self.get(StyleName::Opacity).map(|x| unsafe { x.factor }).unwrap_or(1.0)
+	}
+
+	pub fn set_opacity(&mut self, opacity: f32) {
+		self.map.insert(StyleName::Opacity, StyleValue { factor: opacity });
+	}
+
+	/// Moves the node and its descendants from where layout put them, in pixels at a UI scale of 1. It doesn't
+	/// affect the layout of anything else.
+	pub fn translate(&self) -> Vector2<f32> {
+		self.get(StyleName::Translate).map(|x| unsafe { x.offset }).unwrap_or_else(Vector2::zeros)
+	}
+
+	pub fn set_translate(&mut self, offset: Vector2<f32>) {
+		self.map.insert(StyleName::Translate, StyleValue { offset });
+	}
+
+	pub(super) fn set_animated(&self, property: AnimatedProperty, value: Animatable) {
+		let (name, value) = match (property, value) {
+			(AnimatedProperty::Opacity, Animatable::Factor(factor)) => (StyleName::Opacity, StyleValue { factor }),
+			(AnimatedProperty::BackgroundColor, Animatable::Color(color)) => {
+				(StyleName::BackgroundColor, StyleValue { color })
+			},
+			(AnimatedProperty::Translate, Animatable::Offset(offset)) => (StyleName::Translate, StyleValue { offset }),
+			_ => panic!("{:?} can't be animated to {:?}", property, value),
+		};
+		self.animated.borrow_mut().insert(name, value);
+		self.animated_changed.set(true);
+	}
+
+	/// Returns whether an animated value changed since the last call.
+	fn take_animated_change(&self) -> bool {
+		self.animated_changed.replace(false)
+	}
+
+	fn get(&self, name: StyleName) -> Option<StyleValue> {
+		self.animated.borrow().get(&name).or_else(|| self.map.get(&name)).copied()
+	}
+
+	fn size(&self, name: StyleName) -> Option<Size> {
+		self.get(name).map(|x| unsafe { x.size })
+	}
 }
 
 #[derive(PartialEq, Eq, Hash)]
 enum StyleName {
 	BackgroundColor,
+	Width,
+	Height,
+	MinWidth,
+	MaxWidth,
+	MinHeight,
+	MaxHeight,
+	Padding,
+	Margin,
+	FlexDirection,
+	FlexGrow,
+	Opacity,
+	Translate,
 }
 
+#[derive(Clone, Copy)]
 union StyleValue {
 	color: Color,
+	size: Size,
+	edges: Edges,
+	direction: FlexDirection,
+	factor: f32,
+	offset: Vector2<f32>,
 }
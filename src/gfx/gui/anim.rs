@@ -0,0 +1,109 @@
+use crate::gfx::gui::Color;
+use nalgebra::Vector2;
+use std::time::Duration;
+
+/// Maps linear progress through a tween onto the curve values follow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Easing {
+	Linear,
+	EaseIn,
+	EaseOut,
+	EaseInOut,
+}
+impl Easing {
+	/// Takes and returns a value from 0 to 1.
+	pub fn apply(self, t: f32) -> f32 {
+		match self {
+			Easing::Linear => t,
+			Easing::EaseIn => t * t * t,
+			Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+			Easing::EaseInOut => {
+				if t < 0.5 {
+					4.0 * t * t * t
+				} else {
+					1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+				}
+			},
+		}
+	}
+}
+
+/// A style property that tweens can drive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnimatedProperty {
+	Opacity,
+	BackgroundColor,
+	Translate,
+}
+
+/// A value of an animated property.
+#[derive(Clone, Copy, Debug)]
+pub enum Animatable {
+	Factor(f32),
+	Color(Color),
+	Offset(Vector2<f32>),
+}
+impl Animatable {
+	fn lerp(self, to: Self, t: f32) -> Self {
+		match (self, to) {
+			(Animatable::Factor(a), Animatable::Factor(b)) => Animatable::Factor(a + (b - a) * t),
+			(Animatable::Color(a), Animatable::Color(b)) => {
+				let a = a.map(|x| x as f32);
+				let b = b.map(|x| x as f32);
+				Animatable::Color((a + (b - a) * t).map(|x| x.round().max(0.0).min(255.0) as u8))
+			},
+			(Animatable::Offset(a), Animatable::Offset(b)) => Animatable::Offset(a + (b - a) * t),
+			(a, b) => panic!("can't interpolate between {:?} and {:?}", a, b),
+		}
+	}
+}
+
+/// Moves a style property from one value to another over time.
+#[derive(Clone, Debug)]
+pub struct Tween {
+	pub(super) property: AnimatedProperty,
+	from: Animatable,
+	to: Animatable,
+	duration: Duration,
+	elapsed: Duration,
+	easing: Easing,
+}
+impl Tween {
+	pub fn opacity(from: f32, to: f32, duration: Duration) -> Self {
+		Self::new(AnimatedProperty::Opacity, Animatable::Factor(from), Animatable::Factor(to), duration)
+	}
+
+	pub fn background_color(from: Color, to: Color, duration: Duration) -> Self {
+		Self::new(AnimatedProperty::BackgroundColor, Animatable::Color(from), Animatable::Color(to), duration)
+	}
+
+	/// Slides between two offsets from the node's laid out position, in pixels at a UI scale of 1.
+	pub fn translate(from: Vector2<f32>, to: Vector2<f32>, duration: Duration) -> Self {
+		Self::new(AnimatedProperty::Translate, Animatable::Offset(from), Animatable::Offset(to), duration)
+	}
+
+	fn new(property: AnimatedProperty, from: Animatable, to: Animatable, duration: Duration) -> Self {
+		Self { property, from, to, duration, elapsed: Duration::default(), easing: Easing::EaseInOut }
+	}
+
+	/// Defaults to `Easing::EaseInOut`.
+	pub fn with_easing(mut self, easing: Easing) -> Self {
+		self.easing = easing;
+		self
+	}
+
+	pub fn is_finished(&self) -> bool {
+		self.elapsed >= self.duration
+	}
+
+	/// Advances the tween by `delta` and returns the property's new value.
+	pub(super) fn tick(&mut self, delta: Duration) -> Animatable {
+		self.elapsed = (self.elapsed + delta).min(self.duration);
+		let t = if self.duration == Duration::default() {
+			1.0
+		} else {
+			self.elapsed.as_secs_f32() / self.duration.as_secs_f32()
+		};
+		self.from.lerp(self.to, self.easing.apply(t))
+	}
+}
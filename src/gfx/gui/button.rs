@@ -0,0 +1,126 @@
+use crate::gfx::gui::{hit_test, render::GuiBatch, Change, ChangeFlag, Color, GuiEvent, Node, PointerEvent, Styles};
+use std::{cell::Cell, sync::Arc};
+use vulkan::Rect2D;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ButtonState {
+	Idle,
+	Hovered,
+	Pressed,
+}
+
+/// A clickable block. Raises `GuiEvent::Clicked(id)` when pressed and released inside its rect.
+pub struct ButtonElement {
+	id: &'static str,
+	children: Vec<Arc<dyn Node>>,
+	style: Styles,
+	hover_style: Styles,
+	pressed_style: Styles,
+	state: Cell<ButtonState>,
+	change: ChangeFlag,
+	on_click: Option<Box<dyn Fn()>>,
+}
+impl ButtonElement {
+	pub fn new(id: &'static str, style: Styles) -> Self {
+		Self {
+			id,
+			children: vec![],
+			style,
+			hover_style: Styles::default(),
+			pressed_style: Styles::default(),
+			state: Cell::new(ButtonState::Idle),
+			change: ChangeFlag::new(),
+			on_click: None,
+		}
+	}
+
+	pub fn with_child(mut self, child: Arc<dyn Node>) -> Self {
+		self.children.push(child);
+		self
+	}
+
+	/// Styles applied on top of the base style while the cursor is over the button.
+	pub fn with_hover_style(mut self, style: Styles) -> Self {
+		self.hover_style = style;
+		self
+	}
+
+	/// Styles applied on top of the hover style while the button is held down.
+	pub fn with_pressed_style(mut self, style: Styles) -> Self {
+		self.pressed_style = style;
+		self
+	}
+
+	/// Runs `f` on click, in addition to raising the click event.
+	pub fn with_on_click(mut self, f: impl Fn() + 'static) -> Self {
+		self.on_click = Some(Box::new(f));
+		self
+	}
+
+	pub fn is_hovered(&self) -> bool {
+		self.state.get() != ButtonState::Idle
+	}
+
+	pub fn is_pressed(&self) -> bool {
+		self.state.get() == ButtonState::Pressed
+	}
+
+	fn background_color(&self) -> Option<Color> {
+		let pressed = match self.state.get() {
+			ButtonState::Pressed => self.pressed_style.background_color(),
+			_ => None,
+		};
+		let hovered = match self.state.get() {
+			ButtonState::Idle => None,
+			_ => self.hover_style.background_color(),
+		};
+		pressed.or(hovered).or_else(|| self.style.background_color())
+	}
+}
+impl Node for ButtonElement {
+	fn draw(&self, rect: Rect2D, batch: &mut GuiBatch) {
+		if let Some(color) = self.background_color() {
+			batch.quad(rect, color);
+		}
+	}
+
+	fn children(&self) -> &[Arc<dyn Node>] {
+		&self.children
+	}
+
+	fn pointer_event(&self, rect: Rect2D, event: PointerEvent, events: &mut Vec<GuiEvent>) {
+		let state = self.state.get();
+		let new_state = match event {
+			PointerEvent::Moved(pos) => match (state, hit_test(rect, pos)) {
+				(ButtonState::Pressed, true) => ButtonState::Pressed,
+				(_, true) => ButtonState::Hovered,
+				(_, false) => ButtonState::Idle,
+			},
+			PointerEvent::Exited => ButtonState::Idle,
+			PointerEvent::Pressed(pos) if hit_test(rect, pos) => ButtonState::Pressed,
+			PointerEvent::Pressed(_) => ButtonState::Idle,
+			PointerEvent::Released(pos) if hit_test(rect, pos) => {
+				if state == ButtonState::Pressed {
+					events.push(GuiEvent::Clicked(self.id));
+					if let Some(on_click) = &self.on_click {
+						on_click();
+					}
+				}
+				ButtonState::Hovered
+			},
+			PointerEvent::Released(_) => ButtonState::Idle,
+		};
+		if new_state != state {
+			self.state.set(new_state);
+			self.change.set(Change::Redraw);
+		}
+	}
+
+	fn take_change(&self) -> Change {
+		self.change.take()
+	}
+
+	fn style(&self) -> &Styles {
+		&self.style
+	}
+}
@@ -0,0 +1,39 @@
+use crate::gfx::gui::{render::GuiBatch, Color, Node, Styles};
+use vulkan::{Extent2D, Offset2D, Rect2D};
+
+/// A plus sign at the center of the screen. It ignores the rect it's laid out in, so it should be given no size.
+pub struct CrosshairElement {
+	/// The length of each bar in pixels at a UI scale of 1.
+	size: u32,
+	thickness: u32,
+	color: Color,
+	style: Styles,
+}
+impl CrosshairElement {
+	pub fn new(size: u32, thickness: u32, color: Color) -> Self {
+		let mut style = Styles::default();
+		style.set_width(0);
+		style.set_height(0);
+		Self { size, thickness, color, style }
+	}
+}
+impl Node for CrosshairElement {
+	fn draw(&self, _rect: Rect2D, batch: &mut GuiBatch) {
+		let scale = |px: u32| ((px as f32 * batch.scale()).round() as u32).max(1);
+		let size = scale(self.size);
+		let thickness = scale(self.thickness);
+		let center_x = (batch.image_extent.width / 2) as i32;
+		let center_y = (batch.image_extent.height / 2) as i32;
+
+		let bar = |width: u32, height: u32| Rect2D {
+			offset: Offset2D { x: center_x - (width / 2) as i32, y: center_y - (height / 2) as i32 },
+			extent: Extent2D { width, height },
+		};
+		batch.quad(bar(size, thickness), self.color);
+		batch.quad(bar(thickness, size), self.color);
+	}
+
+	fn style(&self) -> &Styles {
+		&self.style
+	}
+}
@@ -0,0 +1,204 @@
+use crate::gfx::{image::Mips, Gfx};
+use ash::vk;
+use nalgebra::Vector2;
+use std::sync::Arc;
+use vulkan::{
+	image::{Image, ImageView},
+	Extent2D,
+};
+
+/// The first character in the atlas.
+const FIRST_CHAR: u8 = b' ';
+/// Glyph bitmaps for printable ASCII, from the public domain font8x8 by Daniel Hepper. Each byte is a row, top to
+/// bottom, with the least significant bit on the left.
+#[rustfmt::skip]
+const GLYPHS: [[u8; 8]; 95] = [
+	[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+	[0x18, 0x3C, 0x3C, 0x18, 0x18, 0x00, 0x18, 0x00], // !
+	[0x36, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // "
+	[0x36, 0x36, 0x7F, 0x36, 0x7F, 0x36, 0x36, 0x00], // #
+	[0x0C, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x0C, 0x00], // $
+	[0x00, 0x63, 0x33, 0x18, 0x0C, 0x66, 0x63, 0x00], // %
+	[0x1C, 0x36, 0x1C, 0x6E, 0x3B, 0x33, 0x6E, 0x00], // &
+	[0x06, 0x06, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00], // '
+	[0x18, 0x0C, 0x06, 0x06, 0x06, 0x0C, 0x18, 0x00], // (
+	[0x06, 0x0C, 0x18, 0x18, 0x18, 0x0C, 0x06, 0x00], // )
+	[0x00, 0x66, 0x3C, 0xFF, 0x3C, 0x66, 0x00, 0x00], // *
+	[0x00, 0x0C, 0x0C, 0x3F, 0x0C, 0x0C, 0x00, 0x00], // +
+	[0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ,
+	[0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00, 0x00], // -
+	[0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x00], // .
+	[0x60, 0x30, 0x18, 0x0C, 0x06, 0x03, 0x01, 0x00], // /
+	[0x3E, 0x63, 0x73, 0x7B, 0x6F, 0x67, 0x3E, 0x00], // 0
+	[0x0C, 0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x3F, 0x00], // 1
+	[0x1E, 0x33, 0x30, 0x1C, 0x06, 0x33, 0x3F, 0x00], // 2
+	[0x1E, 0x33, 0x30, 0x1C, 0x30, 0x33, 0x1E, 0x00], // 3
+	[0x38, 0x3C, 0x36, 0x33, 0x7F, 0x30, 0x78, 0x00], // 4
+	[0x3F, 0x03, 0x1F, 0x30, 0x30, 0x33, 0x1E, 0x00], // 5
+	[0x1C, 0x06, 0x03, 0x1F, 0x33, 0x33, 0x1E, 0x00], // 6
+	[0x3F, 0x33, 0x30, 0x18, 0x0C, 0x0C, 0x0C, 0x00], // 7
+	[0x1E, 0x33, 0x33, 0x1E, 0x33, 0x33, 0x1E, 0x00], // 8
+	[0x1E, 0x33, 0x33, 0x3E, 0x30, 0x18, 0x0E, 0x00], // 9
+	[0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x00], // :
+	[0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ;
+	[0x18, 0x0C, 0x06, 0x03, 0x06, 0x0C, 0x18, 0x00], // <
+	[0x00, 0x00, 0x3F, 0x00, 0x00, 0x3F, 0x00, 0x00], // =
+	[0x06, 0x0C, 0x18, 0x30, 0x18, 0x0C, 0x06, 0x00], // >
+	[0x1E, 0x33, 0x30, 0x18, 0x0C, 0x00, 0x0C, 0x00], // ?
+	[0x3E, 0x63, 0x7B, 0x7B, 0x7B, 0x03, 0x1E, 0x00], // @
+	[0x0C, 0x1E, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x00], // A
+	[0x3F, 0x66, 0x66, 0x3E, 0x66, 0x66, 0x3F, 0x00], // B
+	[0x3C, 0x66, 0x03, 0x03, 0x03, 0x66, 0x3C, 0x00], // C
+	[0x1F, 0x36, 0x66, 0x66, 0x66, 0x36, 0x1F, 0x00], // D
+	[0x7F, 0x46, 0x16, 0x1E, 0x16, 0x46, 0x7F, 0x00], // E
+	[0x7F, 0x46, 0x16, 0x1E, 0x16, 0x06, 0x0F, 0x00], // F
+	[0x3C, 0x66, 0x03, 0x03, 0x73, 0x66, 0x7C, 0x00], // G
+	[0x33, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x33, 0x00], // H
+	[0x1E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // I
+	[0x78, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E, 0x00], // J
+	[0x67, 0x66, 0x36, 0x1E, 0x36, 0x66, 0x67, 0x00], // K
+	[0x0F, 0x06, 0x06, 0x06, 0x46, 0x66, 0x7F, 0x00], // L
+	[0x63, 0x77, 0x7F, 0x7F, 0x6B, 0x63, 0x63, 0x00], // M
+	[0x63, 0x67, 0x6F, 0x7B, 0x73, 0x63, 0x63, 0x00], // N
+	[0x1C, 0x36, 0x63, 0x63, 0x63, 0x36, 0x1C, 0x00], // O
+	[0x3F, 0x66, 0x66, 0x3E, 0x06, 0x06, 0x0F, 0x00], // P
+	[0x1E, 0x33, 0x33, 0x33, 0x3B, 0x1E, 0x38, 0x00], // Q
+	[0x3F, 0x66, 0x66, 0x3E, 0x36, 0x66, 0x67, 0x00], // R
+	[0x1E, 0x33, 0x07, 0x0E, 0x38, 0x33, 0x1E, 0x00], // S
+	[0x3F, 0x2D, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // T
+	[0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3F, 0x00], // U
+	[0x33, 0x33, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // V
+	[0x63, 0x63, 0x63, 0x6B, 0x7F, 0x77, 0x63, 0x00], // W
+	[0x63, 0x63, 0x36, 0x1C, 0x1C, 0x36, 0x63, 0x00], // X
+	[0x33, 0x33, 0x33, 0x1E, 0x0C, 0x0C, 0x1E, 0x00], // Y
+	[0x7F, 0x63, 0x31, 0x18, 0x4C, 0x66, 0x7F, 0x00], // Z
+	[0x1E, 0x06, 0x06, 0x06, 0x06, 0x06, 0x1E, 0x00], // [
+	[0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x40, 0x00], // \
+	[0x1E, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1E, 0x00], // ]
+	[0x08, 0x1C, 0x36, 0x63, 0x00, 0x00, 0x00, 0x00], // ^
+	[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF], // _
+	[0x0C, 0x0C, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00], // `
+	[0x00, 0x00, 0x1E, 0x30, 0x3E, 0x33, 0x6E, 0x00], // a
+	[0x07, 0x06, 0x06, 0x3E, 0x66, 0x66, 0x3B, 0x00], // b
+	[0x00, 0x00, 0x1E, 0x33, 0x03, 0x33, 0x1E, 0x00], // c
+	[0x38, 0x30, 0x30, 0x3E, 0x33, 0x33, 0x6E, 0x00], // d
+	[0x00, 0x00, 0x1E, 0x33, 0x3F, 0x03, 0x1E, 0x00], // e
+	[0x1C, 0x36, 0x06, 0x0F, 0x06, 0x06, 0x0F, 0x00], // f
+	[0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x1F], // g
+	[0x07, 0x06, 0x36, 0x6E, 0x66, 0x66, 0x67, 0x00], // h
+	[0x0C, 0x00, 0x0E, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // i
+	[0x30, 0x00, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E], // j
+	[0x07, 0x06, 0x66, 0x36, 0x1E, 0x36, 0x67, 0x00], // k
+	[0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // l
+	[0x00, 0x00, 0x33, 0x7F, 0x7F, 0x6B, 0x63, 0x00], // m
+	[0x00, 0x00, 0x1F, 0x33, 0x33, 0x33, 0x33, 0x00], // n
+	[0x00, 0x00, 0x1E, 0x33, 0x33, 0x33, 0x1E, 0x00], // o
+	[0x00, 0x00, 0x3B, 0x66, 0x66, 0x3E, 0x06, 0x0F], // p
+	[0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x78], // q
+	[0x00, 0x00, 0x3B, 0x6E, 0x66, 0x06, 0x0F, 0x00], // r
+	[0x00, 0x00, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x00], // s
+	[0x08, 0x0C, 0x3E, 0x0C, 0x0C, 0x2C, 0x18, 0x00], // t
+	[0x00, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6E, 0x00], // u
+	[0x00, 0x00, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // v
+	[0x00, 0x00, 0x63, 0x6B, 0x7F, 0x7F, 0x36, 0x00], // w
+	[0x00, 0x00, 0x63, 0x36, 0x1C, 0x36, 0x63, 0x00], // x
+	[0x00, 0x00, 0x33, 0x33, 0x33, 0x3E, 0x30, 0x1F], // y
+	[0x00, 0x00, 0x3F, 0x19, 0x0C, 0x26, 0x3F, 0x00], // z
+	[0x38, 0x0C, 0x0C, 0x07, 0x0C, 0x0C, 0x38, 0x00], // {
+	[0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00], // |
+	[0x07, 0x0C, 0x0C, 0x38, 0x0C, 0x0C, 0x07, 0x00], // }
+	[0x6E, 0x3B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ~
+];
+
+/// Width and height of a glyph bitmap.
+const GLYPH_SIZE: usize = 8;
+/// Atlas texels per glyph bitmap pixel.
+const TEXELS_PER_PIXEL: usize = 2;
+/// Empty texels around each glyph, so the distance field can fall off outside it.
+const PADDING: usize = 4;
+/// Distance in glyph pixels at which the field saturates.
+const SPREAD: f32 = 2.0;
+const CELL_SIZE: usize = GLYPH_SIZE * TEXELS_PER_PIXEL + PADDING * 2;
+const COLUMNS: usize = 16;
+const ROWS: usize = (GLYPHS.len() + COLUMNS - 1) / COLUMNS;
+const ATLAS_WIDTH: usize = CELL_SIZE * COLUMNS;
+const ATLAS_HEIGHT: usize = CELL_SIZE * ROWS;
+
+/// A signed distance field atlas of the built-in font, where 0.5 is the glyph edge.
+pub struct FontAtlas {
+	_image: Arc<Image>,
+	pub view: Arc<ImageView>,
+}
+impl FontAtlas {
+	pub fn new(gfx: &Gfx) -> Self {
+		let extent = Extent2D { width: ATLAS_WIDTH as _, height: ATLAS_HEIGHT as _ };
+		let (image, _) = gfx.upload_image(vk::Format::R8_UNORM, extent, Mips::Provided(1), &build_sdf());
+		let view = image.create_view();
+
+		gfx.uploads().finish();
+
+		Self { _image: image, view }
+	}
+}
+
+/// The quad drawn for a glyph is larger than the glyph itself by this factor, to include the atlas padding.
+pub const CELL_SCALE: f32 = CELL_SIZE as f32 / (GLYPH_SIZE * TEXELS_PER_PIXEL) as f32;
+
+/// Returns the top left and bottom right texture coordinates of the cell for `ch`. Unsupported characters are
+/// rendered as `?`.
+pub fn glyph_uvs(ch: char) -> (Vector2<f32>, Vector2<f32>) {
+	let idx = glyph_index(ch).unwrap_or_else(|| glyph_index('?').unwrap());
+	let (col, row) = (idx % COLUMNS, idx / COLUMNS);
+	let min =
+		Vector2::new((col * CELL_SIZE) as f32 / ATLAS_WIDTH as f32, (row * CELL_SIZE) as f32 / ATLAS_HEIGHT as f32);
+	let size = Vector2::new(CELL_SIZE as f32 / ATLAS_WIDTH as f32, CELL_SIZE as f32 / ATLAS_HEIGHT as f32);
+	(min, min + size)
+}
+
+fn glyph_index(ch: char) -> Option<usize> {
+	let code = ch as u32;
+	if code >= FIRST_CHAR as u32 && code < FIRST_CHAR as u32 + GLYPHS.len() as u32 {
+		Some((code - FIRST_CHAR as u32) as usize)
+	} else {
+		None
+	}
+}
+
+fn build_sdf() -> Vec<u8> {
+	let mut data = vec![0; ATLAS_WIDTH * ATLAS_HEIGHT];
+
+	for (idx, glyph) in GLYPHS.iter().enumerate() {
+		let filled = |x: i32, y: i32| {
+			x >= 0 && y >= 0 && x < GLYPH_SIZE as i32 && y < GLYPH_SIZE as i32 && (glyph[y as usize] >> x) & 1 == 1
+		};
+
+		let (cell_x, cell_y) = ((idx % COLUMNS) * CELL_SIZE, (idx / COLUMNS) * CELL_SIZE);
+		for ty in 0..CELL_SIZE {
+			for tx in 0..CELL_SIZE {
+				// texel center in glyph pixel space
+				let px = (tx as f32 + 0.5 - PADDING as f32) / TEXELS_PER_PIXEL as f32;
+				let py = (ty as f32 + 0.5 - PADDING as f32) / TEXELS_PER_PIXEL as f32;
+				let inside = filled(px.floor() as i32, py.floor() as i32);
+
+				// distance to the nearest pixel in the opposite state, including the empty border around the bitmap
+				let mut dist = SPREAD;
+				for y in -1..=GLYPH_SIZE as i32 {
+					for x in -1..=GLYPH_SIZE as i32 {
+						if filled(x, y) == inside {
+							continue;
+						}
+						let dx = (x as f32 - px).max(px - (x + 1) as f32).max(0.0);
+						let dy = (y as f32 - py).max(py - (y + 1) as f32).max(0.0);
+						dist = dist.min((dx * dx + dy * dy).sqrt());
+					}
+				}
+
+				let signed = if inside { dist } else { -dist };
+				let value = (0.5 + signed / (SPREAD * 2.0)).max(0.0).min(1.0);
+				data[(cell_y + ty) * ATLAS_WIDTH + cell_x + tx] = (value * 255.0).round() as u8;
+			}
+		}
+	}
+
+	data
+}
@@ -0,0 +1,33 @@
+use crate::gfx::{
+	gui::{render::GuiBatch, Color, Node, Styles},
+	texture::TextureHandle,
+};
+use vulkan::Rect2D;
+
+pub struct ImageElement {
+	texture: TextureHandle,
+	tint: Color,
+	style: Styles,
+}
+impl ImageElement {
+	pub fn new(texture: TextureHandle, style: Styles) -> Self {
+		Self { texture, tint: Color::new(255, 255, 255, 255), style }
+	}
+
+	pub fn with_tint(mut self, tint: Color) -> Self {
+		self.tint = tint;
+		self
+	}
+}
+impl Node for ImageElement {
+	fn draw(&self, rect: Rect2D, batch: &mut GuiBatch) {
+		if let Some(color) = self.style.background_color() {
+			batch.quad(rect, color);
+		}
+		batch.image(rect, self.texture, self.tint);
+	}
+
+	fn style(&self) -> &Styles {
+		&self.style
+	}
+}
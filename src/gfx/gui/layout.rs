@@ -0,0 +1,331 @@
+use crate::gfx::gui::{render::GuiBatch, FlexDirection, Node, Size, Styles};
+use std::sync::Arc;
+use vulkan::{Extent2D, Offset2D, Rect2D};
+
+/// The computed border box of a node, excluding its margin, and the layouts of its children.
+pub struct Layout {
+	pub rect: Rect2D,
+	pub children: Vec<Layout>,
+	/// The node's own quads from the last time it was drawn, or `None` if it needs to be drawn again.
+	pub(super) cache: Option<GuiBatch>,
+}
+
+/// Lays out `nodes` as the children of a container with the given style, which occupies `rect`.
+///
+/// Children are placed one after another along the container's flex direction and stretched across the other axis.
+/// Space left over on the main axis is shared between children by their flex grow factors. Pixel sizes in styles are
+/// multiplied by `scale`.
+pub fn layout_children(nodes: &[Arc<dyn Node>], style: &Styles, rect: Rect2D, scale: f32) -> Vec<Layout> {
+	let inner = style.padding().scaled(scale).shrink(rect);
+	let axis = Axis(style.flex_direction());
+	let main_avail = axis.main(inner.extent);
+	let cross_avail = axis.cross(inner.extent);
+
+	let mut items = nodes
+		.iter()
+		.map(|node| {
+			let style = node.style();
+			let margin = style.margin().scaled(scale);
+			let (main_margin, cross_margin) = match axis.0 {
+				FlexDirection::Row => (margin.horizontal(), margin.vertical()),
+				FlexDirection::Column => (margin.vertical(), margin.horizontal()),
+			};
+
+			let cross = axis
+				.cross_size(style)
+				.map(|size| size.resolve(cross_avail, scale))
+				.unwrap_or_else(|| cross_avail.saturating_sub(cross_margin));
+			let cross = clamp(cross, axis.cross_min(style), axis.cross_max(style), cross_avail, scale);
+
+			let measured = match axis.0 {
+				FlexDirection::Row => measure_width(&**node, scale),
+				FlexDirection::Column => measure_height(&**node, cross, scale),
+			};
+			let main_size = axis.main_size(style).map(|size| size.resolve(main_avail, scale));
+			let (main, grow) = match main_size.or(measured) {
+				Some(main) => (main, style.flex_grow().unwrap_or(0.0)),
+				None => (0, style.flex_grow().unwrap_or(1.0)),
+			};
+
+			Item { main, cross, grow, main_margin }
+		})
+		.collect::<Vec<_>>();
+
+	let used = items.iter().map(|item| item.main + item.main_margin).sum::<u32>();
+	let free = main_avail.saturating_sub(used);
+	let total_grow = items.iter().map(|item| item.grow).sum::<f32>();
+	if free > 0 && total_grow > 0.0 {
+		for item in &mut items {
+			item.main += (free as f32 * item.grow / total_grow) as u32;
+		}
+	}
+
+	let mut pos = 0;
+	nodes
+		.iter()
+		.zip(items)
+		.map(|(node, item)| {
+			let style = node.style();
+			let margin = style.margin().scaled(scale);
+			let main = clamp(item.main, axis.main_min(style), axis.main_max(style), main_avail, scale);
+
+			let rect = match axis.0 {
+				FlexDirection::Row => {
+					let x = inner.offset.x + (pos + margin.left) as i32;
+					let y = inner.offset.y + margin.top as i32;
+					pos += margin.left + main + margin.right;
+					Rect2D { offset: Offset2D { x, y }, extent: Extent2D { width: main, height: item.cross } }
+				},
+				FlexDirection::Column => {
+					let x = inner.offset.x + margin.left as i32;
+					let y = inner.offset.y + (pos + margin.top) as i32;
+					pos += margin.top + main + margin.bottom;
+					Rect2D { offset: Offset2D { x, y }, extent: Extent2D { width: item.cross, height: main } }
+				},
+			};
+
+			Layout { rect, children: layout_children(node.children(), style, rect, scale), cache: None }
+		})
+		.collect()
+}
+
+struct Item {
+	main: u32,
+	cross: u32,
+	grow: f32,
+	main_margin: u32,
+}
+
+/// Picks style properties by whether they're along or across a flex direction.
+#[derive(Clone, Copy)]
+struct Axis(FlexDirection);
+impl Axis {
+	fn main(self, extent: Extent2D) -> u32 {
+		match self.0 {
+			FlexDirection::Row => extent.width,
+			FlexDirection::Column => extent.height,
+		}
+	}
+
+	fn cross(self, extent: Extent2D) -> u32 {
+		match self.0 {
+			FlexDirection::Row => extent.height,
+			FlexDirection::Column => extent.width,
+		}
+	}
+
+	fn main_size(self, style: &Styles) -> Option<Size> {
+		match self.0 {
+			FlexDirection::Row => style.width(),
+			FlexDirection::Column => style.height(),
+		}
+	}
+
+	fn cross_size(self, style: &Styles) -> Option<Size> {
+		match self.0 {
+			FlexDirection::Row => style.height(),
+			FlexDirection::Column => style.width(),
+		}
+	}
+
+	fn main_min(self, style: &Styles) -> Option<Size> {
+		match self.0 {
+			FlexDirection::Row => style.min_width(),
+			FlexDirection::Column => style.min_height(),
+		}
+	}
+
+	fn main_max(self, style: &Styles) -> Option<Size> {
+		match self.0 {
+			FlexDirection::Row => style.max_width(),
+			FlexDirection::Column => style.max_height(),
+		}
+	}
+
+	fn cross_min(self, style: &Styles) -> Option<Size> {
+		match self.0 {
+			FlexDirection::Row => style.min_height(),
+			FlexDirection::Column => style.min_width(),
+		}
+	}
+
+	fn cross_max(self, style: &Styles) -> Option<Size> {
+		match self.0 {
+			FlexDirection::Row => style.max_height(),
+			FlexDirection::Column => style.max_width(),
+		}
+	}
+}
+
+fn clamp(value: u32, min: Option<Size>, max: Option<Size>, parent: u32, scale: f32) -> u32 {
+	let value = max.map(|max| value.min(max.resolve(parent, scale))).unwrap_or(value);
+	min.map(|min| value.max(min.resolve(parent, scale))).unwrap_or(value)
+}
+
+/// The border box width `node` would like, if it can be known without a parent.
+fn measure_width(node: &dyn Node, scale: f32) -> Option<u32> {
+	let style = node.style();
+	if let Some(size @ Size::Px(_)) = style.width() {
+		return Some(size.resolve(0, scale));
+	}
+
+	let content = if node.children().is_empty() {
+		node.content_width(scale)
+	} else {
+		let widths = node.children().iter().map(|child| {
+			measure_width(&**child, scale).map(|width| width + child.style().margin().scaled(scale).horizontal())
+		});
+		match style.flex_direction() {
+			FlexDirection::Row => widths.fold(None, sum),
+			FlexDirection::Column => widths.fold(None, |acc, width| acc.max(width)),
+		}
+	};
+	content.map(|width| width + style.padding().scaled(scale).horizontal())
+}
+
+/// The border box height `node` would like when given a border box `width`, if it can be known without a parent.
+fn measure_height(node: &dyn Node, width: u32, scale: f32) -> Option<u32> {
+	let style = node.style();
+	if let Some(size @ Size::Px(_)) = style.height() {
+		return Some(size.resolve(0, scale));
+	}
+
+	let padding = style.padding().scaled(scale);
+	let inner_width = width.saturating_sub(padding.horizontal());
+	let content = if node.children().is_empty() {
+		node.content_height(inner_width, scale)
+	} else {
+		let heights = node.children().iter().map(|child| {
+			let margin = child.style().margin().scaled(scale);
+			let width = match child.style().width() {
+				Some(size) => size.resolve(inner_width, scale),
+				None => inner_width.saturating_sub(margin.horizontal()),
+			};
+			measure_height(&**child, width, scale).map(|height| height + margin.vertical())
+		});
+		match style.flex_direction() {
+			FlexDirection::Row => heights.fold(None, |acc, height| acc.max(height)),
+			FlexDirection::Column => heights.fold(None, sum),
+		}
+	};
+	content.map(|height| height + padding.vertical())
+}
+
+fn sum(a: Option<u32>, b: Option<u32>) -> Option<u32> {
+	match (a, b) {
+		(Some(a), Some(b)) => Some(a + b),
+		(a, b) => a.or(b),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::gfx::gui::{DivElement, Edges};
+
+	/// A leaf with content of a fixed size, like a line of text.
+	struct Content {
+		width: u32,
+		height: u32,
+		style: Styles,
+	}
+	impl Node for Content {
+		fn draw(&self, _rect: Rect2D, _batch: &mut GuiBatch) {}
+
+		fn content_width(&self, scale: f32) -> Option<u32> {
+			Some((self.width as f32 * scale) as u32)
+		}
+
+		fn content_height(&self, _width: u32, scale: f32) -> Option<u32> {
+			Some((self.height as f32 * scale) as u32)
+		}
+
+		fn style(&self) -> &Styles {
+			&self.style
+		}
+	}
+
+	fn content(width: u32, height: u32, style: Styles) -> Arc<dyn Node> {
+		Arc::new(Content { width, height, style })
+	}
+
+	fn rect(x: i32, y: i32, width: u32, height: u32) -> Rect2D {
+		Rect2D { offset: Offset2D { x, y }, extent: Extent2D { width, height } }
+	}
+
+	/// Each rect as `(x, y, width, height)`, since Vulkan's structs can't be compared.
+	fn rects(layouts: &[Layout]) -> Vec<(i32, i32, u32, u32)> {
+		layouts.iter().map(|layout| unpack(layout.rect)).collect()
+	}
+
+	fn unpack(rect: Rect2D) -> (i32, i32, u32, u32) {
+		(rect.offset.x, rect.offset.y, rect.extent.width, rect.extent.height)
+	}
+
+	#[test]
+	fn column_stacks_and_stretches() {
+		let nodes = [content(10, 20, Styles::default()), content(30, 40, Styles::default())];
+		let layouts = layout_children(&nodes, &Styles::default(), rect(5, 5, 100, 200), 1.0);
+		assert_eq!(rects(&layouts), [(5, 5, 100, 20), (5, 25, 100, 40)]);
+	}
+
+	#[test]
+	fn row_uses_widths() {
+		let mut style = Styles::default();
+		style.set_flex_direction(FlexDirection::Row);
+		let nodes = [content(10, 20, Styles::default()), content(30, 40, Styles::default())];
+		let layouts = layout_children(&nodes, &style, rect(0, 0, 100, 50), 1.0);
+		assert_eq!(rects(&layouts), [(0, 0, 10, 50), (10, 0, 30, 50)]);
+	}
+
+	#[test]
+	fn grow_shares_free_space() {
+		let mut one = Styles::default();
+		one.set_flex_grow(1.0);
+		let mut three = Styles::default();
+		three.set_flex_grow(3.0);
+		let nodes = [content(0, 10, one), content(0, 10, three)];
+		let layouts = layout_children(&nodes, &Styles::default(), rect(0, 0, 100, 100), 1.0);
+		assert_eq!(rects(&layouts), [(0, 0, 100, 30), (0, 30, 100, 70)]);
+	}
+
+	#[test]
+	fn nodes_without_content_fill_the_rest() {
+		let nodes = [content(0, 30, Styles::default()), Arc::new(DivElement::new(Styles::default())) as Arc<dyn Node>];
+		let layouts = layout_children(&nodes, &Styles::default(), rect(0, 0, 100, 100), 1.0);
+		assert_eq!(rects(&layouts), [(0, 0, 100, 30), (0, 30, 100, 70)]);
+	}
+
+	#[test]
+	fn padding_margins_and_scale() {
+		let mut parent = Styles::default();
+		parent.set_padding(Edges::all(5));
+		let mut child = Styles::default();
+		child.set_margin(Edges { top: 2, left: 4, ..Edges::default() });
+		child.set_width(20);
+		let layouts = layout_children(&[content(0, 10, child)], &parent, rect(0, 0, 100, 100), 2.0);
+		// everything in pixels doubles
+		assert_eq!(rects(&layouts), [(18, 14, 40, 20)]);
+	}
+
+	#[test]
+	fn sizes_are_clamped() {
+		let mut style = Styles::default();
+		style.set_height(Size::Percent(80.0));
+		style.set_max_height(50);
+		style.set_min_width(Size::Percent(50.0));
+		style.set_width(10);
+		let layouts = layout_children(&[content(0, 0, style)], &Styles::default(), rect(0, 0, 100, 100), 1.0);
+		assert_eq!(rects(&layouts), [(0, 0, 50, 50)]);
+	}
+
+	#[test]
+	fn children_are_laid_out_inside() {
+		let mut style = Styles::default();
+		style.set_padding(Edges::all(10));
+		let div = DivElement::new(style).with_child(content(0, 5, Styles::default()));
+		let layouts = layout_children(&[Arc::new(div) as Arc<dyn Node>], &Styles::default(), rect(0, 0, 100, 100), 1.0);
+		assert_eq!(unpack(layouts[0].rect), (0, 0, 100, 25));
+		assert_eq!(rects(&layouts[0].children), [(10, 10, 80, 5)]);
+	}
+}
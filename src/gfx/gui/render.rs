@@ -0,0 +1,450 @@
+use crate::gfx::{
+	gui::{
+		font::{glyph_uvs, FontAtlas, CELL_SCALE},
+		Color, Document,
+	},
+	texture::TextureHandle,
+	window::viewport,
+	Gfx,
+};
+use ash::vk;
+use memoffset::offset_of;
+use nalgebra::{Vector2, Vector4};
+use std::{collections::HashMap, iter::once, mem::size_of_val, ops::Range, sync::Arc};
+use typenum::B1;
+use vulkan::{
+	buffer::Buffer,
+	command::{CommandBuffer, CommandPool, InheritanceInfo},
+	descriptor::{
+		DescriptorPool, DescriptorPoolSize, DescriptorSet, DescriptorSetLayout, DescriptorSetLayoutBinding,
+		DescriptorType, ShaderStageFlags,
+	},
+	device::BufferUsageFlags,
+	pipeline::{Pipeline, PipelineLayout, VertexDesc},
+	render_pass::RenderPass,
+	sampler::{Filter, SamplerAddressMode, SamplerMipmapMode},
+	Extent2D, Rect2D,
+};
+
+const MODE_SOLID: u32 = 0;
+const MODE_TEXT: u32 = 1;
+const MODE_IMAGE: u32 = 2;
+/// Descriptor sets per pool. More pools are created as textures are used.
+const SETS_PER_POOL: u32 = 64;
+
+pub struct GuiRenderer {
+	gfx: Arc<Gfx>,
+	_font: FontAtlas,
+	set_layout: Arc<DescriptorSetLayout>,
+	desc_pools: Vec<Arc<DescriptorPool>>,
+	font_set: Arc<DescriptorSet>,
+	texture_sets: HashMap<TextureHandle, Arc<DescriptorSet>>,
+	layout: Arc<PipelineLayout>,
+	pipeline: Arc<Pipeline>,
+	/// The document's quads for each frame in flight, indexed by the frame.
+	frames: Vec<Option<FrameBuffers>>,
+	/// Counts the document's changes, so frames whose buffers are already up to date can skip comparing them.
+	generation: u64,
+}
+impl GuiRenderer {
+	pub fn new(gfx: Arc<Gfx>, render_pass: Arc<RenderPass>, samples: vk::SampleCountFlags) -> Self {
+		let font = FontAtlas::new(&gfx);
+
+		let set_layout = create_set_layout(&gfx);
+		let desc_pool = create_desc_pool(&gfx);
+		let font_set = desc_pool.allocate(set_layout.clone()).unwrap();
+		font_set.write().sampled_view(0, font.view.clone()).submit();
+
+		let layout = gfx.device.create_pipeline_layout(vec![set_layout.clone()], &[]);
+		let pipeline = create_pipeline(&gfx, layout.clone(), render_pass, samples);
+
+		Self {
+			gfx,
+			_font: font,
+			set_layout,
+			desc_pools: vec![desc_pool],
+			font_set,
+			texture_sets: HashMap::new(),
+			layout,
+			pipeline,
+			frames: vec![],
+			generation: 0,
+		}
+	}
+
+	/// Rebuilds the pipeline from the current shader modules, after they've been hot reloaded.
+	#[cfg(debug_assertions)]
+	pub fn reload_pipeline(&mut self, render_pass: Arc<RenderPass>, samples: vk::SampleCountFlags) {
+		self.pipeline = create_pipeline(&self.gfx, self.layout.clone(), render_pass, samples);
+	}
+
+	/// Records a secondary command buffer that draws `doc` over whatever was rendered before it in the subpass.
+	///
+	/// Returns `None` if the document has nothing visible.
+	/// Each frame in flight keeps its buffers, which are only replaced when the document outgrows them. Otherwise only
+	/// the ranges that changed since `frame` last drew are written, so the GPU must be done with that frame.
+	pub fn record(
+		&mut self,
+		frame: usize,
+		cmdpool: &Arc<CommandPool>,
+		inherit: InheritanceInfo,
+		doc: &mut Document,
+		image_extent: Extent2D,
+	) -> Option<Arc<CommandBuffer<B1>>> {
+		if doc.update(image_extent) {
+			self.generation += 1;
+		}
+		let batch = doc.batch();
+		if batch.verts.is_empty() {
+			return None;
+		}
+
+		if self.frames.len() <= frame {
+			self.frames.resize_with(frame + 1, || None);
+		}
+		let slot = &mut self.frames[frame];
+		if !slot.as_ref().map_or(false, |buffers| buffers.fits(batch)) {
+			*slot = Some(FrameBuffers::new(&self.gfx, batch));
+		}
+		let buffers = slot.as_mut().unwrap();
+		if buffers.generation != Some(self.generation) {
+			// `FramesInFlight::begin` waited until the GPU was done with this frame's buffers
+			let written = unsafe {
+				write_changes(&buffers.verts, &mut buffers.written_verts, &batch.verts)
+					+ write_changes(&buffers.indices, &mut buffers.written_indices, &batch.indices)
+			};
+			self.gfx.stats().add_upload(written as _);
+			buffers.generation = Some(self.generation);
+		}
+		let (verts, indices) = (buffers.verts.clone(), buffers.indices.clone());
+
+		let mut cmd = cmdpool
+			.record_secondary(true, false, Some(inherit))
+			.begin_label("gui")
+			.bind_pipeline(self.pipeline.clone())
+			.set_viewport(0, &[viewport(image_extent)])
+			.set_scissor(0, &[Rect2D::builder().extent(image_extent).build()])
+			.bind_vertex_buffer(0, verts, 0)
+			.bind_index_buffer(indices, 0);
+		self.gfx.stats().add_draws(doc.batch().draws.len() as _);
+		for (binding, range) in doc.batch().draws.clone() {
+			let set = match binding {
+				None | Some(Binding::Font) => self.font_set.clone(),
+				Some(Binding::Texture(texture)) => self.texture_set(texture),
+			};
+			let count = range.end - range.start;
+			cmd = cmd.bind_descriptor_sets(self.layout.clone(), 0, once(set)).draw_indexed(count, 1, range.start, 0, 0);
+		}
+		Some(cmd.end_label().build())
+	}
+
+	fn texture_set(&mut self, texture: TextureHandle) -> Arc<DescriptorSet> {
+		if let Some(set) = self.texture_sets.get(&texture) {
+			return set.clone();
+		}
+
+		let set = match self.desc_pools.last().unwrap().allocate(self.set_layout.clone()) {
+			Ok(set) => set,
+			Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY) | Err(vk::Result::ERROR_FRAGMENTED_POOL) => {
+				let desc_pool = create_desc_pool(&self.gfx);
+				let set = desc_pool.allocate(self.set_layout.clone()).unwrap();
+				self.desc_pools.push(desc_pool);
+				set
+			},
+			Err(err) => panic!("{}", err),
+		};
+		set.write().sampled_view(0, self.gfx.texture(texture).view.clone()).submit();
+
+		self.texture_sets.insert(texture, set.clone());
+		set
+	}
+}
+
+/// A frame in flight's vertex and index buffers, which stay mapped into host visible memory. They're made bigger
+/// than needed, so a document that grows a little doesn't need new ones.
+struct FrameBuffers {
+	verts: Arc<Buffer<[GuiVertex]>>,
+	indices: Arc<Buffer<[u32]>>,
+	/// Copies of what the buffers hold, to compare the next batch with.
+	written_verts: Vec<GuiVertex>,
+	written_indices: Vec<u32>,
+	/// The `GuiRenderer::generation` the buffers hold, or `None` before anything has been written.
+	generation: Option<u64>,
+}
+impl FrameBuffers {
+	fn new(gfx: &Gfx, batch: &GuiBatch) -> Self {
+		let device = &gfx.device;
+		let verts = device
+			.create_buffer_slice(batch.verts.len().next_power_of_two(), B1, BufferUsageFlags::VERTEX_BUFFER)
+			.uninitialized();
+		let indices = device
+			.create_buffer_slice(batch.indices.len().next_power_of_two(), B1, BufferUsageFlags::INDEX_BUFFER)
+			.uninitialized();
+		Self { verts, indices, written_verts: vec![], written_indices: vec![], generation: None }
+	}
+
+	fn fits(&self, batch: &GuiBatch) -> bool {
+		size_of_val(&batch.verts[..]) as u64 <= self.verts.size()
+			&& size_of_val(&batch.indices[..]) as u64 <= self.indices.size()
+	}
+}
+
+/// Writes the range of `data` that differs from `written` into `buffer`, and updates `written` to match. Returns how
+/// many bytes were written.
+///
+/// The GPU must not be using `buffer`.
+unsafe fn write_changes<T: Copy + PartialEq + 'static>(
+	buffer: &Buffer<[T]>,
+	written: &mut Vec<T>,
+	data: &[T],
+) -> usize {
+	let changed = changed_range(written, data);
+	if !changed.is_empty() {
+		buffer.write_at(changed.start, &data[changed.clone()]);
+	}
+	written.clear();
+	written.extend_from_slice(data);
+	size_of_val(&data[changed])
+}
+
+/// The range of `data` that has to be written over `written` for the buffer to hold `data`. Whatever's past the end
+/// of `data` is left alone.
+fn changed_range<T: PartialEq>(written: &[T], data: &[T]) -> Range<usize> {
+	let start = written.iter().zip(data).take_while(|(a, b)| a == b).count();
+	if start == data.len() {
+		return start..start;
+	}
+	// the unchanged tail only lines up if nothing was added or removed before it
+	let end = if written.len() == data.len() {
+		let same = written[start..].iter().rev().zip(data[start..].iter().rev()).take_while(|(a, b)| a == b).count();
+		data.len() - same
+	} else {
+		data.len()
+	};
+	start..end
+}
+
+/// A layout with a single combined image sampler for the fragment shader. The sampler is immutable, so writes only
+/// need an image view.
+pub(in crate::gfx) fn create_set_layout(gfx: &Gfx) -> Arc<DescriptorSetLayout> {
+	let sampler = gfx
+		.device
+		.build_sampler()
+		.filter(Filter::LINEAR)
+		.mipmap_mode(SamplerMipmapMode::NEAREST)
+		.address_mode(SamplerAddressMode::CLAMP_TO_EDGE)
+		.build();
+	let bindings = [DescriptorSetLayoutBinding::builder()
+		.binding(0)
+		.descriptor_type(DescriptorType::COMBINED_IMAGE_SAMPLER)
+		.descriptor_count(1)
+		.stage_flags(ShaderStageFlags::FRAGMENT)
+		.build()];
+	gfx.device.create_descriptor_set_layout(&bindings, vec![(0, sampler)])
+}
+
+pub(in crate::gfx) fn create_desc_pool(gfx: &Gfx) -> Arc<DescriptorPool> {
+	let pool_sizes = [DescriptorPoolSize::builder()
+		.ty(DescriptorType::COMBINED_IMAGE_SAMPLER)
+		.descriptor_count(SETS_PER_POOL)
+		.build()];
+	gfx.device.create_descriptor_pool(SETS_PER_POOL, &pool_sizes)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Binding {
+	Font,
+	Texture(TextureHandle),
+}
+
+/// GUI quads in normalized device coordinates, split into draws by texture. Each quad is four vertices and six
+/// indices, and draws are ranges of indices. Draws with no binding only contain solid quads, so they can use any
+/// texture.
+pub struct GuiBatch {
+	pub(super) image_extent: Extent2D,
+	scale: f32,
+	/// Multiplies the alpha of everything added to the batch.
+	pub(super) opacity: f32,
+	verts: Vec<GuiVertex>,
+	indices: Vec<u32>,
+	draws: Vec<(Option<Binding>, Range<u32>)>,
+}
+impl GuiBatch {
+	pub(super) fn new(image_extent: Extent2D, scale: f32) -> Self {
+		Self { image_extent, scale, opacity: 1.0, verts: vec![], indices: vec![], draws: vec![] }
+	}
+
+	/// The UI scale nodes should draw at. Layout rects already include it.
+	pub fn scale(&self) -> f32 {
+		self.scale
+	}
+
+	/// Adds all of `other`'s quads after this batch's.
+	pub(super) fn append(&mut self, other: &GuiBatch) {
+		let base = self.verts.len() as u32;
+		let offset = self.indices.len() as u32;
+		self.verts.extend_from_slice(&other.verts);
+		self.indices.extend(other.indices.iter().map(|i| i + base));
+		for (binding, range) in &other.draws {
+			self.push_draw(*binding, range.start + offset..range.end + offset);
+		}
+	}
+
+	/// Adds a solid quad covering `rect`, which is in pixels.
+	pub fn quad(&mut self, rect: Rect2D, color: Color) {
+		if rect.extent.width == 0 || rect.extent.height == 0 || color.w == 0 {
+			return;
+		}
+
+		let min = Vector2::new(rect.offset.x as f32, rect.offset.y as f32);
+		let max = min + Vector2::new(rect.extent.width as f32, rect.extent.height as f32);
+		self.push_quad(min, max, Vector2::zeros(), Vector2::zeros(), color, MODE_SOLID, None);
+	}
+
+	/// Adds a quad covering `rect` that samples all of `texture`, multiplied by `tint`.
+	pub fn image(&mut self, rect: Rect2D, texture: TextureHandle, tint: Color) {
+		if rect.extent.width == 0 || rect.extent.height == 0 || tint.w == 0 {
+			return;
+		}
+
+		let min = Vector2::new(rect.offset.x as f32, rect.offset.y as f32);
+		let max = min + Vector2::new(rect.extent.width as f32, rect.extent.height as f32);
+		let uv_max = Vector2::new(1.0, 1.0);
+		self.push_quad(min, max, Vector2::zeros(), uv_max, tint, MODE_IMAGE, Some(Binding::Texture(texture)));
+	}
+
+	/// Adds a quad for a glyph `size` pixels tall with its top left corner at `x`, `y`.
+	pub fn glyph(&mut self, x: i32, y: i32, size: u32, ch: char, color: Color) {
+		let size = size as f32;
+		let padding = size * (CELL_SCALE - 1.0) / 2.0;
+		let min = Vector2::new(x as f32 - padding, y as f32 - padding);
+		let max = min + Vector2::new(size * CELL_SCALE, size * CELL_SCALE);
+		let (uv_min, uv_max) = glyph_uvs(ch);
+		self.push_quad(min, max, uv_min, uv_max, color, MODE_TEXT, Some(Binding::Font));
+	}
+
+	fn push_quad(
+		&mut self,
+		min: Vector2<f32>,
+		max: Vector2<f32>,
+		uv_min: Vector2<f32>,
+		uv_max: Vector2<f32>,
+		color: Color,
+		mode: u32,
+		binding: Option<Binding>,
+	) {
+		let scale = Vector2::new(2.0 / self.image_extent.width as f32, 2.0 / self.image_extent.height as f32);
+		let color = Color::new(color.x, color.y, color.z, (color.w as f32 * self.opacity).round() as u8);
+		let vert = |x: f32, y: f32, u: f32, v: f32| GuiVertex {
+			pos: Vector2::new(x, y).component_mul(&scale) - Vector2::new(1.0, 1.0),
+			uv: Vector2::new(u, v),
+			color,
+			mode,
+		};
+		let tl = vert(min.x, min.y, uv_min.x, uv_min.y);
+		let tr = vert(max.x, min.y, uv_max.x, uv_min.y);
+		let bl = vert(min.x, max.y, uv_min.x, uv_max.y);
+		let br = vert(max.x, max.y, uv_max.x, uv_max.y);
+
+		// clockwise, to survive back face culling
+		let base = self.verts.len() as u32;
+		let start = self.indices.len() as u32;
+		self.verts.extend_from_slice(&[tl, tr, bl, br]);
+		self.indices.extend([0, 1, 2, 1, 3, 2].iter().map(|i| i + base));
+		self.push_draw(binding, start..self.indices.len() as u32);
+	}
+
+	/// Extends the last draw with `range` if their bindings are compatible, or starts a new draw.
+	fn push_draw(&mut self, binding: Option<Binding>, range: Range<u32>) {
+		match self.draws.last_mut() {
+			Some(last) if binding.is_none() || last.0.is_none() || last.0 == binding => {
+				last.0 = last.0.or(binding);
+				last.1.end = range.end;
+			},
+			_ => self.draws.push((binding, range)),
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct GuiVertex {
+	pub pos: Vector2<f32>,
+	pub uv: Vector2<f32>,
+	pub color: Vector4<u8>,
+	pub mode: u32,
+}
+impl VertexDesc for GuiVertex {
+	fn attribute_descs() -> Vec<vk::VertexInputAttributeDescription> {
+		vec![
+			vk::VertexInputAttributeDescription::builder()
+				.binding(0)
+				.location(0)
+				.format(vk::Format::R32G32_SFLOAT)
+				.offset(offset_of!(Self, pos) as _)
+				.build(),
+			vk::VertexInputAttributeDescription::builder()
+				.binding(0)
+				.location(1)
+				.format(vk::Format::R32G32_SFLOAT)
+				.offset(offset_of!(Self, uv) as _)
+				.build(),
+			vk::VertexInputAttributeDescription::builder()
+				.binding(0)
+				.location(2)
+				.format(vk::Format::R8G8B8A8_UNORM)
+				.offset(offset_of!(Self, color) as _)
+				.build(),
+			vk::VertexInputAttributeDescription::builder()
+				.binding(0)
+				.location(3)
+				.format(vk::Format::R32_UINT)
+				.offset(offset_of!(Self, mode) as _)
+				.build(),
+		]
+	}
+}
+
+fn create_pipeline(
+	gfx: &Gfx,
+	layout: Arc<PipelineLayout>,
+	render_pass: Arc<RenderPass>,
+	samples: vk::SampleCountFlags,
+) -> Arc<Pipeline> {
+	let shaders = gfx.shaders();
+	let pipeline = gfx
+		.device
+		.build_pipeline(layout, render_pass)
+		.vertex_shader(shaders.gui_vert.clone())
+		.fragment_shader(shaders.gui_frag.clone())
+		.vertex_input::<GuiVertex>()
+		.alpha_blending()
+		.dynamic_viewport()
+		.samples(samples)
+		.build();
+	gfx.device.set_object_name(pipeline.vk, "gui");
+	pipeline
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn changed_range_is_the_part_that_differs() {
+		assert_eq!(changed_range(&[1, 2, 3, 4, 5], &[1, 2, 3, 4, 5]), 5..5);
+		assert_eq!(changed_range(&[1, 2, 3, 4, 5], &[1, 9, 9, 4, 5]), 1..3);
+		assert_eq!(changed_range(&[1, 2, 3], &[7, 2, 3]), 0..1);
+		assert_eq!(changed_range::<u32>(&[], &[1, 2]), 0..2);
+	}
+
+	#[test]
+	fn changed_range_with_a_new_length() {
+		// growing writes everything after the first change, since the tail moved
+		assert_eq!(changed_range(&[1, 2, 3], &[1, 2, 3, 4]), 3..4);
+		assert_eq!(changed_range(&[1, 2, 3], &[1, 9, 2, 3]), 1..4);
+		// shrinking to a prefix writes nothing, since the rest is never drawn
+		assert_eq!(changed_range(&[1, 2, 3], &[1, 2]), 2..2);
+		assert_eq!(changed_range(&[1, 2, 3], &[1, 3]), 1..2);
+	}
+}
@@ -0,0 +1,138 @@
+use crate::gfx::gui::{render::GuiBatch, Change, ChangeFlag, Color, Node, Styles};
+use std::cell::RefCell;
+use vulkan::Rect2D;
+
+pub struct TextElement {
+	content: RefCell<String>,
+	font_size: u32,
+	color: Color,
+	wrap: bool,
+	style: Styles,
+	change: ChangeFlag,
+}
+impl TextElement {
+	pub fn new(content: impl Into<String>, font_size: u32, color: Color) -> Self {
+		Self {
+			content: RefCell::new(content.into()),
+			font_size,
+			color,
+			wrap: true,
+			style: Styles::default(),
+			change: ChangeFlag::new(),
+		}
+	}
+
+	/// Replaces the text. The document lays it out again on its next update.
+	pub fn set_content(&self, content: impl Into<String>) {
+		let content = content.into();
+		if *self.content.borrow() != content {
+			*self.content.borrow_mut() = content;
+			self.change.set(Change::Relayout);
+		}
+	}
+
+	pub fn with_wrap(mut self, wrap: bool) -> Self {
+		self.wrap = wrap;
+		self
+	}
+
+	pub fn with_style(mut self, style: Styles) -> Self {
+		self.style = style;
+		self
+	}
+
+	/// The font size in pixels at the given UI scale. Glyphs come from a distance field, so they stay sharp at any
+	/// size.
+	fn scaled_font_size(&self, scale: f32) -> u32 {
+		((self.font_size as f32 * scale).round() as u32).max(1)
+	}
+
+	fn line_height(font_size: u32) -> u32 {
+		font_size + font_size / 4
+	}
+
+	/// Splits the content into lines no wider than `width` pixels, breaking at spaces where possible.
+	fn lines(&self, width: u32, font_size: u32) -> Vec<String> {
+		let max_chars = (width / font_size).max(1) as usize;
+
+		let mut lines = vec![];
+		for paragraph in self.content.borrow().split('\n') {
+			if !self.wrap {
+				lines.push(paragraph.to_string());
+				continue;
+			}
+
+			let mut line = String::new();
+			for word in paragraph.split(' ') {
+				let line_len = line.chars().count();
+				let word_len = word.chars().count();
+				if line_len > 0 && line_len + 1 + word_len > max_chars {
+					lines.push(line);
+					line = String::new();
+				}
+				if !line.is_empty() {
+					line.push(' ');
+				}
+				line.push_str(word);
+
+				// a single word longer than the line has to be broken
+				while line.chars().count() > max_chars {
+					let split = line.char_indices().nth(max_chars).unwrap().0;
+					let rest = line.split_off(split);
+					lines.push(line);
+					line = rest;
+				}
+			}
+			lines.push(line);
+		}
+		lines
+	}
+}
+impl Node for TextElement {
+	fn draw(&self, rect: Rect2D, batch: &mut GuiBatch) {
+		if let Some(color) = self.style.background_color() {
+			batch.quad(rect, color);
+		}
+
+		let font_size = self.scaled_font_size(batch.scale());
+		let rect = self.style.padding().scaled(batch.scale()).shrink(rect);
+		let bottom = rect.offset.y + rect.extent.height as i32;
+		let mut y = rect.offset.y;
+		for line in self.lines(rect.extent.width, font_size) {
+			if y + font_size as i32 > bottom {
+				break;
+			}
+
+			let mut x = rect.offset.x;
+			for ch in line.chars() {
+				if x + font_size as i32 > rect.offset.x + rect.extent.width as i32 {
+					break;
+				}
+				if ch != ' ' {
+					batch.glyph(x, y, font_size, ch, self.color);
+				}
+				x += font_size as i32;
+			}
+
+			y += Self::line_height(font_size) as i32;
+		}
+	}
+
+	fn content_width(&self, scale: f32) -> Option<u32> {
+		let chars = self.content.borrow().split('\n').map(|line| line.chars().count()).max().unwrap_or(0);
+		Some(chars as u32 * self.scaled_font_size(scale))
+	}
+
+	fn content_height(&self, width: u32, scale: f32) -> Option<u32> {
+		let font_size = self.scaled_font_size(scale);
+		Some(self.lines(width, font_size).len() as u32 * Self::line_height(font_size))
+	}
+
+	fn take_change(&self) -> Change {
+		self.change.take()
+	}
+
+	fn style(&self) -> &Styles {
+		&self.style
+	}
+}
@@ -0,0 +1,102 @@
+use crate::gfx::Gfx;
+use ash::vk;
+use std::sync::Arc;
+use vulkan::image::{aspect, Image, ImageInit};
+
+/// Which mip levels an uploaded image has.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mips {
+	/// The data holds this many levels, in order and tightly packed.
+	Provided(u32),
+	/// The data only holds the base level, and a full chain is generated from it on the GPU. Formats that can't be
+	/// blitted with linear filtering, such as compressed ones, get only the base level instead.
+	Generate,
+}
+
+/// Creates a sampled 2D image in device local memory, without initializing it. Images with more than one level can
+/// also be a transfer source, so their mips can be generated.
+pub(super) fn create_image(gfx: &Gfx, format: vk::Format, extent: vk::Extent2D, mip_levels: u32) -> ImageInit {
+	let mut usage = vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED;
+	if mip_levels > 1 {
+		usage |= vk::ImageUsageFlags::TRANSFER_SRC;
+	}
+	let extent = vk::Extent3D { width: extent.width, height: extent.height, depth: 1 };
+	let ci = vk::ImageCreateInfo::builder()
+		.image_type(vk::ImageType::TYPE_2D)
+		.format(format)
+		.extent(extent)
+		.mip_levels(mip_levels)
+		.array_layers(1)
+		.samples(vk::SampleCountFlags::TYPE_1)
+		.usage(usage)
+		.sharing_mode(vk::SharingMode::EXCLUSIVE)
+		.initial_layout(vk::ImageLayout::UNDEFINED);
+	gfx.device.create_image(&ci)
+}
+
+/// Creates a color or depth attachment that only lives for the duration of a render pass.
+pub(super) fn create_attachment(
+	gfx: &Gfx,
+	format: vk::Format,
+	extent: vk::Extent2D,
+	samples: vk::SampleCountFlags,
+) -> Arc<Image> {
+	let (usage, name) = if aspect(format) == vk::ImageAspectFlags::DEPTH {
+		(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT, "depth attachment")
+	} else {
+		(vk::ImageUsageFlags::COLOR_ATTACHMENT, "multisampled color attachment")
+	};
+	let extent = vk::Extent3D { width: extent.width, height: extent.height, depth: 1 };
+	let ci = vk::ImageCreateInfo::builder()
+		.image_type(vk::ImageType::TYPE_2D)
+		.format(format)
+		.extent(extent)
+		.mip_levels(1)
+		.array_layers(1)
+		.samples(samples)
+		.usage(usage | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT)
+		.sharing_mode(vk::SharingMode::EXCLUSIVE)
+		.initial_layout(vk::ImageLayout::UNDEFINED);
+	let image = gfx.device.create_image(&ci).attachment();
+	gfx.device.set_object_name(image.vk, name);
+	image
+}
+
+/// Creates a single sampled color or depth image that's rendered into and then sampled, or for color images copied
+/// from, like the world before it's upscaled to the window.
+pub(super) fn create_render_target(gfx: &Gfx, format: vk::Format, extent: vk::Extent2D, name: &str) -> Arc<Image> {
+	let usage = if aspect(format) == vk::ImageAspectFlags::DEPTH {
+		vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+	} else {
+		vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC
+	};
+	let extent = vk::Extent3D { width: extent.width, height: extent.height, depth: 1 };
+	let ci = vk::ImageCreateInfo::builder()
+		.image_type(vk::ImageType::TYPE_2D)
+		.format(format)
+		.extent(extent)
+		.mip_levels(1)
+		.array_layers(1)
+		.samples(vk::SampleCountFlags::TYPE_1)
+		.usage(usage | vk::ImageUsageFlags::SAMPLED)
+		.sharing_mode(vk::SharingMode::EXCLUSIVE)
+		.initial_layout(vk::ImageLayout::UNDEFINED);
+	let image = gfx.device.create_image(&ci).attachment();
+	gfx.device.set_object_name(image.vk, name);
+	image
+}
+
+/// The number of levels in a full mip chain, down to 1x1.
+pub(super) fn full_mip_levels(extent: vk::Extent2D) -> u32 {
+	32 - extent.width.max(extent.height).max(1).leading_zeros()
+}
+
+/// Whether mips can be generated for images in `format` with `Image::record_generate_mips`.
+pub(super) fn can_generate_mips(gfx: &Gfx, format: vk::Format) -> bool {
+	let props = gfx.device.physical_device().get_format_properties(format);
+	props.optimal_tiling_features.contains(
+		vk::FormatFeatureFlags::BLIT_SRC
+			| vk::FormatFeatureFlags::BLIT_DST
+			| vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+	)
+}
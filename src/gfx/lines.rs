@@ -0,0 +1,172 @@
+//! Colored lines in world space, for editor and debugging visuals like chunk boundaries.
+
+use crate::gfx::{window::viewport, Gfx};
+use ash::vk;
+use memoffset::offset_of;
+use nalgebra::{Vector3, Vector4};
+use sim::math::Aabb;
+use std::{f32::consts::PI, iter::once, mem::size_of_val, sync::Arc};
+use typenum::B1;
+use vulkan::{
+	command::{CommandBuffer, CommandPool, InheritanceInfo},
+	descriptor::DescriptorSet,
+	device::BufferUsageFlags,
+	pipeline::{Pipeline, VertexDesc},
+	render_pass::RenderPass,
+	Extent2D, Rect2D,
+};
+
+/// Segments in each of a sphere's circles.
+const CIRCLE_SEGMENTS: usize = 32;
+
+/// Lines to draw in the next frame. They're cleared once they've been recorded, so anything that should stay visible
+/// has to be added again every frame.
+#[derive(Default)]
+pub struct DebugLines {
+	verts: Vec<LineVertex>,
+}
+impl DebugLines {
+	/// `color` is linear RGBA.
+	pub fn line(&mut self, a: Vector3<f32>, b: Vector3<f32>, color: Vector4<f32>) {
+		self.verts.push(LineVertex { pos: a, color });
+		self.verts.push(LineVertex { pos: b, color });
+	}
+
+	/// The twelve edges of `aabb`.
+	pub fn aabb(&mut self, aabb: &Aabb, color: Vector4<f32>) {
+		let corner = |i: usize| {
+			Vector3::new(
+				if i & 1 == 0 { aabb.min.x } else { aabb.max.x },
+				if i & 2 == 0 { aabb.min.y } else { aabb.max.y },
+				if i & 4 == 0 { aabb.min.z } else { aabb.max.z },
+			)
+		};
+		// each edge joins two corners that differ in one axis
+		for i in 0..8 {
+			for &axis in &[1, 2, 4] {
+				if i & axis == 0 {
+					self.line(corner(i), corner(i | axis), color);
+				}
+			}
+		}
+	}
+
+	/// A circle around each axis, which reads as a sphere from any direction.
+	pub fn sphere(&mut self, center: Vector3<f32>, radius: f32, color: Vector4<f32>) {
+		let point = |axis: usize, i: usize| {
+			let angle = i as f32 / CIRCLE_SEGMENTS as f32 * 2.0 * PI;
+			let mut offset = Vector3::zeros();
+			offset[(axis + 1) % 3] = angle.cos() * radius;
+			offset[(axis + 2) % 3] = angle.sin() * radius;
+			center + offset
+		};
+		for axis in 0..3 {
+			for i in 0..CIRCLE_SEGMENTS {
+				self.line(point(axis, i), point(axis, i + 1), color);
+			}
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.verts.is_empty()
+	}
+}
+
+pub struct LineRenderer {
+	gfx: Arc<Gfx>,
+	pipeline: Arc<Pipeline>,
+}
+impl LineRenderer {
+	pub fn new(gfx: Arc<Gfx>, render_pass: Arc<RenderPass>, samples: vk::SampleCountFlags) -> Self {
+		let pipeline = create_pipeline(&gfx, render_pass, samples);
+		Self { gfx, pipeline }
+	}
+
+	/// Rebuilds the pipeline from the current shader modules, after they've been hot reloaded.
+	#[cfg(debug_assertions)]
+	pub fn reload_pipeline(&mut self, render_pass: Arc<RenderPass>, samples: vk::SampleCountFlags) {
+		self.pipeline = create_pipeline(&self.gfx, render_pass, samples);
+	}
+
+	/// Records a secondary command buffer that draws `lines` and clears them, or returns `None` if there are none.
+	/// Lines are depth tested, so they're hidden by whatever was drawn before them.
+	pub fn record(
+		&mut self,
+		cmdpool: &Arc<CommandPool>,
+		inherit: InheritanceInfo,
+		image_extent: Extent2D,
+		frame_set: Arc<DescriptorSet>,
+		lines: &mut DebugLines,
+	) -> Option<Arc<CommandBuffer<B1>>> {
+		if lines.is_empty() {
+			return None;
+		}
+
+		let verts = self
+			.gfx
+			.device
+			.create_buffer_slice(lines.verts.len(), B1, BufferUsageFlags::VERTEX_BUFFER)
+			.copy_from_slice(&lines.verts);
+		self.gfx.stats().add_upload(size_of_val(&lines.verts[..]) as _);
+		self.gfx.stats().add_draws(1);
+		let count = lines.verts.len() as u32;
+		lines.verts.clear();
+
+		let cmd = cmdpool
+			.record_secondary(true, false, Some(inherit))
+			.begin_label("lines")
+			.bind_pipeline(self.pipeline.clone())
+			.set_viewport(0, &[viewport(image_extent)])
+			.set_scissor(0, &[Rect2D::builder().extent(image_extent).build()])
+			.bind_descriptor_sets(self.gfx.layout.clone(), 0, once(frame_set))
+			.bind_vertex_buffer(0, verts, 0)
+			.draw(count, 1, 0, 0)
+			.end_label();
+		Some(cmd.build())
+	}
+}
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct LineVertex {
+	pos: Vector3<f32>,
+	color: Vector4<f32>,
+}
+impl VertexDesc for LineVertex {
+	fn attribute_descs() -> Vec<vk::VertexInputAttributeDescription> {
+		vec![
+			vk::VertexInputAttributeDescription::builder()
+				.binding(0)
+				.location(0)
+				.format(vk::Format::R32G32B32_SFLOAT)
+				.offset(offset_of!(Self, pos) as _)
+				.build(),
+			vk::VertexInputAttributeDescription::builder()
+				.binding(0)
+				.location(1)
+				.format(vk::Format::R32G32B32A32_SFLOAT)
+				.offset(offset_of!(Self, color) as _)
+				.build(),
+		]
+	}
+}
+
+/// Only needs the frame uniforms, so it uses the shared layout.
+fn create_pipeline(gfx: &Gfx, render_pass: Arc<RenderPass>, samples: vk::SampleCountFlags) -> Arc<Pipeline> {
+	let shaders = gfx.shaders();
+	let pipeline = gfx
+		.device
+		.build_pipeline(gfx.layout.clone(), render_pass)
+		.vertex_shader(shaders.lines_vert.clone())
+		.fragment_shader(shaders.lines_frag.clone())
+		.vertex_input::<LineVertex>()
+		.topology(vk::PrimitiveTopology::LINE_LIST)
+		.cull_mode(vk::CullModeFlags::NONE)
+		.alpha_blending()
+		.dynamic_viewport()
+		.depth_test(false)
+		.samples(samples)
+		.build();
+	gfx.device.set_object_name(pipeline.vk, "lines");
+	pipeline
+}
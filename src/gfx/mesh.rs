@@ -0,0 +1,347 @@
+//! Rasterized triangle meshes, for objects that move independently of the voxel world.
+
+use crate::gfx::{gui::render::create_desc_pool, texture::TextureHandle, window::viewport, Gfx};
+use ash::vk;
+use memoffset::offset_of;
+use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
+use sim::math::Aabb;
+use std::{
+	collections::HashMap,
+	iter::once,
+	mem::{size_of, size_of_val},
+	sync::Arc,
+};
+use typenum::B1;
+use vulkan::{
+	buffer::Buffer,
+	command::{CommandBuffer, CommandPool, InheritanceInfo},
+	descriptor::{
+		DescriptorPool, DescriptorSet, DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorType,
+		ShaderStageFlags,
+	},
+	device::BufferUsageFlags,
+	pipeline::{Pipeline, PipelineLayout, PushConstantRange, VertexDesc},
+	render_pass::RenderPass,
+	sampler::{Filter, SamplerAddressMode, SamplerMipmapMode},
+	Extent2D, Rect2D,
+};
+
+/// An indexed triangle list. Front faces are counter-clockwise when viewed from outside.
+pub struct Mesh {
+	verts: Arc<Buffer<[MeshVertex]>>,
+	indices: Arc<Buffer<[u32]>>,
+	index_count: u32,
+	pub material: Material,
+}
+impl Mesh {
+	/// The buffers stay in host visible memory, which is fine for the small props this is meant for.
+	pub fn new(gfx: &Gfx, verts: &[MeshVertex], indices: &[u32], material: Material) -> Arc<Self> {
+		let device = &gfx.device;
+		let vert_buffer =
+			device.create_buffer_slice(verts.len(), B1, BufferUsageFlags::VERTEX_BUFFER).copy_from_slice(verts);
+		let index_buffer =
+			device.create_buffer_slice(indices.len(), B1, BufferUsageFlags::INDEX_BUFFER).copy_from_slice(indices);
+		gfx.stats().add_upload((size_of_val(verts) + size_of_val(indices)) as _);
+		Arc::new(Self { verts: vert_buffer, indices: index_buffer, index_count: indices.len() as _, material })
+	}
+
+	/// A box with flat faces and UVs covering each face, for placeholders.
+	pub fn cuboid(gfx: &Gfx, aabb: &Aabb, material: Material) -> Arc<Self> {
+		let mut verts = Vec::with_capacity(24);
+		let mut indices = Vec::with_capacity(36);
+		for axis in 0..3 {
+			for &sign in &[-1.0, 1.0] {
+				let mut normal = Vector3::zeros();
+				normal[axis] = sign;
+				// the two axes across the face, swapped on the negative side so both faces wind counter-clockwise
+				let (u, v) =
+					if sign > 0.0 { ((axis + 1) % 3, (axis + 2) % 3) } else { ((axis + 2) % 3, (axis + 1) % 3) };
+
+				let base = verts.len() as u32;
+				for &(s, t) in &[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+					let mut pos = Vector3::zeros();
+					pos[axis] = if sign > 0.0 { aabb.max[axis] } else { aabb.min[axis] };
+					pos[u] = aabb.min[u] + (aabb.max[u] - aabb.min[u]) * s;
+					pos[v] = aabb.min[v] + (aabb.max[v] - aabb.min[v]) * t;
+					verts.push(MeshVertex { pos, normal, uv: Vector2::new(s, t) });
+				}
+				indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+			}
+		}
+		Self::new(gfx, &verts, &indices, material)
+	}
+}
+
+/// How a mesh's surface looks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Material {
+	/// Linear RGBA, multiplied with the texture.
+	pub color: Vector4<f32>,
+	/// Sampled with the mesh's UVs. Meshes without one are a solid `color`.
+	pub texture: Option<TextureHandle>,
+}
+impl Default for Material {
+	fn default() -> Self {
+		Self { color: Vector4::repeat(1.0), texture: None }
+	}
+}
+
+/// The meshes of one object, such as everything in a glTF file, in a shared model space.
+pub struct Model {
+	pub meshes: Vec<Arc<Mesh>>,
+}
+impl From<Arc<Mesh>> for Model {
+	fn from(mesh: Arc<Mesh>) -> Self {
+		Self { meshes: vec![mesh] }
+	}
+}
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct MeshVertex {
+	pub pos: Vector3<f32>,
+	pub normal: Vector3<f32>,
+	pub uv: Vector2<f32>,
+}
+impl VertexDesc for MeshVertex {
+	fn attribute_descs() -> Vec<vk::VertexInputAttributeDescription> {
+		vec![
+			vk::VertexInputAttributeDescription::builder()
+				.binding(0)
+				.location(0)
+				.format(vk::Format::R32G32B32_SFLOAT)
+				.offset(offset_of!(Self, pos) as _)
+				.build(),
+			vk::VertexInputAttributeDescription::builder()
+				.binding(0)
+				.location(1)
+				.format(vk::Format::R32G32B32_SFLOAT)
+				.offset(offset_of!(Self, normal) as _)
+				.build(),
+			vk::VertexInputAttributeDescription::builder()
+				.binding(0)
+				.location(2)
+				.format(vk::Format::R32G32_SFLOAT)
+				.offset(offset_of!(Self, uv) as _)
+				.build(),
+		]
+	}
+}
+
+/// Per-instance data, read from the second vertex binding.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct MeshInstance {
+	/// Must only scale uniformly, so it can transform normals too.
+	pub model: Matrix4<f32>,
+	pub color: Vector4<f32>,
+}
+impl VertexDesc for MeshInstance {
+	fn attribute_descs() -> Vec<vk::VertexInputAttributeDescription> {
+		// a matrix attribute takes one location per column
+		let column = size_of::<Vector4<f32>>();
+		let mut descs = (0..4)
+			.map(|i| {
+				vk::VertexInputAttributeDescription::builder()
+					.binding(1)
+					.location(3 + i as u32)
+					.format(vk::Format::R32G32B32A32_SFLOAT)
+					.offset((offset_of!(Self, model) + i * column) as _)
+					.build()
+			})
+			.collect::<Vec<_>>();
+		descs.push(
+			vk::VertexInputAttributeDescription::builder()
+				.binding(1)
+				.location(7)
+				.format(vk::Format::R32G32B32A32_SFLOAT)
+				.offset(offset_of!(Self, color) as _)
+				.build(),
+		);
+		descs
+	}
+}
+
+/// Draws every instance of each mesh with one instanced draw call.
+pub struct MeshRenderer {
+	gfx: Arc<Gfx>,
+	set_layout: Arc<DescriptorSetLayout>,
+	desc_pools: Vec<Arc<DescriptorPool>>,
+	/// Bound for meshes without a texture.
+	white: TextureHandle,
+	texture_sets: HashMap<TextureHandle, Arc<DescriptorSet>>,
+	layout: Arc<PipelineLayout>,
+	pipeline: Arc<Pipeline>,
+	/// Draws triangle outlines, or `None` if the device can't.
+	wireframe_pipeline: Option<Arc<Pipeline>>,
+	/// Whether to draw with `wireframe_pipeline`.
+	pub wireframe: bool,
+}
+impl MeshRenderer {
+	pub fn new(gfx: Arc<Gfx>, render_pass: Arc<RenderPass>, samples: vk::SampleCountFlags, anisotropy: f32) -> Self {
+		let set_layout = create_set_layout(&gfx, anisotropy);
+		let desc_pool = create_desc_pool(&gfx);
+		let white = gfx.white_texture();
+
+		// the material color
+		let push_constants = [PushConstantRange::builder()
+			.stage_flags(vk::ShaderStageFlags::FRAGMENT)
+			.size(size_of::<Vector4<f32>>() as _)
+			.build()];
+		let layouts = vec![gfx.frame_set_layout.clone(), set_layout.clone()];
+		let layout = gfx.device.create_pipeline_layout(layouts, &push_constants);
+		let (pipeline, wireframe_pipeline) = create_pipelines(&gfx, layout.clone(), render_pass, samples);
+		Self {
+			gfx,
+			set_layout,
+			desc_pools: vec![desc_pool],
+			white,
+			texture_sets: HashMap::new(),
+			layout,
+			pipeline,
+			wireframe_pipeline,
+			wireframe: false,
+		}
+	}
+
+	/// Rebuilds the pipelines from the current shader modules, after they've been hot reloaded.
+	#[cfg(debug_assertions)]
+	pub fn reload_pipeline(&mut self, render_pass: Arc<RenderPass>, samples: vk::SampleCountFlags) {
+		let (pipeline, wireframe_pipeline) = create_pipelines(&self.gfx, self.layout.clone(), render_pass, samples);
+		self.pipeline = pipeline;
+		self.wireframe_pipeline = wireframe_pipeline;
+	}
+
+	/// Records a secondary command buffer that draws `draws`, or returns `None` if there's nothing to draw. The
+	/// instances are copied into a new buffer every frame, which the command buffer keeps alive.
+	pub fn record(
+		&mut self,
+		cmdpool: &Arc<CommandPool>,
+		inherit: InheritanceInfo,
+		image_extent: Extent2D,
+		frame_set: Arc<DescriptorSet>,
+		draws: &[(Arc<Mesh>, Vec<MeshInstance>)],
+	) -> Option<Arc<CommandBuffer<B1>>> {
+		let instances = draws.iter().flat_map(|(_, instances)| instances.iter().copied()).collect::<Vec<_>>();
+		if instances.is_empty() {
+			return None;
+		}
+		let instance_buffer = self
+			.gfx
+			.device
+			.create_buffer_slice(instances.len(), B1, BufferUsageFlags::VERTEX_BUFFER)
+			.copy_from_slice(&instances);
+		self.gfx.stats().add_upload(size_of_val(&instances[..]) as _);
+
+		let pipeline = match &self.wireframe_pipeline {
+			Some(wireframe) if self.wireframe => wireframe.clone(),
+			_ => self.pipeline.clone(),
+		};
+		let mut cmd = cmdpool
+			.record_secondary(true, false, Some(inherit))
+			.begin_label("meshes")
+			.bind_pipeline(pipeline)
+			.set_viewport(0, &[viewport(image_extent)])
+			.set_scissor(0, &[Rect2D::builder().extent(image_extent).build()])
+			.bind_descriptor_sets(self.layout.clone(), 0, once(frame_set))
+			.bind_vertex_buffer(1, instance_buffer, 0);
+		let mut first_instance = 0;
+		let mut draw_count = 0;
+		for (mesh, instances) in draws.iter().filter(|(_, instances)| !instances.is_empty()) {
+			let set = self.texture_set(mesh.material.texture.unwrap_or(self.white));
+			cmd = cmd
+				.bind_descriptor_sets(self.layout.clone(), 1, once(set))
+				.push_constants(self.layout.clone(), vk::ShaderStageFlags::FRAGMENT, 0, &mesh.material.color)
+				.bind_vertex_buffer(0, mesh.verts.clone(), 0)
+				.bind_index_buffer(mesh.indices.clone(), 0)
+				.draw_indexed(mesh.index_count, instances.len() as _, 0, 0, first_instance);
+			first_instance += instances.len() as u32;
+			draw_count += 1;
+		}
+		self.gfx.stats().add_draws(draw_count);
+		Some(cmd.end_label().build())
+	}
+
+	fn texture_set(&mut self, texture: TextureHandle) -> Arc<DescriptorSet> {
+		if let Some(set) = self.texture_sets.get(&texture) {
+			return set.clone();
+		}
+
+		let set = match self.desc_pools.last().unwrap().allocate(self.set_layout.clone()) {
+			Ok(set) => set,
+			Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY) | Err(vk::Result::ERROR_FRAGMENTED_POOL) => {
+				let desc_pool = create_desc_pool(&self.gfx);
+				let set = desc_pool.allocate(self.set_layout.clone()).unwrap();
+				self.desc_pools.push(desc_pool);
+				set
+			},
+			Err(err) => panic!("{}", err),
+		};
+		set.write().sampled_view(0, self.gfx.texture(texture).view.clone()).submit();
+
+		self.texture_sets.insert(texture, set.clone());
+		set
+	}
+}
+
+/// Like the GUI's layout, but the sampler repeats, since mesh UVs often wrap, and blends between mip levels.
+/// Anisotropic filtering keeps surfaces sharp at grazing angles.
+fn create_set_layout(gfx: &Gfx, anisotropy: f32) -> Arc<DescriptorSetLayout> {
+	let mut sampler = gfx
+		.device
+		.build_sampler()
+		.filter(Filter::LINEAR)
+		.mipmap_mode(SamplerMipmapMode::LINEAR)
+		.address_mode(SamplerAddressMode::REPEAT);
+	if anisotropy > 1.0 {
+		sampler = sampler.anisotropy(anisotropy);
+	}
+	let sampler = sampler.build();
+	let bindings = [DescriptorSetLayoutBinding::builder()
+		.binding(0)
+		.descriptor_type(DescriptorType::COMBINED_IMAGE_SAMPLER)
+		.descriptor_count(1)
+		.stage_flags(ShaderStageFlags::FRAGMENT)
+		.build()];
+	gfx.device.create_descriptor_set_layout(&bindings, vec![(0, sampler)])
+}
+
+/// The normal pipeline, and a wireframe one if the device supports it.
+fn create_pipelines(
+	gfx: &Gfx,
+	layout: Arc<PipelineLayout>,
+	render_pass: Arc<RenderPass>,
+	samples: vk::SampleCountFlags,
+) -> (Arc<Pipeline>, Option<Arc<Pipeline>>) {
+	let pipeline = create_pipeline(gfx, layout.clone(), render_pass.clone(), samples, vk::PolygonMode::FILL);
+	gfx.device.set_object_name(pipeline.vk, "meshes");
+	let wireframe = if gfx.device.features().fill_mode_non_solid == vk::TRUE {
+		let wireframe = create_pipeline(gfx, layout, render_pass, samples, vk::PolygonMode::LINE);
+		gfx.device.set_object_name(wireframe.vk, "mesh wireframes");
+		Some(wireframe)
+	} else {
+		None
+	};
+	(pipeline, wireframe)
+}
+
+fn create_pipeline(
+	gfx: &Gfx,
+	layout: Arc<PipelineLayout>,
+	render_pass: Arc<RenderPass>,
+	samples: vk::SampleCountFlags,
+	polygon_mode: vk::PolygonMode,
+) -> Arc<Pipeline> {
+	let shaders = gfx.shaders();
+	gfx.device
+		.build_pipeline(layout, render_pass)
+		.vertex_shader(shaders.mesh_vert.clone())
+		.fragment_shader(shaders.mesh_frag.clone())
+		.vertex_input::<MeshVertex>()
+		.instance_input::<MeshInstance>()
+		.polygon_mode(polygon_mode)
+		.dynamic_viewport()
+		.depth_test(true)
+		.samples(samples)
+		.build()
+}
@@ -0,0 +1,147 @@
+//! Rendering without a window, for automated tests and thumbnails. Frames are drawn into an image and read back.
+
+use crate::{
+	camera::Camera,
+	gfx::{
+		error::GfxError,
+		frame::{FrameUniformRing, FrameUniforms, FramesInFlight},
+		graph::{Pass, RenderGraph, Usage},
+		image,
+		mesh::MeshRenderer,
+		volume::WorldVolumes,
+		window::{
+			clear_values, create_framebuffers, create_pipeline, create_render_pass, scissor, DebugView, RenderSettings,
+			VolumeRecorder,
+		},
+		Gfx,
+	},
+	lighting::Light,
+	scene::Scene,
+};
+use ash::vk;
+use sim::math::Frustum;
+use std::sync::Arc;
+use typenum::B0;
+use vulkan::{
+	command::{CommandBufferBuilder, CommandPool, InheritanceInfo},
+	image::{Framebuffer, Image},
+	pipeline::Pipeline,
+	render_pass::RenderPass,
+	Extent2D, Rect2D,
+};
+
+/// Matches how the window's swapchain images are written, so frames look the same.
+const FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+/// Draws the volumes and meshes the way `Window` does, but without the GUI.
+pub struct Offscreen {
+	gfx: Arc<Gfx>,
+	extent: Extent2D,
+	samples: vk::SampleCountFlags,
+	render_pass: Arc<RenderPass>,
+	pipeline: Arc<Pipeline>,
+	color: Arc<Image>,
+	framebuffer: Arc<Framebuffer>,
+	cmdpool: Arc<CommandPool>,
+	/// Every frame is waited for, so one is enough.
+	frame_uniforms: FrameUniformRing,
+	mesh_renderer: MeshRenderer,
+}
+impl Offscreen {
+	pub fn new(gfx: Arc<Gfx>, extent: Extent2D, render: &RenderSettings) -> Result<Self, GfxError> {
+		let samples = gfx.supported_samples(render.msaa);
+		let (initial_layout, final_layout) = (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+		let render_pass = create_render_pass(&gfx, FORMAT, samples, initial_layout, final_layout, false)?;
+		let pipeline = create_pipeline(&gfx, render_pass.clone(), samples);
+
+		let color = image::create_render_target(&gfx, FORMAT, extent, "offscreen color");
+		let framebuffer =
+			create_framebuffers(&gfx, &render_pass, vec![color.create_view()], extent, FORMAT, samples).pop().unwrap();
+
+		let cmdpool = gfx.device.create_command_pool(gfx.queue.family(), true);
+		let frame_uniforms = FrameUniformRing::new(&gfx.device, &gfx.frame_set_layout, &FramesInFlight::new(1));
+		let mesh_renderer = MeshRenderer::new(gfx.clone(), render_pass.clone(), samples, render.anisotropy);
+		Ok(Self {
+			gfx,
+			extent,
+			samples,
+			render_pass,
+			pipeline,
+			color,
+			framebuffer,
+			cmdpool,
+			frame_uniforms,
+			mesh_renderer,
+		})
+	}
+
+	/// Draws a frame and blocks until it's read back, as tightly packed RGBA rows from the top. `time` is what the
+	/// shaders see as seconds since startup. `volumes` can be made for a single frame in flight.
+	pub fn render(
+		&mut self,
+		camera: &Camera,
+		light: &Light,
+		time: f32,
+		volumes: &mut WorldVolumes,
+		scene: &Scene,
+	) -> Vec<u8> {
+		let uniforms = FrameUniforms::new(camera, light, time, self.extent);
+		// every frame is waited for, so the GPU is done with the buffer
+		let frame_set = unsafe { self.frame_uniforms.write(0, &uniforms) };
+		self.cmdpool.reset_all(false);
+
+		let frustum = Frustum::from_matrix(&uniforms.view_proj);
+		let recorder = VolumeRecorder {
+			cmdpool: self.cmdpool.clone(),
+			render_pass: self.render_pass.clone(),
+			framebuffer: self.framebuffer.clone(),
+			pipeline: self.pipeline.clone(),
+			layout: self.gfx.layout.clone(),
+			frame_set: frame_set.clone(),
+			volume_set: volumes.set(0).clone(),
+			triangle: self.gfx.triangle.clone(),
+			image_extent: self.extent,
+			view: DebugView::default(),
+			jitter: false,
+		};
+		let mut secondaries = volumes
+			.bounds()
+			.iter()
+			.filter(|volume| frustum.intersects_aabb(volume))
+			.filter_map(|volume| scissor(volume, &uniforms.view_proj, self.extent))
+			.map(|scissor| recorder.record(scissor))
+			.collect::<Vec<_>>();
+		secondaries.extend(self.mesh_renderer.record(
+			&self.cmdpool,
+			InheritanceInfo {
+				render_pass: self.render_pass.clone(),
+				subpass: 0,
+				framebuffer: Some(self.framebuffer.clone()),
+			},
+			self.extent,
+			frame_set,
+			&scene.mesh_instances(),
+		));
+
+		// the render pass moves the color image from undefined to ready to copy, so the graph doesn't need it
+		let mut graph = RenderGraph::new();
+		let volume_images = volumes.add_passes(&mut graph, 0);
+		let (render_pass, framebuffer) = (&self.render_pass, &self.framebuffer);
+		let (area, clear_values) = (Rect2D::builder().extent(self.extent).build(), clear_values(self.samples));
+		let draw = move |cmd: CommandBufferBuilder<B0>| {
+			cmd.begin_render_pass(render_pass.clone(), framebuffer.clone(), area, &clear_values)
+				.execute_commands(secondaries)
+				.end_render_pass()
+		};
+		let pass = volume_images
+			.into_iter()
+			.fold(Pass::new("world", draw), |pass, image| pass.image(image, Usage::StorageRead));
+		graph.add_pass(pass);
+		let cmd = graph.record(self.cmdpool.record(true, false).begin_label("offscreen frame")).end_label().build();
+		let _frame = self.gfx.queue.submit(cmd).end();
+
+		// the render pass leaves the image ready to copy, and the copy is submitted after it
+		let (layout, extent) = (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, self.color.extent);
+		self.color.read_region(&self.gfx.queue, &self.cmdpool, layout, 0, vk::Offset3D::default(), extent).wait()
+	}
+}
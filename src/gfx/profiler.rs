@@ -0,0 +1,120 @@
+//! GPU timings for the parts of a frame, measured with timestamp queries. Results are read once the frame's fence
+//! has been waited on, so they show up in the stats of a later frame.
+
+use crate::gfx::{frame::FramesInFlight, stats::StatsCollector, Gfx};
+use ash::vk;
+use log::warn;
+use std::{sync::Arc, time::Duration};
+use typenum::B0;
+use vulkan::{command::CommandBufferBuilder, query::QueryPool};
+
+/// A part of the frame that gets its own GPU timing.
+#[derive(Clone, Copy, Debug)]
+pub enum GpuScope {
+	/// Everything in the primary command buffer: the volumes, meshes, GUI and overlay.
+	RenderPass,
+}
+impl GpuScope {
+	const ALL: [GpuScope; 1] = [GpuScope::RenderPass];
+
+	fn name(self) -> &'static str {
+		match self {
+			GpuScope::RenderPass => "render pass",
+		}
+	}
+
+	/// The first of the two queries the scope writes.
+	fn query(self) -> u32 {
+		self as u32 * 2
+	}
+}
+
+pub struct GpuProfiler {
+	/// A pool for each frame in flight, or none if the queue can't write timestamps.
+	pools: Option<Vec<Arc<QueryPool>>>,
+	/// Whether each frame's queries were written since they were last read, since unwritten ones can't be read.
+	written: Vec<bool>,
+	/// Nanoseconds per tick.
+	period: f64,
+	/// Which bits of a timestamp are meaningful. The rest are garbage and must be ignored.
+	mask: u64,
+}
+impl GpuProfiler {
+	pub(super) fn new(gfx: &Gfx, frames: &FramesInFlight) -> Self {
+		let family = gfx.queue.family();
+		let bits = family
+			.physical_device()
+			.get_queue_family_properties()
+			.nth(family.idx as usize)
+			.map_or(0, |props| props.timestamp_valid_bits());
+		let period = gfx.device.physical_device().get_properties().limits.timestamp_period as f64;
+
+		let count = GpuScope::ALL.len() as u32 * 2;
+		let pools = if bits == 0 {
+			warn!("the graphics queue doesn't support timestamps, so GPU times won't be measured");
+			None
+		} else {
+			Some(frames.per_frame(|_| gfx.device.create_query_pool(vk::QueryType::TIMESTAMP, count)))
+		};
+		let mask = if bits >= 64 { !0 } else { (1 << bits) - 1 };
+		Self { pools, written: vec![false; frames.count()], period, mask }
+	}
+
+	/// Resets `frame`'s queries. It has to be recorded before any scope, outside the render pass.
+	pub fn reset(&mut self, frame: usize, cmd: CommandBufferBuilder<B0>) -> CommandBufferBuilder<B0> {
+		match &self.pools {
+			Some(pools) => {
+				self.written[frame] = true;
+				cmd.reset_query_pool(pools[frame].clone(), 0, GpuScope::ALL.len() as u32 * 2)
+			},
+			None => cmd,
+		}
+	}
+
+	/// Marks the start of `scope`. Timestamps can't be written between a render pass's secondary command buffers, so
+	/// this has to be outside one.
+	pub fn begin(&self, frame: usize, scope: GpuScope, cmd: CommandBufferBuilder<B0>) -> CommandBufferBuilder<B0> {
+		self.write(frame, scope.query(), vk::PipelineStageFlags::TOP_OF_PIPE, cmd)
+	}
+
+	/// Marks the end of `scope`, once everything recorded before it has finished.
+	pub fn end(&self, frame: usize, scope: GpuScope, cmd: CommandBufferBuilder<B0>) -> CommandBufferBuilder<B0> {
+		self.write(frame, scope.query() + 1, vk::PipelineStageFlags::BOTTOM_OF_PIPE, cmd)
+	}
+
+	fn write(
+		&self,
+		frame: usize,
+		query: u32,
+		stage: vk::PipelineStageFlags,
+		cmd: CommandBufferBuilder<B0>,
+	) -> CommandBufferBuilder<B0> {
+		match &self.pools {
+			Some(pools) => cmd.write_timestamp(stage, pools[frame].clone(), query),
+			None => cmd,
+		}
+	}
+
+	/// Adds the timings from the last time `frame` was drawn to `stats`. Its fence must have been waited on.
+	pub fn resolve(&mut self, frame: usize, stats: &StatsCollector) {
+		let pools = match &self.pools {
+			Some(pools) if self.written[frame] => pools,
+			_ => return,
+		};
+		self.written[frame] = false;
+
+		let mut timestamps = [0; GpuScope::ALL.len() * 2];
+		match pools[frame].get_results(0, &mut timestamps) {
+			Ok(true) => (),
+			// the fence was waited on, so this only happens if the frame was never submitted
+			Ok(false) => return,
+			Err(err) => panic!("{}", err),
+		}
+		for &scope in &GpuScope::ALL {
+			let start = timestamps[scope.query() as usize];
+			let end = timestamps[scope.query() as usize + 1];
+			let ticks = end.wrapping_sub(start) & self.mask;
+			stats.add_gpu_time(scope.name(), Duration::from_nanos((ticks as f64 * self.period) as u64));
+		}
+	}
+}
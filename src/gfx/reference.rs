@@ -0,0 +1,217 @@
+//! A CPU copy of the raymarch in `shader.frag`, for checking the GPU's frames against. It's slow and single threaded,
+//! so it's only meant for small images. Keep it in step with the shader, since any difference shows up as a mismatch.
+//!
+//! Both it and the GPU are checked against a golden image, so a change to either that changes what's drawn fails a
+//! test. After changing how the volume is drawn on purpose, run the tests with `SPACE_THING_UPDATE_GOLDEN` set to save
+//! the reference's frame as the new golden image.
+
+use crate::gfx::{frame::FrameUniforms, volume::table_size};
+use nalgebra::{Vector2, Vector3, Vector4};
+use sim::{
+	math::Ray,
+	world::{World, CHUNK_SIZE, EMPTY_DISTANCE},
+};
+
+// the same constants as `shader.frag`
+const PALETTE: [[f32; 3]; 4] = [[0.8; 3], [0.55, 0.4, 0.3], [0.35, 0.55, 0.3], [0.6, 0.6, 0.7]];
+const MARCH_STEPS: usize = 256;
+const MAX_HIT_DISTANCE: f32 = 0.5;
+const SHADOW_STEPS: usize = 32;
+const SHADOW_DISTANCE: f32 = 64.0;
+const SHADOW_HARDNESS: f32 = 16.0;
+
+/// Draws `world` the way the volume shader would without jitter, as tightly packed RGBA rows from the top like
+/// `Offscreen::render`. `radius` is the one the `WorldVolumes` were made with, and pixels whose rays miss are the clear
+/// color.
+pub fn render(world: &World, uniforms: &FrameUniforms, radius: i32) -> Vec<u8> {
+	let (width, height) = (uniforms.resolution.x as usize, uniforms.resolution.y as usize);
+	let max_distance = (2 * table_size(radius) * CHUNK_SIZE as i32) as f32;
+	let mut pixels = Vec::with_capacity(width * height * 4);
+	for y in 0..height {
+		for x in 0..width {
+			// fragments are shaded at pixel centers
+			let frag_coord = Vector2::new(x as f32 + 0.5, y as f32 + 0.5);
+			let color = shade(world, uniforms, max_distance, frag_coord).unwrap_or_else(Vector3::zeros);
+			let unorm = |x: f32| (x.max(0.0).min(1.0) * 255.0).round() as u8;
+			pixels.extend_from_slice(&[unorm(color.x), unorm(color.y), unorm(color.z), 255]);
+		}
+	}
+	pixels
+}
+
+/// How much two images differ.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Difference {
+	/// The largest difference in any channel of any pixel.
+	pub max: u8,
+	/// How many pixels have a channel that differs by more than the tolerance.
+	pub pixels: usize,
+}
+
+/// Compares two RGBA images of the same size, like the ones from `render` and `Offscreen::render`.
+pub fn compare(a: &[u8], b: &[u8], tolerance: u8) -> Difference {
+	assert_eq!(a.len(), b.len());
+	let mut diff = Difference::default();
+	for (a, b) in a.chunks(4).zip(b.chunks(4)) {
+		let max = a.iter().zip(b).map(|(&a, &b)| (a as i16 - b as i16).abs() as u8).max().unwrap_or(0);
+		diff.max = diff.max.max(max);
+		if max > tolerance {
+			diff.pixels += 1;
+		}
+	}
+	diff
+}
+
+/// The color of the pixel at `frag_coord`, or `None` if its ray misses, where the shader discards it.
+fn shade(world: &World, uniforms: &FrameUniforms, max_distance: f32, frag_coord: Vector2<f32>) -> Option<Vector3<f32>> {
+	let ndc = frag_coord.component_div(&uniforms.resolution) * 2.0 - Vector2::repeat(1.0);
+	let dir = view_ray(uniforms, ndc);
+	let pixel = (view_ray(uniforms, ndc + Vector2::new(0.0, 2.0 / uniforms.resolution.y)) - dir).norm();
+
+	let origin = uniforms.cam_pos;
+	// `World::skip_empty` skips the same chunks and bricks as the shader's
+	let ray = Ray::new(origin, dir);
+	let mut sampler = world.sampler();
+	let mut distance = EMPTY_DISTANCE;
+	let mut t = 0.0;
+	for _ in 0..MARCH_STEPS {
+		if t >= max_distance {
+			break;
+		}
+		t = world.skip_empty(&ray, t).unwrap_or(t);
+		distance = sampler.sample(origin + dir * t);
+		if distance <= (pixel * t).min(MAX_HIT_DISTANCE) {
+			break;
+		}
+		t += distance;
+	}
+	if distance > (pixel * t).min(MAX_HIT_DISTANCE) {
+		return None;
+	}
+
+	let pos = origin + dir * t;
+	let light = &uniforms.light;
+	let n = world.normal(pos);
+	let sun_dir = light.sun_dir.xyz();
+	let mut sun = n.dot(&sun_dir).max(0.0);
+	if sun > 0.0 {
+		sun *= soft_shadow(world, pos + n * 0.01, sun_dir, 0.01);
+	}
+	let material = world.material(pos.map(|x| (x + 0.5).floor() as i32)).unwrap_or(0);
+	let albedo = Vector3::from(PALETTE[material as usize % PALETTE.len()]);
+	Some((light.ambient.xyz() + light.sun_color.xyz() * sun).component_mul(&albedo))
+}
+
+/// Like `view_ray` in the shader, with `ndc` in Vulkan's convention.
+fn view_ray(uniforms: &FrameUniforms, ndc: Vector2<f32>) -> Vector3<f32> {
+	// the projection is OpenGL style, so Y is flipped
+	let unproject = |z| {
+		let pos = uniforms.inv_view_proj * Vector4::new(ndc.x, -ndc.y, z, 1.0);
+		pos.xyz() / pos.w
+	};
+	(unproject(1.0) - unproject(-1.0)).normalize()
+}
+
+fn soft_shadow(world: &World, pos: Vector3<f32>, dir: Vector3<f32>, start: f32) -> f32 {
+	let ray = Ray::new(pos, dir);
+	let mut sampler = world.sampler();
+	let mut light: f32 = 1.0;
+	let mut t = start;
+	for _ in 0..SHADOW_STEPS {
+		if t >= SHADOW_DISTANCE {
+			break;
+		}
+		t = world.skip_empty(&ray, t).unwrap_or(t);
+		let distance = sampler.sample(pos + dir * t);
+		if distance < 0.0001 {
+			return 0.0;
+		}
+		light = light.min(SHADOW_HARDNESS * distance / t);
+		t += distance;
+	}
+	light
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		camera::Camera,
+		gfx::{offscreen::Offscreen, volume::WorldVolumes, window::RenderSettings, Gfx},
+		lighting::{DayCycle, Light},
+		scene::Scene,
+		settings::Settings,
+	};
+	use futures::executor::block_on;
+	use sim::world::{
+		edit::{Edit, Shape},
+		Chunk,
+	};
+	use std::{env, path::PathBuf};
+	use vulkan::Extent2D;
+
+	/// Small, since the reference is slow.
+	const EXTENT: Extent2D = Extent2D { width: 64, height: 48 };
+	/// How far each channel of a pixel can be from the golden image before it counts as different. Floats differ a
+	/// little between the GPU and the CPU.
+	const TOLERANCE: u8 = 4;
+	/// The fraction of pixels that can differ, since rays that graze an edge can land on either side of it.
+	const MISMATCH: f32 = 0.02;
+	/// The radius of the volumes the GPU draws the world with.
+	const RADIUS: i32 = 1;
+
+	fn golden_path() -> PathBuf {
+		PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/gfx/reference/sphere.png")
+	}
+
+	/// A sphere in the middle of the only chunk.
+	fn world() -> World {
+		let mut world = World::new();
+		world.insert_chunk(Vector3::zeros(), Chunk::new());
+		let center = Vector3::repeat(CHUNK_SIZE as f32 / 2.0);
+		world.edit(Edit::Add(Shape::Sphere { center, radius: 8.0 }, 1));
+		world
+	}
+
+	/// Looking at the sphere from the side, from outside the chunk.
+	fn view() -> (Camera, Light) {
+		let camera = Camera::new(Vector3::new(16.0, -4.0, 18.0), &Settings::default());
+		(camera, DayCycle::new(60.0).light())
+	}
+
+	fn assert_matches_golden(pixels: &[u8]) {
+		let golden = image::open(golden_path()).unwrap().into_rgba8().into_raw();
+		let diff = compare(pixels, &golden, TOLERANCE);
+		let allowed = (EXTENT.width * EXTENT.height) as f32 * MISMATCH;
+		assert!(
+			diff.pixels as f32 <= allowed,
+			"{} pixels differ from the golden image, by up to {}",
+			diff.pixels,
+			diff.max
+		);
+	}
+
+	#[test]
+	fn reference_matches_golden() {
+		let (camera, light) = view();
+		let pixels = render(&world(), &FrameUniforms::new(&camera, &light, 0.0, EXTENT), RADIUS);
+		if env::var_os("SPACE_THING_UPDATE_GOLDEN").is_some() {
+			image::save_buffer(golden_path(), &pixels, EXTENT.width, EXTENT.height, image::ColorType::Rgba8).unwrap();
+		}
+		assert_matches_golden(&pixels);
+	}
+
+	/// Needs a GPU, so it only runs with `cargo test -- --ignored`.
+	#[test]
+	#[ignore]
+	fn gpu_matches_golden() {
+		let gfx = block_on(Gfx::new(None, None)).unwrap();
+		let render = RenderSettings { msaa: 1, ..RenderSettings::default() };
+		let mut offscreen = Offscreen::new(gfx.clone(), EXTENT, &render).unwrap();
+		let world = world();
+		let mut volumes = WorldVolumes::new(gfx, RADIUS, 1);
+		volumes.update(&world, &[], |_| 0);
+		let (camera, light) = view();
+		assert_matches_golden(&offscreen.render(&camera, &light, 0.0, &mut volumes, &Scene::new()));
+	}
+}
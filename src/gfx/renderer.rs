@@ -0,0 +1,353 @@
+//! The parts of a frame that draw inside a window's render passes. Each is a stage with its own pipelines and state, so
+//! a new one only has to be added to `Renderer::new`, not to `Window::draw`.
+
+#[cfg(feature = "debug-overlay")]
+use crate::gfx::debug_overlay::DebugOverlay;
+use crate::{
+	gfx::{
+		graph::{BufferId, RenderGraph, Usage},
+		gui::{render::GuiRenderer, Document},
+		lines::{DebugLines, LineRenderer},
+		mesh::MeshRenderer,
+		terrain::TerrainMeshes,
+		visibility::ChunkVisibility,
+		window::{viewport, DebugView},
+		Gfx,
+	},
+	scene::Scene,
+};
+use ash::vk;
+use nalgebra::Matrix4;
+use sim::{
+	math::Aabb,
+	threads::{JobHandle, JobKind, JOBS},
+};
+use std::{mem::size_of, sync::Arc};
+use typenum::B1;
+use vulkan::{
+	command::{CommandBuffer, CommandPool, InheritanceInfo},
+	descriptor::DescriptorSet,
+	image::Framebuffer,
+	pipeline::{Pipeline, PipelineLayout, PushConstantRange},
+	render_pass::RenderPass,
+	Extent2D, Rect2D,
+};
+
+/// Which of the window's render passes a stage draws in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layer {
+	/// The scene, depth tested and multisampled, at the internal render resolution.
+	World,
+	/// Drawn over the upscaled world at the window's full resolution, without depth or multisampling.
+	Ui,
+}
+
+/// A render pass and the framebuffer it draws into this frame.
+#[derive(Clone, Copy)]
+pub struct Target<'a> {
+	pub render_pass: &'a Arc<RenderPass>,
+	pub framebuffer: &'a Arc<Framebuffer>,
+	pub extent: Extent2D,
+}
+
+/// Everything the stages draw from in one frame.
+pub struct FrameContext<'a> {
+	/// The index of the frame in flight, for stages that keep a copy of something for each.
+	pub frame: usize,
+	/// The frame in flight's pool, which secondary command buffers are recorded from.
+	pub cmdpool: &'a Arc<CommandPool>,
+	pub world: Target<'a>,
+	pub ui: Target<'a>,
+	pub frame_set: &'a Arc<DescriptorSet>,
+	/// The GPU copy of the world that the volumes march, for set 1 of their pipelines.
+	pub volume_set: &'a Arc<DescriptorSet>,
+	pub view_proj: Matrix4<f32>,
+	pub hidpi_factor: f32,
+	pub view: DebugView,
+	/// Whether the volumes jitter their rays, for the temporal pass to average out.
+	pub jitter: bool,
+	pub volumes: &'a [Aabb],
+	pub scene: &'a Scene,
+	/// Drawn with the scene's meshes if the debug view asks for them.
+	pub terrain: &'a TerrainMeshes,
+	pub lines: &'a mut DebugLines,
+	pub gui: &'a mut Document,
+	#[cfg(feature = "debug-overlay")]
+	pub overlay: &'a mut DebugOverlay,
+}
+impl<'a> FrameContext<'a> {
+	pub fn target(&self, layer: Layer) -> Target<'a> {
+		match layer {
+			Layer::World => self.world,
+			Layer::Ui => self.ui,
+		}
+	}
+
+	/// For recording secondary command buffers that run in `layer`'s render pass.
+	pub fn inherit(&self, layer: Layer) -> InheritanceInfo {
+		let target = self.target(layer);
+		let (render_pass, framebuffer) = (target.render_pass.clone(), Some(target.framebuffer.clone()));
+		InheritanceInfo { render_pass, subpass: 0, framebuffer }
+	}
+}
+
+/// Something drawn in one of the render passes. Every stage is prepared before any of them records, and they're
+/// recorded last to first, so work started on the job workers in `prepare` runs while the later stages record.
+pub trait RenderPassStage {
+	fn layer(&self) -> Layer {
+		Layer::World
+	}
+
+	fn prepare(&mut self, _ctx: &mut FrameContext) {}
+
+	/// Returns secondary command buffers, which run in order after the previous stage's in the same layer.
+	fn record(&mut self, ctx: &mut FrameContext) -> Vec<Arc<CommandBuffer<B1>>>;
+
+	/// Adds the passes that the stage's draws depend on, like compute work, which the render graph runs before the
+	/// stage's layer. Returns the buffers the draws read, and how. Called after `record`.
+	fn add_passes(&mut self, _graph: &mut RenderGraph, _ctx: &FrameContext) -> Vec<(BufferId, Usage)> {
+		vec![]
+	}
+
+	/// Rebuilds the stage's pipelines from the current shader modules, after they've been hot reloaded. `render_pass`
+	/// and `samples` are for the stage's layer.
+	#[cfg(debug_assertions)]
+	fn reload_pipeline(&mut self, _render_pass: Arc<RenderPass>, _samples: vk::SampleCountFlags) {}
+}
+
+/// The stages of a window's render passes, in the order they're drawn.
+pub struct Renderer {
+	stages: Vec<Box<dyn RenderPassStage>>,
+}
+impl Renderer {
+	/// Draws the volumes first, then the scene's meshes and the debug lines, which are depth tested against them, all
+	/// in `world`. The GUI and debug overlay go in `ui`, which is always single sampled. `frames` is how many frames
+	/// can be in flight.
+	pub fn new(
+		gfx: &Arc<Gfx>,
+		world: &Arc<RenderPass>,
+		samples: vk::SampleCountFlags,
+		ui: &Arc<RenderPass>,
+		anisotropy: f32,
+		frames: usize,
+	) -> Self {
+		let stages: Vec<Box<dyn RenderPassStage>> = vec![
+			Box::new(VolumeStage::new(gfx.clone(), world.clone(), samples, frames)),
+			Box::new(MeshStage(MeshRenderer::new(gfx.clone(), world.clone(), samples, anisotropy))),
+			Box::new(LineStage(LineRenderer::new(gfx.clone(), world.clone(), samples))),
+			Box::new(GuiStage(GuiRenderer::new(gfx.clone(), ui.clone(), vk::SampleCountFlags::TYPE_1))),
+			#[cfg(feature = "debug-overlay")]
+			Box::new(OverlayStage),
+		];
+		Self { stages }
+	}
+
+	/// Returns the world's and the UI's secondary command buffers, each in the order they should run.
+	pub fn record(&mut self, ctx: &mut FrameContext) -> (Vec<Arc<CommandBuffer<B1>>>, Vec<Arc<CommandBuffer<B1>>>) {
+		for stage in &mut self.stages {
+			stage.prepare(ctx);
+		}
+		let mut recorded =
+			self.stages.iter_mut().rev().map(|stage| (stage.layer(), stage.record(ctx))).collect::<Vec<_>>();
+		recorded.reverse();
+
+		let (mut world, mut ui) = (vec![], vec![]);
+		for (layer, commands) in recorded {
+			match layer {
+				Layer::World => world.extend(commands),
+				Layer::Ui => ui.extend(commands),
+			}
+		}
+		(world, ui)
+	}
+
+	/// Adds every stage's passes to `graph`. Returns the buffers that the world's and the UI's draws read, for
+	/// declaring on the passes that run them.
+	pub fn add_passes(
+		&mut self,
+		graph: &mut RenderGraph,
+		ctx: &FrameContext,
+	) -> (Vec<(BufferId, Usage)>, Vec<(BufferId, Usage)>) {
+		let (mut world, mut ui) = (vec![], vec![]);
+		for stage in &mut self.stages {
+			let uses = stage.add_passes(graph, ctx);
+			match stage.layer() {
+				Layer::World => world.extend(uses),
+				Layer::Ui => ui.extend(uses),
+			}
+		}
+		(world, ui)
+	}
+
+	#[cfg(debug_assertions)]
+	pub fn reload_pipelines(&mut self, world: &Arc<RenderPass>, samples: vk::SampleCountFlags, ui: &Arc<RenderPass>) {
+		for stage in &mut self.stages {
+			match stage.layer() {
+				Layer::World => stage.reload_pipeline(world.clone(), samples),
+				Layer::Ui => stage.reload_pipeline(ui.clone(), vk::SampleCountFlags::TYPE_1),
+			}
+		}
+	}
+}
+
+/// Raymarches the volumes inside the camera's frustum, each in a box drawn around it so only the pixels it covers are
+/// marched. Which ones are inside is decided on the GPU, and they're all drawn with one indirect draw, which is
+/// recorded on the job workers along with uploading the volumes' bounds.
+struct VolumeStage {
+	gfx: Arc<Gfx>,
+	visibility: ChunkVisibility,
+	layout: Arc<PipelineLayout>,
+	pipeline: Arc<Pipeline>,
+	/// Started in `prepare`, and waited on in `record`.
+	job: Option<JobHandle<Arc<CommandBuffer<B1>>>>,
+}
+impl VolumeStage {
+	fn new(gfx: Arc<Gfx>, render_pass: Arc<RenderPass>, samples: vk::SampleCountFlags, frames: usize) -> Self {
+		let visibility = ChunkVisibility::new(gfx.clone(), frames);
+		let push_constants = [PushConstantRange::builder()
+			.stage_flags(vk::ShaderStageFlags::FRAGMENT)
+			.size(size_of::<u32>() as _)
+			.build()];
+		let set_layouts =
+			vec![gfx.frame_set_layout.clone(), gfx.volume_set_layout.clone(), visibility.set_layout().clone()];
+		let layout = gfx.device.create_pipeline_layout(set_layouts, &push_constants);
+		let pipeline = create_volume_pipeline(&gfx, layout.clone(), render_pass, samples);
+		Self { gfx, visibility, layout, pipeline, job: None }
+	}
+}
+impl RenderPassStage for VolumeStage {
+	fn prepare(&mut self, ctx: &mut FrameContext) {
+		let visible = match self.visibility.prepare(ctx.frame, ctx.volumes.len()) {
+			Some(visible) => visible,
+			None => return,
+		};
+		self.gfx.stats().add_draws(1);
+
+		let (cmdpool, inherit, extent) = (ctx.cmdpool.clone(), ctx.inherit(Layer::World), ctx.world.extent);
+		let (layout, pipeline) = (self.layout.clone(), self.pipeline.clone());
+		let (frame_set, volume_set) = (ctx.frame_set.clone(), ctx.volume_set.clone());
+		let (volumes, flags) = (ctx.volumes.to_vec(), ctx.view.volume_flags(ctx.jitter));
+		let job = JOBS.spawn(JobKind::Frame, move || {
+			// `FramesInFlight::begin` waited until the GPU was done with this frame's bounds
+			unsafe { visible.write_bounds(&volumes) };
+			cmdpool
+				.record_secondary(true, false, Some(inherit))
+				.begin_label("volumes")
+				.bind_pipeline(pipeline)
+				.set_viewport(0, &[viewport(extent)])
+				.set_scissor(0, &[Rect2D::builder().extent(extent).build()])
+				.bind_descriptor_sets(layout.clone(), 0, vec![frame_set, volume_set, visible.set])
+				.push_constants(layout, vk::ShaderStageFlags::FRAGMENT, 0, &flags)
+				.draw_indirect(visible.draw, 0, 1)
+				.end_label()
+				.build()
+		});
+		self.job = Some(job);
+	}
+
+	fn record(&mut self, _ctx: &mut FrameContext) -> Vec<Arc<CommandBuffer<B1>>> {
+		// runs the job here if the workers haven't started it instead of only waiting for it
+		JOBS.wait_frame();
+		self.job.take().map(JobHandle::wait).into_iter().collect()
+	}
+
+	fn add_passes(&mut self, graph: &mut RenderGraph, ctx: &FrameContext) -> Vec<(BufferId, Usage)> {
+		self.visibility.add_passes(graph, ctx.frame, &ctx.view_proj)
+	}
+
+	#[cfg(debug_assertions)]
+	fn reload_pipeline(&mut self, render_pass: Arc<RenderPass>, samples: vk::SampleCountFlags) {
+		self.pipeline = create_volume_pipeline(&self.gfx, self.layout.clone(), render_pass, samples);
+		self.visibility.reload_pipeline();
+	}
+}
+
+/// The scene's meshes, and the world's when it's meshed.
+struct MeshStage(MeshRenderer);
+impl RenderPassStage for MeshStage {
+	fn prepare(&mut self, ctx: &mut FrameContext) {
+		self.0.wireframe = ctx.view.wireframe;
+	}
+
+	fn record(&mut self, ctx: &mut FrameContext) -> Vec<Arc<CommandBuffer<B1>>> {
+		let inherit = ctx.inherit(Layer::World);
+		let mut draws = ctx.scene.mesh_instances();
+		if ctx.view.meshed_world {
+			draws.extend(ctx.terrain.draws());
+		}
+		self.0.record(ctx.cmdpool, inherit, ctx.world.extent, ctx.frame_set.clone(), &draws).into_iter().collect()
+	}
+
+	#[cfg(debug_assertions)]
+	fn reload_pipeline(&mut self, render_pass: Arc<RenderPass>, samples: vk::SampleCountFlags) {
+		self.0.reload_pipeline(render_pass, samples);
+	}
+}
+
+/// The frame's debug lines, which are cleared once they're recorded.
+struct LineStage(LineRenderer);
+impl RenderPassStage for LineStage {
+	fn record(&mut self, ctx: &mut FrameContext) -> Vec<Arc<CommandBuffer<B1>>> {
+		let inherit = ctx.inherit(Layer::World);
+		self.0.record(ctx.cmdpool, inherit, ctx.world.extent, ctx.frame_set.clone(), ctx.lines).into_iter().collect()
+	}
+
+	#[cfg(debug_assertions)]
+	fn reload_pipeline(&mut self, render_pass: Arc<RenderPass>, samples: vk::SampleCountFlags) {
+		self.0.reload_pipeline(render_pass, samples);
+	}
+}
+
+struct GuiStage(GuiRenderer);
+impl RenderPassStage for GuiStage {
+	fn layer(&self) -> Layer {
+		Layer::Ui
+	}
+
+	fn record(&mut self, ctx: &mut FrameContext) -> Vec<Arc<CommandBuffer<B1>>> {
+		let inherit = ctx.inherit(Layer::Ui);
+		self.0.record(ctx.frame, ctx.cmdpool, inherit, ctx.gui, ctx.ui.extent).into_iter().collect()
+	}
+
+	#[cfg(debug_assertions)]
+	fn reload_pipeline(&mut self, render_pass: Arc<RenderPass>, samples: vk::SampleCountFlags) {
+		self.0.reload_pipeline(render_pass, samples);
+	}
+}
+
+/// The overlay lives in the window, since it takes input too, so its pipeline is reloaded there.
+#[cfg(feature = "debug-overlay")]
+struct OverlayStage;
+#[cfg(feature = "debug-overlay")]
+impl RenderPassStage for OverlayStage {
+	fn layer(&self) -> Layer {
+		Layer::Ui
+	}
+
+	fn record(&mut self, ctx: &mut FrameContext) -> Vec<Arc<CommandBuffer<B1>>> {
+		let inherit = ctx.inherit(Layer::Ui);
+		ctx.overlay.record(ctx.cmdpool, inherit, ctx.ui.extent, ctx.hidpi_factor).into_iter().collect()
+	}
+}
+
+/// Draws a box for each visible chunk, with their far sides facing the camera so it can be inside them.
+fn create_volume_pipeline(
+	gfx: &Gfx,
+	layout: Arc<PipelineLayout>,
+	render_pass: Arc<RenderPass>,
+	samples: vk::SampleCountFlags,
+) -> Arc<Pipeline> {
+	let shaders = gfx.shaders();
+	let pipeline = gfx
+		.device
+		.build_pipeline(layout, render_pass)
+		.vertex_shader(shaders.volume_vert.clone())
+		.fragment_shader(shaders.frag.clone())
+		.cull_mode(vk::CullModeFlags::FRONT)
+		.dynamic_viewport()
+		.depth_test(true)
+		.samples(samples)
+		.build();
+	gfx.device.set_object_name(pipeline.vk, "volumes");
+	pipeline
+}
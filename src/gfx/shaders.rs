@@ -0,0 +1,218 @@
+use crate::gfx::error::GfxError;
+use byteorder::{ByteOrder, NativeEndian};
+#[cfg(debug_assertions)]
+use shaderc::{Compiler, ShaderKind};
+#[cfg(debug_assertions)]
+use std::hash::Hasher;
+#[cfg(debug_assertions)]
+use std::{
+	fs, io,
+	path::{Path, PathBuf},
+	time::{Duration, Instant, SystemTime},
+};
+
+macro_rules! shader {
+	($name:expr, $kind:ident) => {
+		Shader {
+			name: $name,
+			#[cfg(debug_assertions)]
+			kind: ShaderKind::$kind,
+			#[cfg(debug_assertions)]
+			source: include_str!(concat!("shaders/", $name)),
+			spv: include_bytes!(concat!(env!("OUT_DIR"), "/", $name, ".spv")),
+		}
+	};
+}
+
+pub static SHADER_VERT: Shader = shader!("shader.vert", Vertex);
+pub static SHADER_FRAG: Shader = shader!("shader.frag", Fragment);
+pub static GUI_VERT: Shader = shader!("gui.vert", Vertex);
+pub static GUI_FRAG: Shader = shader!("gui.frag", Fragment);
+pub static MESH_VERT: Shader = shader!("mesh.vert", Vertex);
+pub static MESH_FRAG: Shader = shader!("mesh.frag", Fragment);
+pub static LINES_VERT: Shader = shader!("lines.vert", Vertex);
+pub static LINES_FRAG: Shader = shader!("lines.frag", Fragment);
+pub static TEMPORAL_FRAG: Shader = shader!("temporal.frag", Fragment);
+pub static VOLUME_VERT: Shader = shader!("volume.vert", Vertex);
+pub static CULL_COMP: Shader = shader!("cull.comp", Compute);
+pub static MIPS_COMP: Shader = shader!("mips.comp", Compute);
+pub static EDIT_COMP: Shader = shader!("edit.comp", Compute);
+#[cfg(feature = "debug-overlay")]
+pub static EGUI_VERT: Shader = shader!("egui.vert", Vertex);
+#[cfg(feature = "debug-overlay")]
+pub static EGUI_FRAG: Shader = shader!("egui.frag", Fragment);
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+/// Where debug builds keep SPIR-V compiled at runtime, relative to the crate and named after a hash of the source.
+#[cfg(debug_assertions)]
+const CACHE_DIR: &str = "target/shader-cache";
+
+/// A shader compiled to SPIR-V by the build script and embedded in the binary.
+pub struct Shader {
+	name: &'static str,
+	#[cfg(debug_assertions)]
+	kind: ShaderKind,
+	/// The GLSL the embedded SPIR-V was compiled from.
+	#[cfg(debug_assertions)]
+	source: &'static str,
+	spv: &'static [u8],
+}
+impl Shader {
+	/// Returns the SPIR-V for this shader.
+	///
+	/// In debug builds the GLSL in the source tree is the source of truth. If it differs from what the binary was built
+	/// with, it's compiled at startup, and the result is cached by the source's hash so unchanged shaders aren't
+	/// compiled again on the next run. If that fails, the error is logged and the embedded binary is used.
+	pub async fn load(&self) -> Result<Vec<u32>, GfxError> {
+		#[cfg(debug_assertions)]
+		{
+			match self.compile_if_stale().await {
+				Ok(Some(code)) => return Ok(code),
+				Ok(None) => (),
+				Err(err) => log::error!("failed to recompile {}: {}", self.name, err),
+			}
+		}
+
+		if self.spv.len() % 4 != 0 {
+			return Err(self.error("the SPIR-V isn't a whole number of words"));
+		}
+		let mut code = vec![0; self.spv.len() / 4];
+		NativeEndian::read_u32_into(self.spv, &mut code);
+		if code.first() != Some(&SPIRV_MAGIC) {
+			return Err(self.error("the SPIR-V has the wrong magic number"));
+		}
+		Ok(code)
+	}
+
+	fn error(&self, reason: &str) -> GfxError {
+		GfxError::ShaderLoad { name: self.name, reason: reason.to_string() }
+	}
+
+	#[cfg(debug_assertions)]
+	async fn compile_if_stale(&self) -> io::Result<Option<Vec<u32>>> {
+		let source = match sim::fs::read_to_string(self.source_path()).await {
+			Ok(source) => source,
+			// the source tree isn't available, so the embedded binary is all we have
+			Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+			Err(err) => return Err(err),
+		};
+		if source == self.source {
+			return Ok(None);
+		}
+		self.compile_cached(&source).map(Some)
+	}
+
+	/// Compiles `source`, unless the same source has been compiled before and is in `CACHE_DIR`.
+	#[cfg(debug_assertions)]
+	fn compile_cached(&self, source: &str) -> io::Result<Vec<u32>> {
+		let mut hasher = Fnv1a::default();
+		hasher.write(source.as_bytes());
+		let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(CACHE_DIR);
+		let path = dir.join(format!("{}.{:016x}.spv", self.name, hasher.finish()));
+
+		if let Ok(bytes) = fs::read(&path) {
+			if bytes.len() % 4 == 0 {
+				let mut code = vec![0; bytes.len() / 4];
+				NativeEndian::read_u32_into(&bytes, &mut code);
+				return Ok(code);
+			}
+		}
+
+		log::info!("compiling {}", self.name);
+		let code = self.compile(source)?;
+		let mut bytes = vec![0; code.len() * 4];
+		NativeEndian::write_u32_into(&code, &mut bytes);
+		// a failed write only costs a compile on the next run
+		if let Err(err) = fs::create_dir_all(&dir).and_then(|_| fs::write(&path, bytes)) {
+			log::warn!("failed to cache {}: {}", self.name, err);
+		}
+		Ok(code)
+	}
+
+	#[cfg(debug_assertions)]
+	fn source_path(&self) -> PathBuf {
+		Path::new(env!("CARGO_MANIFEST_DIR")).join("src/gfx/shaders").join(self.name)
+	}
+
+	#[cfg(debug_assertions)]
+	fn source_modified(&self) -> Option<SystemTime> {
+		fs::metadata(self.source_path()).and_then(|meta| meta.modified()).ok()
+	}
+
+	#[cfg(debug_assertions)]
+	fn compile(&self, source: &str) -> io::Result<Vec<u32>> {
+		let mut compiler = Compiler::new().unwrap();
+		let artifact = compiler
+			.compile_into_spirv(source, self.kind, self.name, "main", None)
+			.map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+		Ok(artifact.as_binary().to_vec())
+	}
+}
+
+/// How often `ShaderWatcher` checks the sources. Checking every frame would stat every shader every frame.
+#[cfg(debug_assertions)]
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Recompiles shaders when their GLSL sources are saved, so pipelines can be rebuilt without restarting.
+#[cfg(debug_assertions)]
+pub struct ShaderWatcher {
+	shaders: Vec<(&'static Shader, Option<SystemTime>)>,
+	last_poll: Instant,
+}
+#[cfg(debug_assertions)]
+impl ShaderWatcher {
+	pub fn new(shaders: &[&'static Shader]) -> Self {
+		let shaders = shaders.iter().map(|&shader| (shader, shader.source_modified())).collect();
+		Self { shaders, last_poll: Instant::now() }
+	}
+
+	/// Returns the new SPIR-V of every shader whose source changed since the last poll. A shader that fails to compile
+	/// is logged and left out, so the old version keeps running until the error is fixed.
+	pub fn poll(&mut self) -> Vec<(&'static Shader, Vec<u32>)> {
+		if self.last_poll.elapsed() < POLL_INTERVAL {
+			return vec![];
+		}
+		self.last_poll = Instant::now();
+
+		let mut changed = vec![];
+		for (shader, modified) in &mut self.shaders {
+			let now = shader.source_modified();
+			if now == *modified {
+				continue;
+			}
+			*modified = now;
+
+			log::info!("{} changed, recompiling", shader.name);
+			match fs::read_to_string(shader.source_path()).and_then(|source| shader.compile_cached(&source)) {
+				Ok(code) => changed.push((*shader, code)),
+				Err(err) => log::error!("failed to recompile {}: {}", shader.name, err),
+			}
+		}
+		changed
+	}
+}
+
+/// The hash `CACHE_DIR` is keyed by. `DefaultHasher` isn't used because its output may change between Rust versions,
+/// which would orphan the cache.
+#[cfg(debug_assertions)]
+struct Fnv1a(u64);
+#[cfg(debug_assertions)]
+impl Default for Fnv1a {
+	fn default() -> Self {
+		Self(0xcbf2_9ce4_8422_2325)
+	}
+}
+#[cfg(debug_assertions)]
+impl Hasher for Fnv1a {
+	fn finish(&self) -> u64 {
+		self.0
+	}
+
+	fn write(&mut self, bytes: &[u8]) {
+		for &byte in bytes {
+			self.0 ^= byte as u64;
+			self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+		}
+	}
+}
@@ -0,0 +1,25 @@
+#version 450
+
+const uint MODE_SOLID = 0;
+const uint MODE_TEXT = 1;
+const uint MODE_IMAGE = 2;
+
+layout(set = 0, binding = 0) uniform sampler2D tex;
+
+layout(location = 0) in vec2 in_uv;
+layout(location = 1) in vec4 in_color;
+layout(location = 2) flat in uint in_mode;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+	if (in_mode == MODE_TEXT) {
+		float dist = texture(tex, in_uv).r;
+		float width = fwidth(dist) * 0.5;
+		out_color = vec4(in_color.rgb, in_color.a * smoothstep(0.5 - width, 0.5 + width, dist));
+	} else if (in_mode == MODE_IMAGE) {
+		out_color = texture(tex, in_uv) * in_color;
+	} else {
+		out_color = in_color;
+	}
+}
@@ -0,0 +1,24 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform Frame {
+	mat4 view_proj;
+	mat4 inv_view_proj;
+	vec4 sun_dir;
+	vec4 sun_color;
+	vec4 ambient;
+	vec3 cam_pos;
+	float time;
+	vec2 resolution;
+} frame;
+
+layout(location = 0) in vec3 in_pos;
+layout(location = 1) in vec4 in_color;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+	vec4 pos = frame.view_proj * vec4(in_pos, 1.0);
+	// the projection is OpenGL style, so flip Y and squash depth from [-1, 1] to [0, 1]
+	gl_Position = vec4(pos.x, -pos.y, (pos.z + pos.w) * 0.5, pos.w);
+	out_color = in_color;
+}
@@ -0,0 +1,30 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform Frame {
+	mat4 view_proj;
+	mat4 inv_view_proj;
+	vec4 sun_dir;
+	vec4 sun_color;
+	vec4 ambient;
+	vec3 cam_pos;
+	float time;
+	vec2 resolution;
+} frame;
+
+layout(push_constant) uniform PushConstants {
+	vec4 material_color;
+};
+
+layout(set = 1, binding = 0) uniform sampler2D tex;
+
+layout(location = 0) in vec3 in_normal;
+layout(location = 1) in vec2 in_uv;
+layout(location = 2) in vec4 in_color;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+	vec4 color = texture(tex, in_uv) * material_color * in_color;
+	float sun = max(dot(normalize(in_normal), frame.sun_dir.xyz), 0.0);
+	out_color = vec4(color.rgb * (frame.ambient.rgb + frame.sun_color.rgb * sun), color.a);
+}
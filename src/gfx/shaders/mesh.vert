@@ -0,0 +1,32 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform Frame {
+	mat4 view_proj;
+	mat4 inv_view_proj;
+	vec4 sun_dir;
+	vec4 sun_color;
+	vec4 ambient;
+	vec3 cam_pos;
+	float time;
+	vec2 resolution;
+} frame;
+
+layout(location = 0) in vec3 in_pos;
+layout(location = 1) in vec3 in_normal;
+layout(location = 2) in vec2 in_uv;
+layout(location = 3) in mat4 in_model;
+layout(location = 7) in vec4 in_color;
+
+layout(location = 0) out vec3 out_normal;
+layout(location = 1) out vec2 out_uv;
+layout(location = 2) out vec4 out_color;
+
+void main() {
+	vec4 pos = frame.view_proj * in_model * vec4(in_pos, 1.0);
+	// the projection is OpenGL style, so flip Y and squash depth from [-1, 1] to [0, 1]
+	gl_Position = vec4(pos.x, -pos.y, (pos.z + pos.w) * 0.5, pos.w);
+	// transforms only scale uniformly, so the model matrix keeps normals perpendicular
+	out_normal = mat3(in_model) * in_normal;
+	out_uv = in_uv;
+	out_color = in_color;
+}
@@ -1,43 +1,340 @@
 #version 450
 
-layout(location = 0) in vec2 in_pos;
+layout(set = 0, binding = 0) uniform Frame {
+	mat4 view_proj;
+	mat4 inv_view_proj;
+	vec4 sun_dir;
+	vec4 sun_color;
+	vec4 ambient;
+	vec3 cam_pos;
+	float time;
+	vec2 resolution;
+} frame;
+
+// the GPU copy of the world, from WorldVolumes. each chunk with a surface has its voxels in a cell of the atlas, which
+// is a whole slot at full resolution and an eighth of the size for each level of detail below that
+layout(set = 1, binding = 0, r8_snorm) uniform readonly image3D atlas;
+
+struct ChunkEntry {
+	ivec3 key;
+	// the slot, then 3 bits for each level of detail saying which eighth of the last level's cell it is
+	uint cell;
+	uint lod;
+};
+
+// which cell holds each chunk, indexed by the chunk's key wrapped around the table's size
+layout(set = 1, binding = 1) readonly buffer Chunks {
+	ChunkEntry table[];
+};
+
+// a texel for each brick of each slot in the atlas, which is 0 if every voxel in the brick is empty
+layout(set = 1, binding = 2, r8ui) uniform readonly uimage3D occupancy;
+
+// levels 1 to 4 of the atlas. each texel is the minimum of the ones under it, so 1 means they're all empty
+layout(set = 1, binding = 3, r8_snorm) uniform readonly image3D mips[4];
+
+// the material of each voxel in the atlas
+layout(set = 1, binding = 4, r8ui) uniform readonly uimage3D materials;
+
+layout(push_constant) uniform PushConstants {
+	uint flags;
+};
 
 layout(location = 0) out vec4 out_color;
 
-vec4 cam_proj = vec4(0.5625, 1, -1.002002, -1.001001);
-vec3 cam_pos = vec3(0, -5, 0);
-vec4 cam_rot = vec4(0, 0, 0, 1);
-float sphere_radius = 1;
+// colors each pixel by how many steps its ray took instead of shading it
+const uint FLAG_STEP_HEATMAP = 1;
+// moves rays around inside their pixels and shadow rays along themselves every frame, for the temporal pass to average
+const uint FLAG_JITTER = 2;
+
+// the same as the world's
+const int CHUNK_SHIFT = 5;
+const int CHUNK_SIZE = 1 << CHUNK_SHIFT;
+const int CHUNK_MASK = CHUNK_SIZE - 1;
+const int MIP_LEVELS = 4;
+// what unloaded space reads as
+const float EMPTY_DISTANCE = 1;
+// a table entry whose chunk isn't in the atlas, because it's empty or unloaded
+const uint NO_CELL = 0xffffffff;
+
+// the color of each material. materials past the end wrap around
+const vec3 PALETTE[4] = vec3[](vec3(0.8), vec3(0.55, 0.4, 0.3), vec3(0.35, 0.55, 0.3), vec3(0.6, 0.6, 0.7));
+// distances are clamped to a voxel, so each step moves at most that far
+const int MARCH_STEPS = 256;
+// the most a hit can be from the surface. distances are clamped, so past this far a pixel is wide enough that empty
+// space would count as a hit
+const float MAX_HIT_DISTANCE = 0.5;
+const int SHADOW_STEPS = 32;
+const float SHADOW_DISTANCE = 64;
+// higher makes shadow edges sharper
+const float SHADOW_HARDNESS = 16;
+
+// entries along each edge of the table, which is a power of two. set at the start of main
+int table_size;
+
+// where a resident chunk is in the atlas
+struct Cell {
+	// the texel of its first voxel
+	ivec3 origin;
+	// each level halves the resolution, so a texel covers 1 << lod voxels along each edge
+	int lod;
+};
+
+// the first voxel of a slot in the atlas
+ivec3 slot_origin(uint slot) {
+	ivec3 slots = imageSize(atlas) / CHUNK_SIZE;
+	int i = int(slot);
+	return ivec3(i % slots.x, i / slots.x % slots.y, i / (slots.x * slots.y)) * CHUNK_SIZE;
+}
+
+// finds the cell holding the chunk at key. returns false if it isn't resident
+bool find_cell(ivec3 key, out Cell cell) {
+	ivec3 wrapped = key & (table_size - 1);
+	ChunkEntry entry = table[wrapped.x + (wrapped.y + wrapped.z * table_size) * table_size];
+	if (entry.key != key || entry.cell == NO_CELL) {
+		return false;
+	}
+	int lod = int(entry.lod);
+	int cells = 1 << lod;
+	int sub = int(entry.cell) & ((1 << (3 * lod)) - 1);
+	ivec3 offset = ivec3(sub % cells, sub / cells % cells, sub / (cells * cells));
+	cell.origin = slot_origin(entry.cell >> (3 * lod)) + offset * (CHUNK_SIZE >> lod);
+	cell.lod = lod;
+	return true;
+}
+
+// the texel in the atlas holding the voxel at local, a position in the cell's chunk
+ivec3 cell_texel(Cell cell, ivec3 local) {
+	return cell.origin + (local >> cell.lod);
+}
+
+// the distance stored for a single voxel
+float voxel(ivec3 pos) {
+	Cell cell;
+	if (!find_cell(pos >> CHUNK_SHIFT, cell)) {
+		return EMPTY_DISTANCE;
+	}
+	return imageLoad(atlas, cell_texel(cell, pos & CHUNK_MASK)).r;
+}
+
+float trilinear(float corners[8], vec3 t) {
+	float x00 = mix(corners[0], corners[1], t.x);
+	float x10 = mix(corners[2], corners[3], t.x);
+	float x01 = mix(corners[4], corners[5], t.x);
+	float x11 = mix(corners[6], corners[7], t.x);
+	return mix(mix(x00, x10, t.y), mix(x01, x11, t.y), t.z);
+}
+
+// the distance at pos in the chunk at key, whose cell is at a lower level of detail. each texel is centered on the
+// block of voxels it covers, and the ones next to the cell's edges are clamped to it rather than blended with the
+// neighboring chunks, which may be at other levels
+float coarse_distance(vec3 pos, ivec3 key, Cell cell) {
+	float scale = float(1 << cell.lod);
+	vec3 texel_pos = (pos - vec3(key * CHUNK_SIZE) + 0.5) / scale - 0.5;
+	vec3 floor_pos = floor(texel_pos);
+	ivec3 min_texel = ivec3(floor_pos);
+	ivec3 max_texel = ivec3((CHUNK_SIZE >> cell.lod) - 1);
+	float corners[8];
+	for (int i = 0; i < 8; ++i) {
+		ivec3 texel = clamp(min_texel + ivec3(i & 1, (i >> 1) & 1, i >> 2), ivec3(0), max_texel);
+		corners[i] = imageLoad(atlas, cell.origin + texel).r;
+	}
+	return trilinear(corners, texel_pos - floor_pos);
+}
 
+// the trilinearly interpolated distance at pos, like Sampler::sample. the atlas is read a texel at a time, since
+// filtering would blend cells together and only interpolates with 8 bits of precision
 float F(vec3 pos) {
-	return length(pos) - 1;
+	vec3 floor_pos = floor(pos);
+	ivec3 min_voxel = ivec3(floor_pos);
+	ivec3 key = min_voxel >> CHUNK_SHIFT;
+	Cell cell;
+	bool resident = find_cell(key, cell);
+	if (resident && cell.lod > 0) {
+		return coarse_distance(pos, key, cell);
+	}
+
+	float corners[8];
+	ivec3 local = min_voxel & CHUNK_MASK;
+	if (all(lessThan(local, ivec3(CHUNK_MASK)))) {
+		// the whole neighborhood is in one chunk, so it only has to be found once
+		if (!resident) {
+			return EMPTY_DISTANCE;
+		}
+		ivec3 base = cell.origin + local;
+		for (int i = 0; i < 8; ++i) {
+			corners[i] = imageLoad(atlas, base + ivec3(i & 1, (i >> 1) & 1, i >> 2)).r;
+		}
+	} else {
+		for (int i = 0; i < 8; ++i) {
+			corners[i] = voxel(min_voxel + ivec3(i & 1, (i >> 1) & 1, i >> 2));
+		}
+	}
+	return trilinear(corners, pos - floor_pos);
 }
 
-vec3 perspective(vec4 proj, vec3 pos) {
-	return vec3(pos.xy * proj.xy, pos.z * proj.z + proj.w);
+// the material of the voxel nearest pos, which is 0 where its chunk isn't resident
+uint material(vec3 pos) {
+	ivec3 voxel = ivec3(floor(pos + 0.5));
+	Cell cell;
+	if (!find_cell(voxel >> CHUNK_SHIFT, cell)) {
+		return 0;
+	}
+	return imageLoad(materials, cell_texel(cell, voxel & CHUNK_MASK)).r;
 }
 
-vec3 quat_mul(vec4 quat, vec3 vec) {
-	return cross(quat.xyz, cross(quat.xyz, vec) + vec * quat.w) * 2.0 + vec;
+// whether every voxel is empty in the block of the atlas that a texel of the given level covers, where voxel is in
+// the block. level 3 is the bricks, whose occupancy is a byte instead of a distance
+bool block_empty(int level, ivec3 voxel) {
+	ivec3 texel = voxel >> level;
+	switch (level) {
+	case 1:
+		return imageLoad(mips[0], texel).r >= EMPTY_DISTANCE;
+	case 2:
+		return imageLoad(mips[1], texel).r >= EMPTY_DISTANCE;
+	case 3:
+		return imageLoad(occupancy, texel).r == 0;
+	default:
+		return imageLoad(mips[3], texel).r >= EMPTY_DISTANCE;
+	}
+}
+
+// the largest t the ray can skip ahead to from t without passing anything, like World::skip_empty. that's where it
+// leaves the chunk it's in if that chunk isn't resident, or else the coarsest empty block of the mips it's in. samples
+// interpolate between neighboring texels, so they only stay in a region up to the center of its last one
+float skip_empty(vec3 origin, vec3 dir, float t) {
+	ivec3 voxel = ivec3(floor(origin + dir * t));
+	ivec3 key = voxel >> CHUNK_SHIFT;
+	ivec3 region_min = key * CHUNK_SIZE;
+	int region_size = CHUNK_SIZE;
+	// the voxels a texel covers along each edge
+	int texel_size = 1;
+	Cell cell;
+	if (find_cell(key, cell)) {
+		ivec3 local = voxel & CHUNK_MASK;
+		ivec3 texel = cell_texel(cell, local);
+		// a block that's empty is in empty blocks at every finer level, so the first found from the top is the
+		// biggest. a cell at a lower level of detail is smaller in the atlas, so its blocks stop at fewer levels
+		int level = min(MIP_LEVELS, CHUNK_SHIFT - cell.lod);
+		while (level > 0 && !block_empty(level, texel)) {
+			--level;
+		}
+		if (level == 0) {
+			return t;
+		}
+		int shift = level + cell.lod;
+		region_min += local >> shift << shift;
+		region_size = 1 << shift;
+		texel_size = 1 << cell.lod;
+	}
+
+	vec3 min_corner = vec3(region_min) + float(texel_size - 1) * 0.5;
+	vec3 max_corner = min_corner + float(region_size - texel_size);
+	vec3 exits = (mix(min_corner, max_corner, greaterThanEqual(dir, vec3(0))) - origin) / dir;
+	// rays parallel to an axis never leave through its sides
+	exits = mix(exits, vec3(1e30), equal(dir, vec3(0)));
+	float exit = min(exits.x, min(exits.y, exits.z));
+	return exit > t ? exit : t;
+}
+
+// the direction the distance increases fastest, like World::normal. +Z where the field is flat
+vec3 normal(vec3 pos) {
+	vec2 e = vec2(0.5, 0);
+	vec3 gradient = vec3(
+		F(pos + e.xyy) - F(pos - e.xyy),
+		F(pos + e.yxy) - F(pos - e.yxy),
+		F(pos + e.yyx) - F(pos - e.yyx)
+	);
+	float len = length(gradient);
+	return len > 0 ? gradient / len : vec3(0, 0, 1);
+}
+
+// how much of the sun reaches pos, marching from `start` along dir. rays that pass close to a surface without hitting it
+// are partly shadowed, which softens the edges
+float soft_shadow(vec3 pos, vec3 dir, float start) {
+	float light = 1.0;
+	float t = start;
+	for (int i = 0; i < SHADOW_STEPS && t < SHADOW_DISTANCE; ++i) {
+		t = skip_empty(pos, dir, t);
+		float distance = F(pos + dir * t);
+		if (distance < 0.0001) {
+			return 0.0;
+		}
+		light = min(light, SHADOW_HARDNESS * distance / t);
+		t += distance;
+	}
+	return light;
+}
+
+// the world space direction through a point on the screen, given in Vulkan's NDC
+vec3 view_ray(vec2 ndc) {
+	// the projection is OpenGL style, so Y is flipped
+	vec4 near = frame.inv_view_proj * vec4(ndc.x, -ndc.y, -1, 1);
+	vec4 far = frame.inv_view_proj * vec4(ndc.x, -ndc.y, 1, 1);
+	return normalize(far.xyz / far.w - near.xyz / near.w);
+}
+
+// interleaved gradient noise, from 0 to 1, which is different every frame
+float noise(vec2 pixel) {
+	pixel += 5.588238 * floor(frame.time * 60);
+	return fract(52.9829189 * fract(dot(pixel, vec2(0.06711056, 0.00583715))));
 }
 
 void main() {
-	vec3 cam_dir_cs = quat_mul(cam_rot, vec3(0, 1, 0));
-	vec3 cam_dir_es = normalize(cam_dir_cs + vec3(in_pos.x, 0, in_pos.y));
-	vec2 in_pos_nor = (in_pos + 1) / 2;
-	vec2 px = vec2(1) * in_pos_nor / gl_FragCoord.xy;
-
-	float distance;
-	vec3 pos = cam_pos;
-	for (int i = 0; i < 32; ++i) {
-		distance = F(pos);
-		pos += cam_dir_es * distance;
-	}
-	float depth = length(pos - cam_pos);
-	if (distance > length(px * depth)) {
+	table_size = int(round(pow(float(table.length()), 1.0 / 3.0)));
+	// loaded chunks are never more than half the table from the camera's, so rays that get twice as far as the table is
+	// wide can't hit anything
+	float max_distance = float(2 * table_size * CHUNK_SIZE);
+	bool jitter = (flags & FLAG_JITTER) != 0;
+	// from the pixel rather than an input, since volumes are drawn both as fullscreen triangles and as boxes
+	vec2 ndc = gl_FragCoord.xy / frame.resolution * 2 - 1;
+	if (jitter) {
+		vec2 offset = vec2(noise(gl_FragCoord.xy), noise(gl_FragCoord.xy + vec2(47, 17))) - 0.5;
+		ndc += offset * 2 / frame.resolution;
+	}
+	vec3 dir = view_ray(ndc);
+	// how far apart neighboring pixels' rays are after one unit, so hits are accepted within about a pixel
+	float pixel = length(view_ray(ndc + vec2(0, 2 / frame.resolution.y)) - dir);
+
+	float distance = EMPTY_DISTANCE;
+	float t = 0;
+	int steps = 0;
+	for (; steps < MARCH_STEPS && t < max_distance; ++steps) {
+		t = skip_empty(frame.cam_pos, dir, t);
+		distance = F(frame.cam_pos + dir * t);
+		if (distance <= min(pixel * t, MAX_HIT_DISTANCE)) {
+			break;
+		}
+		t += distance;
+	}
+	vec3 pos = frame.cam_pos + dir * t;
+	bool hit = distance <= min(pixel * t, MAX_HIT_DISTANCE);
+
+	if ((flags & FLAG_STEP_HEATMAP) != 0) {
+		// blue for rays that finished right away, through green, to red for ones that used every step
+		float heat = float(steps) / float(MARCH_STEPS);
+		out_color = vec4(clamp(vec3(heat * 2 - 1, 1 - abs(heat * 2 - 1), 1 - heat * 2), 0, 1), 1);
+		// misses cost steps too, so they're drawn just in front of the far plane instead of discarded
+		vec4 clip = frame.view_proj * vec4(pos, 1);
+		gl_FragDepth = hit ? clip.z / clip.w * 0.5 + 0.5 : 0.9999999;
+		return;
+	}
+	if (!hit) {
 		discard;
 	}
 
-	out_color = vec4(0.8, 0.8, 0.8, 1.0);
-	// output normalized depth
+	vec3 n = normal(pos);
+	float sun = max(dot(n, frame.sun_dir.xyz), 0);
+	if (sun > 0) {
+		// starting slightly off the surface keeps the march from hitting it right away, and jittering the start breaks
+		// up the bands where the steps land
+		float start = jitter ? 0.01 + 0.02 * noise(gl_FragCoord.yx) : 0.01;
+		sun *= soft_shadow(pos + n * 0.01, frame.sun_dir.xyz, start);
+	}
+	vec3 albedo = PALETTE[material(pos) % PALETTE.length()];
+	out_color = vec4(albedo * (frame.ambient.rgb + frame.sun_color.rgb * sun), 1.0);
+
+	// the depth a rasterized surface at the hit would have, so other geometry can be depth tested against the world
+	vec4 clip = frame.view_proj * vec4(pos, 1);
+	gl_FragDepth = clip.z / clip.w * 0.5 + 0.5;
 }
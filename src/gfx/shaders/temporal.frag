@@ -0,0 +1,74 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform Frame {
+	mat4 view_proj;
+	mat4 inv_view_proj;
+	vec4 sun_dir;
+	vec4 sun_color;
+	vec4 ambient;
+	vec3 cam_pos;
+	float time;
+	vec2 resolution;
+} frame;
+
+layout(set = 1, binding = 0) uniform sampler2D current;
+layout(set = 1, binding = 1) uniform sampler2D depth;
+// rgb is the accumulated color, and a is the view depth it was accumulated at
+layout(set = 1, binding = 2) uniform sampler2D history;
+
+layout(push_constant) uniform PushConstants {
+	mat4 prev_view_proj;
+	// how much of the history to keep, or 0 if there isn't any yet
+	float history_weight;
+};
+
+layout(location = 0) in vec2 in_pos;
+
+layout(location = 0) out vec4 out_color;
+
+// history further than this fraction of the depth from where the surface was is something else, which the surface
+// was hidden behind
+const float DEPTH_TOLERANCE = 0.05;
+// how many standard deviations from the neighborhood's mean the history can be before it's clamped
+const float VARIANCE_CLIP = 1.25;
+
+void main() {
+	ivec2 pixel = ivec2(gl_FragCoord.xy);
+	vec3 color = texelFetch(current, pixel, 0).rgb;
+
+	// the projection is OpenGL style, so Y is flipped and depth was squashed from [-1, 1] to [0, 1]
+	vec4 world = frame.inv_view_proj * vec4(in_pos.x, -in_pos.y, texelFetch(depth, pixel, 0).r * 2 - 1, 1);
+	world /= world.w;
+	float view_depth = (frame.view_proj * world).w;
+
+	vec4 prev_clip = prev_view_proj * world;
+	vec2 prev_uv = vec2(prev_clip.x, -prev_clip.y) / prev_clip.w * 0.5 + 0.5;
+	bool on_screen = prev_clip.w > 0 && all(greaterThanEqual(prev_uv, vec2(0))) && all(lessThanEqual(prev_uv, vec2(1)));
+	if (history_weight == 0 || !on_screen) {
+		out_color = vec4(color, view_depth);
+		return;
+	}
+
+	vec4 prev = texture(history, prev_uv);
+	if (abs(prev.a - prev_clip.w) > DEPTH_TOLERANCE * prev_clip.w) {
+		out_color = vec4(color, view_depth);
+		return;
+	}
+
+	// history far outside the colors around the pixel is from lighting or geometry that changed, and would smear
+	vec3 sum = vec3(0);
+	vec3 sum_sq = vec3(0);
+	for (int y = -1; y <= 1; ++y) {
+		for (int x = -1; x <= 1; ++x) {
+			ivec2 neighbor = clamp(pixel + ivec2(x, y), ivec2(0), ivec2(frame.resolution) - 1);
+			vec3 c = texelFetch(current, neighbor, 0).rgb;
+			sum += c;
+			sum_sq += c * c;
+		}
+	}
+	vec3 mean = sum / 9;
+	vec3 deviation = sqrt(max(sum_sq / 9 - mean * mean, 0));
+	vec3 clamped = clamp(prev.rgb, mean - deviation * VARIANCE_CLIP, mean + deviation * VARIANCE_CLIP);
+
+	out_color = vec4(mix(color, clamped, history_weight), view_depth);
+}
@@ -0,0 +1,46 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform Frame {
+	mat4 view_proj;
+	mat4 inv_view_proj;
+	vec4 sun_dir;
+	vec4 sun_color;
+	vec4 ambient;
+	vec3 cam_pos;
+	float time;
+	vec2 resolution;
+} frame;
+
+struct Chunk {
+	vec4 min;
+	vec4 max;
+};
+
+layout(set = 2, binding = 0) readonly buffer Chunks {
+	Chunk chunks[];
+};
+// written by cull.comp, with an instance for each
+layout(set = 2, binding = 1) readonly buffer Visible {
+	uint visible[];
+};
+
+// the corners of a box's triangles, with a bit each for whether x, y, and z are at the max. they're counter-clockwise
+// seen from outside, like meshes
+const uint CORNERS[36] = uint[](
+	0, 6, 2, 0, 4, 6,
+	1, 3, 7, 1, 7, 5,
+	0, 1, 5, 0, 5, 4,
+	2, 7, 3, 2, 6, 7,
+	0, 3, 1, 0, 2, 3,
+	4, 5, 7, 4, 7, 6
+);
+
+void main() {
+	Chunk chunk = chunks[visible[gl_InstanceIndex]];
+	uint corner = CORNERS[gl_VertexIndex];
+	vec3 pos = mix(chunk.min.xyz, chunk.max.xyz, bvec3(corner & 1, corner & 2, corner & 4));
+	vec4 clip = frame.view_proj * vec4(pos, 1.0);
+	// the projection is OpenGL style, so flip Y. the fragment shader writes the depth of whatever its ray hits, so the
+	// box only has to cover the right pixels, and a depth of 0 keeps its far side from being cut off by the far plane
+	gl_Position = vec4(clip.x, -clip.y, 0, clip.w);
+}
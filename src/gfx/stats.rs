@@ -0,0 +1,111 @@
+//! Per-frame counters and timings. Anything with a `Gfx` can add to the current frame, and the HUD and tools read
+//! the last finished one from `Gfx::frame_stats`.
+
+use std::{
+	sync::{
+		atomic::{AtomicU32, AtomicU64, Ordering},
+		Mutex,
+	},
+	time::{Duration, Instant},
+};
+
+/// Everything measured during one frame.
+#[derive(Clone, Debug, Default)]
+pub struct FrameStats {
+	/// Wall time from the end of the previous frame to the end of this one.
+	pub frame_time: Duration,
+	/// CPU time spent in named sections, in the order they finished.
+	pub cpu: Vec<(&'static str, Duration)>,
+	/// GPU time spent in named passes. These come from timestamp queries, so they describe an earlier frame.
+	pub gpu: Vec<(&'static str, Duration)>,
+	pub draws: u32,
+	pub dispatches: u32,
+	/// Bytes copied from the CPU to buffers and images.
+	pub upload_bytes: u64,
+	pub chunks_generated: u32,
+}
+
+pub struct StatsCollector {
+	frame_start: Mutex<Instant>,
+	cpu: Mutex<Vec<(&'static str, Duration)>>,
+	gpu: Mutex<Vec<(&'static str, Duration)>>,
+	draws: AtomicU32,
+	dispatches: AtomicU32,
+	upload_bytes: AtomicU64,
+	chunks_generated: AtomicU32,
+	last: Mutex<FrameStats>,
+}
+impl StatsCollector {
+	pub(super) fn new() -> Self {
+		Self {
+			frame_start: Mutex::new(Instant::now()),
+			cpu: Mutex::default(),
+			gpu: Mutex::default(),
+			draws: AtomicU32::new(0),
+			dispatches: AtomicU32::new(0),
+			upload_bytes: AtomicU64::new(0),
+			chunks_generated: AtomicU32::new(0),
+			last: Mutex::default(),
+		}
+	}
+
+	pub fn add_draws(&self, count: u32) {
+		self.draws.fetch_add(count, Ordering::Relaxed);
+	}
+
+	pub fn add_dispatches(&self, count: u32) {
+		self.dispatches.fetch_add(count, Ordering::Relaxed);
+	}
+
+	pub fn add_upload(&self, bytes: u64) {
+		self.upload_bytes.fetch_add(bytes, Ordering::Relaxed);
+	}
+
+	pub fn add_chunks_generated(&self, count: u32) {
+		self.chunks_generated.fetch_add(count, Ordering::Relaxed);
+	}
+
+	pub fn add_cpu_time(&self, name: &'static str, time: Duration) {
+		self.cpu.lock().unwrap().push((name, time));
+	}
+
+	pub fn add_gpu_time(&self, name: &'static str, time: Duration) {
+		self.gpu.lock().unwrap().push((name, time));
+	}
+
+	/// Times a CPU section until the returned guard is dropped.
+	pub fn cpu_scope(&self, name: &'static str) -> CpuScope<'_> {
+		CpuScope { stats: self, name, start: Instant::now() }
+	}
+
+	/// Finishes the current frame, making it the one returned by `Gfx::frame_stats`.
+	pub fn end_frame(&self) {
+		let now = Instant::now();
+		let frame_time = now - std::mem::replace(&mut *self.frame_start.lock().unwrap(), now);
+
+		*self.last.lock().unwrap() = FrameStats {
+			frame_time,
+			cpu: std::mem::take(&mut *self.cpu.lock().unwrap()),
+			gpu: std::mem::take(&mut *self.gpu.lock().unwrap()),
+			draws: self.draws.swap(0, Ordering::Relaxed),
+			dispatches: self.dispatches.swap(0, Ordering::Relaxed),
+			upload_bytes: self.upload_bytes.swap(0, Ordering::Relaxed),
+			chunks_generated: self.chunks_generated.swap(0, Ordering::Relaxed),
+		};
+	}
+
+	pub(super) fn last(&self) -> FrameStats {
+		self.last.lock().unwrap().clone()
+	}
+}
+
+pub struct CpuScope<'a> {
+	stats: &'a StatsCollector,
+	name: &'static str,
+	start: Instant,
+}
+impl Drop for CpuScope<'_> {
+	fn drop(&mut self) {
+		self.stats.add_cpu_time(self.name, self.start.elapsed());
+	}
+}
@@ -0,0 +1,228 @@
+//! Temporal accumulation, which blends each frame of the world with the ones before it. The history is reprojected
+//! with the world's depth and the last frame's camera, so it follows the surfaces as the camera moves, and averaging it
+//! removes the noise the volume shader jitters its rays and shadows with. History that was behind something else last
+//! frame is rejected by its depth, and the rest is clamped to the colors around each pixel, so moving lights and
+//! objects don't leave trails.
+
+use crate::gfx::{
+	graph::{ImageId, Pass, RenderGraph, Usage},
+	image,
+	window::viewport,
+	Gfx, TriangleVertex,
+};
+use ash::{prelude::VkResult, vk};
+use nalgebra::Matrix4;
+use std::{iter::once, mem::size_of, sync::Arc};
+use typenum::B0;
+use vulkan::{
+	command::{CommandBufferBuilder, CommandPool, InheritanceInfo},
+	descriptor::{
+		DescriptorPoolSize, DescriptorSet, DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorType,
+		ShaderStageFlags,
+	},
+	image::{Framebuffer, Image},
+	pipeline::{Pipeline, PipelineLayout, PushConstantRange},
+	render_pass::{Attachment, RenderPass, Subpass},
+	sampler::{Filter, SamplerAddressMode, SamplerMipmapMode},
+	Extent2D, Rect2D,
+};
+
+/// Colors are averaged over many frames, so 8 bits would band. The alpha channel holds the view depth each pixel was
+/// accumulated at, for rejecting history from other surfaces.
+const FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// How much of the history each frame keeps. Higher is smoother, but takes longer to catch up with changes.
+const HISTORY_WEIGHT: f32 = 0.9;
+
+/// Laid out like the push constants in `temporal.frag`.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct Params {
+	prev_view_proj: Matrix4<f32>,
+	/// 0 when there's no history yet.
+	history_weight: f32,
+}
+
+pub struct Temporal {
+	gfx: Arc<Gfx>,
+	render_pass: Arc<RenderPass>,
+	set_layout: Arc<DescriptorSetLayout>,
+	layout: Arc<PipelineLayout>,
+	pipeline: Arc<Pipeline>,
+	/// `None` until `resize` is called.
+	history: Option<History>,
+}
+impl Temporal {
+	pub fn new(gfx: Arc<Gfx>) -> VkResult<Self> {
+		let render_pass = create_render_pass(&gfx)?;
+		let set_layout = create_set_layout(&gfx);
+		let push_constants = [PushConstantRange::builder()
+			.stage_flags(vk::ShaderStageFlags::FRAGMENT)
+			.size(size_of::<Params>() as _)
+			.build()];
+		let set_layouts = vec![gfx.frame_set_layout.clone(), set_layout.clone()];
+		let layout = gfx.device.create_pipeline_layout(set_layouts, &push_constants);
+		let pipeline = create_pipeline(&gfx, layout.clone(), render_pass.clone());
+		Ok(Self { gfx, render_pass, set_layout, layout, pipeline, history: None })
+	}
+
+	/// Rebuilds the pipeline from the current shader modules, after they've been hot reloaded.
+	#[cfg(debug_assertions)]
+	pub fn reload_pipeline(&mut self) {
+		self.pipeline = create_pipeline(&self.gfx, self.layout.clone(), self.render_pass.clone());
+	}
+
+	/// Starts over with new history for `color` and `depth`, which the world is drawn into. Call it whenever they're
+	/// replaced. Frames in flight keep the old history alive until they're done with it.
+	pub fn resize(&mut self, color: &Arc<Image>, depth: &Arc<Image>, extent: Extent2D) {
+		let gfx = &self.gfx;
+		let create_image = || image::create_render_target(gfx, FORMAT, extent, "temporal history");
+		let images = [create_image(), create_image()];
+		let create_framebuffer = |image: &Arc<Image>| {
+			gfx.device.create_framebuffer(self.render_pass.clone(), vec![image.create_view()], extent)
+		};
+		let framebuffers = [create_framebuffer(&images[0]), create_framebuffer(&images[1])];
+
+		let pool_sizes =
+			[DescriptorPoolSize::builder().ty(DescriptorType::COMBINED_IMAGE_SAMPLER).descriptor_count(6).build()];
+		let pool = gfx.device.create_descriptor_pool(2, &pool_sizes);
+		let (color, depth) = (color.create_view(), depth.create_view());
+		let create_set = |prev: &Arc<Image>| {
+			let set = pool.allocate(self.set_layout.clone()).unwrap();
+			set.write()
+				.sampled_view(0, color.clone())
+				.sampled_view(1, depth.clone())
+				.sampled_view(2, prev.create_view())
+				.submit();
+			set
+		};
+		let sets = [create_set(&images[1]), create_set(&images[0])];
+
+		self.history =
+			Some(History { images, framebuffers, sets, extent, written: [false; 2], current: 0, prev_view_proj: None });
+	}
+
+	/// Adds a pass that blends the world with the history, and returns the result, which is the same size.
+	///
+	/// `color` and `depth` must be the images from the last `resize`, and both written by earlier passes. The result
+	/// is left in `SHADER_READ_ONLY_OPTIMAL` at the end of the graph, since the next frame samples it.
+	pub fn add_pass<'a>(
+		&mut self,
+		graph: &mut RenderGraph<'a>,
+		cmdpool: &Arc<CommandPool>,
+		frame_set: &Arc<DescriptorSet>,
+		color: ImageId,
+		depth: ImageId,
+		view_proj: Matrix4<f32>,
+	) -> (ImageId, Arc<Image>) {
+		let history = self.history.as_mut().expect("the history is made by `resize`");
+		let (read, write) = (1 - history.current, history.current);
+		history.current = read;
+
+		// the images are undefined until they're first written, and every frame after leaves them sampled
+		let last_use = |written| if written { Some(Usage::Sampled) } else { None };
+		let prev = graph.import_image(history.images[read].clone(), last_use(history.written[read]));
+		let target = graph.import_discarded_image(history.images[write].clone(), last_use(history.written[write]));
+		graph.export_image(target, Usage::Sampled);
+
+		let params = Params {
+			prev_view_proj: history.prev_view_proj.unwrap_or(view_proj),
+			history_weight: if history.prev_view_proj.is_some() { HISTORY_WEIGHT } else { 0.0 },
+		};
+		history.written[write] = true;
+		history.prev_view_proj = Some(view_proj);
+
+		let framebuffer = history.framebuffers[write].clone();
+		let inherit = InheritanceInfo {
+			render_pass: self.render_pass.clone(),
+			subpass: 0,
+			framebuffer: Some(framebuffer.clone()),
+		};
+		let area = Rect2D::builder().extent(history.extent).build();
+		let commands = cmdpool
+			.record_secondary(true, false, Some(inherit))
+			.bind_pipeline(self.pipeline.clone())
+			.set_viewport(0, &[viewport(history.extent)])
+			.set_scissor(0, &[area])
+			.bind_descriptor_sets(self.layout.clone(), 0, vec![frame_set.clone(), history.sets[write].clone()])
+			.push_constants(self.layout.clone(), vk::ShaderStageFlags::FRAGMENT, 0, &params)
+			.bind_vertex_buffers(0, once(self.gfx.triangle.clone() as _), &[0])
+			.draw(3, 1, 0, 0)
+			.build();
+
+		let render_pass = self.render_pass.clone();
+		let record = move |cmd: CommandBufferBuilder<B0>| {
+			cmd.begin_render_pass(render_pass, framebuffer, area, &[])
+				.execute_commands(once(commands))
+				.end_render_pass()
+		};
+		graph.add_pass(
+			Pass::new("temporal", record)
+				.image(color, Usage::Sampled)
+				.image(depth, Usage::Sampled)
+				.image(prev, Usage::Sampled)
+				.image(target, Usage::ColorAttachment),
+		);
+		(target, history.images[write].clone())
+	}
+}
+
+/// Two images that take turns: each frame reads the one the last frame wrote, and writes the other.
+struct History {
+	images: [Arc<Image>; 2],
+	framebuffers: [Arc<Framebuffer>; 2],
+	/// `sets[i]` reads the world and `images[1 - i]`, for writing `images[i]`.
+	sets: [Arc<DescriptorSet>; 2],
+	extent: Extent2D,
+	written: [bool; 2],
+	/// The image the next frame writes.
+	current: usize,
+	/// The last frame's `view_proj`, or `None` before the first frame.
+	prev_view_proj: Option<Matrix4<f32>>,
+}
+
+/// A single color attachment whose old contents are discarded, since every pixel is overwritten. Like the window's
+/// passes, the render graph moves it in and out of the attachment layout.
+fn create_render_pass(gfx: &Gfx) -> VkResult<Arc<RenderPass>> {
+	let attachment = vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL;
+	let color = Attachment::new(FORMAT, attachment).initial_layout(attachment).load(vk::AttachmentLoadOp::DONT_CARE);
+	gfx.device.build_render_pass().attachment(color).subpass(Subpass::new().color(0)).build()
+}
+
+/// The world's color and depth, which are read a texel at a time, then the history, which is filtered since it's read
+/// between texels wherever the camera moved by a fraction of a pixel.
+fn create_set_layout(gfx: &Gfx) -> Arc<DescriptorSetLayout> {
+	let sampler = |filter| {
+		gfx.device
+			.build_sampler()
+			.filter(filter)
+			.mipmap_mode(SamplerMipmapMode::NEAREST)
+			.address_mode(SamplerAddressMode::CLAMP_TO_EDGE)
+			.build()
+	};
+	let (nearest, linear) = (sampler(Filter::NEAREST), sampler(Filter::LINEAR));
+	let binding = |binding| {
+		DescriptorSetLayoutBinding::builder()
+			.binding(binding)
+			.descriptor_type(DescriptorType::COMBINED_IMAGE_SAMPLER)
+			.descriptor_count(1)
+			.stage_flags(ShaderStageFlags::FRAGMENT)
+			.build()
+	};
+	let bindings = [binding(0), binding(1), binding(2)];
+	gfx.device.create_descriptor_set_layout(&bindings, vec![(0, nearest.clone()), (1, nearest), (2, linear)])
+}
+
+fn create_pipeline(gfx: &Gfx, layout: Arc<PipelineLayout>, render_pass: Arc<RenderPass>) -> Arc<Pipeline> {
+	let shaders = gfx.shaders();
+	let pipeline = gfx
+		.device
+		.build_pipeline(layout, render_pass)
+		.vertex_shader(shaders.vert.clone())
+		.fragment_shader(shaders.temporal_frag.clone())
+		.vertex_input::<TriangleVertex>()
+		.dynamic_viewport()
+		.build();
+	gfx.device.set_object_name(pipeline.vk, "temporal");
+	pipeline
+}
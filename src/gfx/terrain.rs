@@ -0,0 +1,101 @@
+//! The world drawn as triangle meshes of its chunks instead of raymarched, to compare the two. The meshes are built on
+//! the job workers, so chunks show up a few frames after they load or change.
+
+use crate::gfx::{
+	mesh::{Material, Mesh, MeshInstance, MeshVertex},
+	Gfx,
+};
+use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
+use sim::{
+	threads::{JobHandle, JobKind, JOBS},
+	world::{
+		mesher::{surface_nets, SurfaceMesh, PADDED_SIZE},
+		World, CHUNK_SIZE,
+	},
+};
+use std::{
+	collections::{HashMap, HashSet},
+	sync::Arc,
+};
+
+/// The same as `ALBEDO` in `shader.frag`, so both ways of drawing the world are the same color.
+const ALBEDO: f32 = 0.8;
+
+pub struct TerrainMeshes {
+	gfx: Arc<Gfx>,
+	/// Every loaded chunk that's been meshed, with `None` for the ones without a surface.
+	meshes: HashMap<Vector3<i32>, Option<Arc<Mesh>>>,
+	/// Meshes being built, which replace the ones in `meshes` when they're done.
+	pending: HashMap<Vector3<i32>, JobHandle<SurfaceMesh>>,
+}
+impl TerrainMeshes {
+	pub fn new(gfx: Arc<Gfx>) -> Self {
+		Self { gfx, meshes: HashMap::new(), pending: HashMap::new() }
+	}
+
+	/// Starts meshing the chunks that loaded since the last call or are in `changed`, along with their neighbors, whose
+	/// meshes read a voxel into them, then uploads the meshes that are done. Call it every frame with the chunks from
+	/// `World::take_changed`. While it's not `enabled`, every mesh is dropped, so it costs nothing when the world is
+	/// raymarched.
+	pub fn update(&mut self, world: &World, changed: &HashSet<Vector3<i32>>, enabled: bool) {
+		if !enabled {
+			self.meshes.clear();
+			self.pending.clear();
+			return;
+		}
+
+		self.meshes.retain(|&key, _| world.chunk(key).is_some());
+		self.pending.retain(|&key, _| world.chunk(key).is_some());
+		let (meshes, pending) = (&self.meshes, &self.pending);
+		let loaded = world.chunk_keys().filter(|key| !meshes.contains_key(key) && !pending.contains_key(key));
+		let mut remesh = HashSet::new();
+		for key in loaded.chain(changed.iter().copied()) {
+			for z in -1..=1 {
+				for y in -1..=1 {
+					for x in -1..=1 {
+						let key = key + Vector3::new(x, y, z);
+						if world.chunk(key).is_some() {
+							remesh.insert(key);
+						}
+					}
+				}
+			}
+		}
+		for key in remesh {
+			let voxels = world.copy_voxels(key * CHUNK_SIZE as i32 - Vector3::repeat(1), PADDED_SIZE);
+			// replacing a pending job drops its handle, and with it the stale mesh
+			self.pending.insert(key, JOBS.spawn(JobKind::Background, move || surface_nets(key, &voxels)));
+		}
+
+		let (gfx, meshes) = (&self.gfx, &mut self.meshes);
+		self.pending.retain(|&key, job| match job.try_take() {
+			Some(surface) => {
+				meshes.insert(key, upload(gfx, &surface));
+				false
+			},
+			None => true,
+		});
+	}
+
+	/// The meshes to draw along with the scene's.
+	pub fn draws(&self) -> Vec<(Arc<Mesh>, Vec<MeshInstance>)> {
+		let instance = MeshInstance { model: Matrix4::identity(), color: Vector4::repeat(1.0) };
+		self.meshes.values().flatten().map(|mesh| (mesh.clone(), vec![instance])).collect()
+	}
+}
+
+fn upload(gfx: &Gfx, surface: &SurfaceMesh) -> Option<Arc<Mesh>> {
+	if surface.indices.is_empty() {
+		return None;
+	}
+	let verts = surface
+		.positions
+		.iter()
+		.zip(&surface.normals)
+		.map(|(&pos, &normal)| MeshVertex { pos, normal, uv: Vector2::zeros() })
+		.collect::<Vec<_>>();
+	Some(Mesh::new(gfx, &verts, &surface.indices, Material {
+		color: Vector4::new(ALBEDO, ALBEDO, ALBEDO, 1.0),
+		texture: None,
+	}))
+}
@@ -0,0 +1,11 @@
+use std::sync::Arc;
+use vulkan::image::{Image, ImageView};
+
+/// Identifies a texture loaded through `Gfx::load_texture`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureHandle(pub(super) u32);
+
+pub struct Texture {
+	pub image: Arc<Image>,
+	pub view: Arc<ImageView>,
+}
@@ -0,0 +1,192 @@
+//! Batches CPU to GPU copies, so loading many resources costs one submit per frame instead of a submit and a wait
+//! each.
+//!
+//! If the device has a dedicated transfer queue family, the copies run there so they don't contend with rendering,
+//! and the images are then handed to the graphics family.
+
+use crate::gfx::{
+	buffer::{Staging, StagingPool},
+	fence::FENCES,
+};
+use ash::vk;
+use futures::channel::oneshot;
+use sim::threads::{JobKind, JOBS};
+use std::{
+	future::Future,
+	mem,
+	pin::Pin,
+	sync::{Arc, Mutex},
+	task::{Context, Poll},
+};
+use vulkan::{
+	command::CommandPool,
+	device::{Device, Queue},
+	image::{ImageInit, QueueTransfer},
+	sync::Fence,
+};
+
+pub struct UploadQueue {
+	graphics: Arc<Queue>,
+	graphics_pool: Arc<CommandPool>,
+	transfer: Option<(Arc<Queue>, Arc<CommandPool>)>,
+	staging: StagingPool,
+	pending: Mutex<Vec<PendingUpload>>,
+	in_flight: Mutex<Vec<Batch>>,
+}
+impl UploadQueue {
+	pub(super) fn new(device: &Arc<Device>, graphics: Arc<Queue>, transfer: Option<Arc<Queue>>) -> Self {
+		let graphics_pool = device.create_command_pool(graphics.family(), true);
+		let transfer = transfer.map(|queue| {
+			let pool = device.create_command_pool(queue.family(), true);
+			(queue, pool)
+		});
+		Self {
+			graphics,
+			graphics_pool,
+			transfer,
+			staging: StagingPool::new(device.clone()),
+			pending: Mutex::default(),
+			in_flight: Mutex::default(),
+		}
+	}
+
+	/// Whether uploads run on a separate transfer queue.
+	pub fn has_transfer_queue(&self) -> bool {
+		self.transfer.is_some()
+	}
+
+	/// Queues a copy of `data` into the first `levels` mip levels of `image`, and generates any levels after them.
+	/// `data` is staged right away, but nothing is submitted until the next `flush`.
+	pub(super) fn push_image(&self, data: &[u8], image: ImageInit, levels: u32) -> UploadHandle {
+		let staging = self.staging.push(data);
+		let (done, recv) = oneshot::channel();
+		self.pending.lock().unwrap().push(PendingUpload { staging, image, levels, done });
+		UploadHandle(recv)
+	}
+
+	/// Records every queued copy into one command buffer and submits it, and reuses the staging memory of earlier
+	/// batches that have finished.
+	pub fn flush(&self) {
+		let mut in_flight = self.in_flight.lock().unwrap();
+		for batch in mem::take(&mut *in_flight) {
+			if batch.fences.last().unwrap().is_signalled() {
+				batch.complete(&self.staging);
+			} else {
+				in_flight.push(batch);
+			}
+		}
+
+		let pending = mem::take(&mut *self.pending.lock().unwrap());
+		if pending.is_empty() {
+			return;
+		}
+
+		// command buffers only go back to the pools when they're reset, which needs every earlier batch to be finished
+		if in_flight.is_empty() {
+			self.graphics_pool.reset(false);
+			if let Some((_, pool)) = &self.transfer {
+				pool.reset(false);
+			}
+		}
+
+		let mut done = Vec::with_capacity(pending.len());
+		let mut staging = Vec::with_capacity(pending.len());
+		let fences = match &self.transfer {
+			Some((queue, pool)) => {
+				let transfer = QueueTransfer { src: queue.family().idx, dst: self.graphics.family().idx };
+				let mut cmd = pool.record(true, false).begin_label("upload");
+				let mut acquire = self.graphics_pool.record(true, false).begin_label("acquire uploads");
+				for upload in pending {
+					let generate = upload.generates_mips();
+					let (buffer, offset) = (upload.staging.buffer.clone(), upload.staging.offset);
+					let (image, next) =
+						upload.image.record_copy_from_buffer(cmd, buffer, offset, upload.levels, Some(transfer));
+					cmd = next;
+					// blits need a graphics queue, so mips are generated after the image is handed over
+					acquire = image.record_acquire(acquire, transfer);
+					if generate {
+						acquire = image.record_generate_mips(acquire);
+					}
+					staging.push(upload.staging);
+					done.push(upload.done);
+				}
+				let (copied, future) = queue.submit(cmd.end_label().build()).flush(vk::PipelineStageFlags::TRANSFER);
+				let acquired = self.graphics.submit_after(future, acquire.end_label().build()).end();
+				// the acquire waits on the copy, so its fence signals last
+				vec![Arc::new(copied), Arc::new(acquired)]
+			},
+			None => {
+				let mut cmd = self.graphics_pool.record(true, false).begin_label("upload");
+				for upload in pending {
+					let generate = upload.generates_mips();
+					let (buffer, offset) = (upload.staging.buffer.clone(), upload.staging.offset);
+					let (image, next) = upload.image.record_copy_from_buffer(cmd, buffer, offset, upload.levels, None);
+					cmd = if generate { image.record_generate_mips(next) } else { next };
+					staging.push(upload.staging);
+					done.push(upload.done);
+				}
+				vec![Arc::new(self.graphics.submit(cmd.end_label().build()).end())]
+			},
+		};
+
+		// the handles resolve as soon as the GPU is done, even if nothing calls `flush` again
+		let signalled = FENCES.wait(fences.last().unwrap().clone());
+		let resolve = async move {
+			signalled.await;
+			for done in done {
+				// the handle may have been dropped by a caller that doesn't care when the upload finishes
+				let _ = done.send(());
+			}
+		};
+		JOBS.spawn_async(JobKind::Background, resolve).forget();
+		in_flight.push(Batch { fences, staging });
+	}
+
+	/// Submits anything queued and blocks until every upload is complete.
+	pub fn finish(&self) {
+		self.flush();
+		for batch in self.in_flight.lock().unwrap().drain(..) {
+			batch.fences.last().unwrap().wait();
+			batch.complete(&self.staging);
+		}
+	}
+}
+
+struct PendingUpload {
+	staging: Staging,
+	image: ImageInit,
+	/// How many mip levels are staged. The rest are generated.
+	levels: u32,
+	done: oneshot::Sender<()>,
+}
+impl PendingUpload {
+	fn generates_mips(&self) -> bool {
+		self.levels < self.image.image().mip_levels
+	}
+}
+
+struct Batch {
+	/// In submission order, so the last one signals when the whole batch is done.
+	fences: Vec<Arc<Fence>>,
+	/// Returned to the pool once the batch is done.
+	staging: Vec<Staging>,
+}
+impl Batch {
+	fn complete(self, pool: &StagingPool) {
+		for staging in self.staging {
+			pool.release(staging);
+		}
+	}
+}
+
+/// Resolves when the upload is complete and the resource can be used. Nothing is submitted until the next
+/// `UploadQueue::flush`, which `Window::draw` calls every frame.
+pub struct UploadHandle(oneshot::Receiver<()>);
+impl Future for UploadHandle {
+	type Output = ();
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		// if the queue is dropped before submitting the upload, there's nothing left to wait for either way
+		Pin::new(&mut self.0).poll(cx).map(|_| ())
+	}
+}
@@ -0,0 +1,248 @@
+//! Chunk visibility decided on the GPU. A compute pass tests each chunk's bounds against the frustum, and writes the
+//! indices of the ones inside along with the arguments for an indirect draw of a box around each of them, so the CPU
+//! only uploads the bounds and never decides which chunks are drawn.
+
+use crate::gfx::{
+	graph::{BufferId, Pass, RenderGraph, Usage},
+	Gfx,
+};
+use ash::vk;
+use nalgebra::{Matrix4, Vector4};
+use sim::math::{Aabb, Frustum};
+use std::{iter::once, mem::size_of, sync::Arc};
+use typenum::{B0, B1};
+use vulkan::{
+	buffer::Buffer,
+	command::CommandBufferBuilder,
+	descriptor::{
+		DescriptorPoolSize, DescriptorSet, DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorType,
+		ShaderStageFlags,
+	},
+	device::BufferUsageFlags,
+	pipeline::{ComputePipeline, PipelineLayout, PushConstantRange},
+};
+
+/// Matches `local_size_x` in `cull.comp`.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// The fewest chunks the buffers are made for, so they aren't recreated over and over as the first chunks load.
+const MIN_CAPACITY: usize = 64;
+
+/// Laid out like `Chunk` in the shaders, following std430.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct ChunkBounds {
+	min: Vector4<f32>,
+	max: Vector4<f32>,
+}
+
+/// Laid out like the push constants in `cull.comp`.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct CullParams {
+	/// The frustum's planes, with the normal in xyz and the distance in w.
+	planes: [Vector4<f32>; 6],
+	chunk_count: u32,
+}
+
+pub struct ChunkVisibility {
+	gfx: Arc<Gfx>,
+	set_layout: Arc<DescriptorSetLayout>,
+	layout: Arc<PipelineLayout>,
+	pipeline: Arc<ComputePipeline>,
+	/// How many frames can be in flight.
+	frames: usize,
+	/// `None` until there are chunks.
+	buffers: Option<ChunkBuffers>,
+	/// How many chunks the last `prepare` was for.
+	count: usize,
+}
+impl ChunkVisibility {
+	pub fn new(gfx: Arc<Gfx>, frames: usize) -> Self {
+		let set_layout = create_set_layout(&gfx);
+		let push_constants = [PushConstantRange::builder()
+			.stage_flags(vk::ShaderStageFlags::COMPUTE)
+			.size(size_of::<CullParams>() as _)
+			.build()];
+		let layout = gfx.device.create_pipeline_layout(vec![set_layout.clone()], &push_constants);
+		let pipeline = create_pipeline(&gfx, layout.clone());
+		Self { gfx, set_layout, layout, pipeline, frames, buffers: None, count: 0 }
+	}
+
+	/// The layout of `VisibleChunks::set`, for pipelines that draw the visible chunks.
+	pub fn set_layout(&self) -> &Arc<DescriptorSetLayout> {
+		&self.set_layout
+	}
+
+	/// Rebuilds the pipeline from the current shader modules, after they've been hot reloaded.
+	#[cfg(debug_assertions)]
+	pub fn reload_pipeline(&mut self) {
+		self.pipeline = create_pipeline(&self.gfx, self.layout.clone());
+	}
+
+	/// Makes room for `count` chunks in frame `frame`, whose bounds have to be written with
+	/// `VisibleChunks::write_bounds` before the frame is submitted. Returns `None` if there aren't any chunks to draw.
+	pub fn prepare(&mut self, frame: usize, count: usize) -> Option<VisibleChunks> {
+		self.count = count;
+		if count == 0 {
+			return None;
+		}
+
+		let capacity = self.buffers.as_ref().map_or(0, |buffers| buffers.capacity);
+		if count > capacity {
+			// frames in flight hold the old buffers until they're done with them
+			let capacity = count.next_power_of_two().max(MIN_CAPACITY);
+			self.buffers = Some(ChunkBuffers::new(&self.gfx, &self.set_layout, self.frames, capacity));
+		}
+		let buffers = self.buffers.as_ref().unwrap();
+		Some(VisibleChunks {
+			bounds: buffers.bounds[frame].clone(),
+			set: buffers.sets[frame].clone(),
+			draw: buffers.draw.clone(),
+		})
+	}
+
+	/// Adds the passes that decide which of the last `prepare`'s chunks are visible, and returns the buffers that
+	/// drawing them reads, and how, for declaring on the pass that draws them.
+	pub fn add_passes(
+		&mut self,
+		graph: &mut RenderGraph,
+		frame: usize,
+		view_proj: &Matrix4<f32>,
+	) -> Vec<(BufferId, Usage)> {
+		let buffers = match &mut self.buffers {
+			Some(buffers) if self.count > 0 => buffers,
+			_ => return vec![],
+		};
+
+		// the last frame's draw has to finish reading them before they're overwritten
+		let last_use = |usage| if buffers.used { Some(usage) } else { None };
+		let visible = graph.import_buffer(buffers.visible.clone(), last_use(Usage::StorageRead));
+		let draw = graph.import_buffer(buffers.draw.clone(), last_use(Usage::Indirect));
+		buffers.used = true;
+
+		// the shader counts the visible chunks up from 0
+		let clear = buffers.draw.clone();
+		let clear = move |cmd: CommandBufferBuilder<B0>| cmd.fill_buffer(clear, 0);
+		graph.add_pass(Pass::new("clear chunk draw", clear).buffer(draw, Usage::TransferDst));
+
+		let frustum = Frustum::from_matrix(view_proj);
+		let plane = |i: usize| {
+			let plane = &frustum.planes[i];
+			Vector4::new(plane.normal.x, plane.normal.y, plane.normal.z, plane.d)
+		};
+		let params = CullParams {
+			planes: [plane(0), plane(1), plane(2), plane(3), plane(4), plane(5)],
+			chunk_count: self.count as _,
+		};
+		let groups = (self.count as u32 + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE;
+		let (pipeline, layout, set) = (self.pipeline.clone(), self.layout.clone(), buffers.sets[frame].clone());
+		let cull = move |cmd: CommandBufferBuilder<B0>| {
+			cmd.bind_compute_pipeline(pipeline)
+				.bind_compute_descriptor_sets(layout.clone(), 0, once(set))
+				.push_constants(layout, vk::ShaderStageFlags::COMPUTE, 0, &params)
+				.dispatch(groups, 1, 1)
+		};
+		self.gfx.stats().add_dispatches(1);
+		graph.add_pass(
+			Pass::new("cull chunks", cull).buffer(visible, Usage::StorageWrite).buffer(draw, Usage::StorageWrite),
+		);
+
+		vec![(visible, Usage::StorageRead), (draw, Usage::Indirect)]
+	}
+}
+
+/// One frame's view of the chunk buffers.
+#[derive(Clone)]
+pub struct VisibleChunks {
+	bounds: Arc<Buffer<[ChunkBounds]>>,
+	/// The bounds at binding 0, and the indices of the visible chunks at binding 1, for the vertex shader.
+	pub set: Arc<DescriptorSet>,
+	/// A single draw with an instance for each visible chunk.
+	pub draw: Arc<Buffer<[vk::DrawIndirectCommand]>>,
+}
+impl VisibleChunks {
+	/// `chunks` must be as many as `ChunkVisibility::prepare` was given. Any thread can write them.
+	///
+	/// The GPU must be done with the last frame recorded with the same index.
+	pub unsafe fn write_bounds(&self, chunks: &[Aabb]) {
+		let bounds = chunks
+			.iter()
+			.map(|chunk| ChunkBounds { min: chunk.min.push(0.0), max: chunk.max.push(0.0) })
+			.collect::<Vec<_>>();
+		self.bounds.write_at(0, &bounds);
+	}
+}
+
+/// The buffers for up to `capacity` chunks.
+struct ChunkBuffers {
+	capacity: usize,
+	/// Written by the CPU, so each frame in flight has its own.
+	bounds: Vec<Arc<Buffer<[ChunkBounds]>>>,
+	visible: Arc<Buffer<[u32]>>,
+	draw: Arc<Buffer<[vk::DrawIndirectCommand]>>,
+	/// One for each of `bounds`.
+	sets: Vec<Arc<DescriptorSet>>,
+	/// Whether a frame has drawn with `visible` and `draw` yet, which the next one has to wait for.
+	used: bool,
+}
+impl ChunkBuffers {
+	fn new(gfx: &Gfx, set_layout: &Arc<DescriptorSetLayout>, frames: usize, capacity: usize) -> Self {
+		let device = &gfx.device;
+		let bounds = (0..frames)
+			.map(|_| {
+				let bounds = device.create_buffer_slice(capacity, B1, BufferUsageFlags::STORAGE_BUFFER).uninitialized();
+				device.set_object_name(bounds.vk, "chunk bounds");
+				bounds
+			})
+			.collect::<Vec<_>>();
+		let visible = device.create_buffer_slice(capacity, B0, BufferUsageFlags::STORAGE_BUFFER).uninitialized();
+		device.set_object_name(visible.vk, "visible chunks");
+		let usage =
+			BufferUsageFlags::STORAGE_BUFFER | BufferUsageFlags::INDIRECT_BUFFER | BufferUsageFlags::TRANSFER_DST;
+		let draw = device.create_buffer_slice(1, B0, usage).uninitialized();
+		device.set_object_name(draw.vk, "chunk draw");
+
+		let pool_sizes = [DescriptorPoolSize::builder()
+			.ty(DescriptorType::STORAGE_BUFFER)
+			.descriptor_count(3 * frames as u32)
+			.build()];
+		let pool = device.create_descriptor_pool(frames as _, &pool_sizes);
+		let sets = bounds
+			.iter()
+			.map(|bounds| {
+				let set = pool.allocate(set_layout.clone()).unwrap();
+				set.write()
+					.storage_buffer(0, bounds.clone())
+					.storage_buffer(1, visible.clone())
+					.storage_buffer(2, draw.clone())
+					.submit();
+				set
+			})
+			.collect();
+
+		Self { capacity, bounds, visible, draw, sets, used: false }
+	}
+}
+
+/// The chunks' bounds, the indices of the visible ones, and the draw's arguments. The vertex shader reads the first
+/// two to place each box.
+fn create_set_layout(gfx: &Gfx) -> Arc<DescriptorSetLayout> {
+	let binding = |binding, stages| {
+		DescriptorSetLayoutBinding::builder()
+			.binding(binding)
+			.descriptor_type(DescriptorType::STORAGE_BUFFER)
+			.descriptor_count(1)
+			.stage_flags(stages)
+			.build()
+	};
+	let drawn = ShaderStageFlags::COMPUTE | ShaderStageFlags::VERTEX;
+	let bindings = [binding(0, drawn), binding(1, drawn), binding(2, ShaderStageFlags::COMPUTE)];
+	gfx.device.create_descriptor_set_layout(&bindings, vec![])
+}
+
+fn create_pipeline(gfx: &Gfx, layout: Arc<PipelineLayout>) -> Arc<ComputePipeline> {
+	let pipeline = gfx.device.create_compute_pipeline(layout, gfx.shaders().cull_comp.clone());
+	gfx.device.set_object_name(pipeline.vk, "cull chunks");
+	pipeline
+}
@@ -1,42 +1,670 @@
-use crate::gfx::Gfx;
-use ash::{version::DeviceV1_0, vk};
-use std::sync::Arc;
-use vk_mem::{Allocation, AllocationCreateInfo, MemoryUsage};
-
-pub struct Volume {
-	image: vk::Image,
-	allocation: Allocation,
-}
-impl Volume {
-	pub fn new(gfx: Arc<Gfx>) -> Self {
-		#[rustfmt::skip]
-		let data = [
-			53i8, 0, 53,
-			0, 127, 0,
-			53i8, 0, 53,
-
-			53i8, 0, 53,
-			0, 127, 0,
-			53i8, 0, 53,
-
-			53i8, 0, 53,
-			0, 127, 0,
-			53i8, 0, 53,
-		];
-
-		let ci = vk::ImageCreateInfo::builder()
-			.image_type(vk::ImageType::TYPE_3D)
-			.format(vk::Format::R8_SNORM)
-			.extent(vk::Extent3D { width: 3, height: 3, depth: 3 })
-			.mip_levels(1)
-			.array_layers(1)
-			.samples(vk::SampleCountFlags::TYPE_1)
-			.usage(vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED)
-			.sharing_mode(vk::SharingMode::EXCLUSIVE)
-			.initial_layout(vk::ImageLayout::UNDEFINED);
-		let aci = AllocationCreateInfo { usage: MemoryUsage::GpuOnly, ..Default::default() };
-		let (image, allocation, _) = gfx.device.allocator.create_image(&ci, &aci).unwrap();
-
-		Self { image, allocation }
+//! The GPU copy of the world's distance field, which the volume shader marches. Each loaded chunk is copied into a slot
+//! of one 3D image, the atlas, and a table says which slot holds which chunk. The table wraps around the world like a
+//! torus, so the chunks in range of the camera never share an entry and nothing moves when the camera does.
+//!
+//! Only chunks with a surface are resident. Empty ones read the same as unloaded space, so they get no slot, and the
+//! atlas starts with room for a few layers of chunks and grows as more of them need one. This does by hand what sparse
+//! images would, since not every device supports those.
+//!
+//! Each slot also has a byte for each brick in the occupancy image, and the atlas has min-filtered mips. A compute pass
+//! fills both in whenever a slot's voxels change, and the shader skips the coarsest empty block it's in, like
+//! `World::skip_empty` does.
+//!
+//! Chunks are only copied when they get a slot, along with their materials, which are in another image with the same
+//! slots. After that, the world's edits are replayed on both by another compute pass, so an edit costs a dispatch
+//! instead of an upload.
+//!
+//! Chunks further from the camera are copied at a lower level of detail, downsampled by `Chunk::downsample`. Each level
+//! halves the resolution, so a slot is split into 8 cells for chunks one level down, into 64 for two, and so on. The
+//! table says which cell and level each chunk has, and chunks move to a new cell as the camera moves and their level
+//! changes. Edits to them are copied again rather than replayed, since the edit pass only works at full resolution.
+
+use crate::gfx::{
+	buffer::{Staging, StagingPool},
+	graph::{ImageId, Pass, RenderGraph, Usage},
+	EditParams, Gfx, MipParams,
+};
+use ash::vk;
+use nalgebra::Vector3;
+use sim::{
+	math::Aabb,
+	world::{chunk_bounds, edit::Edit, stream::MAX_LOD, Residency, World, BRICK_SIZE, CHUNK_SIZE, MIP_LEVELS},
+};
+use std::{collections::HashSet, iter::once, mem, ops::Range, slice, sync::Arc};
+use typenum::{B0, B1};
+use vulkan::{
+	buffer::Buffer,
+	command::CommandBufferBuilder,
+	descriptor::{
+		DescriptorPoolSize, DescriptorSet, DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorType,
+		ShaderStageFlags,
+	},
+	device::{BufferUsageFlags, Device},
+	image::Image,
+	pipeline::ComputePipeline,
+	shader::ShaderModule,
+};
+
+/// How many chunks can be staged in one `update`, so a burst of loading is spread over several frames.
+const MAX_UPLOADS: usize = 32;
+/// How many layers of slots the atlas starts with. Terrain crosses about two layers of chunks in each column.
+const INITIAL_LAYERS: u32 = 2;
+/// Marks a table entry whose chunk isn't in the atlas. Matches `NO_CELL` in `shader.frag`.
+const NO_CELL: u32 = !0;
+/// Texels of occupancy along each edge of a slot.
+const BRICKS_PER_EDGE: u32 = (CHUNK_SIZE / BRICK_SIZE) as u32;
+/// Matches `local_size_x` in `edit.comp`.
+const EDIT_WORKGROUP_SIZE: u32 = 8;
+
+/// Laid out like `ChunkEntry` in `shader.frag`, following std430.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+struct ChunkEntry {
+	key: Vector3<i32>,
+	/// The chunk's cell in the atlas, or `NO_CELL`. See `CellAllocator`.
+	cell: u32,
+	/// The chunk's level of detail, which is the size of its cell.
+	lod: u32,
+	/// std430 rounds the struct up to a multiple of its `ivec3`'s alignment.
+	_padding: [u32; 3],
+}
+impl ChunkEntry {
+	fn new(key: Vector3<i32>, cell: u32, lod: u32) -> Self {
+		Self { key, cell, lod, _padding: [0; 3] }
+	}
+}
+
+pub struct WorldVolumes {
+	gfx: Arc<Gfx>,
+	images: VolumeImages,
+	/// The atlas's size in slots. It only grows along Z, so slots keep their place when it does.
+	slots: Vector3<u32>,
+	/// How many layers the atlas can grow to, which is enough for every chunk in range.
+	max_layers: u32,
+	/// The images from before the atlas last grew, whose slots the next frame copies into the new ones.
+	retired: Option<VolumeImages>,
+	cells: CellAllocator,
+	/// Entries along each edge of the table. A power of two, so wrapping a key around is a mask.
+	table_size: i32,
+	/// What the table holds, which is copied into each frame's buffer before the frame reads it.
+	entries: Vec<ChunkEntry>,
+	/// Counts the changes to `entries`.
+	generation: u64,
+	/// One for each frame in flight.
+	tables: Vec<Arc<Buffer<[ChunkEntry]>>>,
+	/// The generation each of `tables` was last written at.
+	written: Vec<Option<u64>>,
+	/// One for each frame in flight. See `create_set_layout`.
+	sets: Vec<Arc<DescriptorSet>>,
+	mips_pipeline: Arc<ComputePipeline>,
+	edit_pipeline: Arc<ComputePipeline>,
+	/// The shader generation the pipelines were built from.
+	#[cfg(debug_assertions)]
+	shader_generation: u64,
+	/// Loaded chunks that need copying into the atlas, because they're new or were edited.
+	queued: HashSet<Vector3<i32>>,
+	staging: StagingPool,
+	/// Copies into the atlas for the next frame, by cell and level of detail.
+	staged: Vec<(u32, u32, Staging)>,
+	/// The dispatches of each edit to replay on the atlas in the next frame, in order.
+	edits: Vec<Vec<EditParams>>,
+	/// The staging that each frame in flight copies from, which is freed once the frame is done.
+	in_flight: Vec<Vec<Staging>>,
+	/// Whether a frame has read the atlas yet, which the next one's copies have to wait for.
+	used: bool,
+}
+impl WorldVolumes {
+	/// The atlas can grow to hold every chunk up to `radius` chunks from the camera's in each direction, which is how
+	/// far a `ChunkStreamer` with the same radius loads them. `frames` is how many frames can be in flight.
+	pub fn new(gfx: Arc<Gfx>, radius: i32, frames: usize) -> Self {
+		let width = 2 * radius.max(0) as u32 + 1;
+		let slots = Vector3::new(width, width, INITIAL_LAYERS.min(width));
+		let table_size = table_size(radius);
+		let images = VolumeImages::new(&gfx, slots);
+
+		let empty = ChunkEntry::new(Vector3::zeros(), NO_CELL, 0);
+		let entries = vec![empty; (table_size * table_size * table_size) as usize];
+		let tables = (0..frames)
+			.map(|_| {
+				let table = gfx.device.create_buffer_slice(entries.len(), B1, BufferUsageFlags::STORAGE_BUFFER);
+				let table = table.copy_from_slice(&entries);
+				gfx.device.set_object_name(table.vk, "chunk table");
+				table
+			})
+			.collect::<Vec<_>>();
+
+		let sets = create_sets(&gfx, &images, &tables);
+
+		let mut cells = CellAllocator::new();
+		cells.add_slots(0..slots.x * slots.y * slots.z);
+		let staging = StagingPool::new(gfx.device.clone());
+		Self {
+			mips_pipeline: create_pipeline(&gfx, &gfx.shaders().mips_comp, "chunk mips"),
+			edit_pipeline: create_pipeline(&gfx, &gfx.shaders().edit_comp, "chunk edits"),
+			#[cfg(debug_assertions)]
+			shader_generation: gfx.shader_generation(),
+			gfx,
+			images,
+			slots,
+			max_layers: width,
+			retired: None,
+			cells,
+			table_size,
+			entries,
+			generation: 0,
+			tables,
+			written: vec![None; frames],
+			sets,
+			queued: HashSet::new(),
+			staging,
+			staged: vec![],
+			edits: vec![],
+			in_flight: (0..frames).map(|_| vec![]).collect(),
+			used: false,
+		}
+	}
+
+	/// Frees the cells of chunks that unloaded or were emptied, and stages copies of the occupied chunks that need one,
+	/// up to `MAX_UPLOADS` of them. The rest wait for later calls. `lod` gives the level of detail each chunk should
+	/// be at, like `ChunkStreamer::lod`, and chunks whose level changed are copied again at the new one. `edits` are
+	/// the world's edits since the last call, which are replayed on the chunks that already have a cell. Both are
+	/// recorded by the next `add_passes`.
+	pub fn update(&mut self, world: &World, edits: &[Edit], lod: impl Fn(Vector3<i32>) -> u32) {
+		for i in 0..self.entries.len() {
+			let entry = self.entries[i];
+			if entry.cell != NO_CELL && world.residency(entry.key) != Residency::Occupied {
+				self.entries[i].cell = NO_CELL;
+				self.cells.release(entry.cell, entry.lod);
+				self.generation += 1;
+			}
+		}
+
+		for key in world.chunk_keys().filter(|&key| world.residency(key) == Residency::Occupied) {
+			if self.cell(key).map(|(_, old)| old) != Some(lod(key)) {
+				self.queued.insert(key);
+			}
+		}
+		self.queued.retain(|&key| world.residency(key) == Residency::Occupied);
+
+		let batch = self.queued.iter().take(MAX_UPLOADS).copied().collect::<Vec<_>>();
+		for key in batch {
+			self.queued.remove(&key);
+			let lod = lod(key).min(MAX_LOD);
+			let cell = match self.cell(key) {
+				Some((cell, old)) if old == lod => Some(cell),
+				_ => self.insert(key, lod),
+			};
+			let cell = match cell {
+				Some(cell) => cell,
+				None => {
+					log::warn!("the chunk atlas is full, so chunk {:?} isn't drawn", key);
+					continue;
+				},
+			};
+			let chunk = world.chunk(key).unwrap();
+			let staging = if lod == 0 {
+				let data = chunk.data();
+				// the voxels are bytes either way, and the image reads them as signed
+				let data = unsafe { slice::from_raw_parts(data.as_ptr() as *const u8, data.len()) };
+				stage(&self.staging, data, chunk.materials())
+			} else {
+				let (data, materials) = chunk.downsample(lod);
+				let data = data.into_iter().map(|x| x as u8).collect::<Vec<_>>();
+				stage(&self.staging, &data, &materials)
+			};
+			self.staged.push((cell, lod, staging));
+		}
+
+		for edit in edits {
+			let bounds = edit.bounds();
+			let min = bounds.min.map(|x| (x.floor() as i32).div_euclid(CHUNK_SIZE as i32));
+			let max = bounds.max.map(|x| (x.ceil() as i32).div_euclid(CHUNK_SIZE as i32));
+			let mut dispatches = vec![];
+			for z in min.z..=max.z {
+				for y in min.y..=max.y {
+					for x in min.x..=max.x {
+						let key = Vector3::new(x, y, z);
+						match self.cell(key) {
+							// at full resolution, a cell is a whole slot
+							Some((slot, 0)) => dispatches.push(EditParams::new(edit, slot, key * CHUNK_SIZE as i32)),
+							Some(_) => {
+								self.queued.insert(key);
+							},
+							None => (),
+						}
+					}
+				}
+			}
+			if !dispatches.is_empty() {
+				self.edits.push(dispatches);
+			}
+		}
+		// staged copies are of the chunks with every edit already made, and a slot may have changed hands since its
+		// edits were recorded
+		let staged = self.staged.iter().map(|&(cell, lod, _)| slot(cell, lod)).collect::<HashSet<_>>();
+		for dispatches in &mut self.edits {
+			dispatches.retain(|params| !staged.contains(&params.slot));
+		}
+	}
+
+	/// Whether occupied chunks are still waiting to be staged, because more than `MAX_UPLOADS` came in at once.
+	pub fn is_uploading(&self) -> bool {
+		!self.queued.is_empty()
+	}
+
+	/// The bounds of every chunk in the atlas, for drawing a box around each.
+	pub fn bounds(&self) -> Vec<Aabb> {
+		self.entries.iter().filter(|entry| entry.cell != NO_CELL).map(|entry| chunk_bounds(entry.key)).collect()
+	}
+
+	/// The set to bind as set 1 of the volume pipelines in frame `frame`.
+	pub fn set(&self, frame: usize) -> &Arc<DescriptorSet> {
+		&self.sets[frame]
+	}
+
+	/// Writes frame `frame`'s table and adds the passes that copy the staged chunks into the atlas, replay the edits,
+	/// and fill in the mips and occupancy of every slot that changed. Returns the images, which the pass that draws the
+	/// volumes must declare as `Usage::StorageRead`.
+	///
+	/// The GPU must be done with the last frame recorded with the same index.
+	pub fn add_passes(&mut self, graph: &mut RenderGraph, frame: usize) -> Vec<ImageId> {
+		#[cfg(debug_assertions)]
+		self.reload_pipelines();
+		for staging in self.in_flight[frame].drain(..) {
+			self.staging.release(staging);
+		}
+
+		if self.written[frame] != Some(self.generation) {
+			unsafe { self.tables[frame].write(&self.entries) };
+			self.written[frame] = Some(self.generation);
+		}
+
+		// the last frame's draws have to finish reading the slots before they're overwritten
+		let last_use = if self.used { Some(Usage::StorageRead) } else { None };
+		let images = match self.retired.take() {
+			// nothing has used the grown images yet, and the old ones' slots are copied to the same place in them
+			Some(old) => {
+				let images = self.images.all().into_iter().map(|image| graph.import_image(image, None)).collect();
+				for ((old, new), &id) in old.all().into_iter().zip(self.images.all()).zip(&images) {
+					let old_id = graph.import_image(old.clone(), last_use);
+					let copy = move |cmd: CommandBufferBuilder<B0>| {
+						let regions = (0..old.mip_levels)
+							.map(|level| {
+								let layers = vk::ImageSubresourceLayers::builder()
+									.aspect_mask(vk::ImageAspectFlags::COLOR)
+									.mip_level(level)
+									.layer_count(1)
+									.build();
+								let extent = old.extent;
+								vk::ImageCopy::builder()
+									.src_subresource(layers)
+									.dst_subresource(layers)
+									.extent(vk::Extent3D {
+										width: extent.width >> level,
+										height: extent.height >> level,
+										depth: extent.depth >> level,
+									})
+									.build()
+							})
+							.collect::<Vec<_>>();
+						let (src, dst) = (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+						cmd.copy_image(old, src, new, dst, &regions)
+					};
+					graph.add_pass(
+						Pass::new("grow chunk atlas", copy)
+							.image(old_id, Usage::TransferSrc)
+							.image(id, Usage::TransferDst),
+					);
+				}
+				images
+			},
+			None => self.images.all().into_iter().map(|image| graph.import_image(image, last_use)).collect::<Vec<_>>(),
+		};
+		let (atlas, occupancy, materials) = (images[0], images[1], images[2]);
+		self.used = true;
+		let mut slots = self.staged.iter().map(|&(cell, lod, _)| slot(cell, lod)).collect::<Vec<_>>();
+		slots.extend(self.edits.iter().flatten().map(|params| params.slot));
+		if slots.is_empty() {
+			return images;
+		}
+		slots.sort_unstable();
+		slots.dedup();
+
+		// each chunk's staging has its voxels, then its materials
+		let copies = self
+			.staged
+			.iter()
+			.map(|&(cell, lod, ref staging)| {
+				let voxels = self.region(cell, lod, staging.offset);
+				let materials = self.region(cell, lod, staging.offset + staging.len / 2);
+				(staging.buffer.clone(), voxels, materials)
+			})
+			.collect::<Vec<_>>();
+		let (image, material_image) = (self.images.atlas.clone(), self.images.materials.clone());
+		let upload = move |mut cmd: CommandBufferBuilder<B0>| {
+			let layout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
+			for (buffer, voxels, materials) in copies {
+				cmd = cmd.copy_buffer_to_image(buffer.clone(), image.clone(), layout, &[voxels]);
+				cmd = cmd.copy_buffer_to_image(buffer, material_image.clone(), layout, &[materials]);
+			}
+			cmd
+		};
+		if !self.staged.is_empty() {
+			graph.add_pass(
+				Pass::new("upload chunks", upload)
+					.image(atlas, Usage::TransferDst)
+					.image(materials, Usage::TransferDst),
+			);
+		}
+
+		let dispatches = self.edits.iter().map(Vec::len).sum::<usize>() + slots.len();
+		self.gfx.stats().add_dispatches(dispatches as _);
+		let layout = self.gfx.volume_compute_layout.clone();
+		// a pass for each edit, so each one's dispatches see the last one's writes
+		for dispatches in self.edits.drain(..).filter(|dispatches| !dispatches.is_empty()) {
+			let (layout, pipeline, set) = (layout.clone(), self.edit_pipeline.clone(), self.sets[frame].clone());
+			let edit = move |mut cmd: CommandBufferBuilder<B0>| {
+				cmd = cmd.bind_compute_pipeline(pipeline).bind_compute_descriptor_sets(layout.clone(), 0, once(set));
+				let groups = CHUNK_SIZE as u32 / EDIT_WORKGROUP_SIZE;
+				for params in dispatches {
+					let stage = vk::ShaderStageFlags::COMPUTE;
+					cmd = cmd.push_constants(layout.clone(), stage, 0, &params).dispatch(groups, groups, groups);
+				}
+				cmd
+			};
+			graph.add_pass(
+				Pass::new("edit chunks", edit).image(atlas, Usage::StorageWrite).image(materials, Usage::StorageWrite),
+			);
+		}
+
+		let (pipeline, set) = (self.mips_pipeline.clone(), self.sets[frame].clone());
+		let fill = move |mut cmd: CommandBufferBuilder<B0>| {
+			cmd = cmd.bind_compute_pipeline(pipeline).bind_compute_descriptor_sets(layout.clone(), 0, once(set));
+			for slot in slots {
+				let params = MipParams { slot };
+				cmd = cmd.push_constants(layout.clone(), vk::ShaderStageFlags::COMPUTE, 0, &params).dispatch(1, 1, 1);
+			}
+			cmd
+		};
+		graph.add_pass(
+			Pass::new("chunk mips", fill).image(atlas, Usage::StorageWrite).image(occupancy, Usage::StorageWrite),
+		);
+
+		self.gfx.stats().add_upload(self.staged.iter().map(|(_, _, staging)| staging.len).sum());
+		self.in_flight[frame].extend(self.staged.drain(..).map(|(_, _, staging)| staging));
+		images
+	}
+
+	/// Rebuilds the pipelines if the shaders were reloaded since they were built.
+	#[cfg(debug_assertions)]
+	fn reload_pipelines(&mut self) {
+		let generation = self.gfx.shader_generation();
+		if generation != self.shader_generation {
+			self.shader_generation = generation;
+			self.mips_pipeline = create_pipeline(&self.gfx, &self.gfx.shaders().mips_comp, "chunk mips");
+			self.edit_pipeline = create_pipeline(&self.gfx, &self.gfx.shaders().edit_comp, "chunk edits");
+		}
+	}
+
+	/// The cell holding the chunk at `key` and its level of detail, if there is one.
+	fn cell(&self, key: Vector3<i32>) -> Option<(u32, u32)> {
+		let entry = &self.entries[self.table_index(key)];
+		if entry.cell != NO_CELL && entry.key == key {
+			Some((entry.cell, entry.lod))
+		} else {
+			None
+		}
+	}
+
+	/// Gives the chunk at `key` a cell at `lod`, replacing whatever chunk or cell had its entry and growing the atlas
+	/// if it's full. Returns `None` if it can't grow any more.
+	fn insert(&mut self, key: Vector3<i32>, lod: u32) -> Option<u32> {
+		let idx = self.table_index(key);
+		let old = mem::replace(&mut self.entries[idx].cell, NO_CELL);
+		if old != NO_CELL {
+			self.cells.release(old, self.entries[idx].lod);
+		}
+		let cell = match self.cells.alloc(lod) {
+			Some(cell) => cell,
+			None if self.grow() => self.cells.alloc(lod).unwrap(),
+			None => return None,
+		};
+		self.entries[idx] = ChunkEntry::new(key, cell, lod);
+		self.generation += 1;
+		Some(cell)
+	}
+
+	/// Doubles the layers of slots in the atlas, up to `max_layers`. Returns `false` if it's already that big.
+	fn grow(&mut self) -> bool {
+		if self.slots.z == self.max_layers {
+			return false;
+		}
+		let old_count = self.slots.x * self.slots.y * self.slots.z;
+		self.slots.z = (self.slots.z * 2).min(self.max_layers);
+		log::info!("growing the chunk atlas to {} layers", self.slots.z);
+
+		let old = mem::replace(&mut self.images, VolumeImages::new(&self.gfx, self.slots));
+		// if it grew twice before a frame, the chunks are all still in the first one. if no frame has used it, there's
+		// nothing to copy
+		if self.used && self.retired.is_none() {
+			self.retired = Some(old);
+		}
+		// frames in flight keep the old sets alive, and the next frame with each index uses the new one
+		self.sets = create_sets(&self.gfx, &self.images, &self.tables);
+		self.cells.add_slots(old_count..self.slots.x * self.slots.y * self.slots.z);
+		true
+	}
+
+	/// Where the chunk at `key` goes in the table. Matches `find_cell` in `shader.frag`.
+	fn table_index(&self, key: Vector3<i32>) -> usize {
+		let wrapped = key.map(|x| x & (self.table_size - 1));
+		(wrapped.x + wrapped.y * self.table_size + wrapped.z * self.table_size * self.table_size) as usize
+	}
+
+	/// The copy of a chunk's voxels or materials from `offset` in its staging into `cell`, at `lod`. Matches
+	/// `find_cell` in `shader.frag`.
+	fn region(&self, cell: u32, lod: u32, offset: u64) -> vk::BufferImageCopy {
+		let slot = slot(cell, lod);
+		let slot =
+			Vector3::new(slot % self.slots.x, slot / self.slots.x % self.slots.y, slot / (self.slots.x * self.slots.y));
+		let size = CHUNK_SIZE as u32 >> lod;
+		let (cells, sub) = (1 << lod, cell & ((1 << (3 * lod)) - 1));
+		let sub = Vector3::new(sub % cells, sub / cells % cells, sub / (cells * cells));
+		let origin = slot * CHUNK_SIZE as u32 + sub * size;
+		vk::BufferImageCopy::builder()
+			.buffer_offset(offset)
+			.image_subresource(
+				vk::ImageSubresourceLayers::builder().aspect_mask(vk::ImageAspectFlags::COLOR).layer_count(1).build(),
+			)
+			.image_offset(vk::Offset3D { x: origin.x as _, y: origin.y as _, z: origin.z as _ })
+			.image_extent(vk::Extent3D { width: size, height: size, depth: size })
+			.build()
+	}
+}
+
+/// The images the volumes are kept in, which all have the same slots.
+struct VolumeImages {
+	/// `CHUNK_SIZE` cubes of voxels, in the same format as `Chunk::data`. Each of its `MIP_LEVELS` mips is the minimum
+	/// of the texels under it, so a texel of 1 means the voxels it covers are all empty.
+	atlas: Arc<Image>,
+	/// A byte for each brick of each slot, which is 0 if every voxel in the brick is empty.
+	occupancy: Arc<Image>,
+	/// The material of each voxel in the atlas, in the same format as `Chunk::materials`.
+	materials: Arc<Image>,
+}
+impl VolumeImages {
+	/// With room for `slots` chunks in each direction.
+	fn new(gfx: &Gfx, slots: Vector3<u32>) -> Self {
+		Self {
+			atlas: create_image(
+				gfx,
+				slots * CHUNK_SIZE as u32,
+				vk::Format::R8_SNORM,
+				MIP_LEVELS as u32 + 1,
+				"chunk atlas",
+			),
+			occupancy: create_image(gfx, slots * BRICKS_PER_EDGE, vk::Format::R8_UINT, 1, "chunk occupancy"),
+			materials: create_image(gfx, slots * CHUNK_SIZE as u32, vk::Format::R8_UINT, 1, "chunk materials"),
+		}
+	}
+
+	fn all(&self) -> Vec<Arc<Image>> {
+		vec![self.atlas.clone(), self.occupancy.clone(), self.materials.clone()]
+	}
+}
+
+/// Hands out cells of the atlas. A cell at level of detail 0 is a whole slot, and each level splits the cells of the
+/// one before into 8. A cell's index is its slot's, then 3 bits for each level after 0 saying which eighth of the last
+/// level's cell it's in.
+struct CellAllocator {
+	/// The free cells at each level of detail.
+	free: Vec<Vec<u32>>,
+}
+impl CellAllocator {
+	fn new() -> Self {
+		Self { free: vec![vec![]; MAX_LOD as usize + 1] }
+	}
+
+	/// Adds new slots to hand out.
+	fn add_slots(&mut self, slots: Range<u32>) {
+		// handed out from the end, so the first chunks go in the first slots
+		self.free[0].extend(slots.rev());
+	}
+
+	/// Takes a free cell at `lod`, splitting a bigger one if there aren't any. Returns `None` if every slot is taken.
+	fn alloc(&mut self, lod: u32) -> Option<u32> {
+		if let Some(cell) = self.free[lod as usize].pop() {
+			return Some(cell);
+		}
+		if lod == 0 {
+			return None;
+		}
+		let parent = self.alloc(lod - 1)?;
+		self.free[lod as usize].extend((0..8).rev().map(|i| parent << 3 | i));
+		self.free[lod as usize].pop()
+	}
+
+	/// Frees a cell at `lod`, merging it back into the one it was split from once all 8 of that one's are free.
+	fn release(&mut self, cell: u32, lod: u32) {
+		let free = &mut self.free[lod as usize];
+		free.push(cell);
+		if lod > 0 && free.iter().filter(|&&other| other >> 3 == cell >> 3).count() == 8 {
+			free.retain(|&other| other >> 3 != cell >> 3);
+			self.release(cell >> 3, lod - 1);
+		}
+	}
+}
+
+/// The slot that `cell` at `lod` is in.
+fn slot(cell: u32, lod: u32) -> u32 {
+	cell >> (3 * lod)
+}
+
+/// Writes a chunk's voxels and then its materials straight into a staging range, which is how `add_passes` copies them.
+fn stage(staging: &StagingPool, data: &[u8], materials: &[u8]) -> Staging {
+	staging.push_with(data.len() + materials.len(), |dst| {
+		let (dst_data, dst_materials) = dst.split_at_mut(data.len());
+		dst_data.copy_from_slice(data);
+		dst_materials.copy_from_slice(materials);
+	})
+}
+
+fn create_image(gfx: &Gfx, extent: Vector3<u32>, format: vk::Format, levels: u32, name: &str) -> Arc<Image> {
+	let ci = vk::ImageCreateInfo::builder()
+		.image_type(vk::ImageType::TYPE_3D)
+		.format(format)
+		.extent(vk::Extent3D { width: extent.x, height: extent.y, depth: extent.z })
+		.mip_levels(levels)
+		.array_layers(1)
+		.samples(vk::SampleCountFlags::TYPE_1)
+		.usage(vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::STORAGE)
+		.sharing_mode(vk::SharingMode::EXCLUSIVE)
+		.initial_layout(vk::ImageLayout::UNDEFINED);
+	// the slots are filled in as chunks load, and nothing reads one before then
+	let image = gfx.device.create_image(&ci).attachment();
+	gfx.device.set_object_name(image.vk, name);
+	image
+}
+
+/// A set for each of `tables`, laid out like `create_set_layout`.
+fn create_sets(gfx: &Gfx, images: &VolumeImages, tables: &[Arc<Buffer<[ChunkEntry]>>]) -> Vec<Arc<DescriptorSet>> {
+	let count = tables.len() as u32;
+	let pool_sizes = [
+		DescriptorPoolSize::builder()
+			.ty(DescriptorType::STORAGE_IMAGE)
+			.descriptor_count((3 + MIP_LEVELS as u32) * count)
+			.build(),
+		DescriptorPoolSize::builder().ty(DescriptorType::STORAGE_BUFFER).descriptor_count(count).build(),
+	];
+	let pool = gfx.device.create_descriptor_pool(count, &pool_sizes);
+	let (atlas, occupancy) = (images.atlas.create_level_view(0), images.occupancy.create_view());
+	let materials = images.materials.create_view();
+	let mips = (1..=MIP_LEVELS as u32).map(|level| images.atlas.create_level_view(level)).collect::<Vec<_>>();
+	tables
+		.iter()
+		.map(|table| {
+			let set = pool.allocate(gfx.volume_set_layout.clone()).unwrap();
+			set.write()
+				.storage_image(0, atlas.clone())
+				.storage_buffer(1, table.clone())
+				.storage_image(2, occupancy.clone())
+				.storage_images(3, mips.iter().cloned())
+				.storage_image(4, materials.clone())
+				.submit();
+			set
+		})
+		.collect()
+}
+
+fn create_pipeline(gfx: &Gfx, shader: &Arc<ShaderModule>, name: &str) -> Arc<ComputePipeline> {
+	let pipeline = gfx.device.create_compute_pipeline(gfx.volume_compute_layout.clone(), shader.clone());
+	gfx.device.set_object_name(pipeline.vk, name);
+	pipeline
+}
+
+/// Entries along each edge of the chunk table for `radius`, the smallest power of two that fits every chunk loaded
+/// around the camera.
+pub(super) fn table_size(radius: i32) -> i32 {
+	(2 * radius.max(0) as u32 + 1).next_power_of_two() as i32
+}
+
+/// The atlas, the table, the occupancy, the atlas's mips and the materials. It's set 1 of the volume pipelines, and set
+/// 0 of the compute passes that fill in the volumes.
+pub(super) fn create_set_layout(device: &Arc<Device>) -> Arc<DescriptorSetLayout> {
+	let binding = |binding, ty, count| {
+		DescriptorSetLayoutBinding::builder()
+			.binding(binding)
+			.descriptor_type(ty)
+			.descriptor_count(count)
+			.stage_flags(ShaderStageFlags::FRAGMENT | ShaderStageFlags::COMPUTE)
+			.build()
+	};
+	let bindings = [
+		binding(0, DescriptorType::STORAGE_IMAGE, 1),
+		binding(1, DescriptorType::STORAGE_BUFFER, 1),
+		binding(2, DescriptorType::STORAGE_IMAGE, 1),
+		binding(3, DescriptorType::STORAGE_IMAGE, MIP_LEVELS as u32),
+		binding(4, DescriptorType::STORAGE_IMAGE, 1),
+	];
+	device.create_descriptor_set_layout(&bindings, vec![])
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn cells_split_and_merge() {
+		let mut cells = CellAllocator::new();
+		cells.add_slots(0..2);
+		let a = cells.alloc(1).unwrap();
+		let b = cells.alloc(2).unwrap();
+		assert_eq!((slot(a, 1), slot(b, 2)), (0, 0));
+		assert_eq!(cells.alloc(0), Some(1));
+		assert_eq!(cells.alloc(0), None);
+
+		cells.release(b, 2);
+		cells.release(a, 1);
+		assert_eq!(cells.alloc(0), Some(0));
 	}
 }
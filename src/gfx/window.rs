@@ -1,284 +1,853 @@
-use crate::gfx::{Gfx, TriangleVertex};
-use ash::{version::DeviceV1_0, vk, Device};
-use std::{
-	cmp::{max, min},
-	iter::{empty, once},
-	slice,
-	sync::Arc,
-	u32,
+#[cfg(feature = "debug-overlay")]
+use crate::gfx::debug_overlay::{DebugOverlay, DebugStats};
+use crate::{
+	camera::Camera,
+	gfx::{
+		error::GfxError,
+		frame::{FrameUniformRing, FrameUniforms, FramesInFlight},
+		graph::{Pass, RenderGraph, Usage},
+		gui::Document,
+		image,
+		lines::DebugLines,
+		profiler::{GpuProfiler, GpuScope},
+		renderer::{FrameContext, Renderer, Target},
+		temporal::Temporal,
+		terrain::TerrainMeshes,
+		volume::WorldVolumes,
+		Gfx, TriangleVertex,
+	},
+	lighting::Light,
+	scene::Scene,
 };
+use ash::{prelude::VkResult, vk};
+use nalgebra::{Matrix4, Vector2};
+use sim::math::Aabb;
+use std::{iter::once, sync::Arc, time::Instant};
+use typenum::{B0, B1};
 use vulkan::{
-	command::{ClearValue, CommandPool, InheritanceInfo},
-	image::{Format, Framebuffer, ImageView},
-	ordered_passes_renderpass,
-	pipeline::Pipeline,
-	render_pass::RenderPass,
-	surface::{ColorSpace, PresentMode, Surface, SurfaceCapabilities},
-	swapchain::{CompositeAlphaFlags, Swapchain},
-	sync::Fence,
+	buffer::Buffer,
+	command::{ClearValue, CommandBuffer, CommandBufferBuilder, CommandPool, InheritanceInfo},
+	descriptor::DescriptorSet,
+	geometry::{Extent, Rect},
+	image::{Format, Framebuffer, Image, ImageView},
+	pipeline::{Pipeline, PipelineLayout, Viewport},
+	render_pass::{Attachment, RenderPass, Subpass},
+	surface::{ColorSpace, PresentMode, SurfaceFormat},
+	swapchain::{SwapchainImage, SwapchainManager},
 	Extent2D, Rect2D,
 };
 use winit::{
 	event_loop::EventLoop,
-	window::{Window as IWindow, WindowBuilder},
+	monitor::MonitorHandle,
+	window::{Fullscreen, Window as IWindow, WindowBuilder, WindowId},
 };
 
+/// How the window covers its monitor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowMode {
+	Windowed,
+	/// A borderless window the size of the monitor, which switches to and from other windows instantly.
+	Borderless,
+	/// Takes over the monitor at its largest video mode.
+	Exclusive,
+}
+
+/// Display options chosen by the player.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DisplaySettings {
+	pub mode: WindowMode,
+	/// The monitor's name, or `None` for the primary monitor. Names are stored because handles don't survive restarts.
+	pub monitor: Option<String>,
+	/// The window's inner size in windowed mode, in logical pixels.
+	pub resolution: (u32, u32),
+}
+impl Default for DisplaySettings {
+	fn default() -> Self {
+		Self { mode: WindowMode::Windowed, monitor: None, resolution: (1440, 810) }
+	}
+}
+
+/// How finished frames are handed to the monitor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresentModePreference {
+	/// Presents as soon as a frame is ready. The lowest latency, but it tears.
+	Immediate,
+	/// Waits for vertical blank. Never tears, and is supported everywhere.
+	Vsync,
+	/// Replaces the waiting frame with each newer one, so latency stays low without tearing.
+	LowLatency,
+}
+impl PresentModePreference {
+	/// The modes to try, closest first. FIFO is always supported, so it's the last resort.
+	fn order(self) -> &'static [PresentMode] {
+		match self {
+			PresentModePreference::Immediate => {
+				&[PresentMode::IMMEDIATE, PresentMode::MAILBOX, PresentMode::FIFO_RELAXED]
+			},
+			PresentModePreference::Vsync => &[],
+			PresentModePreference::LowLatency => {
+				&[PresentMode::MAILBOX, PresentMode::IMMEDIATE, PresentMode::FIFO_RELAXED]
+			},
+		}
+	}
+}
+
+/// The lowest render scale, below which the world is too blurry to be worth the time saved.
+pub const MIN_RENDER_SCALE: f32 = 0.25;
+
+/// Rendering options. Only `present_mode` and `render_scale` can be changed after the window is created.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderSettings {
+	/// Samples per pixel. Lowered to the highest count the device supports, and 1 disables multisampling. Ignored
+	/// while `temporal` is on, which smooths edges over several frames instead.
+	pub msaa: u32,
+	/// Maximum anisotropic filtering for textures in the world. Lowered to the device's limit, and 1 disables it.
+	pub anisotropy: f32,
+	pub present_mode: PresentModePreference,
+	/// How many frames the CPU can record before waiting for the GPU to finish the oldest. Raised to at least 1.
+	pub frames_in_flight: usize,
+	/// The world's resolution as a fraction of the window's, from `MIN_RENDER_SCALE` to 1. Lower values trade
+	/// sharpness for speed, since raymarching costs about the same for every pixel. The GUI is always drawn at full
+	/// resolution.
+	pub render_scale: f32,
+	/// Blends each frame of the world with the ones before it, which removes the volumes' noise and aliasing but blurs
+	/// things that move quickly.
+	pub temporal: bool,
+}
+impl Default for RenderSettings {
+	fn default() -> Self {
+		Self {
+			msaa: 4,
+			anisotropy: 16.0,
+			present_mode: PresentModePreference::LowLatency,
+			frames_in_flight: 2,
+			render_scale: 1.0,
+			temporal: true,
+		}
+	}
+}
+
+/// Visualizations for diagnosing rendering, switched at runtime.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DebugView {
+	/// Draws meshes as the outlines of their triangles. Ignored if the device can't draw lines for polygons.
+	pub wireframe: bool,
+	/// Colors volumes by how many steps each pixel's ray took, from blue for one to red for the limit, to find where
+	/// raymarching is expensive.
+	pub step_heatmap: bool,
+	/// Draws the world as triangle meshes of its chunks instead of raymarching it, to compare the two.
+	pub meshed_world: bool,
+}
+impl DebugView {
+	/// The push constant for the volume shader. `jitter` is for the temporal pass to average out.
+	pub(super) fn volume_flags(self, jitter: bool) -> u32 {
+		self.step_heatmap as u32 | if jitter { FLAG_JITTER } else { 0 }
+	}
+}
+
+/// Tells the volume shader to jitter its rays and shadows.
+const FLAG_JITTER: u32 = 2;
+
+/// A window and everything needed to draw into it. Any number of them can share one `Gfx`, which owns the device,
+/// shaders and textures, while each window has its own surface, swapchain, pipelines and frames in flight.
 pub struct Window {
 	pub(super) gfx: Arc<Gfx>,
-	surface: Arc<Surface<IWindow>>,
-	surface_format: vk::SurfaceFormatKHR,
-	pub(super) render_pass: Arc<RenderPass>,
-	frame_data: [FrameData; 2],
+	swapchain: SwapchainManager<IWindow>,
+	/// Draws the world into `world`.
+	world_pass: Arc<RenderPass>,
+	/// Draws the GUI over the upscaled world in the swapchain image.
+	ui_pass: Arc<RenderPass>,
+	samples: vk::SampleCountFlags,
+	frames: FramesInFlight,
+	/// One for each frame in flight.
+	cmdpools: Vec<Arc<CommandPool>>,
+	frame_uniforms: FrameUniformRing,
+	profiler: GpuProfiler,
 	image_extent: Extent2D,
-	present_mode: PresentMode,
-	swapchain: Arc<Swapchain<IWindow>>,
-	pub(super) pipeline: Arc<Pipeline>,
-	pub(super) framebuffers: Vec<Arc<Framebuffer>>,
-	frame: bool,
-	recreate_swapchain: bool,
+	present_preference: PresentModePreference,
+	/// The UI pass's, one for each swapchain image.
+	framebuffers: Vec<Arc<Framebuffer>>,
+	render_scale: f32,
+	world: WorldTarget,
+	temporal: Option<Temporal>,
+	display: DisplaySettings,
+	debug_view: DebugView,
+	renderer: Renderer,
+	/// Drawn over the scene in the next frame, then cleared.
+	pub lines: DebugLines,
+	/// The world's meshes, drawn instead of its volumes while the debug view asks for them.
+	pub terrain: TerrainMeshes,
+	pub gui: Document,
+	ui_scale: f32,
+	/// When the window was created, which the shaders' time counts from.
+	start: Instant,
+	/// The shader generation the pipelines were built from.
+	#[cfg(debug_assertions)]
+	shader_generation: u64,
+	#[cfg(feature = "debug-overlay")]
+	pub debug_overlay: DebugOverlay,
 }
 impl Window {
-	pub fn new(gfx: Arc<Gfx>, event_loop: &EventLoop<()>) -> Self {
-		let window = WindowBuilder::new().with_inner_size((1440, 810).into()).build(&event_loop).unwrap();
-		let surface = gfx.instance.create_surface(window);
-		assert!(gfx.device.physical_device().get_surface_support(gfx.queue.family(), &surface));
-
-		let surface_format = gfx
-			.device
-			.physical_device()
-			.get_surface_formats(&surface)
-			.into_iter()
-			.max_by_key(|format| {
-				format.format == Format::B8G8R8A8_UNORM && format.color_space == ColorSpace::SRGB_NONLINEAR
-			})
-			.unwrap();
-
-		let render_pass = ordered_passes_renderpass!(gfx.device.clone(),
-			attachments: { color: { load: Clear, store: Store, format: surface_format.format, samples: 1, } },
-			passes: [{ color: [color], depth_stencil: {}, input: [] }]
-		);
+	pub fn new(
+		gfx: Arc<Gfx>,
+		event_loop: &EventLoop<()>,
+		display: DisplaySettings,
+		render: RenderSettings,
+	) -> Result<Self, GfxError> {
+		let monitor = find_monitor(event_loop.available_monitors(), event_loop.primary_monitor(), &display);
+		let window = WindowBuilder::new()
+			.with_inner_size(display.resolution.into())
+			.with_fullscreen(fullscreen(monitor, display.mode))
+			.build(&event_loop)?;
+		let surface = gfx.instance.create_surface(window).map_err(|err| match err {
+			vk::Result::ERROR_EXTENSION_NOT_PRESENT => GfxError::SurfaceUnsupported,
+			err => GfxError::VkError(err),
+		})?;
+		if !gfx.device.physical_device().get_surface_support(gfx.queue.family(), &surface) {
+			return Err(GfxError::SurfaceUnsupported);
+		}
 
-		let (caps, image_extent) = get_caps(&gfx, &surface);
-		let present_mode = gfx
-			.device
-			.physical_device()
-			.get_surface_present_modes(&surface)
-			.into_iter()
-			.min_by_key(|&mode| match mode {
-				PresentMode::MAILBOX => 0,
-				PresentMode::IMMEDIATE => 1,
-				PresentMode::FIFO_RELAXED => 2,
-				PresentMode::FIFO => 3,
-				_ => 4,
-			})
-			.unwrap();
-
-		let (swapchain, image_views) =
-			create_swapchain(&gfx, surface.clone(), &caps, &surface_format, image_extent, present_mode, None);
-		let pipeline = create_pipeline(&gfx, image_extent, render_pass.clone());
-		let framebuffers = create_framebuffers(&render_pass, image_views, image_extent);
-
-		let frame_data = [FrameData::new(&gfx), FrameData::new(&gfx)];
+		let formats = [SurfaceFormat { format: Format::B8G8R8A8_UNORM, color_space: ColorSpace::SRGB_NONLINEAR }];
+		// the world is blitted into the swapchain images, then the GUI is drawn over it
+		let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST;
+		let present_modes = render.present_mode.order();
+		let swapchain =
+			SwapchainManager::new(gfx.device.clone(), surface, &formats, usage, present_modes, window_extent)
+				.ok_or(GfxError::SurfaceUnsupported)?;
+		let format = swapchain.format().format;
 
-		Self {
+		#[cfg(debug_assertions)]
+		let shader_generation = gfx.shader_generation();
+		let mut temporal = if render.temporal { Some(Temporal::new(gfx.clone())?) } else { None };
+		// the temporal pass samples the world's depth, which is simpler without multisampling
+		let samples =
+			if temporal.is_some() { vk::SampleCountFlags::TYPE_1 } else { gfx.supported_samples(render.msaa) };
+		// the render graph moves the images in and out of the attachment layout
+		let attachment = vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL;
+		let world_pass = create_render_pass(&gfx, format, samples, attachment, attachment, temporal.is_some())?;
+		let ui_pass = create_ui_render_pass(&gfx, format)?;
+
+		let image_extent = swapchain.extent();
+		let image_views = create_image_views(&gfx, swapchain.images(), format);
+		let framebuffers = create_ui_framebuffers(&ui_pass, image_views, image_extent);
+		let render_scale = render.render_scale.max(MIN_RENDER_SCALE).min(1.0);
+		let world_extent = world_extent(image_extent, render_scale);
+		let world = WorldTarget::new(&gfx, &world_pass, format, samples, world_extent, temporal.as_mut());
+
+		let frames = FramesInFlight::new(render.frames_in_flight);
+		let cmdpools = frames.per_frame(|_| gfx.device.create_command_pool(gfx.queue.family(), true));
+		let frame_uniforms = FrameUniformRing::new(&gfx.device, &gfx.frame_set_layout, &frames);
+		let profiler = GpuProfiler::new(&gfx, &frames);
+
+		let renderer = Renderer::new(&gfx, &world_pass, samples, &ui_pass, render.anisotropy, frames.count());
+		let terrain = TerrainMeshes::new(gfx.clone());
+		let mut gui = Document::new(Rect2D::builder().extent(image_extent).build());
+		gui.set_scale(swapchain.surface().window().hidpi_factor() as f32);
+		#[cfg(feature = "debug-overlay")]
+		let debug_overlay = DebugOverlay::new(gfx.clone(), ui_pass.clone(), vk::SampleCountFlags::TYPE_1);
+
+		Ok(Self {
 			gfx,
-			surface,
-			surface_format,
-			render_pass,
-			frame_data,
-			image_extent,
-			present_mode,
 			swapchain,
-			pipeline,
+			world_pass,
+			ui_pass,
+			samples,
+			frames,
+			cmdpools,
+			frame_uniforms,
+			profiler,
+			image_extent,
+			present_preference: render.present_mode,
 			framebuffers,
-			frame: false,
-			recreate_swapchain: false,
+			render_scale,
+			world,
+			temporal,
+			display,
+			debug_view: DebugView::default(),
+			renderer,
+			lines: DebugLines::default(),
+			terrain,
+			gui,
+			ui_scale: 1.0,
+			start: Instant::now(),
+			#[cfg(debug_assertions)]
+			shader_generation,
+			#[cfg(feature = "debug-overlay")]
+			debug_overlay,
+		})
+	}
+
+	pub fn winit_window(&self) -> &IWindow {
+		self.swapchain.surface().window()
+	}
+
+	/// Identifies the window in winit's events.
+	pub fn id(&self) -> WindowId {
+		self.winit_window().id()
+	}
+
+	pub fn display(&self) -> &DisplaySettings {
+		&self.display
+	}
+
+	/// Switches window mode, monitor or windowed size. The swapchain is recreated before the next frame.
+	pub fn set_display(&mut self, display: DisplaySettings) {
+		let window = self.winit_window();
+		let monitor = find_monitor(window.available_monitors(), window.primary_monitor(), &display);
+		if display.mode == WindowMode::Windowed && display.monitor != self.display.monitor {
+			let pos = monitor.position().to_logical(monitor.hidpi_factor());
+			window.set_outer_position(pos);
 		}
+		if display.resolution != self.display.resolution {
+			window.set_inner_size(display.resolution.into());
+		}
+		window.set_fullscreen(fullscreen(monitor, display.mode));
+
+		self.display = display;
+		self.swapchain.invalidate();
+	}
+
+	pub fn present_mode(&self) -> PresentModePreference {
+		self.present_preference
+	}
+
+	/// Switches how frames are presented. If the surface doesn't support the preferred mode, the closest one it does
+	/// support is used instead. The swapchain is recreated before the next frame if the mode actually changes.
+	pub fn set_present_mode(&mut self, preference: PresentModePreference) {
+		self.present_preference = preference;
+		self.swapchain.set_present_modes(preference.order());
+	}
+
+	/// The names of the connected monitors, for choosing one in `DisplaySettings`.
+	pub fn monitor_names(&self) -> Vec<String> {
+		self.winit_window().available_monitors().into_iter().filter_map(|monitor| monitor.name()).collect()
+	}
+
+	/// The ratio of physical to logical pixels on the window's current monitor.
+	pub fn hidpi_factor(&self) -> f64 {
+		self.winit_window().hidpi_factor()
 	}
 
-	pub fn draw(&mut self) {
-		if self.recreate_swapchain {
-			self.recreate_swapchain();
+	/// Sets the user's UI scale, which is applied on top of the monitor's DPI factor.
+	pub fn set_ui_scale(&mut self, scale: f32) {
+		self.ui_scale = scale;
+		self.gui.set_scale(self.hidpi_factor() as f32 * self.ui_scale);
+	}
+
+	/// Runs the debug overlay's UI for this frame and applies any settings changed through it.
+	#[cfg(feature = "debug-overlay")]
+	pub fn update_debug_overlay(&mut self) {
+		let frame = self.gfx.frame_stats();
+		let mut stats = DebugStats { frame, image_extent: self.image_extent, ui_scale: self.ui_scale };
+		let hidpi_factor = self.hidpi_factor() as f32;
+		self.debug_overlay.update(self.image_extent, hidpi_factor, &mut stats);
+
+		if stats.ui_scale != self.ui_scale {
+			self.set_ui_scale(stats.ui_scale);
 		}
+	}
 
-		let res = self.swapchain.acquire_next_image(!0);
-		let (image_idx, future) = match res {
-			Ok((idx, suboptimal, future)) => {
-				if suboptimal {
-					self.recreate_swapchain = true;
-				}
-				(idx, future)
-			},
-			Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
-				self.recreate_swapchain = true;
-				return;
-			},
-			Err(err) => panic!(err),
+	pub fn render_scale(&self) -> f32 {
+		self.render_scale
+	}
+
+	/// Changes the world's resolution relative to the window's, starting with the next frame. It's clamped to
+	/// `MIN_RENDER_SCALE` through 1.
+	pub fn set_render_scale(&mut self, scale: f32) {
+		let scale = scale.max(MIN_RENDER_SCALE).min(1.0);
+		if scale == self.render_scale {
+			return;
+		}
+		self.render_scale = scale;
+		// frames in flight hold the old images until they're done with them
+		let (format, extent) = (self.swapchain.format().format, world_extent(self.image_extent, scale));
+		self.world =
+			WorldTarget::new(&self.gfx, &self.world_pass, format, self.samples, extent, self.temporal.as_mut());
+	}
+
+	/// Call when the window moves to a monitor with a different DPI factor.
+	pub fn hidpi_factor_changed(&mut self) {
+		self.swapchain.invalidate();
+		self.gui.set_scale(self.hidpi_factor() as f32 * self.ui_scale);
+	}
+
+	pub fn debug_view(&self) -> DebugView {
+		self.debug_view
+	}
+
+	/// Switches debug visualizations, starting with the next frame.
+	pub fn set_debug_view(&mut self, view: DebugView) {
+		self.debug_view = view;
+	}
+
+	/// The frames in flight, for systems that keep a resource per frame.
+	pub fn frames(&self) -> &FramesInFlight {
+		&self.frames
+	}
+
+	/// Width over height, for building projections.
+	pub fn aspect(&self) -> f32 {
+		self.image_extent.width as f32 / self.image_extent.height as f32
+	}
+
+	/// Draws a frame with the stages of the window's `Renderer`: the chunks of `volumes` inside the camera's frustum,
+	/// the scene's meshes, both lit by `light`, then `lines`, which are cleared. They're drawn at the render scale and
+	/// upscaled to the window, then the GUI is drawn over them at full resolution. `volumes` must have been made for at
+	/// least as many frames in flight as the window has.
+	///
+	/// The stats frame isn't ended here, since other windows may draw into it too.
+	pub fn draw(&mut self, camera: &Camera, light: &Light, volumes: &mut WorldVolumes, scene: &Scene) {
+		let (gfx, world_pass, ui_pass, samples) = (&self.gfx, &self.world_pass, &self.ui_pass, self.samples);
+		let (framebuffers, world, render_scale) = (&mut self.framebuffers, &mut self.world, self.render_scale);
+		let (temporal, gui, image_extent) = (&mut self.temporal, &mut self.gui, &mut self.image_extent);
+		let format = self.swapchain.format().format;
+		let acquired = self.swapchain.acquire(!0, |images, extent| {
+			*framebuffers = create_ui_framebuffers(ui_pass, create_image_views(gfx, images, format), extent);
+			let world_extent = world_extent(extent, render_scale);
+			*world = WorldTarget::new(gfx, world_pass, format, samples, world_extent, temporal.as_mut());
+			gui.set_rect(Rect2D::builder().extent(extent).build());
+			*image_extent = extent;
+		});
+		let (image_idx, future) = match acquired {
+			Some(acquired) => acquired,
+			// minimized
+			None => return,
 		};
 		let image_uidx = image_idx as usize;
 
-		let frame = self.frame as usize;
-		if let Some(fence) = self.frame_data[frame].fence.take() {
-			fence.wait();
-		}
-		self.frame = !self.frame;
+		let frame = self.frames.begin();
+		self.profiler.resolve(frame, self.gfx.stats());
+
+		let uniforms = FrameUniforms::new(camera, light, self.start.elapsed().as_secs_f32(), self.world.extent);
+		let view_proj = uniforms.view_proj;
+		// `begin` waited until the GPU was done with this frame's buffer
+		let frame_set = unsafe { self.frame_uniforms.write(frame, &uniforms) };
+
+		#[cfg(debug_assertions)]
+		self.reload_shaders();
 
 		let framebuffer = &self.framebuffers[image_uidx];
 
-		self.frame_data[frame].cmdpool.reset(false);
+		// the volumes were recorded from the job workers' pools
+		self.cmdpools[frame].reset_all(false);
+
+		// submitted before this frame's commands, so anything queued so far is ready by the time they run
+		self.gfx.uploads().flush();
+
+		let record = self.gfx.stats().cpu_scope("record");
+		let hidpi_factor = self.hidpi_factor() as f32;
+		// the meshes stand in for the volumes
+		let bounds = if self.debug_view.meshed_world { vec![] } else { volumes.bounds() };
+		let mut ctx = FrameContext {
+			frame,
+			cmdpool: &self.cmdpools[frame],
+			world: Target {
+				render_pass: &self.world_pass,
+				framebuffer: &self.world.framebuffer,
+				extent: self.world.extent,
+			},
+			ui: Target { render_pass: &self.ui_pass, framebuffer, extent: self.image_extent },
+			frame_set: &frame_set,
+			volume_set: volumes.set(frame),
+			view_proj,
+			hidpi_factor,
+			view: self.debug_view,
+			jitter: self.temporal.is_some(),
+			volumes: &bounds,
+			scene,
+			terrain: &self.terrain,
+			lines: &mut self.lines,
+			gui: &mut self.gui,
+			#[cfg(feature = "debug-overlay")]
+			overlay: &mut self.debug_overlay,
+		};
+		let (world_commands, ui_commands) = self.renderer.record(&mut ctx);
+
+		let primary = self.cmdpools[frame].record(true, false);
+		let primary = self.profiler.reset(frame, primary).begin_label("frame");
+
+		let mut graph = RenderGraph::new();
+		// redrawn every frame, but the last frame's reads have to finish first
+		let world_read = if self.temporal.is_some() { Usage::Sampled } else { Usage::TransferSrc };
+		let world_image = graph.import_discarded_image(self.world.image.clone(), Some(world_read));
+		let world_depth =
+			self.world.depth.as_ref().map(|depth| graph.import_discarded_image(depth.clone(), Some(Usage::Sampled)));
+		let swapchain_image = self.swapchain.images()[image_uidx].clone();
+		let target = graph.import_image(swapchain_image.clone(), Some(Usage::Acquired));
+		graph.export_image(target, Usage::Present);
+		let (world_uses, ui_uses) = self.renderer.add_passes(&mut graph, &ctx);
+		let volume_images = volumes.add_passes(&mut graph, frame);
+
+		let (profiler, world, world_pass, ui_pass) = (&self.profiler, &self.world, &self.world_pass, &self.ui_pass);
+		let world_area = Rect2D::builder().extent(world.extent).build();
+		let clear_values = clear_values(self.samples);
+		let draw_world = move |cmd| {
+			let cmd = profiler
+				.begin(frame, GpuScope::RenderPass, cmd)
+				.begin_render_pass(world_pass.clone(), world.framebuffer.clone(), world_area, &clear_values)
+				.execute_commands(world_commands)
+				.end_render_pass();
+			profiler.end(frame, GpuScope::RenderPass, cmd)
+		};
+		let mut world_pass = Pass::new("world", draw_world).image(world_image, Usage::ColorAttachment);
+		if let Some(depth) = world_depth {
+			world_pass = world_pass.image(depth, Usage::DepthAttachment);
+		}
+		for image in volume_images {
+			world_pass = world_pass.image(image, Usage::StorageRead);
+		}
+		for (buffer, usage) in world_uses {
+			world_pass = world_pass.buffer(buffer, usage);
+		}
+		graph.add_pass(world_pass);
+
+		let (upscaled, src) = match (&mut self.temporal, world_depth) {
+			(Some(temporal), Some(depth)) => {
+				temporal.add_pass(&mut graph, &self.cmdpools[frame], &frame_set, world_image, depth, view_proj)
+			},
+			_ => (world_image, world.image.clone()),
+		};
+		let region = [blit_region(world.extent, self.image_extent)];
+		let upscale = move |cmd: CommandBufferBuilder<B0>| {
+			let (src_layout, dst_layout) =
+				(vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+			cmd.blit_image(src, src_layout, swapchain_image, dst_layout, &region, vk::Filter::LINEAR)
+		};
+		graph.add_pass(
+			Pass::new("upscale", upscale).image(upscaled, Usage::TransferSrc).image(target, Usage::TransferDst),
+		);
 
-		// TODO: replace with real volumes
-		let secondaries = (0..2).map(|_| {
-			let inherit = InheritanceInfo {
-				render_pass: self.render_pass.clone(),
-				subpass: 0,
-				framebuffer: Some(framebuffer.clone()),
+		// executing no secondaries isn't allowed, and there's nothing to draw anyway
+		if !ui_commands.is_empty() {
+			let ui_area = Rect2D::builder().extent(self.image_extent).build();
+			let draw_ui = move |cmd: CommandBufferBuilder<B0>| {
+				cmd.begin_render_pass(ui_pass.clone(), framebuffer.clone(), ui_area, &[])
+					.execute_commands(ui_commands)
+					.end_render_pass()
 			};
-			self.frame_data[frame]
-				.cmdpool
-				.record_secondary(true, false, Some(inherit))
-				.bind_pipeline(self.pipeline.clone())
-				.bind_vertex_buffers(0, once(self.gfx.triangle.clone() as _), &[0])
-				.draw(3, 1, 0, 0)
-				.build()
-		});
+			let mut ui_pass = Pass::new("ui", draw_ui).image(target, Usage::ColorAttachment);
+			for (buffer, usage) in ui_uses {
+				ui_pass = ui_pass.buffer(buffer, usage);
+			}
+			graph.add_pass(ui_pass);
+		}
+		let primary = graph.record(primary).end_label().build();
+		drop(record);
 
-		let primary = self.frame_data[frame]
-			.cmdpool
-			.record(true, false)
-			.begin_render_pass(
-				self.render_pass.clone(),
-				framebuffer.clone(),
-				Rect2D::builder().extent(self.image_extent).build(),
-				&[ClearValue { color: vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 1.0] } }],
-			)
-			.execute_commands(secondaries)
-			.end_render_pass()
-			.build();
-		let (fence, future) = self.gfx.queue.submit_after(future, primary).flush();
-		self.frame_data[frame].fence = Some(fence);
-
-		match Swapchain::present_after(future, self.gfx.queue.clone(), &[self.swapchain.clone()], &[image_idx]) {
-			Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.recreate_swapchain = true,
-			Ok(false) => (),
-			Err(err) => panic!(err),
+		let submit = self.gfx.stats().cpu_scope("submit");
+		// presenting only waits on the semaphore, so the stage doesn't matter
+		let (fence, future) =
+			self.gfx.queue.submit_after(future, primary).flush(vk::PipelineStageFlags::BOTTOM_OF_PIPE);
+		self.frames.submitted(fence);
+
+		self.swapchain.present(future, self.gfx.queue.clone(), image_idx);
+		drop(submit);
+	}
+
+	/// Rebuilds every pipeline if a shader source has been saved. Command buffers hold the pipelines they use, so the
+	/// old ones live until the frames in flight are done with them.
+	#[cfg(debug_assertions)]
+	fn reload_shaders(&mut self) {
+		// another window may have been the one to find the change
+		self.gfx.reload_shaders();
+		let generation = self.gfx.shader_generation();
+		if generation == self.shader_generation {
+			return;
 		}
+		self.shader_generation = generation;
+		self.renderer.reload_pipelines(&self.world_pass, self.samples, &self.ui_pass);
+		if let Some(temporal) = &mut self.temporal {
+			temporal.reload_pipeline();
+		}
+		#[cfg(feature = "debug-overlay")]
+		self.debug_overlay.reload_pipeline(self.ui_pass.clone(), vk::SampleCountFlags::TYPE_1);
 	}
+}
 
-	fn recreate_swapchain(&mut self) {
-		self.frame_data[(!self.frame) as usize].fence.as_ref().unwrap().wait();
+/// The image the world is drawn into at the render scale, before it's upscaled into the swapchain image.
+struct WorldTarget {
+	image: Arc<Image>,
+	/// Only kept for the temporal pass. Otherwise the framebuffer has its own, which is discarded after each frame.
+	depth: Option<Arc<Image>>,
+	framebuffer: Arc<Framebuffer>,
+	extent: Extent2D,
+}
+impl WorldTarget {
+	/// Starts `temporal`'s history over, if it's on, since it's tied to the images.
+	fn new(
+		gfx: &Gfx,
+		render_pass: &Arc<RenderPass>,
+		format: vk::Format,
+		samples: vk::SampleCountFlags,
+		extent: Extent2D,
+		temporal: Option<&mut Temporal>,
+	) -> Self {
+		let image = image::create_render_target(gfx, format, extent, "world");
+		match temporal {
+			Some(temporal) => {
+				let depth = image::create_render_target(gfx, gfx.depth_format(), extent, "world depth");
+				let views = vec![image.create_view(), depth.create_view()];
+				let framebuffer = gfx.device.create_framebuffer(render_pass.clone(), views, extent);
+				temporal.resize(&image, &depth, extent);
+				Self { image, depth: Some(depth), framebuffer, extent }
+			},
+			None => {
+				let views = vec![image.create_view()];
+				let framebuffer = create_framebuffers(gfx, render_pass, views, extent, format, samples).pop().unwrap();
+				Self { image, depth: None, framebuffer, extent }
+			},
+		}
+	}
+}
 
-		let (caps, image_extent) = get_caps(&self.gfx, &self.surface);
-		let (swapchain, image_views) = create_swapchain(
-			&self.gfx,
-			self.surface.clone(),
-			&caps,
-			&self.surface_format,
-			image_extent,
-			self.present_mode,
-			Some(&self.swapchain),
-		);
-		self.swapchain = swapchain;
+/// `image_extent` times `render_scale`, but at least a pixel on each side.
+fn world_extent(image_extent: Extent2D, render_scale: f32) -> Extent2D {
+	Extent::from(image_extent).scale(render_scale).clamp(Extent::new(1, 1), image_extent.into()).into()
+}
 
-		self.pipeline = create_pipeline(&self.gfx, image_extent, self.render_pass.clone());
-		self.framebuffers = create_framebuffers(&self.render_pass, image_views, image_extent);
+/// Everything needed to record a volume's secondary command buffer on another thread.
+#[derive(Clone)]
+pub(super) struct VolumeRecorder {
+	pub(super) cmdpool: Arc<CommandPool>,
+	pub(super) render_pass: Arc<RenderPass>,
+	pub(super) framebuffer: Arc<Framebuffer>,
+	pub(super) pipeline: Arc<Pipeline>,
+	pub(super) layout: Arc<PipelineLayout>,
+	pub(super) frame_set: Arc<DescriptorSet>,
+	pub(super) volume_set: Arc<DescriptorSet>,
+	pub(super) triangle: Arc<Buffer<[TriangleVertex]>>,
+	pub(super) image_extent: Extent2D,
+	pub(super) view: DebugView,
+	pub(super) jitter: bool,
+}
+impl VolumeRecorder {
+	pub(super) fn record(&self, scissor: Rect2D) -> Arc<CommandBuffer<B1>> {
+		let inherit = InheritanceInfo {
+			render_pass: self.render_pass.clone(),
+			subpass: 0,
+			framebuffer: Some(self.framebuffer.clone()),
+		};
+		let flags = self.view.volume_flags(self.jitter);
+		self.cmdpool
+			.record_secondary(true, false, Some(inherit))
+			.begin_label("volume")
+			.bind_pipeline(self.pipeline.clone())
+			.set_viewport(0, &[viewport(self.image_extent)])
+			.set_scissor(0, &[scissor])
+			.bind_descriptor_sets(self.layout.clone(), 0, vec![self.frame_set.clone(), self.volume_set.clone()])
+			.push_constants(self.layout.clone(), vk::ShaderStageFlags::FRAGMENT, 0, &flags)
+			.bind_vertex_buffers(0, once(self.triangle.clone() as _), &[0])
+			.draw(3, 1, 0, 0)
+			.end_label()
+			.build()
+	}
+}
 
-		self.image_extent = image_extent;
+/// The monitor named in `display`, or the primary monitor if it isn't connected.
+fn find_monitor(
+	monitors: impl IntoIterator<Item = MonitorHandle>,
+	primary: MonitorHandle,
+	display: &DisplaySettings,
+) -> MonitorHandle {
+	let name = match &display.monitor {
+		Some(name) => name,
+		None => return primary,
+	};
+	monitors.into_iter().find(|monitor| monitor.name().as_ref() == Some(name)).unwrap_or_else(|| {
+		log::warn!("monitor {} not found, using the primary monitor", name);
+		primary
+	})
+}
 
-		self.recreate_swapchain = false;
+fn fullscreen(monitor: MonitorHandle, mode: WindowMode) -> Option<Fullscreen> {
+	match mode {
+		WindowMode::Windowed => None,
+		WindowMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+		WindowMode::Exclusive => {
+			let video_mode = monitor.video_modes().max_by_key(|mode| {
+				let size = mode.size();
+				((size.width * size.height) as u64, mode.refresh_rate(), mode.bit_depth())
+			});
+			match video_mode {
+				Some(video_mode) => Some(Fullscreen::Exclusive(video_mode)),
+				None => Some(Fullscreen::Borderless(monitor)),
+			}
+		},
 	}
 }
 
-struct FrameData {
-	cmdpool: Arc<CommandPool>,
-	fence: Option<Fence>,
+/// The window's inner size in physical pixels, for surfaces that let the swapchain decide its size.
+fn window_extent(window: &IWindow) -> Extent2D {
+	let (width, height) = window.inner_size().to_physical(window.hidpi_factor()).into();
+	Extent2D { width, height }
 }
-impl FrameData {
-	fn new(gfx: &Arc<Gfx>) -> Self {
-		let cmdpool = gfx.device.create_command_pool(gfx.queue.family(), true);
-		Self { cmdpool, fence: None }
-	}
+
+fn create_image_views<T: Send + Sync + 'static>(
+	gfx: &Gfx,
+	images: &[Arc<SwapchainImage<T>>],
+	format: Format,
+) -> Vec<Arc<ImageView>> {
+	let range = vk::ImageSubresourceRange::builder()
+		.aspect_mask(vk::ImageAspectFlags::COLOR)
+		.level_count(1)
+		.layer_count(1)
+		.build();
+	images
+		.iter()
+		.map(|image| gfx.device.create_image_view(image.clone(), vk::ImageViewType::TYPE_2D, format, range))
+		.collect()
 }
 
-fn get_caps(gfx: &Gfx, surface: &Surface<IWindow>) -> (SurfaceCapabilities, Extent2D) {
-	let caps = gfx.device.physical_device().get_surface_capabilities(surface);
-	let image_extent = if caps.current_extent.width != u32::MAX {
-		caps.current_extent
-	} else {
-		let (width, height) = surface.window().inner_size().to_physical(1.0).into();
-		Extent2D {
-			width: max(caps.min_image_extent.width, min(caps.max_image_extent.width, width)),
-			height: max(caps.min_image_extent.height, min(caps.max_image_extent.height, height)),
-		}
+/// Stretches all of an image `src` in size over all of one `dst` in size.
+fn blit_region(src: Extent2D, dst: Extent2D) -> vk::ImageBlit {
+	let layers = vk::ImageSubresourceLayers::builder().aspect_mask(vk::ImageAspectFlags::COLOR).layer_count(1).build();
+	let corner = |extent: Extent2D| vk::Offset3D { x: extent.width as _, y: extent.height as _, z: 1 };
+	vk::ImageBlit::builder()
+		.src_subresource(layers)
+		.src_offsets([vk::Offset3D::default(), corner(src)])
+		.dst_subresource(layers)
+		.dst_offsets([vk::Offset3D::default(), corner(dst)])
+		.build()
+}
+
+/// A viewport covering the whole framebuffer, for pipelines built with `dynamic_viewport`.
+pub(super) fn viewport(image_extent: Extent2D) -> Viewport {
+	Viewport::builder().width(image_extent.width as _).height(image_extent.height as _).max_depth(1.0).build()
+}
+
+/// The pixels `volume` covers, or `None` if it's entirely off screen.
+pub(super) fn scissor(volume: &Aabb, view_proj: &Matrix4<f32>, image_extent: Extent2D) -> Option<Rect2D> {
+	let extent = Vector2::new(image_extent.width as f32, image_extent.height as f32);
+	let (min, max) = match volume.project(view_proj) {
+		// eye space is Y-up and framebuffers are Y-down
+		Some((min, max)) => (Vector2::new(min.x, -max.y), Vector2::new(max.x, -min.y)),
+		// it surrounds the camera, so it could cover any part of the screen
+		None => (Vector2::repeat(-1.0), Vector2::repeat(1.0)),
 	};
+	let to_pixels = |ndc: Vector2<f32>| (ndc * 0.5 + Vector2::repeat(0.5)).component_mul(&extent);
+	let min = to_pixels(min).map(|x| x.floor().max(0.0) as i32);
+	let max = to_pixels(max).zip_map(&extent, |x, limit| x.ceil().min(limit) as i32);
+	Rect::from_min_max(min, max).map(Into::into)
+}
 
-	(caps, image_extent)
+/// With more than one sample, attachment 0 is a multisampled color buffer that gets resolved into attachment 1, the
+/// image being drawn. Otherwise that image is rendered to directly. The depth buffer is always last.
+/// Clears every attachment of a render pass from `create_render_pass`.
+pub(super) fn clear_values(samples: vk::SampleCountFlags) -> Vec<ClearValue> {
+	let color = ClearValue { color: vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 1.0] } };
+	let depth = ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } };
+	if samples == vk::SampleCountFlags::TYPE_1 {
+		vec![color, depth]
+	} else {
+		vec![color, color, depth]
+	}
 }
 
-fn create_swapchain<T: 'static>(
+/// The single color attachment starts in `initial_layout`, where `UNDEFINED` discards it, and ends in `final_layout`.
+/// The depth buffer is discarded after the pass unless `keep_depth` is set.
+pub(super) fn create_render_pass(
 	gfx: &Gfx,
-	surface: Arc<Surface<T>>,
-	caps: &SurfaceCapabilities,
-	surface_format: &vk::SurfaceFormatKHR,
-	image_extent: Extent2D,
-	present_mode: PresentMode,
-	old_swapchain: Option<&Swapchain<T>>,
-) -> (Arc<Swapchain<T>>, Vec<Arc<ImageView>>) {
-	let (swapchain, images) = gfx.device.create_swapchain(
-		surface,
-		caps.min_image_count + 1,
-		surface_format.format,
-		surface_format.color_space,
-		image_extent,
-		empty(),
-		caps.current_transform,
-		CompositeAlphaFlags::OPAQUE,
-		present_mode,
-		old_swapchain,
-	);
-
-	let image_views = images
-		.map(|image| {
-			let range = vk::ImageSubresourceRange::builder()
-				.aspect_mask(vk::ImageAspectFlags::COLOR)
-				.level_count(1)
-				.layer_count(1)
-				.build();
-			gfx.device.create_image_view(image, surface_format.format, range)
-		})
-		.collect();
+	format: vk::Format,
+	samples: vk::SampleCountFlags,
+	initial_layout: vk::ImageLayout,
+	final_layout: vk::ImageLayout,
+	keep_depth: bool,
+) -> VkResult<Arc<RenderPass>> {
+	let multisampled = samples != vk::SampleCountFlags::TYPE_1;
 
-	(swapchain, image_views)
+	let present = Attachment::new(format, final_layout).initial_layout(initial_layout);
+	let depth_store = if keep_depth { vk::AttachmentStoreOp::STORE } else { vk::AttachmentStoreOp::DONT_CARE };
+	let depth = Attachment::new(gfx.depth_format(), vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+		.samples(samples)
+		.store(depth_store);
+
+	let mut builder = gfx.device.build_render_pass();
+	let subpass = if multisampled {
+		let color = Attachment::new(format, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+			.samples(samples)
+			.store(vk::AttachmentStoreOp::DONT_CARE);
+		builder = builder.attachment(color).attachment(present.load(vk::AttachmentLoadOp::DONT_CARE));
+		Subpass::new().color(0).resolve(1).depth(2)
+	} else {
+		builder = builder.attachment(present);
+		Subpass::new().color(0).depth(1)
+	};
+
+	// the depth buffer is shared between frames, so the last frame's depth writes have to finish before it's cleared
+	builder
+		.attachment(depth)
+		.subpass(subpass)
+		.external_dependency(
+			vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+			vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+			vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+			vk::AccessFlags::COLOR_ATTACHMENT_READ
+				| vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+				| vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+				| vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+		)
+		.build()
+}
+
+/// Draws over what's already in a swapchain image, with a single sample and no depth buffer. Like the world's pass, the
+/// render graph moves the image in and out of the attachment layout.
+fn create_ui_render_pass(gfx: &Gfx, format: vk::Format) -> VkResult<Arc<RenderPass>> {
+	let attachment = vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL;
+	let color = Attachment::new(format, attachment).initial_layout(attachment).load(vk::AttachmentLoadOp::LOAD);
+	gfx.device.build_render_pass().attachment(color).subpass(Subpass::new().color(0)).build()
 }
 
-fn create_pipeline(gfx: &Gfx, image_extent: Extent2D, render_pass: Arc<RenderPass>) -> Arc<Pipeline> {
-	gfx.device
+pub(super) fn create_pipeline(gfx: &Gfx, render_pass: Arc<RenderPass>, samples: vk::SampleCountFlags) -> Arc<Pipeline> {
+	let shaders = gfx.shaders();
+	let pipeline = gfx
+		.device
 		.build_pipeline(gfx.layout.clone(), render_pass)
-		.vertex_shader(gfx.vshader.clone())
-		.fragment_shader(gfx.fshader.clone())
+		.vertex_shader(shaders.vert.clone())
+		.fragment_shader(shaders.frag.clone())
 		.vertex_input::<TriangleVertex>()
-		.viewports(&[vk::Viewport::builder()
-			.width(image_extent.width as _)
-			.height(image_extent.height as _)
-			.max_depth(1.0)
-			.build()])
-		.build()
+		.dynamic_viewport()
+		.depth_test(true)
+		.samples(samples)
+		.build();
+	gfx.device.set_object_name(pipeline.vk, "volumes");
+	pipeline
 }
 
-fn create_framebuffers(
+pub(super) fn create_framebuffers(
+	gfx: &Gfx,
 	render_pass: &Arc<RenderPass>,
 	image_views: Vec<Arc<ImageView>>,
 	image_extent: Extent2D,
+	format: vk::Format,
+	samples: vk::SampleCountFlags,
 ) -> Vec<Arc<Framebuffer>> {
+	// only one frame renders at a time, so every framebuffer can share the multisampled image and depth buffer
+	let color = if samples == vk::SampleCountFlags::TYPE_1 {
+		None
+	} else {
+		Some(image::create_attachment(gfx, format, image_extent, samples).create_view())
+	};
+	let depth = image::create_attachment(gfx, gfx.depth_format(), image_extent, samples).create_view();
+
 	image_views
 		.into_iter()
 		.map(|view| {
 			render_pass.device().create_framebuffer(
 				render_pass.clone(),
-				vec![view],
-				image_extent.width,
-				image_extent.height,
+				color.iter().cloned().chain(once(view)).chain(once(depth.clone())).collect(),
+				image_extent,
 			)
 		})
 		.collect()
 }
+
+fn create_ui_framebuffers(
+	render_pass: &Arc<RenderPass>,
+	image_views: Vec<Arc<ImageView>>,
+	image_extent: Extent2D,
+) -> Vec<Arc<Framebuffer>> {
+	image_views
+		.into_iter()
+		.map(|view| render_pass.device().create_framebuffer(render_pass.clone(), vec![view], image_extent))
+		.collect()
+}
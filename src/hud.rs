@@ -0,0 +1,131 @@
+//! A small performance readout drawn with the GUI, so frame times can be checked without the debug overlay. It reads
+//! the same `Gfx::frame_stats` the overlay does.
+
+use crate::gfx::{
+	gui::{anim::Tween, text::TextElement, Color, Document, Edges, Node, Styles},
+	stats::FrameStats,
+};
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+/// How many frames the statistics cover.
+const SAMPLES: usize = 240;
+/// How often the text changes. Updating it every frame would relayout the document every frame and be unreadable.
+const REFRESH: Duration = Duration::from_millis(250);
+const FADE: Duration = Duration::from_millis(150);
+
+pub struct PerfHud {
+	times: VecDeque<Duration>,
+	/// The last frame recorded, for its counters.
+	last: FrameStats,
+	since_refresh: Duration,
+	text: Arc<TextElement>,
+	visible: bool,
+	/// The camera's speed while it's flying, in voxels per second.
+	fly_speed: Option<f32>,
+}
+impl PerfHud {
+	/// Adds the HUD's text to `doc`, hidden.
+	pub fn new(doc: &mut Document) -> Self {
+		let mut style = Styles::default();
+		style.set_background_color(Color::new(0, 0, 0, 160));
+		style.set_padding(Edges::all(6));
+		style.set_margin(Edges::all(8));
+		style.set_width(200);
+		style.set_opacity(0.0);
+		let text = Arc::new(TextElement::new("", 14, Color::new(255, 255, 255, 255)).with_style(style));
+		doc.push(text.clone());
+
+		Self {
+			times: VecDeque::with_capacity(SAMPLES),
+			last: FrameStats::default(),
+			since_refresh: Duration::from_secs(0),
+			text,
+			visible: false,
+			fly_speed: None,
+		}
+	}
+
+	pub fn visible(&self) -> bool {
+		self.visible
+	}
+
+	/// Fades the HUD in or out.
+	pub fn set_visible(&mut self, doc: &mut Document, visible: bool) {
+		if visible == self.visible {
+			return;
+		}
+		self.visible = visible;
+
+		let from = self.text.style().opacity();
+		let to = if visible { 1.0 } else { 0.0 };
+		doc.animate(self.text.clone(), Tween::opacity(from, to, FADE));
+	}
+
+	/// Shows the camera's flying speed with the next refresh, or hides it if the camera isn't flying.
+	pub fn set_fly_speed(&mut self, speed: Option<f32>) {
+		self.fly_speed = speed;
+	}
+
+	/// Records a finished frame from `Gfx::frame_stats`, and refreshes the text every `REFRESH` while the HUD is
+	/// visible.
+	pub fn record(&mut self, frame: FrameStats) {
+		if self.times.len() == SAMPLES {
+			self.times.pop_front();
+		}
+		self.times.push_back(frame.frame_time);
+
+		self.since_refresh += frame.frame_time;
+		self.last = frame;
+		if self.visible && self.since_refresh >= REFRESH {
+			self.since_refresh = Duration::from_secs(0);
+			self.text.set_content(self.summary());
+		}
+	}
+
+	/// The mean frame time over the last `SAMPLES` frames.
+	pub fn average(&self) -> Duration {
+		if self.times.is_empty() {
+			return Duration::from_secs(0);
+		}
+		self.times.iter().sum::<Duration>() / self.times.len() as u32
+	}
+
+	/// The frame time that `percentile` percent of recent frames were at least as fast as.
+	pub fn percentile(&self, percentile: f32) -> Duration {
+		let mut times = self.times.iter().copied().collect::<Vec<_>>();
+		if times.is_empty() {
+			return Duration::from_secs(0);
+		}
+		times.sort();
+		let idx = ((times.len() - 1) as f32 * percentile / 100.0).round() as usize;
+		times[idx.min(times.len() - 1)]
+	}
+
+	fn summary(&self) -> String {
+		let ms = |time: Duration| time.as_secs_f32() * 1000.0;
+		let average = self.average();
+		let fps = if average > Duration::from_secs(0) { 1.0 / average.as_secs_f32() } else { 0.0 };
+		let mut summary = format!(
+			"{:.0} fps\navg {:.2} ms\n99% {:.2} ms\nmax {:.2} ms",
+			fps,
+			ms(average),
+			ms(self.percentile(99.0)),
+			ms(self.percentile(100.0)),
+		);
+		let cpu = self.last.cpu.iter().map(|&(_, time)| time).sum::<Duration>();
+		let gpu = self.last.gpu.iter().map(|&(_, time)| time).sum::<Duration>();
+		summary.push_str(&format!(
+			"\ncpu {:.2} ms\ngpu {:.2} ms\n{} draws, {} dispatches\nupload {} KiB\n{} chunks loaded",
+			ms(cpu),
+			ms(gpu),
+			self.last.draws,
+			self.last.dispatches,
+			self.last.upload_bytes / 1024,
+			self.last.chunks_generated,
+		));
+		if let Some(speed) = self.fly_speed {
+			summary.push_str(&format!("\nfly {:.1} vox/s", speed));
+		}
+		summary
+	}
+}
@@ -0,0 +1,734 @@
+//! Keyboard, mouse, and gamepad state, collected from winit and gilrs events and read once per frame through rebindable
+//! actions.
+
+use gilrs::{Axis, Button, EventType, Gilrs};
+use nalgebra::Vector2;
+use sim::{fs, threads::JobHandle};
+use std::{
+	collections::{HashMap, HashSet},
+	io,
+};
+use winit::{
+	event::{DeviceEvent, ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent},
+	window::Window,
+};
+
+/// Where the player's bindings are stored, relative to the working directory.
+pub const BINDINGS_PATH: &str = "keybindings.cfg";
+/// How far a gamepad axis has to be pushed to count as pressed, for actions like jumping that are either on or off.
+const AXIS_PRESS: f32 = 0.5;
+/// How far a touchpad has to scroll to count as one notch of a mouse wheel, in logical pixels.
+const WHEEL_NOTCH_PIXELS: f64 = 40.0;
+
+/// Generates `Action`, with `ALL` and the names used in the bindings file.
+macro_rules! actions {
+	($($(#[$attr:meta])* $action:ident,)*) => {
+		/// Something the player can do, independent of which key or button does it.
+		#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+		pub enum Action {
+			$($(#[$attr])* $action,)*
+		}
+		impl Action {
+			pub const ALL: &'static [Action] = &[$(Action::$action),*];
+
+			pub fn name(self) -> &'static str {
+				match self {
+					$(Action::$action => stringify!($action),)*
+				}
+			}
+
+			pub fn from_name(name: &str) -> Option<Self> {
+				match name {
+					$(stringify!($action) => Some(Action::$action),)*
+					_ => None,
+				}
+			}
+		}
+	};
+}
+
+actions! {
+	MoveForward,
+	MoveBack,
+	MoveLeft,
+	MoveRight,
+	MoveUp,
+	MoveDown,
+	Jump,
+	/// Switches between walking and flying through walls.
+	ToggleNoclip,
+	/// Grabs or releases the cursor.
+	ToggleCursor,
+	/// Pauses the game, or backs out of the current menu.
+	Menu,
+	ToggleFullscreen,
+	ToggleDebugOverlay,
+	/// Shows or hides the frame time readout.
+	TogglePerfHud,
+	/// Carves out the block under the crosshair.
+	RemoveBlock,
+	/// Adds a block against the surface under the crosshair.
+	PlaceBlock,
+	/// Changes the block under the crosshair to the next material.
+	PaintBlock,
+	/// Switches to or from the level editor, which flies the camera and edits with a brush instead of blocks.
+	ToggleEditor,
+	BrushBigger,
+	BrushSmaller,
+	/// Switches the editor's brush between a cube and a sphere.
+	CycleBrushShape,
+	/// Draws meshes as wireframes.
+	ToggleWireframe,
+	/// Speeds up or slows down flying, one step at a time.
+	FlyFaster,
+	FlySlower,
+	/// Flies faster while held.
+	Sprint,
+	/// Colors the world by how much work its raymarching took.
+	ToggleStepHeatmap,
+	/// Switches between raymarching the world and drawing meshes of it.
+	ToggleMeshedWorld,
+	/// Turns the camera. Meant for gamepad sticks, since the mouse always turns it.
+	LookLeft,
+	LookRight,
+	LookUp,
+	LookDown,
+}
+
+/// Generates `$name` and `$from_name`, which convert between the variants of `$ty` that can appear in the bindings file
+/// and their names.
+macro_rules! names {
+	($ty:ident, $name:ident, $from_name:ident; $($variant:ident),* $(,)?) => {
+		fn $name(value: $ty) -> Option<&'static str> {
+			match value {
+				$($ty::$variant => Some(stringify!($variant)),)*
+				_ => None,
+			}
+		}
+
+		fn $from_name(name: &str) -> Option<$ty> {
+			match name {
+				$(stringify!($variant) => Some($ty::$variant),)*
+				_ => None,
+			}
+		}
+	};
+}
+
+names! {
+	VirtualKeyCode, key_name, key_from_name;
+	Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+	A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+	Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+	Insert, Home, Delete, End, PageDown, PageUp, Left, Up, Right, Down, Back, Return, Space, Tab,
+	Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+	Add, Subtract, Multiply, Divide, Decimal, NumpadEnter,
+	Apostrophe, Backslash, Comma, Equals, Grave, LBracket, Minus, Period, RBracket, Semicolon, Slash,
+	LAlt, LControl, LShift, RAlt, RControl, RShift,
+}
+
+names! {
+	Button, pad_button_name, pad_button_from_name;
+	South, East, North, West, LeftTrigger, LeftTrigger2, RightTrigger, RightTrigger2,
+	Select, Start, Mode, LeftThumb, RightThumb, DPadUp, DPadDown, DPadLeft, DPadRight,
+}
+
+names! {
+	Axis, pad_axis_name, pad_axis_from_name;
+	LeftStickX, LeftStickY, LeftZ, RightStickX, RightStickY, RightZ,
+}
+
+/// A physical input that can trigger an action.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+	Key(VirtualKeyCode),
+	Mouse(MouseButton),
+	/// A button on any connected gamepad.
+	Pad(Button),
+	/// One direction of a gamepad axis, `true` for positive. Sticks are positive to the right and up.
+	PadAxis(Axis, bool),
+	/// One direction of the mouse wheel, `true` for up. Each notch presses and releases it in the same frame, so only
+	/// `Input::just_pressed` sees it.
+	Wheel(bool),
+}
+impl Binding {
+	/// The name used in the bindings file, or `None` for keys that can't be written there.
+	pub fn name(self) -> Option<String> {
+		match self {
+			Binding::Key(key) => key_name(key).map(str::to_string),
+			Binding::Mouse(MouseButton::Left) => Some("MouseLeft".to_string()),
+			Binding::Mouse(MouseButton::Right) => Some("MouseRight".to_string()),
+			Binding::Mouse(MouseButton::Middle) => Some("MouseMiddle".to_string()),
+			Binding::Mouse(MouseButton::Other(button)) => Some(format!("Mouse{}", button)),
+			Binding::Pad(button) => pad_button_name(button).map(|name| format!("Pad{}", name)),
+			Binding::PadAxis(axis, positive) => {
+				pad_axis_name(axis).map(|name| format!("Pad{}{}", name, if positive { '+' } else { '-' }))
+			},
+			Binding::Wheel(up) => Some(if up { "WheelUp" } else { "WheelDown" }.to_string()),
+		}
+	}
+
+	fn is_pad(self) -> bool {
+		match self {
+			Binding::Pad(_) | Binding::PadAxis(..) => true,
+			_ => false,
+		}
+	}
+
+	pub fn from_name(name: &str) -> Option<Self> {
+		match name {
+			"MouseLeft" => Some(Binding::Mouse(MouseButton::Left)),
+			"MouseRight" => Some(Binding::Mouse(MouseButton::Right)),
+			"MouseMiddle" => Some(Binding::Mouse(MouseButton::Middle)),
+			"WheelUp" => Some(Binding::Wheel(true)),
+			"WheelDown" => Some(Binding::Wheel(false)),
+			_ if name.starts_with("Mouse") => {
+				name["Mouse".len()..].parse().ok().map(|b| Binding::Mouse(MouseButton::Other(b)))
+			},
+			_ if name.starts_with("Pad") => {
+				let name = &name["Pad".len()..];
+				match (name.strip_suffix('+'), name.strip_suffix('-')) {
+					(Some(axis), _) => pad_axis_from_name(axis).map(|axis| Binding::PadAxis(axis, true)),
+					(_, Some(axis)) => pad_axis_from_name(axis).map(|axis| Binding::PadAxis(axis, false)),
+					_ => pad_button_from_name(name).map(Binding::Pad),
+				}
+			},
+			_ => key_from_name(name).map(Binding::Key),
+		}
+	}
+}
+
+/// A change to the input state. Everything `Input` hears is turned into these, so a frame's worth can be recorded and
+/// played back later.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputEvent {
+	Pressed(Binding),
+	Released(Binding),
+	/// A gamepad axis moved to somewhere from -1 to 1.
+	Axis(Axis, f32),
+	/// Mouse motion while the cursor is grabbed.
+	MouseMotion(Vector2<f64>),
+}
+impl InputEvent {
+	/// The event as a line of a replay file, or `None` if it involves a key that can't be named.
+	pub fn to_line(&self) -> Option<String> {
+		match *self {
+			InputEvent::Pressed(binding) => binding.name().map(|name| format!("press {}", name)),
+			InputEvent::Released(binding) => binding.name().map(|name| format!("release {}", name)),
+			InputEvent::Axis(axis, value) => pad_axis_name(axis).map(|name| format!("axis {} {}", name, value)),
+			InputEvent::MouseMotion(delta) => Some(format!("mouse {} {}", delta.x, delta.y)),
+		}
+	}
+
+	pub fn from_line(line: &str) -> Option<Self> {
+		let mut words = line.split_whitespace();
+		let kind = words.next()?;
+		let first = words.next()?;
+		let event = match kind {
+			"press" => InputEvent::Pressed(Binding::from_name(first)?),
+			"release" => InputEvent::Released(Binding::from_name(first)?),
+			"axis" => InputEvent::Axis(pad_axis_from_name(first)?, words.next()?.parse().ok()?),
+			"mouse" => InputEvent::MouseMotion(Vector2::new(first.parse().ok()?, words.next()?.parse().ok()?)),
+			_ => return None,
+		};
+		match words.next() {
+			Some(_) => None,
+			None => Some(event),
+		}
+	}
+}
+
+/// Maps actions to the inputs that trigger them. An input can trigger several actions, like Space for both `Jump` and
+/// `MoveUp`.
+#[derive(Clone, Debug)]
+pub struct Bindings {
+	map: HashMap<Action, Vec<Binding>>,
+}
+impl Bindings {
+	pub fn empty() -> Self {
+		Self { map: HashMap::new() }
+	}
+
+	/// Reads the player's bindings from `BINDINGS_PATH`. If there's no file yet, the defaults are written there so the
+	/// player has something to edit.
+	pub async fn load() -> Self {
+		match fs::read_to_string(BINDINGS_PATH).await {
+			Ok(config) => Self::parse(&config),
+			Err(err) => {
+				if err.kind() != io::ErrorKind::NotFound {
+					log::warn!("failed to read {}: {}", BINDINGS_PATH, err);
+				}
+				let bindings = Self::default();
+				// not awaited; the file is only needed by the next run
+				bindings.save().forget();
+				bindings
+			},
+		}
+	}
+
+	/// Writes the bindings to `BINDINGS_PATH`, for keeping changes made at runtime.
+	pub fn save(&self) -> JobHandle<io::Result<()>> {
+		fs::write(BINDINGS_PATH, self.to_config().into_bytes())
+	}
+
+	/// Parses a bindings file. Each line looks like `MoveForward = W, Up, PadLeftStickY+`, and `#` starts a comment.
+	/// Actions the file doesn't mention keep their default bindings, and bad lines are logged and skipped.
+	pub fn parse(config: &str) -> Self {
+		let mut bindings = Self::default();
+		for (i, line) in config.lines().enumerate() {
+			let line = line.split('#').next().unwrap().trim();
+			if line.is_empty() {
+				continue;
+			}
+
+			let mut parts = line.splitn(2, '=');
+			let (action, inputs) = match (parts.next(), parts.next()) {
+				(Some(action), Some(inputs)) => (action.trim(), inputs),
+				_ => {
+					log::warn!("{}:{}: expected `Action = Key, ...`", BINDINGS_PATH, i + 1);
+					continue;
+				},
+			};
+			let action = match Action::from_name(action) {
+				Some(action) => action,
+				None => {
+					log::warn!("{}:{}: unknown action {}", BINDINGS_PATH, i + 1, action);
+					continue;
+				},
+			};
+
+			bindings.clear(action);
+			for input in inputs.split(',').map(str::trim).filter(|input| !input.is_empty()) {
+				match Binding::from_name(input) {
+					Some(binding) => bindings.bind(action, binding),
+					None => log::warn!("{}:{}: unknown key {}", BINDINGS_PATH, i + 1, input),
+				}
+			}
+		}
+		bindings
+	}
+
+	/// Formats the bindings the way `parse` reads them.
+	pub fn to_config(&self) -> String {
+		let mut config = String::new();
+		for &action in Action::ALL {
+			let names = self.bindings(action).iter().filter_map(|binding| binding.name()).collect::<Vec<_>>();
+			config.push_str(&format!("{} = {}\n", action.name(), names.join(", ")));
+		}
+		config
+	}
+
+	/// Adds `binding` to `action`'s bindings.
+	pub fn bind(&mut self, action: Action, binding: Binding) {
+		let bindings = self.map.entry(action).or_default();
+		if !bindings.contains(&binding) {
+			bindings.push(binding);
+		}
+	}
+
+	pub fn unbind(&mut self, action: Action, binding: Binding) {
+		if let Some(bindings) = self.map.get_mut(&action) {
+			bindings.retain(|&bound| bound != binding);
+		}
+	}
+
+	/// Removes every binding for `action`.
+	pub fn clear(&mut self, action: Action) {
+		self.map.remove(&action);
+	}
+
+	pub fn bindings(&self, action: Action) -> &[Binding] {
+		self.map.get(&action).map(|bindings| &bindings[..]).unwrap_or(&[])
+	}
+}
+impl Default for Bindings {
+	fn default() -> Self {
+		let mut bindings = Self::empty();
+		bindings.bind(Action::MoveForward, Binding::Key(VirtualKeyCode::W));
+		bindings.bind(Action::MoveBack, Binding::Key(VirtualKeyCode::S));
+		bindings.bind(Action::MoveLeft, Binding::Key(VirtualKeyCode::A));
+		bindings.bind(Action::MoveRight, Binding::Key(VirtualKeyCode::D));
+		bindings.bind(Action::MoveUp, Binding::Key(VirtualKeyCode::Space));
+		bindings.bind(Action::MoveDown, Binding::Key(VirtualKeyCode::LShift));
+		bindings.bind(Action::Jump, Binding::Key(VirtualKeyCode::Space));
+		bindings.bind(Action::ToggleNoclip, Binding::Key(VirtualKeyCode::N));
+		bindings.bind(Action::ToggleCursor, Binding::Key(VirtualKeyCode::Tab));
+		bindings.bind(Action::Menu, Binding::Key(VirtualKeyCode::Escape));
+		bindings.bind(Action::ToggleFullscreen, Binding::Key(VirtualKeyCode::F11));
+		bindings.bind(Action::ToggleDebugOverlay, Binding::Key(VirtualKeyCode::F3));
+		bindings.bind(Action::TogglePerfHud, Binding::Key(VirtualKeyCode::F2));
+		bindings.bind(Action::RemoveBlock, Binding::Mouse(MouseButton::Left));
+		bindings.bind(Action::PlaceBlock, Binding::Mouse(MouseButton::Right));
+		bindings.bind(Action::PaintBlock, Binding::Mouse(MouseButton::Middle));
+		bindings.bind(Action::ToggleEditor, Binding::Key(VirtualKeyCode::F4));
+		bindings.bind(Action::BrushBigger, Binding::Key(VirtualKeyCode::Equals));
+		bindings.bind(Action::BrushSmaller, Binding::Key(VirtualKeyCode::Minus));
+		bindings.bind(Action::CycleBrushShape, Binding::Key(VirtualKeyCode::B));
+		bindings.bind(Action::ToggleWireframe, Binding::Key(VirtualKeyCode::F5));
+		bindings.bind(Action::ToggleStepHeatmap, Binding::Key(VirtualKeyCode::F6));
+		bindings.bind(Action::ToggleMeshedWorld, Binding::Key(VirtualKeyCode::F7));
+		bindings.bind(Action::FlyFaster, Binding::Wheel(true));
+		bindings.bind(Action::FlySlower, Binding::Wheel(false));
+		bindings.bind(Action::Sprint, Binding::Key(VirtualKeyCode::LControl));
+
+		bindings.bind(Action::MoveForward, Binding::PadAxis(Axis::LeftStickY, true));
+		bindings.bind(Action::MoveBack, Binding::PadAxis(Axis::LeftStickY, false));
+		bindings.bind(Action::MoveLeft, Binding::PadAxis(Axis::LeftStickX, false));
+		bindings.bind(Action::MoveRight, Binding::PadAxis(Axis::LeftStickX, true));
+		bindings.bind(Action::MoveUp, Binding::Pad(Button::South));
+		bindings.bind(Action::MoveDown, Binding::Pad(Button::East));
+		bindings.bind(Action::Jump, Binding::Pad(Button::South));
+		bindings.bind(Action::Menu, Binding::Pad(Button::Start));
+		bindings.bind(Action::RemoveBlock, Binding::Pad(Button::RightTrigger2));
+		bindings.bind(Action::PlaceBlock, Binding::Pad(Button::LeftTrigger2));
+		bindings.bind(Action::PaintBlock, Binding::Pad(Button::West));
+		bindings.bind(Action::BrushBigger, Binding::Pad(Button::DPadUp));
+		bindings.bind(Action::BrushSmaller, Binding::Pad(Button::DPadDown));
+		bindings.bind(Action::CycleBrushShape, Binding::Pad(Button::North));
+		bindings.bind(Action::Sprint, Binding::Pad(Button::LeftThumb));
+		bindings.bind(Action::LookLeft, Binding::PadAxis(Axis::RightStickX, false));
+		bindings.bind(Action::LookRight, Binding::PadAxis(Axis::RightStickX, true));
+		bindings.bind(Action::LookUp, Binding::PadAxis(Axis::RightStickY, true));
+		bindings.bind(Action::LookDown, Binding::PadAxis(Axis::RightStickY, false));
+		bindings
+	}
+}
+
+/// Feed it every winit event and call `poll_gamepads`, read it during the frame, then call `end_frame`.
+pub struct Input {
+	pub bindings: Bindings,
+	held: HashSet<Binding>,
+	pressed: HashSet<Binding>,
+	released: HashSet<Binding>,
+	mouse_delta: Vector2<f64>,
+	/// Touchpad scrolling that hasn't added up to a whole notch yet.
+	wheel_pixels: f64,
+	cursor_grabbed: bool,
+	/// `None` if gamepads aren't supported on this platform.
+	gilrs: Option<Gilrs>,
+	/// Where each gamepad axis was last reported, from -1 to 1.
+	axes: HashMap<Axis, f32>,
+	/// How far an axis has to move from the center before it counts, since sticks rarely rest at exactly zero.
+	dead_zone: f32,
+	/// Everything applied since the last `end_frame`.
+	events: Vec<InputEvent>,
+}
+impl Input {
+	/// `dead_zone` is from 0 to 1, like the axes it applies to.
+	pub fn new(bindings: Bindings, dead_zone: f32) -> Self {
+		let gilrs = match Gilrs::new() {
+			Ok(gilrs) => Some(gilrs),
+			Err(err) => {
+				log::warn!("gamepads won't work: {}", err);
+				None
+			},
+		};
+		Self { gilrs, ..Self::without_gamepads(bindings, dead_zone) }
+	}
+
+	/// Input that only changes through `apply`, for playing another `Input`'s events into at a different rate.
+	pub fn without_gamepads(bindings: Bindings, dead_zone: f32) -> Self {
+		Self {
+			bindings,
+			held: HashSet::new(),
+			pressed: HashSet::new(),
+			released: HashSet::new(),
+			mouse_delta: Vector2::zeros(),
+			wheel_pixels: 0.0,
+			cursor_grabbed: false,
+			gilrs: None,
+			axes: HashMap::new(),
+			// a dead zone of 1 would divide by zero when scaling the rest of the range
+			dead_zone: dead_zone.max(0.0).min(0.95),
+			events: vec![],
+		}
+	}
+
+	pub fn window_event(&mut self, event: &WindowEvent) {
+		match *event {
+			WindowEvent::KeyboardInput { input: KeyboardInput { state, virtual_keycode: Some(key), .. }, .. } => {
+				self.apply_state(Binding::Key(key), state)
+			},
+			WindowEvent::MouseInput { state, button, .. } => self.apply_state(Binding::Mouse(button), state),
+			WindowEvent::MouseWheel { delta, .. } => {
+				let notches = match delta {
+					MouseScrollDelta::LineDelta(_, y) => y as f64,
+					MouseScrollDelta::PixelDelta(pos) => {
+						self.wheel_pixels += pos.y;
+						let notches = (self.wheel_pixels / WHEEL_NOTCH_PIXELS).trunc();
+						self.wheel_pixels -= notches * WHEEL_NOTCH_PIXELS;
+						notches
+					},
+				};
+				if notches != 0.0 {
+					let binding = Binding::Wheel(notches > 0.0);
+					self.apply(InputEvent::Pressed(binding));
+					self.apply(InputEvent::Released(binding));
+				}
+			},
+			// we won't see the release events while unfocused, so treat the keys and buttons as released now. gamepads
+			// are read through gilrs, which still hears them.
+			WindowEvent::Focused(false) => self.release_all(false),
+			_ => (),
+		}
+	}
+
+	pub fn device_event(&mut self, event: &DeviceEvent) {
+		if let DeviceEvent::MouseMotion { delta: (x, y) } = *event {
+			// raw motion arrives even when the cursor is over another window, so only count it while grabbed
+			if self.cursor_grabbed {
+				self.apply(InputEvent::MouseMotion(Vector2::new(x, y)));
+			}
+		}
+	}
+
+	/// Reads what happened on every gamepad since the last call. Call it once per frame, before reading any actions.
+	pub fn poll_gamepads(&mut self) {
+		let gilrs = match &mut self.gilrs {
+			Some(gilrs) => gilrs,
+			None => return,
+		};
+		let mut events = vec![];
+		while let Some(event) = gilrs.next_event() {
+			match event.event {
+				EventType::Connected => log::info!("connected gamepad {}", gilrs.gamepad(event.id).name()),
+				EventType::Disconnected => log::info!("disconnected gamepad {}", gilrs.gamepad(event.id).name()),
+				_ => (),
+			}
+			events.push(event.event);
+		}
+
+		for event in events {
+			match event {
+				EventType::ButtonPressed(button, _) => self.apply(InputEvent::Pressed(Binding::Pad(button))),
+				EventType::ButtonReleased(button, _) => self.apply(InputEvent::Released(Binding::Pad(button))),
+				EventType::AxisChanged(axis, value, _) => self.apply(InputEvent::Axis(axis, value)),
+				// we won't see the rest of its events, so let go of everything gamepads were holding
+				EventType::Disconnected => {
+					let axes = self.axes.keys().copied().collect::<Vec<_>>();
+					for axis in axes {
+						self.apply(InputEvent::Axis(axis, 0.0));
+					}
+					self.release_all(true);
+				},
+				_ => (),
+			}
+		}
+	}
+
+	/// Updates the state for `event`. The event methods all go through this, and a replay can call it directly instead.
+	pub fn apply(&mut self, event: InputEvent) {
+		let changed = match event {
+			InputEvent::Pressed(binding) => self.set_state(binding, ElementState::Pressed),
+			InputEvent::Released(binding) => self.set_state(binding, ElementState::Released),
+			InputEvent::Axis(axis, value) => {
+				self.set_axis(axis, value);
+				true
+			},
+			InputEvent::MouseMotion(delta) => {
+				self.mouse_delta += delta;
+				true
+			},
+		};
+		if changed {
+			self.events.push(event);
+		}
+	}
+
+	/// The events that changed anything since the last `end_frame`, in order.
+	pub fn frame_events(&self) -> &[InputEvent] {
+		&self.events
+	}
+
+	/// Clears this frame's presses, releases, mouse motion, and events.
+	pub fn end_frame(&mut self) {
+		self.pressed.clear();
+		self.released.clear();
+		self.mouse_delta = Vector2::zeros();
+		self.events.clear();
+	}
+
+	/// Whether any of the action's bindings are held. Gamepad axes are held while they're pushed at least halfway.
+	pub fn pressed(&self, action: Action) -> bool {
+		self.bindings.bindings(action).iter().any(|binding| self.held.contains(binding))
+	}
+
+	/// Whether any of the action's bindings went down this frame.
+	pub fn just_pressed(&self, action: Action) -> bool {
+		self.bindings.bindings(action).iter().any(|binding| self.pressed.contains(binding))
+	}
+
+	/// Whether any of the action's bindings went up this frame.
+	pub fn just_released(&self, action: Action) -> bool {
+		self.bindings.bindings(action).iter().any(|binding| self.released.contains(binding))
+	}
+
+	/// How far the action is pushed, from 0 to 1, going by whichever of its bindings is pushed furthest. Keys and
+	/// buttons are always 0 or 1.
+	pub fn value(&self, action: Action) -> f32 {
+		self.bindings.bindings(action).iter().map(|&binding| self.strength(binding)).fold(0.0, f32::max)
+	}
+
+	/// `positive`'s value minus `negative`'s, so -1 if only `negative` is pressed and 1 if only `positive` is, with
+	/// anything in between for gamepad sticks.
+	pub fn axis(&self, negative: Action, positive: Action) -> f32 {
+		self.value(positive) - self.value(negative)
+	}
+
+	/// Mouse motion since the last `end_frame`, while the cursor is grabbed.
+	pub fn mouse_delta(&self) -> Vector2<f64> {
+		self.mouse_delta
+	}
+
+	pub fn cursor_grabbed(&self) -> bool {
+		self.cursor_grabbed
+	}
+
+	/// Locks and hides the cursor so mouse motion can turn the camera, or releases it.
+	pub fn set_cursor_grab(&mut self, window: &Window, grab: bool) {
+		if let Err(err) = window.set_cursor_grab(grab) {
+			log::warn!("failed to set cursor grab: {:?}", err);
+			return;
+		}
+		window.set_cursor_visible(!grab);
+		self.cursor_grabbed = grab;
+	}
+
+	/// How far `binding` is pushed, from 0 to 1. Axes are scaled so the edge of the dead zone is 0.
+	fn strength(&self, binding: Binding) -> f32 {
+		match binding {
+			Binding::PadAxis(axis, positive) => {
+				let value = self.axes.get(&axis).copied().unwrap_or(0.0);
+				let value = if positive { value } else { -value };
+				((value - self.dead_zone) / (1.0 - self.dead_zone)).max(0.0).min(1.0)
+			},
+			_ => self.held.contains(&binding) as i32 as f32,
+		}
+	}
+
+	/// Records where `axis` is, and presses or releases each of its directions. Those follow from the axis, so they
+	/// aren't events of their own.
+	fn set_axis(&mut self, axis: Axis, value: f32) {
+		self.axes.insert(axis, value);
+		for &positive in &[false, true] {
+			let pushed = if positive { value } else { -value } >= AXIS_PRESS;
+			let state = if pushed { ElementState::Pressed } else { ElementState::Released };
+			self.set_state(Binding::PadAxis(axis, positive), state);
+		}
+	}
+
+	/// Releases every held gamepad binding if `pad` is true, or every key and mouse button otherwise.
+	fn release_all(&mut self, pad: bool) {
+		let held = self.held.iter().filter(|binding| binding.is_pad() == pad).copied().collect::<Vec<_>>();
+		for binding in held {
+			self.apply(InputEvent::Released(binding));
+		}
+	}
+
+	fn apply_state(&mut self, binding: Binding, state: ElementState) {
+		match state {
+			ElementState::Pressed => self.apply(InputEvent::Pressed(binding)),
+			ElementState::Released => self.apply(InputEvent::Released(binding)),
+		}
+	}
+
+	/// Returns false if `binding` was already in that state.
+	fn set_state(&mut self, binding: Binding, state: ElementState) -> bool {
+		match state {
+			ElementState::Pressed => {
+				// key repeat sends more presses without releases
+				let changed = self.held.insert(binding);
+				if changed {
+					self.pressed.insert(binding);
+				}
+				changed
+			},
+			ElementState::Released => {
+				let changed = self.held.remove(&binding);
+				if changed {
+					self.released.insert(binding);
+				}
+				changed
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn binding_names_round_trip() {
+		let bindings = [
+			Binding::Key(VirtualKeyCode::W),
+			Binding::Key(VirtualKeyCode::LControl),
+			Binding::Mouse(MouseButton::Left),
+			Binding::Mouse(MouseButton::Other(4)),
+			Binding::Pad(Button::South),
+			Binding::PadAxis(Axis::LeftStickY, true),
+			Binding::PadAxis(Axis::RightStickX, false),
+			Binding::Wheel(true),
+			Binding::Wheel(false),
+		];
+		for &binding in &bindings {
+			assert_eq!(Binding::from_name(&binding.name().unwrap()), Some(binding));
+		}
+		assert_eq!(Binding::from_name("PadLeftStickX+"), Some(Binding::PadAxis(Axis::LeftStickX, true)));
+		assert_eq!(Binding::from_name("Hyper"), None);
+		assert_eq!(Binding::from_name("PadTurbo"), None);
+		assert_eq!(Binding::from_name("Mousefour"), None);
+	}
+
+	#[test]
+	fn defaults_round_trip() {
+		let parsed = Bindings::parse(&Bindings::default().to_config());
+		for &action in Action::ALL {
+			assert_eq!(parsed.bindings(action), Bindings::default().bindings(action), "{}", action.name());
+		}
+	}
+
+	#[test]
+	fn parse_replaces_mentioned_actions() {
+		let bindings = Bindings::parse("# comment\nJump = J, PadNorth # trailing\nMoveUp =\n");
+		assert_eq!(bindings.bindings(Action::Jump), [Binding::Key(VirtualKeyCode::J), Binding::Pad(Button::North)]);
+		assert_eq!(bindings.bindings(Action::MoveUp), []);
+		assert_eq!(bindings.bindings(Action::MoveForward), Bindings::default().bindings(Action::MoveForward));
+	}
+
+	#[test]
+	fn parse_skips_bad_lines_and_keys() {
+		let bindings = Bindings::parse("Fly = F\nno equals sign\nJump = Space, Hyper\n");
+		assert_eq!(bindings.bindings(Action::Jump), [Binding::Key(VirtualKeyCode::Space)]);
+		assert_eq!(
+			Bindings::parse("no equals sign").bindings(Action::Menu),
+			Bindings::default().bindings(Action::Menu)
+		);
+	}
+
+	#[test]
+	fn event_lines_round_trip() {
+		let events = [
+			InputEvent::Pressed(Binding::Key(VirtualKeyCode::Space)),
+			InputEvent::Released(Binding::Wheel(false)),
+			InputEvent::Axis(Axis::LeftStickX, -0.25),
+			InputEvent::MouseMotion(Vector2::new(3.5, -2.0)),
+		];
+		for event in &events {
+			assert_eq!(InputEvent::from_line(&event.to_line().unwrap()).as_ref(), Some(event));
+		}
+		assert_eq!(InputEvent::from_line("press Space extra"), None);
+		assert_eq!(InputEvent::from_line("jump"), None);
+	}
+
+	#[test]
+	fn presses_last_a_frame() {
+		let mut input = Input::without_gamepads(Bindings::default(), 0.15);
+		input.apply(InputEvent::Pressed(Binding::Key(VirtualKeyCode::Space)));
+		assert!(input.just_pressed(Action::Jump) && input.pressed(Action::MoveUp));
+		input.end_frame();
+		assert!(!input.just_pressed(Action::Jump) && input.pressed(Action::Jump));
+		input.apply(InputEvent::Released(Binding::Key(VirtualKeyCode::Space)));
+		assert!(input.just_released(Action::Jump) && !input.pressed(Action::Jump));
+	}
+}
@@ -0,0 +1,61 @@
+//! The sun, which crosses the sky once a day and lights the world with whatever color suits the time.
+
+use nalgebra::{Vector3, Vector4};
+use std::f32::consts::PI;
+
+/// How tilted the sun's path is from straight overhead, in radians, so it never lights the world from exactly above.
+const TILT: f32 = 0.4;
+const NOON_COLOR: [f32; 3] = [1.0, 0.96, 0.9];
+const SUNSET_COLOR: [f32; 3] = [1.0, 0.45, 0.2];
+const DAY_AMBIENT: [f32; 3] = [0.25, 0.3, 0.4];
+const NIGHT_AMBIENT: [f32; 3] = [0.02, 0.03, 0.06];
+
+/// What the shaders light the scene with. Laid out like the `Light` block in the shaders, so it can be pushed as is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct Light {
+	/// Points toward the sun. W is unused.
+	pub sun_dir: Vector4<f32>,
+	/// Linear RGB, black while the sun is down. A is unused.
+	pub sun_color: Vector4<f32>,
+	/// Linear RGB light that reaches every surface, even in shadow. A is unused.
+	pub ambient: Vector4<f32>,
+}
+
+/// The time of day, which moves the sun.
+pub struct DayCycle {
+	/// The fraction of the day that has passed. 0 is midnight, 0.25 is sunrise, and 0.5 is noon.
+	pub time: f32,
+	/// Seconds per day.
+	pub day_length: f32,
+}
+impl DayCycle {
+	/// Starts in the morning.
+	pub fn new(day_length: f32) -> Self {
+		Self { time: 0.3, day_length }
+	}
+
+	pub fn update(&mut self, delta: f32) {
+		self.time = (self.time + delta / self.day_length).fract();
+	}
+
+	/// Points toward the sun. It rises in +X, peaks at noon, and sets in -X.
+	pub fn sun_dir(&self) -> Vector3<f32> {
+		let angle = (self.time - 0.25) * 2.0 * PI;
+		Vector3::new(angle.cos(), angle.sin() * TILT.sin(), angle.sin() * TILT.cos())
+	}
+
+	pub fn light(&self) -> Light {
+		let sun_dir = self.sun_dir();
+		// reddens near the horizon and fades out just after it sets
+		let height = sun_dir.z;
+		let color = Vector3::from(SUNSET_COLOR).lerp(&Vector3::from(NOON_COLOR), saturate(height * 3.0));
+		let sun = color * saturate(height * 10.0 + 0.5);
+		let ambient = Vector3::from(NIGHT_AMBIENT).lerp(&Vector3::from(DAY_AMBIENT), saturate(height * 2.0 + 0.5));
+		Light { sun_dir: sun_dir.push(0.0), sun_color: sun.push(1.0), ambient: ambient.push(1.0) }
+	}
+}
+
+fn saturate(x: f32) -> f32 {
+	x.max(0.0).min(1.0)
+}
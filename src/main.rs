@@ -1,43 +1,74 @@
-mod fs;
+#[cfg(feature = "gui")]
+mod asset;
+#[cfg(feature = "gui")]
+mod audio;
+#[cfg(feature = "gui")]
+mod camera;
+#[cfg(feature = "gui")]
+mod editor;
+#[cfg(feature = "gui")]
+mod game;
+#[cfg(feature = "gui")]
 mod gfx;
-mod threads;
+#[cfg(feature = "gui")]
+mod hud;
+#[cfg(feature = "gui")]
+mod input;
+#[cfg(feature = "gui")]
+mod lighting;
+#[cfg(feature = "gui")]
+mod menu;
+#[cfg(feature = "net")]
+mod net;
+#[cfg(feature = "gui")]
+mod player;
+#[cfg(feature = "gui")]
+mod replay;
+#[cfg(feature = "gui")]
+mod scene;
+#[cfg(feature = "gui")]
+mod settings;
+#[cfg(feature = "gui")]
+mod timestep;
 
 use futures::executor::block_on;
-use gfx::{window::Window, Gfx};
+#[cfg(feature = "net")]
+use net::server::Server;
 use simplelog::{LevelFilter, SimpleLogger};
-use winit::{
-	event::{Event, KeyboardInput, VirtualKeyCode, WindowEvent},
-	event_loop::{ControlFlow, EventLoop},
-};
+use std::env;
 
 fn main() {
 	block_on(amain());
 }
 
 async fn amain() {
-	SimpleLogger::init(LevelFilter::Warn, Default::default()).unwrap();
-
-	let gfx = Gfx::new().await;
+	let mut args = env::args().skip(1);
+	let mode = args.next();
 
-	let event_loop = EventLoop::new();
-	let mut window = Window::new(gfx.clone(), &event_loop);
-
-	event_loop.run(move |event, _window, control| {
-		*control = ControlFlow::Poll;
+	// `--server [addr]` hosts a multiplayer game without a window or GPU, and reports players joining and leaving
+	#[cfg(feature = "net")]
+	{
+		if mode.as_deref() == Some("--server") {
+			SimpleLogger::init(LevelFilter::Info, Default::default()).unwrap();
+			let addr = args.next().unwrap_or_else(|| format!("0.0.0.0:{}", net::DEFAULT_PORT));
+			match Server::bind(&addr) {
+				Ok(server) => server.run(),
+				Err(err) => log::error!("failed to listen on {}: {}", addr, err),
+			}
+			return;
+		}
+	}
+	SimpleLogger::init(LevelFilter::Warn, Default::default()).unwrap();
+	#[cfg(not(feature = "net"))]
+	{
+		if mode.as_deref() == Some("--server") || mode.as_deref() == Some("--connect") {
+			log::error!("{} needs a build with the net feature", mode.unwrap());
+			return;
+		}
+	}
 
-		match event {
-			Event::WindowEvent { event, .. } => match event {
-				WindowEvent::CloseRequested => *control = ControlFlow::Exit,
-				WindowEvent::KeyboardInput { input: KeyboardInput { virtual_keycode, .. }, .. } => {
-					match virtual_keycode {
-						Some(VirtualKeyCode::Escape) => *control = ControlFlow::Exit,
-						_ => (),
-					}
-				},
-				_ => (),
-			},
-			Event::EventsCleared => window.draw(),
-			_ => (),
-		};
-	});
+	#[cfg(feature = "gui")]
+	game::run(mode, args).await;
+	#[cfg(not(feature = "gui"))]
+	log::error!("{} needs a build with the gui feature", mode.as_deref().unwrap_or("the game"));
 }
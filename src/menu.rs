@@ -0,0 +1,209 @@
+//! The screens shown over the world while the game is paused, and the state that decides whether input goes to them
+//! or to the game.
+
+use crate::{
+	gfx::{
+		gui::{
+			button::ButtonElement, image::ImageElement, text::TextElement, Color, DivElement, Document, Edges,
+			FlexDirection, Node, Size, Styles,
+		},
+		texture::TextureHandle,
+		window::PresentModePreference,
+	},
+	settings::Settings,
+};
+use std::sync::Arc;
+
+/// The ids of the menu buttons, as raised in `GuiEvent::Clicked`.
+pub const RESUME: &str = "resume";
+pub const SETTINGS: &str = "settings";
+pub const QUIT: &str = "quit";
+pub const DISPLAY_MODE: &str = "display_mode";
+pub const FOV: &str = "fov";
+pub const RENDER_SCALE: &str = "render_scale";
+pub const PRESENT_MODE: &str = "present_mode";
+pub const BACK: &str = "back";
+
+/// The fields of view the settings screen cycles through, in degrees.
+const FOVS: [f32; 5] = [70.0, 80.0, 90.0, 100.0, 110.0];
+/// The render scales the settings screen cycles through.
+const RENDER_SCALES: [f32; 4] = [0.5, 0.67, 0.75, 1.0];
+const PANEL_WIDTH: u32 = 280;
+const PANEL_PADDING: u32 = 12;
+/// The logo is stretched to fill the panel's width at this height.
+const LOGO_HEIGHT: u32 = 64;
+const FONT_SIZE: u32 = 18;
+const TEXT_COLOR: [u8; 4] = [255, 255, 255, 255];
+
+/// What the game is doing, which decides where input goes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AppState {
+	/// The world updates, and the cursor is grabbed to turn the camera.
+	Playing,
+	/// The world is frozen behind the pause menu.
+	Paused,
+	/// The settings screen, opened from the pause menu. The world stays frozen.
+	Menu,
+}
+impl AppState {
+	/// Where the menu action leads: into the pause menu while playing, and back out one screen otherwise.
+	pub fn back(self) -> Self {
+		match self {
+			AppState::Playing => AppState::Paused,
+			AppState::Paused => AppState::Playing,
+			AppState::Menu => AppState::Paused,
+		}
+	}
+}
+
+/// The GUI for each state. Only the current state's screen is in the document, so the others' buttons can't be
+/// clicked.
+pub struct Menus {
+	state: AppState,
+	pause: Arc<dyn Node>,
+	settings: Arc<dyn Node>,
+	display_mode: Arc<TextElement>,
+	fov: Arc<TextElement>,
+	render_scale: Arc<TextElement>,
+	present_mode: Arc<TextElement>,
+}
+impl Menus {
+	/// Starts out playing, with no screen shown. `settings` fills in the settings screen's labels, and the pause menu
+	/// shows `logo` if there is one.
+	pub fn new(settings: &Settings, logo: Option<TextureHandle>) -> Self {
+		let mut pause = panel("Paused");
+		if let Some(logo) = logo {
+			let mut style = Styles::default();
+			style.set_width(Size::Percent(100.0));
+			style.set_height(LOGO_HEIGHT);
+			style.set_margin(Edges { bottom: 8, ..Edges::default() });
+			pause = pause.with_child(Arc::new(ImageElement::new(logo, style)));
+		}
+		let pause = pause
+			.with_child(button(RESUME, label("Resume")))
+			.with_child(button(SETTINGS, label("Settings")))
+			.with_child(button(QUIT, label("Quit")));
+
+		let display_mode = label("");
+		let fov = label("");
+		let render_scale = label("");
+		let present_mode = label("");
+		let settings_panel = panel("Settings")
+			.with_child(button(DISPLAY_MODE, display_mode.clone()))
+			.with_child(button(FOV, fov.clone()))
+			.with_child(button(RENDER_SCALE, render_scale.clone()))
+			.with_child(button(PRESENT_MODE, present_mode.clone()))
+			.with_child(button(BACK, label("Back")));
+
+		let menus = Self {
+			state: AppState::Playing,
+			pause: centered(pause),
+			settings: centered(settings_panel),
+			display_mode,
+			fov,
+			render_scale,
+			present_mode,
+		};
+		menus.update_settings(settings);
+		menus
+	}
+
+	pub fn state(&self) -> AppState {
+		self.state
+	}
+
+	/// Swaps the screen in `doc` for the new state's. Grabbing or releasing the cursor is up to the caller.
+	pub fn set_state(&mut self, doc: &mut Document, state: AppState) {
+		if state == self.state {
+			return;
+		}
+		if let Some(screen) = self.screen() {
+			doc.remove(&screen);
+		}
+		self.state = state;
+		if let Some(screen) = self.screen() {
+			doc.push(screen);
+		}
+	}
+
+	/// Shows the current values on the settings screen. Call it whenever they change.
+	pub fn update_settings(&self, settings: &Settings) {
+		self.display_mode.set_content(format!("Display: {:?}", settings.display.mode));
+		self.fov.set_content(format!("Field of view: {:.0}", settings.fov));
+		self.render_scale.set_content(format!("Render scale: {:.0}%", settings.render.render_scale * 100.0));
+		self.present_mode.set_content(format!("Present: {:?}", settings.render.present_mode));
+	}
+
+	fn screen(&self) -> Option<Arc<dyn Node>> {
+		match self.state {
+			AppState::Playing => None,
+			AppState::Paused => Some(self.pause.clone()),
+			AppState::Menu => Some(self.settings.clone()),
+		}
+	}
+}
+
+/// The field of view after `fov` in `FOVS`, wrapping around to the first.
+pub fn next_fov(fov: f32) -> f32 {
+	FOVS.iter().copied().find(|&choice| choice > fov).unwrap_or(FOVS[0])
+}
+
+/// The render scale after `scale` in `RENDER_SCALES`, wrapping around to the first.
+pub fn next_render_scale(scale: f32) -> f32 {
+	RENDER_SCALES.iter().copied().find(|&choice| choice > scale).unwrap_or(RENDER_SCALES[0])
+}
+
+/// The present mode after `mode`, wrapping around to the first.
+pub fn next_present_mode(mode: PresentModePreference) -> PresentModePreference {
+	match mode {
+		PresentModePreference::Immediate => PresentModePreference::Vsync,
+		PresentModePreference::Vsync => PresentModePreference::LowLatency,
+		PresentModePreference::LowLatency => PresentModePreference::Immediate,
+	}
+}
+
+/// A column with a background and a title, for a screen's buttons.
+fn panel(title: &str) -> DivElement {
+	let mut style = Styles::default();
+	style.set_background_color(Color::new(0, 0, 0, 180));
+	style.set_padding(Edges::all(PANEL_PADDING));
+	style.set_width(PANEL_WIDTH);
+
+	let mut title_style = Styles::default();
+	title_style.set_margin(Edges { bottom: 8, ..Edges::default() });
+	let title =
+		TextElement::new(title, FONT_SIZE + 6, Color::from(TEXT_COLOR)).with_wrap(false).with_style(title_style);
+	DivElement::new(style).with_child(Arc::new(title))
+}
+
+fn label(content: &str) -> Arc<TextElement> {
+	Arc::new(TextElement::new(content, FONT_SIZE, Color::from(TEXT_COLOR)).with_wrap(false))
+}
+
+fn button(id: &'static str, label: Arc<TextElement>) -> Arc<dyn Node> {
+	let mut style = Styles::default();
+	style.set_background_color(Color::new(48, 48, 56, 230));
+	style.set_padding(Edges::all(10));
+	style.set_margin(Edges { top: 4, bottom: 4, ..Edges::default() });
+	let mut hover_style = Styles::default();
+	hover_style.set_background_color(Color::new(76, 76, 92, 240));
+	let mut pressed_style = Styles::default();
+	pressed_style.set_background_color(Color::new(32, 32, 38, 240));
+
+	let button =
+		ButtonElement::new(id, style).with_hover_style(hover_style).with_pressed_style(pressed_style).with_child(label);
+	Arc::new(button)
+}
+
+/// Centers `panel` in the space the rest of the document leaves, between spacers that share the free space.
+fn centered(panel: DivElement) -> Arc<dyn Node> {
+	let spacer = || Arc::new(DivElement::new(Styles::default())) as Arc<dyn Node>;
+
+	let mut row_style = Styles::default();
+	row_style.set_flex_direction(FlexDirection::Row);
+	let row = DivElement::new(row_style).with_child(spacer()).with_child(Arc::new(panel)).with_child(spacer());
+
+	let mut style = Styles::default();
+	style.set_flex_grow(1.0);
+	Arc::new(DivElement::new(style).with_child(spacer()).with_child(Arc::new(row)).with_child(spacer()))
+}
@@ -0,0 +1,232 @@
+//! Multiplayer over UDP. A headless server keeps the authoritative log of every edit, in the order it received them,
+//! and relays each player's camera to the others. Clients send their edits to the server without applying them, and
+//! apply every edit, their own included, as it comes back in the log. Overlapping edits don't commute, so applying
+//! them in the server's order is what keeps every client's world the same.
+//!
+//! Every packet carries whatever the other side hasn't acknowledged yet, so a lost packet only delays edits until the
+//! next one. Clients keep the log and replay it on chunks as they load, so edits to chunks a client hadn't loaded yet
+//! aren't lost.
+
+#[cfg(feature = "gui")]
+pub mod client;
+pub mod server;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use nalgebra::Vector3;
+use sim::{
+	math::Aabb,
+	world::edit::{Edit, Shape},
+};
+use std::{
+	io::{self, Cursor},
+	time::Duration,
+};
+
+pub const DEFAULT_PORT: u16 = 27016;
+/// How often each side sends an update.
+const TICK: Duration = Duration::from_millis(50);
+/// How long the other side can go without sending anything before it's considered gone.
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// The most edits in one packet, which keeps packets under a typical MTU.
+const MAX_EDITS: usize = 32;
+/// Big enough for any packet we send.
+const MAX_PACKET: usize = 64 * 1024;
+
+const MAGIC: &[u8; 4] = b"SPTN";
+const VERSION: u8 = 1;
+
+/// Where a player is looking from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlayerState {
+	/// The camera's position.
+	pub pos: Vector3<f32>,
+	pub yaw: f32,
+	pub pitch: f32,
+}
+
+/// What clients send to the server.
+#[derive(Clone, Debug, PartialEq)]
+enum ClientMessage {
+	/// Sent every tick.
+	Update {
+		/// How many of the server's log entries the client has.
+		ack: u64,
+		/// The index of `edits[0]` among every edit this client has made.
+		first_edit: u64,
+		edits: Vec<Edit>,
+		state: PlayerState,
+	},
+	/// The client is leaving, so the server doesn't have to wait for it to time out.
+	Leave,
+}
+impl ClientMessage {
+	fn encode(&self) -> Vec<u8> {
+		let mut out = header();
+		match self {
+			ClientMessage::Update { ack, first_edit, edits, state } => {
+				out.push(0);
+				out.write_u64::<LittleEndian>(*ack).unwrap();
+				out.write_u64::<LittleEndian>(*first_edit).unwrap();
+				out.write_u16::<LittleEndian>(edits.len() as _).unwrap();
+				for edit in edits {
+					write_edit(&mut out, edit);
+				}
+				write_state(&mut out, state);
+			},
+			ClientMessage::Leave => out.push(1),
+		}
+		out
+	}
+
+	fn decode(bytes: &[u8]) -> io::Result<Self> {
+		let mut reader = read_header(bytes)?;
+		match reader.read_u8()? {
+			0 => {
+				let ack = reader.read_u64::<LittleEndian>()?;
+				let first_edit = reader.read_u64::<LittleEndian>()?;
+				let count = reader.read_u16::<LittleEndian>()?;
+				let edits = (0..count).map(|_| read_edit(&mut reader)).collect::<io::Result<_>>()?;
+				let state = read_state(&mut reader)?;
+				Ok(ClientMessage::Update { ack, first_edit, edits, state })
+			},
+			1 => Ok(ClientMessage::Leave),
+			kind => Err(invalid(format!("unknown message kind {}", kind))),
+		}
+	}
+}
+
+/// What the server sends each client every tick.
+#[derive(Clone, Debug, PartialEq)]
+struct ServerUpdate {
+	/// The client's own id.
+	id: u32,
+	/// How many of the client's edits are in the log.
+	edit_ack: u64,
+	/// The index of `edits[0]` in the log.
+	first_edit: u64,
+	/// Log entries, each with the id of the player that made it.
+	edits: Vec<(u32, Edit)>,
+	/// Every other player.
+	players: Vec<(u32, PlayerState)>,
+}
+impl ServerUpdate {
+	fn encode(&self) -> Vec<u8> {
+		let mut out = header();
+		out.write_u32::<LittleEndian>(self.id).unwrap();
+		out.write_u64::<LittleEndian>(self.edit_ack).unwrap();
+		out.write_u64::<LittleEndian>(self.first_edit).unwrap();
+		out.write_u16::<LittleEndian>(self.edits.len() as _).unwrap();
+		for (author, edit) in &self.edits {
+			out.write_u32::<LittleEndian>(*author).unwrap();
+			write_edit(&mut out, edit);
+		}
+		out.write_u16::<LittleEndian>(self.players.len() as _).unwrap();
+		for (id, state) in &self.players {
+			out.write_u32::<LittleEndian>(*id).unwrap();
+			write_state(&mut out, state);
+		}
+		out
+	}
+
+	fn decode(bytes: &[u8]) -> io::Result<Self> {
+		let mut reader = read_header(bytes)?;
+		let id = reader.read_u32::<LittleEndian>()?;
+		let edit_ack = reader.read_u64::<LittleEndian>()?;
+		let first_edit = reader.read_u64::<LittleEndian>()?;
+		let count = reader.read_u16::<LittleEndian>()?;
+		let edits = (0..count)
+			.map(|_| Ok((reader.read_u32::<LittleEndian>()?, read_edit(&mut reader)?)))
+			.collect::<io::Result<_>>()?;
+		let count = reader.read_u16::<LittleEndian>()?;
+		let players = (0..count)
+			.map(|_| Ok((reader.read_u32::<LittleEndian>()?, read_state(&mut reader)?)))
+			.collect::<io::Result<_>>()?;
+		Ok(Self { id, edit_ack, first_edit, edits, players })
+	}
+}
+
+fn header() -> Vec<u8> {
+	let mut out = MAGIC.to_vec();
+	out.push(VERSION);
+	out
+}
+
+/// Checks the magic and version, and returns a reader for the rest of the packet.
+fn read_header(bytes: &[u8]) -> io::Result<Cursor<&[u8]>> {
+	if bytes.len() < 5 || &bytes[..4] != MAGIC {
+		return Err(invalid("not a space-thing packet".to_string()));
+	}
+	if bytes[4] != VERSION {
+		return Err(invalid(format!("unsupported protocol version {}", bytes[4])));
+	}
+	Ok(Cursor::new(&bytes[5..]))
+}
+
+fn write_edit(out: &mut Vec<u8>, edit: &Edit) {
+	let (kind, material) = match *edit {
+		Edit::Add(_, material) => (0, material),
+		Edit::Subtract(_) => (1, 0),
+		Edit::Paint(_, material) => (2, material),
+	};
+	out.push(kind);
+	out.push(material);
+	match edit.shape() {
+		Shape::Box(aabb) => {
+			out.push(0);
+			write_vec3(out, aabb.min);
+			write_vec3(out, aabb.max);
+		},
+		Shape::Sphere { center, radius } => {
+			out.push(1);
+			write_vec3(out, center);
+			out.write_f32::<LittleEndian>(radius).unwrap();
+		},
+	}
+}
+
+fn read_edit(reader: &mut Cursor<&[u8]>) -> io::Result<Edit> {
+	let kind = reader.read_u8()?;
+	let material = reader.read_u8()?;
+	let shape = match reader.read_u8()? {
+		0 => Shape::Box(Aabb::new(read_vec3(reader)?, read_vec3(reader)?)),
+		1 => Shape::Sphere { center: read_vec3(reader)?, radius: reader.read_f32::<LittleEndian>()? },
+		shape => return Err(invalid(format!("unknown shape {}", shape))),
+	};
+	match kind {
+		0 => Ok(Edit::Add(shape, material)),
+		1 => Ok(Edit::Subtract(shape)),
+		2 => Ok(Edit::Paint(shape, material)),
+		_ => Err(invalid(format!("unknown edit {}", kind))),
+	}
+}
+
+fn write_state(out: &mut Vec<u8>, state: &PlayerState) {
+	write_vec3(out, state.pos);
+	out.write_f32::<LittleEndian>(state.yaw).unwrap();
+	out.write_f32::<LittleEndian>(state.pitch).unwrap();
+}
+
+fn read_state(reader: &mut Cursor<&[u8]>) -> io::Result<PlayerState> {
+	let pos = read_vec3(reader)?;
+	let yaw = reader.read_f32::<LittleEndian>()?;
+	let pitch = reader.read_f32::<LittleEndian>()?;
+	Ok(PlayerState { pos, yaw, pitch })
+}
+
+fn write_vec3(out: &mut Vec<u8>, v: Vector3<f32>) {
+	for &x in v.iter() {
+		out.write_f32::<LittleEndian>(x).unwrap();
+	}
+}
+
+fn read_vec3(reader: &mut Cursor<&[u8]>) -> io::Result<Vector3<f32>> {
+	Ok(Vector3::new(
+		reader.read_f32::<LittleEndian>()?,
+		reader.read_f32::<LittleEndian>()?,
+		reader.read_f32::<LittleEndian>()?,
+	))
+}
+
+fn invalid(msg: String) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,199 @@
+//! Joins a server, sends it local edits and the camera, and applies everyone's edits in the order the server logged
+//! them.
+
+use crate::{
+	camera::Camera,
+	gfx::{
+		mesh::{Material, Mesh, Model},
+		Gfx,
+	},
+	net::{ClientMessage, PlayerState, ServerUpdate, MAX_EDITS, MAX_PACKET, TICK, TIMEOUT},
+	scene::{Entity, Renderable, Scene, Transform},
+};
+use nalgebra::{UnitQuaternion, Vector3, Vector4};
+use sim::{
+	math::Aabb,
+	world::{edit::Edit, World},
+};
+use std::{
+	collections::{HashMap, VecDeque},
+	io,
+	net::{SocketAddr, ToSocketAddrs, UdpSocket},
+	sync::Arc,
+	time::Instant,
+};
+
+/// The model other players are drawn with, relative to the working directory. Its origin is at their camera.
+pub const PLAYER_MODEL_PATH: &str = "assets/models/player.glb";
+/// Without the model, other players are drawn as a box around where their body would be, relative to their camera.
+const BODY_MIN: [f32; 3] = [-0.4, -0.4, -1.9];
+const BODY_MAX: [f32; 3] = [0.4, 0.4, 0.2];
+/// Linear RGBA, picked by player id.
+const PLAYER_COLORS: [[f32; 4]; 4] =
+	[[0.9, 0.3, 0.2, 1.0], [0.2, 0.5, 0.9, 1.0], [0.3, 0.8, 0.3, 1.0], [0.9, 0.8, 0.2, 1.0]];
+
+pub struct Client {
+	socket: UdpSocket,
+	/// The server's log entries received so far, in order, kept for chunks that load after them.
+	log: Vec<Edit>,
+	/// Local edits the server hasn't acknowledged yet.
+	unacked: VecDeque<Edit>,
+	/// The index of `unacked[0]` among every edit this client has made.
+	first_unacked: u64,
+	/// The entities standing in for other players, by their ids.
+	players: HashMap<u32, Entity>,
+	model: Arc<Model>,
+	last_send: Instant,
+	last_heard: Instant,
+	connected: bool,
+}
+impl Client {
+	/// Starts talking to the server at `addr`. UDP has no handshake, so this doesn't wait for the server to answer.
+	/// Other players are drawn with `model`, or a box if there isn't one.
+	pub fn connect(gfx: &Gfx, addr: impl ToSocketAddrs, model: Option<Arc<Model>>) -> io::Result<Self> {
+		let addr = addr
+			.to_socket_addrs()?
+			.next()
+			.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "the address didn't resolve to anything"))?;
+		let local = match addr {
+			SocketAddr::V4(_) => SocketAddr::from(([0, 0, 0, 0], 0)),
+			SocketAddr::V6(_) => SocketAddr::from(([0; 8], 0)),
+		};
+		let socket = UdpSocket::bind(local)?;
+		socket.connect(addr)?;
+		socket.set_nonblocking(true)?;
+
+		let model = model.unwrap_or_else(|| {
+			let body = Aabb::new(BODY_MIN.into(), BODY_MAX.into());
+			Arc::new(Model::from(Mesh::cuboid(gfx, &body, Material::default())))
+		});
+		let now = Instant::now();
+		Ok(Self {
+			socket,
+			log: vec![],
+			unacked: VecDeque::new(),
+			first_unacked: 0,
+			players: HashMap::new(),
+			model,
+			last_send: now,
+			last_heard: now,
+			connected: false,
+		})
+	}
+
+	/// Sends the edits `world` deferred since the last call along with the camera, then applies the log entries that
+	/// arrived, including our own edits once the server has ordered them, and moves other players' entities in `scene`.
+	/// The world's edits must be deferred with `World::defer_edits`.
+	pub fn update(&mut self, world: &mut World, camera: &Camera, scene: &mut Scene) {
+		self.unacked.extend(world.take_deferred());
+		self.receive(world, scene);
+
+		if self.connected && self.last_heard.elapsed() >= TIMEOUT {
+			log::warn!("lost the connection to the server");
+			self.connected = false;
+			for (_, entity) in self.players.drain() {
+				scene.despawn(entity);
+			}
+		}
+		if self.last_send.elapsed() >= TICK {
+			self.last_send = Instant::now();
+			self.send(camera);
+		}
+	}
+
+	/// Replays the log on chunks that just loaded, like the ones `ChunkStreamer::update` returns. `World::edit` only
+	/// changes loaded chunks, so without this, edits made before a chunk loaded would be missing from it.
+	pub fn catch_up(&self, world: &mut World, loaded: &[Vector3<i32>]) {
+		for &key in loaded {
+			for &edit in &self.log {
+				world.replay_edit(key, edit);
+			}
+		}
+	}
+
+	fn receive(&mut self, world: &mut World, scene: &mut Scene) {
+		let mut buf = vec![0; MAX_PACKET];
+		loop {
+			let len = match self.socket.recv(&mut buf) {
+				Ok(len) => len,
+				Err(err) if err.kind() == io::ErrorKind::WouldBlock => return,
+				// some platforms report ICMP errors from earlier sends, like when the server isn't up yet
+				Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => continue,
+				Err(err) if err.kind() == io::ErrorKind::ConnectionReset => continue,
+				Err(err) => {
+					log::warn!("failed to receive from the server: {}", err);
+					return;
+				},
+			};
+			match ServerUpdate::decode(&buf[..len]) {
+				Ok(update) => self.apply(update, world, scene),
+				Err(err) => log::warn!("bad packet from the server: {}", err),
+			}
+		}
+	}
+
+	fn apply(&mut self, update: ServerUpdate, world: &mut World, scene: &mut Scene) {
+		if !self.connected {
+			log::info!("joined the server as player {}", update.id);
+			self.connected = true;
+		}
+		self.last_heard = Instant::now();
+
+		let acked = update.edit_ack.saturating_sub(self.first_unacked).min(self.unacked.len() as u64);
+		self.unacked.drain(..acked as usize);
+		self.first_unacked += acked;
+
+		for (i, (_, edit)) in update.edits.into_iter().enumerate() {
+			// packets can arrive twice or out of order, so only the next entry in sequence is taken
+			if update.first_edit + i as u64 != self.log.len() as u64 {
+				continue;
+			}
+			self.log.push(edit);
+			// our own edits too, which waited for this so every client applies edits in the same order
+			world.apply_edit(edit);
+		}
+
+		let model = &self.model;
+		let players = &update.players;
+		for &(id, state) in players {
+			let entity = *self.players.entry(id).or_insert_with(|| {
+				let entity = scene.spawn();
+				let color = Vector4::from(PLAYER_COLORS[id as usize % PLAYER_COLORS.len()]);
+				scene.set_renderable(entity, Renderable { model: model.clone(), color });
+				entity
+			});
+			let rot = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), state.yaw);
+			scene.set_transform(entity, Transform { pos: state.pos, rot, scale: 1.0 });
+		}
+		self.players.retain(|id, entity| {
+			let present = players.iter().any(|(other, _)| other == id);
+			if !present {
+				scene.despawn(*entity);
+			}
+			present
+		});
+	}
+
+	fn send(&self, camera: &Camera) {
+		let msg = ClientMessage::Update {
+			ack: self.log.len() as u64,
+			first_edit: self.first_unacked,
+			edits: self.unacked.iter().take(MAX_EDITS).copied().collect(),
+			state: PlayerState { pos: camera.pos, yaw: camera.yaw, pitch: camera.pitch },
+		};
+		self.send_msg(&msg);
+	}
+
+	fn send_msg(&self, msg: &ClientMessage) {
+		match self.socket.send(&msg.encode()) {
+			Ok(_) => (),
+			Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => (),
+			Err(err) => log::warn!("failed to send to the server: {}", err),
+		}
+	}
+}
+impl Drop for Client {
+	fn drop(&mut self) {
+		self.send_msg(&ClientMessage::Leave);
+	}
+}
@@ -0,0 +1,156 @@
+//! The authoritative side of a multiplayer game. It doesn't load any chunks, or need a window or GPU, so it can run
+//! anywhere. Clients generate the terrain themselves and skip their local saves, then keep every log entry they receive
+//! and replay it on each chunk as it loads, so the edit log is all they need from the server.
+
+use crate::net::{ClientMessage, PlayerState, ServerUpdate, MAX_EDITS, MAX_PACKET, TICK, TIMEOUT};
+use sim::world::edit::Edit;
+use std::{
+	collections::HashMap,
+	io,
+	net::{SocketAddr, ToSocketAddrs, UdpSocket},
+	time::Instant,
+};
+
+struct Peer {
+	id: u32,
+	/// How many of the peer's edits are in the log.
+	applied: u64,
+	/// How many log entries the peer has acknowledged.
+	ack: u64,
+	/// `None` until the peer's first update arrives.
+	state: Option<PlayerState>,
+	last_heard: Instant,
+}
+
+pub struct Server {
+	socket: UdpSocket,
+	/// Every edit so far, with the id of the player that made it.
+	log: Vec<(u32, Edit)>,
+	peers: HashMap<SocketAddr, Peer>,
+	next_id: u32,
+}
+impl Server {
+	pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+		let socket = UdpSocket::bind(addr)?;
+		// waking up at least once a tick is enough to send updates on time
+		socket.set_read_timeout(Some(TICK))?;
+		Ok(Self { socket, log: vec![], peers: HashMap::new(), next_id: 0 })
+	}
+
+	/// Serves clients until the process is killed.
+	pub fn run(mut self) {
+		match self.socket.local_addr() {
+			Ok(addr) => log::info!("listening on {}", addr),
+			Err(err) => log::warn!("failed to get the server's address: {}", err),
+		}
+
+		let mut last_tick = Instant::now();
+		let mut buf = vec![0; MAX_PACKET];
+		loop {
+			match self.socket.recv_from(&mut buf) {
+				Ok((len, addr)) => match ClientMessage::decode(&buf[..len]) {
+					Ok(msg) => self.handle(addr, msg),
+					Err(err) => log::warn!("bad packet from {}: {}", addr, err),
+				},
+				Err(err) if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut => (),
+				Err(err) => log::warn!("failed to receive: {}", err),
+			}
+
+			if last_tick.elapsed() >= TICK {
+				last_tick = Instant::now();
+				self.tick();
+			}
+		}
+	}
+
+	fn handle(&mut self, addr: SocketAddr, msg: ClientMessage) {
+		let (ack, first_edit, edits, state) = match msg {
+			ClientMessage::Update { ack, first_edit, edits, state } => (ack, first_edit, edits, state),
+			ClientMessage::Leave => {
+				if let Some(peer) = self.peers.remove(&addr) {
+					log::info!("player {} left", peer.id);
+				}
+				return;
+			},
+		};
+
+		if !self.peers.contains_key(&addr) {
+			let id = self.next_id;
+			self.next_id += 1;
+			log::info!("{} joined as player {}", addr, id);
+			// a client that timed out and came back still numbers its edits from where it was, so its count starts
+			// wherever the client says
+			let peer = Peer { id, applied: first_edit, ack: 0, state: None, last_heard: Instant::now() };
+			self.peers.insert(addr, peer);
+		}
+		let peer = self.peers.get_mut(&addr).unwrap();
+		peer.last_heard = Instant::now();
+		peer.ack = peer.ack.max(ack).min(self.log.len() as u64);
+		peer.state = Some(state);
+		for (i, edit) in edits.into_iter().enumerate() {
+			// packets can arrive twice or out of order, so only the next edit in sequence is taken
+			if first_edit + i as u64 == peer.applied {
+				self.log.push((peer.id, edit));
+				peer.applied += 1;
+			}
+		}
+	}
+
+	/// Drops peers that timed out, and sends everyone else an update.
+	fn tick(&mut self) {
+		self.peers.retain(|_, peer| {
+			let alive = peer.last_heard.elapsed() < TIMEOUT;
+			if !alive {
+				log::info!("player {} timed out", peer.id);
+			}
+			alive
+		});
+
+		let players =
+			self.peers.values().filter_map(|peer| peer.state.map(|state| (peer.id, state))).collect::<Vec<_>>();
+		for (addr, peer) in &self.peers {
+			let first = peer.ack as usize;
+			let last = (first + MAX_EDITS).min(self.log.len());
+			let update = ServerUpdate {
+				id: peer.id,
+				edit_ack: peer.applied,
+				first_edit: peer.ack,
+				edits: self.log[first..last].to_vec(),
+				players: players.iter().copied().filter(|&(id, _)| id != peer.id).collect(),
+			};
+			if let Err(err) = self.socket.send_to(&update.encode(), addr) {
+				log::warn!("failed to send to {}: {}", addr, err);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use nalgebra::Vector3;
+	use sim::{math::Aabb, world::edit::Shape};
+
+	fn update(first_edit: u64, edits: Vec<Edit>) -> ClientMessage {
+		let state = PlayerState { pos: Vector3::zeros(), yaw: 0.0, pitch: 0.0 };
+		ClientMessage::Update { ack: 0, first_edit, edits, state }
+	}
+
+	#[test]
+	fn rejoining_peers_keep_their_edit_count() {
+		let mut server = Server::bind("127.0.0.1:0").unwrap();
+		let addr = SocketAddr::from(([127, 0, 0, 1], 4000));
+		let edit = Edit::Subtract(Shape::Box(Aabb::new(Vector3::zeros(), Vector3::repeat(1.0))));
+		server.handle(addr, update(0, vec![edit, edit]));
+		assert_eq!(server.log.len(), 2);
+
+		// the peer timed out, and the client comes back with its third edit
+		server.peers.clear();
+		server.handle(addr, update(2, vec![edit]));
+		assert_eq!(server.log.len(), 3);
+		assert_eq!(server.peers[&addr].applied, 3);
+		// later packets still line up, and repeats are still ignored
+		server.handle(addr, update(2, vec![edit, edit]));
+		assert_eq!(server.log.len(), 4);
+	}
+}
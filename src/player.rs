@@ -0,0 +1,133 @@
+//! Walking on the terrain, as opposed to flying through it with `Camera::fly`.
+
+use crate::{
+	camera::Camera,
+	input::{Action, Input},
+};
+use nalgebra::{UnitQuaternion, Vector3};
+use sim::{
+	math::Ray,
+	world::{chunk_key, World},
+};
+
+/// Voxels per second squared.
+const GRAVITY: f32 = 30.0;
+const JUMP_SPEED: f32 = 10.0;
+const WALK_SPEED: f32 = 6.0;
+/// The collision sphere's radius. `World::sphere_sweep` needs it under one voxel.
+const RADIUS: f32 = 0.4;
+/// How far above the collision sphere's center the camera is.
+const EYE_HEIGHT: f32 = 1.5;
+/// Surfaces with normals closer to +Z than this count as ground.
+const MAX_SLOPE_COS: f32 = 0.7;
+/// Collisions per update before the remaining motion is dropped.
+const MAX_SLIDES: usize = 4;
+/// Space kept between the sphere and surfaces, so the next sweep doesn't start touching them.
+const SKIN: f32 = 0.01;
+/// How far the player walks on the ground between footsteps.
+const STRIDE: f32 = 1.8;
+
+pub struct Player {
+	/// The collision sphere's center.
+	pub pos: Vector3<f32>,
+	pub velocity: Vector3<f32>,
+	pub on_ground: bool,
+	/// How far the player has walked since the last footstep.
+	stride: f32,
+	footstep: bool,
+}
+impl Player {
+	pub fn new(pos: Vector3<f32>) -> Self {
+		Self { pos, velocity: Vector3::zeros(), on_ground: false, stride: 0.0, footstep: false }
+	}
+
+	/// Moves the player with gravity and input, sliding along any surfaces it hits, then moves the camera to its eyes.
+	pub fn update(&mut self, world: &World, input: &Input, camera: &mut Camera, delta: f32) {
+		camera.look(input, delta);
+
+		// wait for the ground to load instead of falling through it
+		if world.chunk(chunk_key(self.pos)).is_none() {
+			camera.pos = self.eye();
+			return;
+		}
+
+		let local = Vector3::new(
+			input.axis(Action::MoveLeft, Action::MoveRight),
+			input.axis(Action::MoveBack, Action::MoveForward),
+			0.0,
+		);
+		let walk = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), camera.yaw) * local;
+		let walk = walk.try_normalize(std::f32::EPSILON).unwrap_or_else(Vector3::zeros) * WALK_SPEED;
+		self.velocity.x = walk.x;
+		self.velocity.y = walk.y;
+
+		if self.on_ground && input.just_pressed(Action::Jump) {
+			self.velocity.z = JUMP_SPEED;
+		}
+		self.velocity.z -= GRAVITY * delta;
+
+		let start = self.pos;
+		self.depenetrate(world);
+		self.slide(world, self.velocity * delta);
+		camera.pos = self.eye();
+
+		if self.on_ground {
+			self.stride += (self.pos - start).xy().norm();
+			if self.stride >= STRIDE {
+				self.stride -= STRIDE;
+				self.footstep = true;
+			}
+		}
+	}
+
+	/// Returns whether a foot landed since the last call.
+	pub fn take_footstep(&mut self) -> bool {
+		std::mem::replace(&mut self.footstep, false)
+	}
+
+	/// The bottom of the collision sphere.
+	pub fn feet(&self) -> Vector3<f32> {
+		self.pos - Vector3::new(0.0, 0.0, RADIUS)
+	}
+
+	pub fn eye(&self) -> Vector3<f32> {
+		self.pos + Vector3::new(0.0, 0.0, EYE_HEIGHT)
+	}
+
+	/// Pushes the sphere out of any surface it ended up inside, like after an edit added a block on top of it.
+	fn depenetrate(&mut self, world: &World) {
+		let depth = RADIUS - world.sample(self.pos);
+		if depth > 0.0 {
+			self.pos += world.normal(self.pos) * (depth + SKIN);
+		}
+	}
+
+	fn slide(&mut self, world: &World, mut motion: Vector3<f32>) {
+		self.on_ground = false;
+		for _ in 0..MAX_SLIDES {
+			let length = motion.norm();
+			if length < std::f32::EPSILON {
+				return;
+			}
+
+			let ray = Ray::new(self.pos, motion);
+			let hit = match world.sphere_sweep(&ray, RADIUS + SKIN, length) {
+				Some(hit) => hit,
+				None => {
+					self.pos += motion;
+					return;
+				},
+			};
+
+			self.pos = hit.pos;
+			if hit.normal.z > MAX_SLOPE_COS {
+				self.on_ground = true;
+			}
+
+			// keep only the motion along the surface
+			let remaining = motion * (1.0 - hit.distance / length);
+			motion = remaining - hit.normal * remaining.dot(&hit.normal).min(0.0);
+			self.velocity -= hit.normal * self.velocity.dot(&hit.normal).min(0.0);
+		}
+	}
+}
@@ -0,0 +1,104 @@
+//! Recordings of every frame's input, which can be played back to reproduce a session for debugging or to capture the
+//! same frames again. Each frame's delta time is stored with its events, so the simulation sees exactly the same steps,
+//! and playback waits for each frame's original time so the GPU sees the same pacing.
+//!
+//! Replays are text: `frame <seconds>` starts each frame, followed by its events, one per line. Only input is recorded,
+//! so a replay only matches if it starts from the same world and settings, and chunks load about as fast.
+
+use crate::input::{Input, InputEvent};
+use sim::{fs, threads::JobHandle};
+use std::{
+	collections::VecDeque,
+	fmt::Write,
+	io,
+	path::PathBuf,
+	thread,
+	time::{Duration, Instant},
+};
+
+pub struct Recorder {
+	path: PathBuf,
+	text: String,
+}
+impl Recorder {
+	/// Nothing is written until `save`.
+	pub fn new(path: impl Into<PathBuf>) -> Self {
+		Self { path: path.into(), text: "# space-thing replay\n".to_string() }
+	}
+
+	/// Adds a frame that took `delta` seconds, with the events that happened during it.
+	pub fn record_frame(&mut self, delta: f32, events: &[InputEvent]) {
+		// f32's Display prints enough digits to parse back to the same value
+		writeln!(self.text, "frame {}", delta).unwrap();
+		for event in events {
+			if let Some(line) = event.to_line() {
+				writeln!(self.text, "{}", line).unwrap();
+			}
+		}
+	}
+
+	pub fn save(&self) -> JobHandle<io::Result<()>> {
+		fs::write(self.path.clone(), self.text.clone().into_bytes())
+	}
+}
+
+struct Frame {
+	delta: f32,
+	events: Vec<InputEvent>,
+}
+
+pub struct Playback {
+	frames: VecDeque<Frame>,
+	/// When the first frame was played, or `None` before then.
+	start: Option<Instant>,
+	/// The recorded time from the start to the last frame played.
+	elapsed: Duration,
+}
+impl Playback {
+	pub async fn load(path: impl Into<PathBuf>) -> io::Result<Self> {
+		Self::parse(&fs::read_to_string(path.into()).await?)
+	}
+
+	/// Unlike the config files, a bad line is an error, since skipping anything would make the replay diverge.
+	pub fn parse(text: &str) -> io::Result<Self> {
+		let mut frames = VecDeque::new();
+		for (i, line) in text.lines().enumerate() {
+			let line = line.split('#').next().unwrap().trim();
+			if line.is_empty() {
+				continue;
+			}
+
+			if let Some(delta) = line.strip_prefix("frame ") {
+				let delta = delta.trim().parse::<f32>().map_err(|_| invalid(i, "bad frame time"))?;
+				if !(delta >= 0.0 && delta.is_finite()) {
+					return Err(invalid(i, "bad frame time"));
+				}
+				frames.push_back(Frame { delta, events: vec![] });
+				continue;
+			}
+			let event = InputEvent::from_line(line).ok_or_else(|| invalid(i, "unknown event"))?;
+			frames.back_mut().ok_or_else(|| invalid(i, "event before the first frame"))?.events.push(event);
+		}
+		Ok(Self { frames, start: None, elapsed: Duration::from_secs(0) })
+	}
+
+	/// Waits until the next frame's original time, then feeds its events to `input` and returns its delta time. Returns
+	/// `None` once every frame has been played.
+	pub fn next_frame(&mut self, input: &mut Input) -> Option<f32> {
+		let frame = self.frames.pop_front()?;
+		let start = *self.start.get_or_insert_with(Instant::now);
+		self.elapsed += Duration::from_secs_f32(frame.delta);
+		if let Some(wait) = self.elapsed.checked_sub(start.elapsed()) {
+			thread::sleep(wait);
+		}
+
+		for event in frame.events {
+			input.apply(event);
+		}
+		Some(frame.delta)
+	}
+}
+
+fn invalid(line: usize, msg: &str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line + 1, msg))
+}
@@ -0,0 +1,107 @@
+//! Objects that aren't part of the voxel world. Entities are just ids, and each kind of component is stored in its
+//! own map keyed by them, so systems only visit the entities that have what they need.
+
+use crate::gfx::mesh::{Mesh, MeshInstance, Model};
+use nalgebra::{Matrix4, UnitQuaternion, Vector3, Vector4};
+use std::{collections::HashMap, sync::Arc};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Entity(u32);
+
+/// Where an entity is. Positions are in voxels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+	pub pos: Vector3<f32>,
+	pub rot: UnitQuaternion<f32>,
+	/// Uniform, so normals can be transformed by the same matrix.
+	pub scale: f32,
+}
+impl Transform {
+	pub fn new(pos: Vector3<f32>) -> Self {
+		Self { pos, rot: UnitQuaternion::identity(), scale: 1.0 }
+	}
+
+	/// Transforms model space into world space.
+	pub fn matrix(&self) -> Matrix4<f32> {
+		Matrix4::new_translation(&self.pos) * self.rot.to_homogeneous() * Matrix4::new_scaling(self.scale)
+	}
+}
+impl Default for Transform {
+	fn default() -> Self {
+		Self::new(Vector3::zeros())
+	}
+}
+
+/// Draws a model at the entity's transform.
+#[derive(Clone)]
+pub struct Renderable {
+	pub model: Arc<Model>,
+	/// Linear RGBA, multiplied with each mesh's material.
+	pub color: Vector4<f32>,
+}
+
+#[derive(Default)]
+pub struct Scene {
+	next: u32,
+	transforms: HashMap<Entity, Transform>,
+	renderables: HashMap<Entity, Renderable>,
+}
+impl Scene {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Creates an entity with no components.
+	pub fn spawn(&mut self) -> Entity {
+		let entity = Entity(self.next);
+		self.next += 1;
+		entity
+	}
+
+	/// Removes every component of `entity`.
+	pub fn despawn(&mut self, entity: Entity) {
+		self.transforms.remove(&entity);
+		self.renderables.remove(&entity);
+	}
+
+	pub fn transform(&self, entity: Entity) -> Option<&Transform> {
+		self.transforms.get(&entity)
+	}
+
+	pub fn transform_mut(&mut self, entity: Entity) -> Option<&mut Transform> {
+		self.transforms.get_mut(&entity)
+	}
+
+	pub fn set_transform(&mut self, entity: Entity, transform: Transform) {
+		self.transforms.insert(entity, transform);
+	}
+
+	pub fn renderable(&self, entity: Entity) -> Option<&Renderable> {
+		self.renderables.get(&entity)
+	}
+
+	pub fn set_renderable(&mut self, entity: Entity, renderable: Renderable) {
+		self.renderables.insert(entity, renderable);
+	}
+
+	pub fn remove_renderable(&mut self, entity: Entity) -> Option<Renderable> {
+		self.renderables.remove(&entity)
+	}
+
+	/// The instances of every entity with both a transform and a renderable, grouped by mesh so each mesh can be drawn
+	/// once.
+	pub fn mesh_instances(&self) -> Vec<(Arc<Mesh>, Vec<MeshInstance>)> {
+		let mut draws: HashMap<*const Mesh, (Arc<Mesh>, Vec<MeshInstance>)> = HashMap::new();
+		for (entity, renderable) in &self.renderables {
+			let transform = match self.transforms.get(entity) {
+				Some(transform) => transform,
+				None => continue,
+			};
+			let instance = MeshInstance { model: transform.matrix(), color: renderable.color };
+			for mesh in &renderable.model.meshes {
+				draws.entry(&**mesh as *const Mesh).or_insert_with(|| (mesh.clone(), vec![])).1.push(instance);
+			}
+		}
+		draws.into_iter().map(|(_, draw)| draw).collect()
+	}
+}
@@ -0,0 +1,244 @@
+//! Options the player can change, stored in `settings.cfg` next to the keybindings.
+//!
+//! The file uses the same line-based config syntax as `keybindings.cfg` and `textures.cfg`, which isn't TOML. Each
+//! line is `key = value`, and `#` starts a comment. Numbers and `true`/`false` are written as they are, names like
+//! `mode = Windowed` are bare words, and only free text like a monitor name is quoted. There are no tables, lists,
+//! or escapes.
+
+use crate::gfx::window::{DisplaySettings, PresentModePreference, RenderSettings, WindowMode};
+use sim::{fs, threads::JobHandle};
+use std::{io, str::FromStr};
+
+/// Where the settings are stored, relative to the working directory.
+pub const SETTINGS_PATH: &str = "settings.cfg";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+	pub display: DisplaySettings,
+	pub render: RenderSettings,
+	/// Vertical field of view, in degrees.
+	pub fov: f32,
+	/// Radians turned per pixel of mouse motion.
+	pub mouse_sensitivity: f32,
+	/// Radians turned per second with a gamepad's look stick pushed all the way.
+	pub gamepad_sensitivity: f32,
+	/// How far a gamepad stick has to move from the center before it counts, from 0 to 1.
+	pub gamepad_dead_zone: f32,
+	/// How many chunks are loaded in each direction around the camera.
+	pub render_distance: i32,
+	/// Multiplies the volume of every sound, from 0 to 1.
+	pub volume: f32,
+	/// Forces a GPU by its index, or `None` to choose one automatically.
+	pub adapter: Option<usize>,
+	/// Turns the Vulkan validation layers on or off, or `None` to only use them in debug builds.
+	pub validation: Option<bool>,
+}
+impl Settings {
+	/// Reads the settings from `SETTINGS_PATH`. If there's no file yet, the defaults are written there so the player
+	/// has something to edit.
+	pub async fn load() -> Self {
+		match fs::read_to_string(SETTINGS_PATH).await {
+			Ok(config) => Self::parse(&config),
+			Err(err) => {
+				if err.kind() != io::ErrorKind::NotFound {
+					log::warn!("failed to read {}: {}", SETTINGS_PATH, err);
+				}
+				let settings = Self::default();
+				// not awaited; the file is only needed by the next run
+				settings.save().forget();
+				settings
+			},
+		}
+	}
+
+	/// Writes the settings to `SETTINGS_PATH`. Call it whenever a setting changes at runtime.
+	pub fn save(&self) -> JobHandle<io::Result<()>> {
+		fs::write(SETTINGS_PATH, self.to_config().into_bytes())
+	}
+
+	/// Parses a settings file in the syntax described in the module docs. Settings the file doesn't mention keep their
+	/// defaults, and bad lines are logged and skipped.
+	pub fn parse(config: &str) -> Self {
+		let mut settings = Self::default();
+		for (i, line) in config.lines().enumerate() {
+			let line = line.split('#').next().unwrap().trim();
+			if line.is_empty() {
+				continue;
+			}
+
+			let mut parts = line.splitn(2, '=');
+			let (key, value) = match (parts.next(), parts.next()) {
+				(Some(key), Some(value)) => (key.trim(), value.trim()),
+				_ => {
+					log::warn!("{}:{}: expected `key = value`", SETTINGS_PATH, i + 1);
+					continue;
+				},
+			};
+			if let Err(err) = settings.set(key, value) {
+				log::warn!("{}:{}: {}", SETTINGS_PATH, i + 1, err);
+			}
+		}
+		settings
+	}
+
+	/// Formats the settings the way `parse` reads them.
+	pub fn to_config(&self) -> String {
+		let mut config = String::new();
+		config.push_str(&format!("mode = {}\n", mode_name(self.display.mode)));
+		match &self.display.monitor {
+			Some(monitor) => config.push_str(&format!("monitor = \"{}\"\n", monitor)),
+			None => config.push_str("# monitor = \"name\"\n"),
+		}
+		config.push_str(&format!("width = {}\n", self.display.resolution.0));
+		config.push_str(&format!("height = {}\n", self.display.resolution.1));
+		config.push_str(&format!("present_mode = {}\n", present_mode_name(self.render.present_mode)));
+		config.push_str(&format!("msaa = {}\n", self.render.msaa));
+		config.push_str(&format!("anisotropy = {}\n", self.render.anisotropy));
+		config.push_str(&format!("frames_in_flight = {}\n", self.render.frames_in_flight));
+		config.push_str(&format!("render_scale = {}\n", self.render.render_scale));
+		config.push_str(&format!("temporal = {}\n", self.render.temporal));
+		config.push_str(&format!("fov = {}\n", self.fov));
+		config.push_str(&format!("mouse_sensitivity = {}\n", self.mouse_sensitivity));
+		config.push_str(&format!("gamepad_sensitivity = {}\n", self.gamepad_sensitivity));
+		config.push_str(&format!("gamepad_dead_zone = {}\n", self.gamepad_dead_zone));
+		config.push_str(&format!("render_distance = {}\n", self.render_distance));
+		config.push_str(&format!("volume = {}\n", self.volume));
+		match self.adapter {
+			Some(adapter) => config.push_str(&format!("adapter = {}\n", adapter)),
+			None => config.push_str("# adapter = 0\n"),
+		}
+		match self.validation {
+			Some(validation) => config.push_str(&format!("validation = {}\n", validation)),
+			None => config.push_str("# validation = true\n"),
+		}
+		config
+	}
+
+	fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+		match key {
+			"mode" => self.display.mode = mode_from_name(value).ok_or_else(|| format!("unknown mode {}", value))?,
+			"monitor" => self.display.monitor = Some(parse_string(value)?),
+			"width" => self.display.resolution.0 = parse(value)?,
+			"height" => self.display.resolution.1 = parse(value)?,
+			"present_mode" => {
+				self.render.present_mode =
+					present_mode_from_name(value).ok_or_else(|| format!("unknown present mode {}", value))?
+			},
+			"msaa" => self.render.msaa = parse(value)?,
+			"anisotropy" => self.render.anisotropy = parse(value)?,
+			"frames_in_flight" => self.render.frames_in_flight = parse(value)?,
+			"render_scale" => self.render.render_scale = parse(value)?,
+			"temporal" => self.render.temporal = parse(value)?,
+			"fov" => self.fov = parse(value)?,
+			"mouse_sensitivity" => self.mouse_sensitivity = parse(value)?,
+			"gamepad_sensitivity" => self.gamepad_sensitivity = parse(value)?,
+			"gamepad_dead_zone" => self.gamepad_dead_zone = parse(value)?,
+			"render_distance" => self.render_distance = parse(value)?,
+			"volume" => self.volume = parse(value)?,
+			"adapter" => self.adapter = Some(parse(value)?),
+			"validation" => self.validation = Some(parse(value)?),
+			_ => return Err(format!("unknown setting {}", key)),
+		}
+		Ok(())
+	}
+}
+impl Default for Settings {
+	fn default() -> Self {
+		Self {
+			display: DisplaySettings::default(),
+			render: RenderSettings::default(),
+			fov: 90.0,
+			mouse_sensitivity: 0.002,
+			gamepad_sensitivity: 3.0,
+			gamepad_dead_zone: 0.15,
+			render_distance: 3,
+			volume: 1.0,
+			adapter: None,
+			validation: None,
+		}
+	}
+}
+
+fn mode_name(mode: WindowMode) -> &'static str {
+	match mode {
+		WindowMode::Windowed => "Windowed",
+		WindowMode::Borderless => "Borderless",
+		WindowMode::Exclusive => "Exclusive",
+	}
+}
+
+fn mode_from_name(name: &str) -> Option<WindowMode> {
+	match name {
+		"Windowed" => Some(WindowMode::Windowed),
+		"Borderless" => Some(WindowMode::Borderless),
+		"Exclusive" => Some(WindowMode::Exclusive),
+		_ => None,
+	}
+}
+
+fn present_mode_name(mode: PresentModePreference) -> &'static str {
+	match mode {
+		PresentModePreference::Immediate => "Immediate",
+		PresentModePreference::Vsync => "Vsync",
+		PresentModePreference::LowLatency => "LowLatency",
+	}
+}
+
+fn present_mode_from_name(name: &str) -> Option<PresentModePreference> {
+	match name {
+		"Immediate" => Some(PresentModePreference::Immediate),
+		"Vsync" => Some(PresentModePreference::Vsync),
+		"LowLatency" => Some(PresentModePreference::LowLatency),
+		_ => None,
+	}
+}
+
+fn parse<T: FromStr>(value: &str) -> Result<T, String> {
+	value.parse().map_err(|_| format!("invalid value {}", value))
+}
+
+/// Strips the quotes from a string value. Escapes aren't supported, since monitor names don't need them.
+fn parse_string(value: &str) -> Result<String, String> {
+	if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+		Ok(value[1..value.len() - 1].to_string())
+	} else {
+		Err(format!("expected a quoted string, found {}", value))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn defaults_round_trip() {
+		assert_eq!(Settings::parse(&Settings::default().to_config()), Settings::default());
+	}
+
+	#[test]
+	fn changes_round_trip() {
+		let mut settings = Settings::default();
+		settings.display.mode = WindowMode::Exclusive;
+		settings.display.monitor = Some("DELL U2415".to_string());
+		settings.render.present_mode = PresentModePreference::Vsync;
+		settings.render.temporal = false;
+		settings.fov = 100.0;
+		settings.adapter = Some(1);
+		settings.validation = Some(false);
+		assert_eq!(Settings::parse(&settings.to_config()), settings);
+	}
+
+	#[test]
+	fn comments_and_whitespace() {
+		let settings = Settings::parse("# a comment\n\n  fov=75   # the rest is ignored\nwidth = 800\n");
+		assert_eq!(settings.fov, 75.0);
+		assert_eq!(settings.display.resolution, (800, DisplaySettings::default().resolution.1));
+	}
+
+	#[test]
+	fn bad_lines_keep_defaults() {
+		let settings =
+			Settings::parse("fov = wide\nnonsense\nshininess = 3\nmode = Tiled\nmonitor = unquoted\nvolume = 0.5\n");
+		assert_eq!(settings, Settings { volume: 0.5, ..Settings::default() });
+	}
+}
@@ -0,0 +1,36 @@
+//! A clock that runs the simulation in fixed steps, however long frames take, so movement is the same at any frame rate
+//! and physics can count on the step it's given.
+
+/// How many ticks a frame can run before the clock stops catching up. After a long hitch, the simulation falls behind
+/// instead of spending so long on ticks that the next frame is slow too.
+const MAX_TICKS: u32 = 8;
+
+pub struct FixedTimestep {
+	/// Seconds per tick.
+	tick: f32,
+	/// Time that has passed but isn't a whole tick yet.
+	accumulator: f32,
+}
+impl FixedTimestep {
+	pub fn new(tick: f32) -> Self {
+		Self { tick, accumulator: 0.0 }
+	}
+
+	pub fn tick(&self) -> f32 {
+		self.tick
+	}
+
+	/// Adds a frame's worth of time, and returns how many ticks to run for it.
+	pub fn advance(&mut self, delta: f32) -> u32 {
+		self.accumulator += delta;
+		let ticks = (self.accumulator / self.tick) as u32;
+		self.accumulator -= ticks as f32 * self.tick;
+		ticks.min(MAX_TICKS)
+	}
+
+	/// How far it is from the last tick to the next, from 0 to 1. Drawing the state this far from the one before the
+	/// last tick to the one after it hides the steps.
+	pub fn alpha(&self) -> f32 {
+		(self.accumulator / self.tick).min(1.0)
+	}
+}
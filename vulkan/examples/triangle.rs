@@ -0,0 +1,226 @@
+//! Draws a colored triangle in a window, with everything from creating the instance to presenting.
+
+use ash::vk;
+use memoffset::offset_of;
+use shaderc::{Compiler, ShaderKind};
+use std::{ffi::CString, iter::once, sync::Arc};
+use typenum::B1;
+use vulkan::{
+	command::InheritanceInfo,
+	device::BufferUsageFlags,
+	image::{Format, Framebuffer},
+	instance::{Instance, Version},
+	pipeline::{VertexDesc, Viewport},
+	render_pass::RenderPass,
+	surface::{ColorSpace, PresentMode, SurfaceFormat},
+	swapchain::{SwapchainImage, SwapchainManager},
+	sync::Fence,
+	Extent2D, Rect2D, Vulkan,
+};
+use winit::{
+	event::{Event, WindowEvent},
+	event_loop::{ControlFlow, EventLoop},
+	window::{Window, WindowBuilder},
+};
+
+const VERT: &str = r"
+#version 450
+
+layout(location = 0) in vec2 pos;
+layout(location = 1) in vec3 color;
+
+layout(location = 0) out vec3 out_color;
+
+void main() {
+	gl_Position = vec4(pos, 0.0, 1.0);
+	out_color = color;
+}
+";
+
+const FRAG: &str = r"
+#version 450
+
+layout(location = 0) in vec3 color;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+	out_color = vec4(color, 1.0);
+}
+";
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct Vertex {
+	pos: [f32; 2],
+	color: [f32; 3],
+}
+impl VertexDesc for Vertex {
+	fn attribute_descs() -> Vec<vk::VertexInputAttributeDescription> {
+		let attribute = |location, format, offset| {
+			vk::VertexInputAttributeDescription::builder()
+				.binding(0)
+				.location(location)
+				.format(format)
+				.offset(offset as _)
+				.build()
+		};
+		vec![
+			attribute(0, vk::Format::R32G32_SFLOAT, offset_of!(Self, pos)),
+			attribute(1, vk::Format::R32G32B32_SFLOAT, offset_of!(Self, color)),
+		]
+	}
+}
+
+fn main() {
+	let event_loop = EventLoop::new();
+	let window = WindowBuilder::new().with_title("triangle").build(&event_loop).unwrap();
+
+	let vulkan = Vulkan::new().unwrap();
+	let name = CString::new("triangle").unwrap();
+	let instance = Instance::new(vulkan, &name, Version::new(0, 1, 0), cfg!(debug_assertions));
+	let surface = instance.create_surface(window).unwrap();
+
+	let (device, queue) = {
+		// the first device with a queue family that can both draw and present to the window
+		let (physical, family) = instance
+			.enumerate_physical_devices()
+			.find_map(|physical| {
+				let family = physical
+					.get_queue_family_properties()
+					.filter(|props| props.queue_flags().graphics())
+					.map(|props| props.family())
+					.find(|&family| physical.get_surface_support(family, &surface))?;
+				Some((physical, family))
+			})
+			.expect("no device can draw to the window");
+		let (device, mut queues) = physical.create_device(once((family, &[1.0][..]))).unwrap();
+		(device, queues.next().unwrap())
+	};
+
+	let formats = [SurfaceFormat { format: Format::B8G8R8A8_UNORM, color_space: ColorSpace::SRGB_NONLINEAR }];
+	let mut swapchain = SwapchainManager::new(
+		device.clone(),
+		surface,
+		&formats,
+		vk::ImageUsageFlags::COLOR_ATTACHMENT,
+		&[PresentMode::MAILBOX],
+		window_extent,
+	)
+	.expect("the window can't be drawn to");
+	let format = swapchain.format().format;
+
+	// the swapchain images are undefined when they're acquired, and cleared before anything is drawn
+	let render_pass =
+		device.build_render_pass().single_color(format, vk::ImageLayout::PRESENT_SRC_KHR).build().unwrap();
+	let mut framebuffers = create_framebuffers(&render_pass, swapchain.images(), format, swapchain.extent());
+
+	let compile = |source, kind, name| {
+		let spirv = Compiler::new().unwrap().compile_into_spirv(source, kind, name, "main", None).unwrap();
+		unsafe { device.create_shader_module(spirv.as_binary()) }
+	};
+	let vert = compile(VERT, ShaderKind::Vertex, "triangle.vert");
+	let frag = compile(FRAG, ShaderKind::Fragment, "triangle.frag");
+	let layout = device.create_pipeline_layout(vec![], &[]);
+	let pipeline = device
+		.build_pipeline(layout, render_pass.clone())
+		.vertex_shader(vert)
+		.fragment_shader(frag)
+		.vertex_input::<Vertex>()
+		.dynamic_viewport()
+		.build();
+
+	// clockwise on screen, since Vulkan's Y points down
+	let verts = [
+		Vertex { pos: [0.0, -0.5], color: [1.0, 0.0, 0.0] },
+		Vertex { pos: [0.5, 0.5], color: [0.0, 1.0, 0.0] },
+		Vertex { pos: [-0.5, 0.5], color: [0.0, 0.0, 1.0] },
+	];
+	let vertex_buffer =
+		device.create_buffer_slice(verts.len(), B1, BufferUsageFlags::VERTEX_BUFFER).copy_from_slice(&verts);
+
+	let cmdpool = device.create_command_pool(queue.family(), true);
+	// one frame in flight, which keeps the example simple
+	let mut last_frame: Option<Fence> = None;
+
+	event_loop.run(move |event, _, control| {
+		*control = ControlFlow::Poll;
+		match event {
+			Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => *control = ControlFlow::Exit,
+			Event::EventsCleared => {
+				let acquired = swapchain.acquire(!0, |images, extent| {
+					framebuffers = create_framebuffers(&render_pass, images, format, extent);
+				});
+				let (image_idx, future) = match acquired {
+					Some(acquired) => acquired,
+					// minimized
+					None => return,
+				};
+
+				if let Some(fence) = last_frame.take() {
+					fence.wait();
+				}
+				cmdpool.reset_all(false);
+
+				let extent = swapchain.extent();
+				let framebuffer = framebuffers[image_idx as usize].clone();
+				let area = Rect2D::builder().extent(extent).build();
+				let inherit = InheritanceInfo {
+					render_pass: render_pass.clone(),
+					subpass: 0,
+					framebuffer: Some(framebuffer.clone()),
+				};
+				let viewport =
+					Viewport::builder().width(extent.width as _).height(extent.height as _).max_depth(1.0).build();
+				let draw = cmdpool
+					.record_secondary(true, false, Some(inherit))
+					.bind_pipeline(pipeline.clone())
+					.set_viewport(0, &[viewport])
+					.set_scissor(0, &[area])
+					.bind_vertex_buffers(0, once(vertex_buffer.clone() as _), &[0])
+					.draw(3, 1, 0, 0)
+					.build();
+
+				let clear = [vk::ClearValue { color: vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 1.0] } }];
+				let cmd = cmdpool
+					.record(true, false)
+					.begin_render_pass(render_pass.clone(), framebuffer, area, &clear)
+					.execute_commands(once(draw))
+					.end_render_pass()
+					.build();
+				// presenting only waits on the semaphore, so the stage doesn't matter
+				let (fence, future) = queue.submit_after(future, cmd).flush(vk::PipelineStageFlags::BOTTOM_OF_PIPE);
+				last_frame = Some(fence);
+				swapchain.present(future, queue.clone(), image_idx);
+			},
+			Event::LoopDestroyed => device.wait_idle(),
+			_ => (),
+		}
+	});
+}
+
+fn window_extent(window: &Window) -> Extent2D {
+	let (width, height) = window.inner_size().to_physical(window.hidpi_factor()).into();
+	Extent2D { width, height }
+}
+
+fn create_framebuffers(
+	render_pass: &Arc<RenderPass>,
+	images: &[Arc<SwapchainImage<Window>>],
+	format: Format,
+	extent: Extent2D,
+) -> Vec<Arc<Framebuffer>> {
+	let range = vk::ImageSubresourceRange::builder()
+		.aspect_mask(vk::ImageAspectFlags::COLOR)
+		.level_count(1)
+		.layer_count(1)
+		.build();
+	images
+		.iter()
+		.map(|image| {
+			let device = render_pass.device();
+			let view = device.create_image_view(image.clone(), vk::ImageViewType::TYPE_2D, format, range);
+			device.create_framebuffer(render_pass.clone(), vec![view], extent)
+		})
+		.collect()
+}
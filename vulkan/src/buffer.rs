@@ -1,10 +1,11 @@
 use crate::{
-	command::CommandPool,
-	device::{Device, Queue, SubmitFuture},
+	command::{BufferBarrier, CommandPool},
+	device::{BufferUsageFlags, Device, Queue, SubmitFuture},
+	sync::Fence,
 };
 use ash::{version::DeviceV1_0, vk};
 use std::{marker::PhantomData, mem::size_of, slice, sync::Arc};
-use typenum::B1;
+use typenum::{B0, B1};
 use vk_mem::Allocation;
 
 pub struct Buffer<T: ?Sized> {
@@ -23,6 +24,67 @@ impl<T: ?Sized> Buffer<T> {
 		Arc::new(Self { device, vk, alloc, size, phantom: PhantomData })
 	}
 }
+impl<T: Copy + 'static> Buffer<[T]> {
+	/// Overwrites the buffer, which must be in host visible memory and exactly as long as `data`.
+	///
+	/// The GPU must not be using the buffer, so wait for any command buffer that reads it first.
+	pub unsafe fn write(&self, data: &[T]) {
+		assert_eq!(data.len() as u64 * size_of::<T>() as u64, self.size);
+		self.write_at(0, data);
+	}
+
+	/// Overwrites the elements starting at index `offset`, which must be in host visible memory.
+	///
+	/// The GPU must not be using that part of the buffer. It may use the rest.
+	pub unsafe fn write_at(&self, offset: usize, data: &[T]) {
+		self.write_with(offset, data.len(), |bufdata| bufdata.copy_from_slice(data));
+	}
+
+	/// Maps the `len` elements starting at index `offset` and lets `f` fill them in, for data that's produced in place
+	/// instead of copied from somewhere else. The memory's old contents are undefined, so `f` should write all of it.
+	///
+	/// The same rules apply as for `write_at`.
+	pub unsafe fn write_with(&self, offset: usize, len: usize, f: impl FnOnce(&mut [T])) {
+		assert!((offset + len) as u64 * size_of::<T>() as u64 <= self.size);
+		let allocator = &self.device.allocator;
+		let bufdata = allocator.map_memory(&self.alloc).unwrap();
+		f(slice::from_raw_parts_mut((bufdata as *mut T).add(offset), len));
+		allocator.unmap_memory(&self.alloc).unwrap();
+	}
+
+	/// Copies the buffer's contents out. It must be in host visible memory.
+	///
+	/// The GPU must not be writing the buffer, so wait for any command buffer that does first.
+	pub unsafe fn read(&self) -> Vec<T> {
+		let allocator = &self.device.allocator;
+		let bufdata = allocator.map_memory(&self.alloc).unwrap();
+		let data = slice::from_raw_parts(bufdata as *const T, (self.size / size_of::<T>() as u64) as _).to_vec();
+		allocator.unmap_memory(&self.alloc).unwrap();
+		data
+	}
+
+	/// Copies the buffer into host visible memory on `queue`, after everything submitted to it before. The buffer
+	/// needs `TRANSFER_SRC` usage, and `pool` must be for `queue`'s family.
+	pub fn read_to_vec(self: &Arc<Self>, queue: &Arc<Queue>, pool: &Arc<CommandPool>) -> Readback<T> {
+		let len = (self.size / size_of::<T>() as u64) as usize;
+		let staging = self.device.create_buffer_slice(len, B1, BufferUsageFlags::TRANSFER_DST).uninitialized();
+		self.device.set_object_name(staging.vk, "readback");
+
+		// earlier submissions may still be writing it
+		let src = BufferBarrier::new(self.clone(), vk::AccessFlags::MEMORY_WRITE, vk::AccessFlags::TRANSFER_READ);
+		let copied = BufferBarrier::new(staging.clone(), vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::HOST_READ);
+		let cmd = pool
+			.record(true, false)
+			.pipeline_barrier(vk::PipelineStageFlags::ALL_COMMANDS, vk::PipelineStageFlags::TRANSFER, vec![], vec![src])
+			.copy_buffer(self.clone(), staging.clone())
+			.pipeline_barrier(vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::HOST, vec![], vec![copied])
+			.build();
+		Readback::new(queue.submit(cmd).end(), staging)
+	}
+}
+// `T` only describes the contents of GPU memory, so it doesn't affect which threads can use the buffer
+unsafe impl<T: ?Sized> Send for Buffer<T> {}
+unsafe impl<T: ?Sized> Sync for Buffer<T> {}
 impl<T: ?Sized> Drop for Buffer<T> {
 	fn drop(&mut self) {
 		unsafe { self.device.vk.destroy_buffer(self.vk, None) };
@@ -33,6 +95,14 @@ impl<T: ?Sized> BufferAbstract for Buffer<T> {
 	fn vk(&self) -> vk::Buffer {
 		self.vk
 	}
+
+	fn size(&self) -> u64 {
+		self.size
+	}
+
+	fn device(&self) -> &Arc<Device> {
+		&self.device
+	}
 }
 
 pub struct BufferInit<T: ?Sized, CPU> {
@@ -56,21 +126,67 @@ impl<T: 'static, CPU> BufferInit<[T], CPU> {
 		(self.buf, future)
 	}
 }
+impl<T: ?Sized> BufferInit<T, B0> {
+	/// Skips initialization, for buffers the GPU writes before it reads them.
+	pub fn uninitialized(self) -> Arc<Buffer<T>> {
+		self.buf
+	}
+}
 impl<T: Copy + 'static> BufferInit<[T], B1> {
+	/// Skips initialization, for buffers that are filled in with `Buffer::write_at` before the GPU reads them.
+	pub fn uninitialized(self) -> Arc<Buffer<[T]>> {
+		self.buf
+	}
+
 	pub fn copy_from_slice(self, data: &[T]) -> Arc<Buffer<[T]>> {
-		let buf = self.buf;
-		let allocator = &buf.device.allocator;
-		let alloc = &buf.alloc;
+		// nothing has used the buffer yet
+		unsafe { self.buf.write(data) };
+		self.buf
+	}
+}
 
-		let bufdata = allocator.map_memory(&alloc).unwrap();
-		let bufdata = unsafe { slice::from_raw_parts_mut(bufdata as *mut T, (buf.size / size_of::<T>() as u64) as _) };
-		bufdata.copy_from_slice(data);
-		allocator.unmap_memory(&alloc).unwrap();
+/// A copy from GPU memory that's been submitted. The data can be taken once it's finished.
+pub struct Readback<T> {
+	fence: Fence,
+	staging: Arc<Buffer<[T]>>,
+}
+impl<T: Copy + 'static> Readback<T> {
+	pub(crate) fn new(fence: Fence, staging: Arc<Buffer<[T]>>) -> Self {
+		Self { fence, staging }
+	}
 
-		buf
+	/// Whether the copy has finished, so `wait` won't block.
+	pub fn is_ready(&self) -> bool {
+		self.fence.is_signalled()
+	}
+
+	/// Blocks until the copy has finished, and returns the data.
+	pub fn wait(self) -> Vec<T> {
+		self.fence.wait();
+		// the fence means the copy is done, and nothing else uses the staging buffer
+		unsafe { self.staging.read() }
 	}
 }
 
-pub trait BufferAbstract {
+/// `Send + Sync` so command buffers that hold buffers can be recorded on other threads.
+pub trait BufferAbstract: Send + Sync {
 	fn vk(&self) -> vk::Buffer;
+	/// In bytes.
+	fn size(&self) -> u64;
+	fn device(&self) -> &Arc<Device>;
+}
+
+/// A buffer of vertices or instances. `CommandBufferBuilder::bind_vertex_buffer` only takes element types that
+/// describe their own layout, which keeps raw bytes from being bound by accident.
+pub type VertexBuffer<T> = Buffer<[T]>;
+
+/// A type that indices can be stored as.
+pub trait Index: Copy + Send + Sync + 'static {
+	const TYPE: vk::IndexType;
+}
+impl Index for u16 {
+	const TYPE: vk::IndexType = vk::IndexType::UINT16;
+}
+impl Index for u32 {
+	const TYPE: vk::IndexType = vk::IndexType::UINT32;
 }
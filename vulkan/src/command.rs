@@ -1,20 +1,24 @@
 pub use ash::vk::ClearValue;
 
 use crate::{
-	buffer::{Buffer, BufferAbstract},
+	buffer::{Buffer, BufferAbstract, Index, VertexBuffer},
+	descriptor::DescriptorSet,
 	device::Device,
-	image::Framebuffer,
-	pipeline::Pipeline,
+	image::{Framebuffer, ImageAbstract, QueueTransfer},
+	pipeline::{ComputePipeline, Pipeline, PipelineLayout, VertexDesc, Viewport},
+	query::QueryPool,
 	render_pass::RenderPass,
 	sync::Resource,
 	Rect2D,
 };
 use ash::{version::DeviceV1_0, vk};
 use std::{
-	cell::{RefCell, RefMut},
 	collections::HashMap,
+	ffi::CString,
 	marker::PhantomData,
-	sync::{Arc, Mutex},
+	mem::size_of,
+	slice,
+	sync::{Arc, Mutex, MutexGuard},
 };
 use thread_local::ThreadLocal;
 use typenum::{Bit, B0, B1};
@@ -23,7 +27,9 @@ pub struct CommandPool {
 	device: Arc<Device>,
 	pub(crate) queue_family: u32,
 	transient: bool,
-	pools: ThreadLocal<RefCell<CommandPoolInner>>,
+	pools: ThreadLocal<Arc<Mutex<CommandPoolInner>>>,
+	/// Every thread's pool, so they can all be reset from one thread.
+	all: Mutex<Vec<Arc<Mutex<CommandPoolInner>>>>,
 	free: Mutex<HashMap<vk::CommandPool, CmdCollection>>,
 }
 impl CommandPool {
@@ -52,8 +58,20 @@ impl CommandPool {
 	///
 	/// Panics if any command buffers have been built from the thread-local pool but not dropped.
 	pub fn reset(&self, release: bool) {
-		let mut pool = self.get_pool();
+		self.reset_inner(&mut self.get_pool(), release);
+	}
 
+	/// Resets every thread's pool, for when command buffers were recorded on other threads. No thread may be recording
+	/// from this `CommandPool` while it runs.
+	///
+	/// Panics if any command buffers have been built from any of the pools but not dropped.
+	pub fn reset_all(&self, release: bool) {
+		for pool in self.all.lock().unwrap().iter() {
+			self.reset_inner(&mut pool.lock().unwrap(), release);
+		}
+	}
+
+	fn reset_inner(&self, pool: &mut CommandPoolInner, release: bool) {
 		let (free_primary, free_secondary) = {
 			let free_lock = self.free.lock().unwrap();
 			let free = free_lock.get(&pool.vk).unwrap();
@@ -73,7 +91,14 @@ impl CommandPool {
 	}
 
 	pub(crate) unsafe fn from_vk(device: Arc<Device>, queue_family: u32, transient: bool) -> Arc<Self> {
-		Arc::new(Self { device, queue_family, transient, pools: ThreadLocal::new(), free: Mutex::default() })
+		Arc::new(Self {
+			device,
+			queue_family,
+			transient,
+			pools: ThreadLocal::new(),
+			all: Mutex::default(),
+			free: Mutex::default(),
+		})
 	}
 
 	unsafe fn begin(
@@ -133,7 +158,7 @@ impl CommandPool {
 		if secondary { pool.cmds.secondary.pop() } else { pool.cmds.primary.pop() }.unwrap()
 	}
 
-	fn get_pool(&self) -> RefMut<CommandPoolInner> {
+	fn get_pool(&self) -> MutexGuard<CommandPoolInner> {
 		self.pools
 			.get_or(|| {
 				let mut flags = vk::CommandPoolCreateFlags::empty();
@@ -146,15 +171,19 @@ impl CommandPool {
 
 				self.free.lock().unwrap().insert(vk, CmdCollection::new());
 
-				RefCell::new(CommandPoolInner { vk, cmds: CmdCollection::new(), primary_size: 0, secondary_size: 0 })
+				let pool = CommandPoolInner { vk, cmds: CmdCollection::new(), primary_size: 0, secondary_size: 0 };
+				let pool = Arc::new(Mutex::new(pool));
+				self.all.lock().unwrap().push(pool.clone());
+				pool
 			})
-			.borrow_mut()
+			.lock()
+			.unwrap()
 	}
 }
 impl Drop for CommandPool {
 	fn drop(&mut self) {
-		for pool in self.pools.iter_mut() {
-			unsafe { self.device.vk.destroy_command_pool(pool.get_mut().vk, None) };
+		for pool in self.all.get_mut().unwrap() {
+			unsafe { self.device.vk.destroy_command_pool(pool.lock().unwrap().vk, None) };
 		}
 	}
 }
@@ -207,6 +236,17 @@ impl<SEC: Bit> CommandBufferBuilder<SEC> {
 		}
 	}
 
+	/// Starts a named region that shows up in captures from tools like RenderDoc. It must be ended in the same command
+	/// buffer. Does nothing unless the instance was created with validation.
+	pub fn begin_label(self, name: &str) -> Self {
+		if let Some(debug_utils) = self.pool.device.debug_utils() {
+			let name = CString::new(name).unwrap();
+			let label = vk::DebugUtilsLabelEXT::builder().label_name(&name);
+			unsafe { debug_utils.cmd_begin_debug_utils_label(self.vk, &label) };
+		}
+		self
+	}
+
 	pub fn begin_render_pass(
 		mut self,
 		render_pass: Arc<RenderPass>,
@@ -235,12 +275,77 @@ impl<SEC: Bit> CommandBufferBuilder<SEC> {
 		}
 	}
 
+	pub fn bind_descriptor_sets(
+		self,
+		layout: Arc<PipelineLayout>,
+		first_set: u32,
+		descriptor_sets: impl IntoIterator<Item = Arc<DescriptorSet>>,
+	) -> Self {
+		self.bind_sets(vk::PipelineBindPoint::GRAPHICS, layout, first_set, descriptor_sets)
+	}
+
+	/// Like `bind_descriptor_sets`, but for the bound compute pipeline, which has its own sets.
+	pub fn bind_compute_descriptor_sets(
+		self,
+		layout: Arc<PipelineLayout>,
+		first_set: u32,
+		descriptor_sets: impl IntoIterator<Item = Arc<DescriptorSet>>,
+	) -> Self {
+		self.bind_sets(vk::PipelineBindPoint::COMPUTE, layout, first_set, descriptor_sets)
+	}
+
+	fn bind_sets(
+		mut self,
+		bind_point: vk::PipelineBindPoint,
+		layout: Arc<PipelineLayout>,
+		first_set: u32,
+		descriptor_sets: impl IntoIterator<Item = Arc<DescriptorSet>>,
+	) -> Self {
+		let mut set_vks = vec![];
+		for set in descriptor_sets {
+			set_vks.push(set.vk);
+			self.resources.push(Resource::DescriptorSet(set));
+		}
+		unsafe {
+			self.pool.device.vk.cmd_bind_descriptor_sets(self.vk, bind_point, layout.vk, first_set, &set_vks, &[])
+		};
+		self.resources.push(Resource::PipelineLayout(layout));
+		self
+	}
+
 	pub fn bind_pipeline(mut self, pipeline: Arc<Pipeline>) -> Self {
 		unsafe { self.pool.device.vk.cmd_bind_pipeline(self.vk, vk::PipelineBindPoint::GRAPHICS, pipeline.vk) };
 		self.resources.push(Resource::Pipeline(pipeline));
 		self
 	}
 
+	pub fn bind_compute_pipeline(mut self, pipeline: Arc<ComputePipeline>) -> Self {
+		unsafe { self.pool.device.vk.cmd_bind_pipeline(self.vk, vk::PipelineBindPoint::COMPUTE, pipeline.vk) };
+		self.resources.push(Resource::ComputePipeline(pipeline));
+		self
+	}
+
+	/// Binds `buffer` to `binding`, starting at element `first`.
+	pub fn bind_vertex_buffer<T: VertexDesc + Send + Sync + 'static>(
+		mut self,
+		binding: u32,
+		buffer: Arc<VertexBuffer<T>>,
+		first: u64,
+	) -> Self {
+		let offset = first * size_of::<T>() as u64;
+		unsafe { self.pool.device.vk.cmd_bind_vertex_buffers(self.vk, binding, &[buffer.vk], &[offset]) };
+		self.resources.push(Resource::Buffer(buffer));
+		self
+	}
+
+	/// Binds `buffer` for `draw_indexed`, starting at element `first`.
+	pub fn bind_index_buffer<I: Index>(mut self, buffer: Arc<Buffer<[I]>>, first: u64) -> Self {
+		let offset = first * size_of::<I>() as u64;
+		unsafe { self.pool.device.vk.cmd_bind_index_buffer(self.vk, buffer.vk, offset, I::TYPE) };
+		self.resources.push(Resource::Buffer(buffer));
+		self
+	}
+
 	pub fn bind_vertex_buffers(
 		mut self,
 		first_binding: u32,
@@ -259,6 +364,24 @@ impl<SEC: Bit> CommandBufferBuilder<SEC> {
 		self
 	}
 
+	pub fn blit_image(
+		mut self,
+		src: Arc<dyn ImageAbstract>,
+		src_layout: vk::ImageLayout,
+		dst: Arc<dyn ImageAbstract>,
+		dst_layout: vk::ImageLayout,
+		regions: &[vk::ImageBlit],
+		filter: vk::Filter,
+	) -> Self {
+		unsafe {
+			self.pool.device.vk.cmd_blit_image(self.vk, src.vk(), src_layout, dst.vk(), dst_layout, regions, filter)
+		};
+
+		self.resources.push(Resource::Image(src));
+		self.resources.push(Resource::Image(dst));
+		self
+	}
+
 	pub fn copy_buffer<T: ?Sized + 'static>(mut self, src: Arc<Buffer<T>>, dst: Arc<Buffer<T>>) -> Self {
 		assert!(src.size() <= dst.size());
 
@@ -270,16 +393,207 @@ impl<SEC: Bit> CommandBufferBuilder<SEC> {
 		self
 	}
 
+	pub fn copy_buffer_to_image<T: ?Sized + 'static>(
+		mut self,
+		src: Arc<Buffer<T>>,
+		dst: Arc<dyn ImageAbstract>,
+		dst_layout: vk::ImageLayout,
+		regions: &[vk::BufferImageCopy],
+	) -> Self {
+		unsafe { self.pool.device.vk.cmd_copy_buffer_to_image(self.vk, src.vk, dst.vk(), dst_layout, regions) };
+
+		self.resources.push(Resource::Buffer(src));
+		self.resources.push(Resource::Image(dst));
+		self
+	}
+
+	pub fn copy_image(
+		mut self,
+		src: Arc<dyn ImageAbstract>,
+		src_layout: vk::ImageLayout,
+		dst: Arc<dyn ImageAbstract>,
+		dst_layout: vk::ImageLayout,
+		regions: &[vk::ImageCopy],
+	) -> Self {
+		unsafe { self.pool.device.vk.cmd_copy_image(self.vk, src.vk(), src_layout, dst.vk(), dst_layout, regions) };
+
+		self.resources.push(Resource::Image(src));
+		self.resources.push(Resource::Image(dst));
+		self
+	}
+
+	pub fn copy_image_to_buffer<T: ?Sized + 'static>(
+		mut self,
+		src: Arc<dyn ImageAbstract>,
+		src_layout: vk::ImageLayout,
+		dst: Arc<Buffer<T>>,
+		regions: &[vk::BufferImageCopy],
+	) -> Self {
+		unsafe { self.pool.device.vk.cmd_copy_image_to_buffer(self.vk, src.vk(), src_layout, dst.vk, regions) };
+
+		self.resources.push(Resource::Image(src));
+		self.resources.push(Resource::Buffer(dst));
+		self
+	}
+
+	/// Runs the bound compute pipeline with `x * y * z` workgroups. Must be recorded outside a render pass.
+	pub fn dispatch(self, x: u32, y: u32, z: u32) -> Self {
+		unsafe { self.pool.device.vk.cmd_dispatch(self.vk, x, y, z) };
+		self
+	}
+
+	/// Like `dispatch`, but the workgroup counts are read from element `first` of `buffer` when the command runs, so
+	/// earlier commands on the GPU can decide them.
+	pub fn dispatch_indirect(mut self, buffer: Arc<Buffer<[vk::DispatchIndirectCommand]>>, first: u64) -> Self {
+		let offset = first * size_of::<vk::DispatchIndirectCommand>() as u64;
+		unsafe { self.pool.device.vk.cmd_dispatch_indirect(self.vk, buffer.vk, offset) };
+		self.resources.push(Resource::Buffer(buffer));
+		self
+	}
+
 	pub fn draw(self, vertex_count: u32, instance_count: u32, first_vertex: u32, first_instance: u32) -> Self {
 		unsafe { self.pool.device.vk.cmd_draw(self.vk, vertex_count, instance_count, first_vertex, first_instance) };
 		self
 	}
 
+	/// Draws with the bound index buffer. `vertex_offset` is added to each index before it's looked up.
+	pub fn draw_indexed(
+		self,
+		index_count: u32,
+		instance_count: u32,
+		first_index: u32,
+		vertex_offset: i32,
+		first_instance: u32,
+	) -> Self {
+		unsafe {
+			self.pool.device.vk.cmd_draw_indexed(
+				self.vk,
+				index_count,
+				instance_count,
+				first_index,
+				vertex_offset,
+				first_instance,
+			)
+		};
+		self
+	}
+
+	/// Like `draw`, but `count` draws whose parameters are read from `buffer`, starting at element `first`, when the
+	/// command runs. More than one needs the device's `multi_draw_indirect` feature.
+	pub fn draw_indirect(mut self, buffer: Arc<Buffer<[vk::DrawIndirectCommand]>>, first: u64, count: u32) -> Self {
+		let stride = size_of::<vk::DrawIndirectCommand>();
+		let offset = first * stride as u64;
+		unsafe { self.pool.device.vk.cmd_draw_indirect(self.vk, buffer.vk, offset, count, stride as _) };
+		self.resources.push(Resource::Buffer(buffer));
+		self
+	}
+
+	/// Writes the time once every earlier command has finished `stage`.
+	pub fn write_timestamp(mut self, stage: vk::PipelineStageFlags, pool: Arc<QueryPool>, query: u32) -> Self {
+		unsafe { self.pool.device.vk.cmd_write_timestamp(self.vk, stage, pool.vk, query) };
+		self.resources.push(Resource::QueryPool(pool));
+		self
+	}
+
+	pub fn end_label(self) -> Self {
+		if let Some(debug_utils) = self.pool.device.debug_utils() {
+			unsafe { debug_utils.cmd_end_debug_utils_label(self.vk) };
+		}
+		self
+	}
+
 	pub fn end_render_pass(self) -> Self {
 		unsafe { self.pool.device.vk.cmd_end_render_pass(self.vk) };
 		self
 	}
 
+	/// Sets every 4 bytes of `buffer` to `data`. Must be recorded outside a render pass, and the buffer needs the
+	/// `TRANSFER_DST` usage.
+	pub fn fill_buffer<T: ?Sized + 'static>(mut self, buffer: Arc<Buffer<T>>, data: u32) -> Self {
+		unsafe { self.pool.device.vk.cmd_fill_buffer(self.vk, buffer.vk, 0, vk::WHOLE_SIZE, data) };
+		self.resources.push(Resource::Buffer(buffer));
+		self
+	}
+
+	pub fn pipeline_barrier(
+		mut self,
+		src_stage_mask: vk::PipelineStageFlags,
+		dst_stage_mask: vk::PipelineStageFlags,
+		image_barriers: Vec<ImageBarrier>,
+		buffer_barriers: Vec<BufferBarrier>,
+	) -> Self {
+		let image_vks: Vec<_> = image_barriers.iter().map(ImageBarrier::to_vk).collect();
+		let buffer_vks: Vec<_> = buffer_barriers.iter().map(BufferBarrier::to_vk).collect();
+		unsafe {
+			self.pool.device.vk.cmd_pipeline_barrier(
+				self.vk,
+				src_stage_mask,
+				dst_stage_mask,
+				vk::DependencyFlags::empty(),
+				&[],
+				&buffer_vks,
+				&image_vks,
+			)
+		};
+
+		for barrier in image_barriers {
+			self.resources.push(Resource::Image(barrier.image));
+		}
+		for barrier in buffer_barriers {
+			self.resources.push(Resource::Buffer(barrier.buffer));
+		}
+		self
+	}
+
+	/// Changes the layout of the whole image, waiting for the accesses `old_layout` implies and blocking the ones
+	/// `new_layout` implies. The previous contents are discarded if `old_layout` is `UNDEFINED`.
+	pub fn transition_layout(
+		self,
+		image: Arc<dyn ImageAbstract>,
+		old_layout: vk::ImageLayout,
+		new_layout: vk::ImageLayout,
+	) -> Self {
+		let (_, src_stage) = layout_access(old_layout);
+		let (_, dst_stage) = layout_access(new_layout);
+		self.pipeline_barrier(src_stage, dst_stage, vec![ImageBarrier::new(image, old_layout, new_layout)], vec![])
+	}
+
+	/// Copies `data` into the push constants at `offset`. Panics if the layout has no range for it.
+	pub fn push_constants<T: Copy + 'static>(
+		mut self,
+		layout: Arc<PipelineLayout>,
+		stages: vk::ShaderStageFlags,
+		offset: u32,
+		data: &T,
+	) -> Self {
+		let size = size_of::<T>();
+		layout.check_push_constants(stages, offset, size as _);
+
+		let bytes = unsafe { slice::from_raw_parts(data as *const T as *const u8, size) };
+		unsafe { self.pool.device.vk.cmd_push_constants(self.vk, layout.vk, stages, offset, bytes) };
+		self.resources.push(Resource::PipelineLayout(layout));
+		self
+	}
+
+	/// Must be recorded outside a render pass, before the queries are written again.
+	pub fn reset_query_pool(mut self, pool: Arc<QueryPool>, first: u32, count: u32) -> Self {
+		unsafe { self.pool.device.vk.cmd_reset_query_pool(self.vk, pool.vk, first, count) };
+		self.resources.push(Resource::QueryPool(pool));
+		self
+	}
+
+	/// Requires a pipeline built with `dynamic_scissor` or `dynamic_viewport`.
+	pub fn set_scissor(self, first_scissor: u32, scissors: &[Rect2D]) -> Self {
+		unsafe { self.pool.device.vk.cmd_set_scissor(self.vk, first_scissor, scissors) };
+		self
+	}
+
+	/// Requires a pipeline built with `dynamic_viewport`.
+	pub fn set_viewport(self, first_viewport: u32, viewports: &[Viewport]) -> Self {
+		unsafe { self.pool.device.vk.cmd_set_viewport(self.vk, first_viewport, viewports) };
+		self
+	}
+
 	pub fn execute_commands(mut self, secondaries: impl IntoIterator<Item = Arc<CommandBuffer<B1>>>) -> Self {
 		let secondaries = secondaries.into_iter();
 		let (lower, upper) = secondaries.size_hint();
@@ -294,6 +608,113 @@ impl<SEC: Bit> CommandBufferBuilder<SEC> {
 	}
 }
 
+/// A memory dependency on an image, which can also change its layout or queue family. The access masks default to the
+/// ones implied by the layouts, and it covers every mip level unless limited with `levels`.
+pub struct ImageBarrier {
+	image: Arc<dyn ImageAbstract>,
+	levels: Option<(u32, u32)>,
+	old_layout: vk::ImageLayout,
+	new_layout: vk::ImageLayout,
+	src_access: vk::AccessFlags,
+	dst_access: vk::AccessFlags,
+	transfer: QueueTransfer,
+}
+impl ImageBarrier {
+	pub fn new(image: Arc<dyn ImageAbstract>, old_layout: vk::ImageLayout, new_layout: vk::ImageLayout) -> Self {
+		let (src_access, _) = layout_access(old_layout);
+		let (dst_access, _) = layout_access(new_layout);
+		Self { image, levels: None, old_layout, new_layout, src_access, dst_access, transfer: QueueTransfer::IGNORED }
+	}
+
+	/// Limits the barrier to `count` mip levels starting at `base`.
+	pub fn levels(mut self, base: u32, count: u32) -> Self {
+		self.levels = Some((base, count));
+		self
+	}
+
+	pub fn access(mut self, src_access: vk::AccessFlags, dst_access: vk::AccessFlags) -> Self {
+		self.src_access = src_access;
+		self.dst_access = dst_access;
+		self
+	}
+
+	/// Moves the image to another queue family. The barrier has to be recorded on both families' queues.
+	pub fn transfer(mut self, transfer: QueueTransfer) -> Self {
+		self.transfer = transfer;
+		self
+	}
+
+	fn to_vk(&self) -> vk::ImageMemoryBarrier {
+		let mut range = self.image.range();
+		if let Some((base, count)) = self.levels {
+			range.base_mip_level = base;
+			range.level_count = count;
+		}
+		vk::ImageMemoryBarrier::builder()
+			.src_access_mask(self.src_access)
+			.dst_access_mask(self.dst_access)
+			.old_layout(self.old_layout)
+			.new_layout(self.new_layout)
+			.src_queue_family_index(self.transfer.src)
+			.dst_queue_family_index(self.transfer.dst)
+			.image(self.image.vk())
+			.subresource_range(range)
+			.build()
+	}
+}
+
+/// A memory dependency on a whole buffer, which can also change its queue family.
+pub struct BufferBarrier {
+	buffer: Arc<dyn BufferAbstract>,
+	src_access: vk::AccessFlags,
+	dst_access: vk::AccessFlags,
+	transfer: QueueTransfer,
+}
+impl BufferBarrier {
+	pub fn new(buffer: Arc<dyn BufferAbstract>, src_access: vk::AccessFlags, dst_access: vk::AccessFlags) -> Self {
+		Self { buffer, src_access, dst_access, transfer: QueueTransfer::IGNORED }
+	}
+
+	/// Moves the buffer to another queue family. The barrier has to be recorded on both families' queues.
+	pub fn transfer(mut self, transfer: QueueTransfer) -> Self {
+		self.transfer = transfer;
+		self
+	}
+
+	fn to_vk(&self) -> vk::BufferMemoryBarrier {
+		vk::BufferMemoryBarrier::builder()
+			.src_access_mask(self.src_access)
+			.dst_access_mask(self.dst_access)
+			.src_queue_family_index(self.transfer.src)
+			.dst_queue_family_index(self.transfer.dst)
+			.buffer(self.buffer.vk())
+			.size(vk::WHOLE_SIZE)
+			.build()
+	}
+}
+
+/// The accesses and stages that use an image in `layout`, for barriers that don't need to be more precise.
+fn layout_access(layout: vk::ImageLayout) -> (vk::AccessFlags, vk::PipelineStageFlags) {
+	let shader_stages = vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER;
+	match layout {
+		vk::ImageLayout::UNDEFINED => (vk::AccessFlags::empty(), vk::PipelineStageFlags::TOP_OF_PIPE),
+		vk::ImageLayout::TRANSFER_SRC_OPTIMAL => (vk::AccessFlags::TRANSFER_READ, vk::PipelineStageFlags::TRANSFER),
+		vk::ImageLayout::TRANSFER_DST_OPTIMAL => (vk::AccessFlags::TRANSFER_WRITE, vk::PipelineStageFlags::TRANSFER),
+		vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => (vk::AccessFlags::SHADER_READ, shader_stages),
+		vk::ImageLayout::GENERAL => (vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE, shader_stages),
+		vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => (
+			vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+			vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+		),
+		vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL => (
+			vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+			vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+		),
+		vk::ImageLayout::PRESENT_SRC_KHR => (vk::AccessFlags::empty(), vk::PipelineStageFlags::BOTTOM_OF_PIPE),
+		_ => (vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE, vk::PipelineStageFlags::ALL_COMMANDS),
+	}
+}
+
 pub struct InheritanceInfo {
 	pub render_pass: Arc<RenderPass>,
 	pub subpass: u32,
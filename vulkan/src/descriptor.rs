@@ -0,0 +1,179 @@
+pub use ash::vk::{DescriptorPoolSize, DescriptorSetLayoutBinding, DescriptorType, ShaderStageFlags};
+
+use crate::{
+	buffer::{Buffer, BufferAbstract},
+	device::Device,
+	image::ImageView,
+	sampler::Sampler,
+	sync::Resource,
+};
+use ash::{prelude::VkResult, version::DeviceV1_0, vk};
+use std::{
+	collections::HashMap,
+	slice,
+	sync::{Arc, Mutex},
+};
+
+pub struct DescriptorSetLayout {
+	device: Arc<Device>,
+	_immutable_samplers: Vec<Arc<Sampler>>,
+	pub vk: vk::DescriptorSetLayout,
+}
+impl DescriptorSetLayout {
+	pub(crate) unsafe fn from_vk(
+		device: Arc<Device>,
+		immutable_samplers: Vec<Arc<Sampler>>,
+		vk: vk::DescriptorSetLayout,
+	) -> Arc<Self> {
+		Arc::new(Self { device, _immutable_samplers: immutable_samplers, vk })
+	}
+}
+impl Drop for DescriptorSetLayout {
+	fn drop(&mut self) {
+		unsafe { self.device.vk.destroy_descriptor_set_layout(self.vk, None) };
+	}
+}
+
+/// Sets aren't freed individually. They keep their pool alive, and are all freed with it.
+pub struct DescriptorPool {
+	device: Arc<Device>,
+	pub vk: vk::DescriptorPool,
+	/// allocating from a pool must be externally synchronized
+	lock: Mutex<()>,
+}
+impl DescriptorPool {
+	pub(crate) unsafe fn from_vk(device: Arc<Device>, vk: vk::DescriptorPool) -> Arc<Self> {
+		Arc::new(Self { device, vk, lock: Mutex::new(()) })
+	}
+
+	/// Fails with `ERROR_OUT_OF_POOL_MEMORY` or `ERROR_FRAGMENTED_POOL` when the pool is full, so the caller can make
+	/// another one.
+	pub fn allocate(self: &Arc<Self>, layout: Arc<DescriptorSetLayout>) -> VkResult<Arc<DescriptorSet>> {
+		let set_layouts = [layout.vk];
+		let ci = vk::DescriptorSetAllocateInfo::builder().descriptor_pool(self.vk).set_layouts(&set_layouts);
+		let vk = {
+			let _lock = self.lock.lock().unwrap();
+			unsafe { self.device.vk.allocate_descriptor_sets(&ci) }?[0]
+		};
+		Ok(Arc::new(DescriptorSet { pool: self.clone(), _layout: layout, vk, resources: Mutex::new(HashMap::new()) }))
+	}
+}
+impl Drop for DescriptorPool {
+	fn drop(&mut self) {
+		unsafe { self.device.vk.destroy_descriptor_pool(self.vk, None) };
+	}
+}
+
+pub struct DescriptorSet {
+	pool: Arc<DescriptorPool>,
+	_layout: Arc<DescriptorSetLayout>,
+	pub vk: vk::DescriptorSet,
+	/// what each binding was last written with, kept alive as long as the set
+	resources: Mutex<HashMap<u32, Vec<Resource>>>,
+}
+impl DescriptorSet {
+	/// Starts a batch of writes, which are applied together by `DescriptorSetWrite::submit`.
+	///
+	/// As in Vulkan, a set must not be rewritten while a pending command buffer uses it.
+	pub fn write(&self) -> DescriptorSetWrite<'_> {
+		DescriptorSetWrite { set: self, images: vec![], buffers: vec![], resources: vec![] }
+	}
+}
+
+pub struct DescriptorSetWrite<'a> {
+	set: &'a DescriptorSet,
+	/// By binding and array element.
+	images: Vec<(u32, u32, vk::DescriptorType, vk::DescriptorImageInfo)>,
+	buffers: Vec<(u32, vk::DescriptorType, vk::DescriptorBufferInfo)>,
+	resources: Vec<(u32, Resource)>,
+}
+impl<'a> DescriptorSetWrite<'a> {
+	/// Writes a combined image sampler. The image must be in `SHADER_READ_ONLY_OPTIMAL` when the set is used.
+	pub fn sampled_image(mut self, binding: u32, sampler: Arc<Sampler>, view: Arc<ImageView>) -> Self {
+		let info = vk::DescriptorImageInfo::builder()
+			.sampler(sampler.vk)
+			.image_view(view.vk)
+			.image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+			.build();
+		self.images.push((binding, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, info));
+		self.resources.push((binding, Resource::Sampler(sampler)));
+		self.resources.push((binding, Resource::ImageView(view)));
+		self
+	}
+
+	/// Writes a combined image sampler to a binding whose sampler is immutable in the set layout.
+	pub fn sampled_view(mut self, binding: u32, view: Arc<ImageView>) -> Self {
+		let info = vk::DescriptorImageInfo::builder()
+			.image_view(view.vk)
+			.image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+			.build();
+		self.images.push((binding, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, info));
+		self.resources.push((binding, Resource::ImageView(view)));
+		self
+	}
+
+	/// Writes a storage image. The image must be in `GENERAL` when the set is used.
+	pub fn storage_image(self, binding: u32, view: Arc<ImageView>) -> Self {
+		self.storage_images(binding, Some(view))
+	}
+
+	/// Writes storage images to the elements of an array binding, starting at the first.
+	pub fn storage_images(mut self, binding: u32, views: impl IntoIterator<Item = Arc<ImageView>>) -> Self {
+		for (element, view) in views.into_iter().enumerate() {
+			let info =
+				vk::DescriptorImageInfo::builder().image_view(view.vk).image_layout(vk::ImageLayout::GENERAL).build();
+			self.images.push((binding, element as _, vk::DescriptorType::STORAGE_IMAGE, info));
+			self.resources.push((binding, Resource::ImageView(view)));
+		}
+		self
+	}
+
+	/// Writes the whole of `buffer` as a uniform buffer.
+	pub fn uniform_buffer<T: ?Sized + 'static>(mut self, binding: u32, buffer: Arc<Buffer<T>>) -> Self {
+		let info = vk::DescriptorBufferInfo::builder().buffer(buffer.vk).range(vk::WHOLE_SIZE).build();
+		self.buffers.push((binding, vk::DescriptorType::UNIFORM_BUFFER, info));
+		self.resources.push((binding, Resource::Buffer(buffer as Arc<dyn BufferAbstract>)));
+		self
+	}
+
+	/// Writes the whole of `buffer` as a storage buffer.
+	pub fn storage_buffer<T: ?Sized + 'static>(mut self, binding: u32, buffer: Arc<Buffer<T>>) -> Self {
+		let info = vk::DescriptorBufferInfo::builder().buffer(buffer.vk).range(vk::WHOLE_SIZE).build();
+		self.buffers.push((binding, vk::DescriptorType::STORAGE_BUFFER, info));
+		self.resources.push((binding, Resource::Buffer(buffer as Arc<dyn BufferAbstract>)));
+		self
+	}
+
+	pub fn submit(self) {
+		let image_writes = self.images.iter().map(|(binding, element, ty, info)| {
+			vk::WriteDescriptorSet::builder()
+				.dst_set(self.set.vk)
+				.dst_binding(*binding)
+				.dst_array_element(*element)
+				.descriptor_type(*ty)
+				.image_info(slice::from_ref(info))
+				.build()
+		});
+		let buffer_writes = self.buffers.iter().map(|(binding, ty, info)| {
+			vk::WriteDescriptorSet::builder()
+				.dst_set(self.set.vk)
+				.dst_binding(*binding)
+				.descriptor_type(*ty)
+				.buffer_info(slice::from_ref(info))
+				.build()
+		});
+		let writes: Vec<_> = image_writes.chain(buffer_writes).collect();
+
+		// updating a set must also be externally synchronized, so hold the lock for the update
+		let mut resources = self.set.resources.lock().unwrap();
+		unsafe { self.set.pool.device.vk.update_descriptor_sets(&writes, &[]) };
+
+		// replace whatever the written bindings held before
+		for (binding, _) in &self.resources {
+			resources.remove(binding);
+		}
+		for (binding, resource) in self.resources {
+			resources.entry(binding).or_insert_with(Vec::new).push(resource);
+		}
+	}
+}
@@ -1,24 +1,41 @@
-use crate::{image::ImageAbstract, pipeline::PipelineBuilder, render_pass::RenderPass, sync::GpuFuture};
+use crate::{
+	image::ImageAbstract,
+	pipeline::PipelineBuilder,
+	render_pass::{RenderPass, RenderPassBuilder},
+	sync::GpuFuture,
+};
 pub use ash::vk::BufferUsageFlags;
 use typenum::B0;
 
 use crate::{
 	buffer::BufferInit,
 	command::{CommandBuffer, CommandPool},
-	image::{Format, Framebuffer, ImageSubresourceRange, ImageView},
+	descriptor::{DescriptorPool, DescriptorPoolSize, DescriptorSetLayout, DescriptorSetLayoutBinding},
+	geometry::Extent,
+	image::{Format, Framebuffer, Image, ImageInit, ImageSubresourceRange, ImageView},
 	instance::Instance,
 	physical_device::{PhysicalDevice, QueueFamily},
-	pipeline::PipelineLayout,
+	pipeline::{ComputePipeline, PipelineLayout, PushConstantRange},
+	query::QueryPool,
+	sampler::{Sampler, SamplerBuilder},
 	shader::ShaderModule,
 	surface::{ColorSpace, PresentMode, Surface, SurfaceTransformFlags},
 	swapchain::{CompositeAlphaFlags, Swapchain, SwapchainImage},
 	sync::{Fence, Resource, Semaphore},
-	Extent2D,
 };
-use ash::{extensions::khr, version::DeviceV1_0, vk, Device as VkDevice};
-use std::{mem::size_of, sync::Arc};
+use ash::{
+	extensions::{ext, khr},
+	prelude::VkResult,
+	version::DeviceV1_0,
+	vk, Device as VkDevice,
+};
+use std::{
+	ffi::{CStr, CString},
+	mem::size_of,
+	sync::Arc,
+};
 use typenum::Bit;
-use vk_mem::{AllocationCreateInfo, Allocator, AllocatorCreateInfo, MemoryUsage};
+use vk_mem::{AllocationCreateFlags, AllocationCreateInfo, Allocator, AllocatorCreateInfo, MemoryUsage};
 
 pub struct Device {
 	instance: Arc<Instance>,
@@ -26,6 +43,7 @@ pub struct Device {
 	pub vk: VkDevice,
 	pub khr_swapchain: khr::Swapchain,
 	pub allocator: Allocator,
+	features: vk::PhysicalDeviceFeatures,
 }
 impl Device {
 	pub fn build_pipeline(
@@ -36,6 +54,14 @@ impl Device {
 		PipelineBuilder::new(self.clone(), layout, render_pass)
 	}
 
+	pub fn build_render_pass(self: &Arc<Self>) -> RenderPassBuilder {
+		RenderPassBuilder::new(self.clone())
+	}
+
+	pub fn build_sampler(self: &Arc<Self>) -> SamplerBuilder {
+		SamplerBuilder::new(self.clone())
+	}
+
 	pub fn create_buffer_slice<T, CPU: Bit>(
 		self: &Arc<Self>,
 		len: usize,
@@ -46,8 +72,16 @@ impl Device {
 
 		let ci = ash::vk::BufferCreateInfo::builder().size(size).usage(usage).build();
 
-		let usage = if CPU::BOOL { MemoryUsage::CpuOnly } else { MemoryUsage::GpuOnly };
-		let aci = AllocationCreateInfo { usage, ..Default::default() };
+		// host buffers stay mapped, so writing them doesn't map and unmap the memory every time
+		let aci = if CPU::BOOL {
+			AllocationCreateInfo {
+				usage: MemoryUsage::CpuOnly,
+				flags: AllocationCreateFlags::MAPPED,
+				..Default::default()
+			}
+		} else {
+			AllocationCreateInfo { usage: MemoryUsage::GpuOnly, ..Default::default() }
+		};
 
 		let (vk, alloc, _) = self.allocator.create_buffer(&ci, &aci).unwrap();
 
@@ -58,7 +92,7 @@ impl Device {
 		unsafe { CommandPool::from_vk(self.clone(), family.idx, transient) }
 	}
 
-	pub(crate) fn create_fence(self: &Arc<Self>, signalled: bool, resources: Vec<Arc<CommandBuffer<B0>>>) -> Fence {
+	pub(crate) fn create_fence(self: &Arc<Self>, signalled: bool, resources: Vec<Resource>) -> Fence {
 		unsafe {
 			let mut flags = vk::FenceCreateFlags::empty();
 			if signalled {
@@ -70,51 +104,128 @@ impl Device {
 		}
 	}
 
-	pub(crate) fn create_semaphore(self: &Arc<Self>) -> Arc<Semaphore> {
+	pub fn create_semaphore(self: &Arc<Self>) -> Arc<Semaphore> {
 		unsafe {
 			let vk = self.vk.create_semaphore(&vk::SemaphoreCreateInfo::builder(), None).unwrap();
 			Semaphore::from_vk(self.clone(), vk)
 		}
 	}
 
+	/// Each of `immutable_samplers` is baked into the binding with the same number, which must hold a single sampler
+	/// or combined image sampler.
+	pub fn create_descriptor_set_layout(
+		self: &Arc<Self>,
+		bindings: &[DescriptorSetLayoutBinding],
+		immutable_samplers: Vec<(u32, Arc<Sampler>)>,
+	) -> Arc<DescriptorSetLayout> {
+		let sampler_vks: Vec<_> = immutable_samplers.iter().map(|(binding, sampler)| (*binding, sampler.vk)).collect();
+		let bindings: Vec<_> = bindings
+			.iter()
+			.map(|&binding| match sampler_vks.iter().find(|(idx, _)| *idx == binding.binding) {
+				Some((_, sampler)) => {
+					assert!(binding.descriptor_count == 1);
+					vk::DescriptorSetLayoutBinding { p_immutable_samplers: sampler, ..binding }
+				},
+				None => binding,
+			})
+			.collect();
+
+		let ci = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+		let vk = unsafe { self.vk.create_descriptor_set_layout(&ci, None) }.unwrap();
+		let samplers = immutable_samplers.into_iter().map(|(_, sampler)| sampler).collect();
+		unsafe { DescriptorSetLayout::from_vk(self.clone(), samplers, vk) }
+	}
+
+	/// `shader`'s entry point must be named `main`.
+	pub fn create_compute_pipeline(
+		self: &Arc<Self>,
+		layout: Arc<PipelineLayout>,
+		shader: Arc<ShaderModule>,
+	) -> Arc<ComputePipeline> {
+		let stage = vk::PipelineShaderStageCreateInfo::builder()
+			.stage(vk::ShaderStageFlags::COMPUTE)
+			.module(shader.vk)
+			.name(CStr::from_bytes_with_nul(b"main\0").unwrap())
+			.build();
+		let cis = [vk::ComputePipelineCreateInfo::builder().stage(stage).layout(layout.vk).build()];
+		let vk = unsafe { self.vk.create_compute_pipelines(vk::PipelineCache::null(), &cis, None) }.unwrap()[0];
+		unsafe { ComputePipeline::from_vk(self.clone(), layout, shader, vk) }
+	}
+
+	pub fn create_descriptor_pool(
+		self: &Arc<Self>,
+		max_sets: u32,
+		pool_sizes: &[DescriptorPoolSize],
+	) -> Arc<DescriptorPool> {
+		let ci = vk::DescriptorPoolCreateInfo::builder().max_sets(max_sets).pool_sizes(pool_sizes);
+		let vk = unsafe { self.vk.create_descriptor_pool(&ci, None) }.unwrap();
+		unsafe { DescriptorPool::from_vk(self.clone(), vk) }
+	}
+
 	pub fn create_framebuffer(
 		self: &Arc<Self>,
 		render_pass: Arc<RenderPass>,
 		attachments: Vec<Arc<ImageView>>,
-		width: u32,
-		height: u32,
+		extent: impl Into<Extent>,
 	) -> Arc<Framebuffer> {
 		let attachment_vks: Vec<_> = attachments.iter().map(|x| x.vk).collect();
+		let extent: Extent = extent.into();
 
 		let ci = vk::FramebufferCreateInfo::builder()
 			.render_pass(render_pass.vk)
 			.attachments(&attachment_vks)
-			.width(width)
-			.height(height)
+			.width(extent.width)
+			.height(extent.height)
 			.layers(1);
 		let vk = unsafe { self.vk.create_framebuffer(&ci, None) }.unwrap();
 		unsafe { Framebuffer::from_vk(render_pass, attachments, vk) }
 	}
 
+	/// Creates an image in device local memory.
+	pub fn create_image(self: &Arc<Self>, ci: &vk::ImageCreateInfo) -> ImageInit {
+		let aci = AllocationCreateInfo { usage: MemoryUsage::GpuOnly, ..Default::default() };
+		let (vk, allocation, _) = self.allocator.create_image(ci, &aci).unwrap();
+		ImageInit::new(unsafe { Image::from_vk(self.clone(), vk, allocation, ci) })
+	}
+
 	pub fn create_image_view(
 		&self,
 		image: Arc<dyn ImageAbstract>,
+		view_type: vk::ImageViewType,
 		format: Format,
 		subresource_range: ImageSubresourceRange,
 	) -> Arc<ImageView> {
 		let ci = vk::ImageViewCreateInfo::builder()
 			.image(image.vk())
-			.view_type(vk::ImageViewType::TYPE_2D)
+			.view_type(view_type)
 			.format(format)
 			.subresource_range(subresource_range);
 		let vk = unsafe { self.vk.create_image_view(&ci, None) }.unwrap();
 		unsafe { ImageView::from_vk(image, vk) }
 	}
 
-	pub fn create_pipeline_layout(self: &Arc<Self>) -> Arc<PipelineLayout> {
-		let ci = vk::PipelineLayoutCreateInfo::builder();
+	pub fn create_pipeline_layout(
+		self: &Arc<Self>,
+		set_layouts: Vec<Arc<DescriptorSetLayout>>,
+		push_constant_ranges: &[PushConstantRange],
+	) -> Arc<PipelineLayout> {
+		let set_layout_vks: Vec<_> = set_layouts.iter().map(|x| x.vk).collect();
+		let ci = vk::PipelineLayoutCreateInfo::builder()
+			.set_layouts(&set_layout_vks)
+			.push_constant_ranges(push_constant_ranges);
 		let vk = unsafe { self.vk.create_pipeline_layout(&ci, None) }.unwrap();
-		unsafe { PipelineLayout::from_vk(self.clone(), vk) }
+		unsafe { PipelineLayout::from_vk(self.clone(), set_layouts, push_constant_ranges.to_vec(), vk) }
+	}
+
+	pub fn create_query_pool(self: &Arc<Self>, query_type: vk::QueryType, count: u32) -> Arc<QueryPool> {
+		let ci = vk::QueryPoolCreateInfo::builder().query_type(query_type).query_count(count);
+		let vk = unsafe { self.vk.create_query_pool(&ci, None) }.unwrap();
+		unsafe { QueryPool::from_vk(self.clone(), vk) }
+	}
+
+	pub fn create_render_pass(self: &Arc<Self>, ci: &vk::RenderPassCreateInfo) -> VkResult<Arc<RenderPass>> {
+		let vk = unsafe { self.vk.create_render_pass(ci, None) }?;
+		Ok(unsafe { RenderPass::from_vk(self.clone(), vk) })
 	}
 
 	pub unsafe fn create_shader_module(self: &Arc<Self>, code: &[u32]) -> Arc<ShaderModule> {
@@ -129,7 +240,8 @@ impl Device {
 		min_image_count: u32,
 		image_format: Format,
 		image_color_space: ColorSpace,
-		image_extent: Extent2D,
+		image_extent: impl Into<Extent>,
+		image_usage: vk::ImageUsageFlags,
 		queue_families: impl IntoIterator<Item = QueueFamily<'a>>,
 		pre_transform: SurfaceTransformFlags,
 		composite_alpha: CompositeAlphaFlags,
@@ -142,6 +254,7 @@ impl Device {
 			.map(|qfam| qfam.idx)
 			.collect();
 
+		let image_extent: Extent = image_extent.into();
 		let image_sharing_mode =
 			if queue_family_indices.len() > 1 { vk::SharingMode::CONCURRENT } else { vk::SharingMode::EXCLUSIVE };
 
@@ -150,9 +263,9 @@ impl Device {
 			.min_image_count(min_image_count)
 			.image_format(image_format)
 			.image_color_space(image_color_space)
-			.image_extent(image_extent)
+			.image_extent(image_extent.into())
 			.image_array_layers(1)
-			.image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+			.image_usage(image_usage)
 			.image_sharing_mode(image_sharing_mode)
 			.queue_family_indices(&queue_family_indices)
 			.pre_transform(pre_transform)
@@ -171,11 +284,43 @@ impl Device {
 		(swapchain, images)
 	}
 
+	/// Blocks until every queue has finished its work.
+	pub fn wait_idle(&self) {
+		unsafe { self.vk.device_wait_idle() }.unwrap();
+	}
+
+	/// The optional features that were enabled when the device was created.
+	pub fn features(&self) -> &vk::PhysicalDeviceFeatures {
+		&self.features
+	}
+
 	pub fn physical_device(&self) -> PhysicalDevice {
 		PhysicalDevice::from_vk(&self.instance, self.physical_device)
 	}
 
-	pub(crate) fn from_vk(instance: Arc<Instance>, physical_device: vk::PhysicalDevice, vk: VkDevice) -> Arc<Self> {
+	/// Names `object` in validation messages and in captures from tools like RenderDoc. Does nothing unless the
+	/// instance was created with validation.
+	pub fn set_object_name<T: vk::Handle>(&self, object: T, name: &str) {
+		if let Some(debug_utils) = self.debug_utils() {
+			let name = CString::new(name).unwrap();
+			let info = vk::DebugUtilsObjectNameInfoEXT::builder()
+				.object_type(T::TYPE)
+				.object_handle(object.as_raw())
+				.object_name(&name);
+			unsafe { debug_utils.debug_utils_set_object_name(self.vk.handle(), &info) }.unwrap();
+		}
+	}
+
+	pub(crate) fn debug_utils(&self) -> Option<&ext::DebugUtils> {
+		self.instance.debug_utils.as_ref()
+	}
+
+	pub(crate) fn from_vk(
+		instance: Arc<Instance>,
+		physical_device: vk::PhysicalDevice,
+		vk: VkDevice,
+		features: vk::PhysicalDeviceFeatures,
+	) -> Arc<Self> {
 		let khr_swapchain = khr::Swapchain::new(&instance.vk, &vk);
 
 		let ci = AllocatorCreateInfo {
@@ -186,7 +331,7 @@ impl Device {
 		};
 		let allocator = Allocator::new(&ci).unwrap();
 
-		Arc::new(Self { instance, physical_device, vk, khr_swapchain, allocator })
+		Arc::new(Self { instance, physical_device, vk, khr_swapchain, allocator, features })
 	}
 
 	pub(crate) unsafe fn get_queue(self: &Arc<Self>, queue_family_index: u32, queue_index: u32) -> Arc<Queue> {
@@ -216,89 +361,94 @@ impl Queue {
 		QueueFamily::from_vk(self.device.physical_device(), self.family)
 	}
 
+	/// Starts a submission of `cmd`. More command buffers, waits and signals can be added before it's ended or
+	/// flushed.
 	pub fn submit(self: &Arc<Self>, cmd: Arc<CommandBuffer<B0>>) -> SubmitFuture {
-		assert!(cmd.pool.queue_family == self.family);
-		SubmitFuture { queue: self.clone(), cmd }
+		SubmitFuture { queue: self.clone(), cmds: vec![], waits: vec![], wait_stages: vec![], signals: vec![] }
+			.then(cmd)
 	}
 
-	pub fn submit_after<T: GpuFuture>(self: &Arc<Self>, prev: T, cmd: Arc<CommandBuffer<B0>>) -> SubmitAfterFuture<T> {
-		assert!(cmd.pool.queue_family == self.family);
-		SubmitAfterFuture { queue: self.clone(), cmd, prev }
+	pub fn submit_after(self: &Arc<Self>, prev: impl GpuFuture, cmd: Arc<CommandBuffer<B0>>) -> SubmitFuture {
+		self.submit(cmd).after(prev)
 	}
 }
 
 pub struct SubmitFuture {
 	queue: Arc<Queue>,
-	cmd: Arc<CommandBuffer<B0>>,
+	cmds: Vec<Arc<CommandBuffer<B0>>>,
+	waits: Vec<Arc<Semaphore>>,
+	wait_stages: Vec<vk::PipelineStageFlags>,
+	signals: Vec<Arc<Semaphore>>,
 }
 impl SubmitFuture {
-	pub fn end(self) -> Fence {
-		let fence = self.queue.device.create_fence(false, vec![self.cmd.clone()]);
+	/// Adds a command buffer, which starts after the ones before it.
+	pub fn then(mut self, cmd: Arc<CommandBuffer<B0>>) -> Self {
+		assert!(cmd.pool.queue_family == self.queue.family);
+		self.cmds.push(cmd);
+		self
+	}
 
-		let submits = [vk::SubmitInfo::builder().command_buffers(&[self.cmd.vk]).build()];
-		unsafe { self.queue.device().vk.queue_submit(self.queue.vk, &submits, fence.vk) }.unwrap();
+	/// Waits on `prev` before the command buffers reach the stages it blocks. Can be called more than once to wait
+	/// on several futures.
+	pub fn after(mut self, prev: impl GpuFuture) -> Self {
+		let (semaphores, stages) = prev.semaphores();
+		self.waits.extend(semaphores);
+		self.wait_stages.extend(stages);
+		self
+	}
 
-		fence
+	/// Also signals `semaphore` when the command buffers finish.
+	pub fn signal(mut self, semaphore: Arc<Semaphore>) -> Self {
+		self.signals.push(semaphore);
+		self
 	}
-}
 
-pub struct SubmitAfterFuture<T: GpuFuture> {
-	queue: Arc<Queue>,
-	cmd: Arc<CommandBuffer<B0>>,
-	prev: T,
-}
-impl<T: GpuFuture> SubmitAfterFuture<T> {
 	pub fn end(self) -> Fence {
-		let (semaphores, stages) = self.prev.semaphores();
-		let mut resources = Vec::with_capacity(semaphores.len() + 1);
-		let mut semaphore_vks = Vec::with_capacity(semaphores.len());
-		for semaphore in semaphores {
-			semaphore_vks.push(semaphore.vk);
-			resources.push(Resource::Semaphore(semaphore));
-		}
-
-		let fence = self.queue.device.create_fence(false, vec![self.cmd.clone()]);
-
-		let submits = [vk::SubmitInfo::builder()
-			.wait_semaphores(&semaphore_vks)
-			.wait_dst_stage_mask(&stages)
-			.command_buffers(&[self.cmd.vk])
-			.build()];
-		unsafe { self.queue.device().vk.queue_submit(self.queue.vk, &submits, fence.vk) }.unwrap();
+		self.submit()
+	}
 
-		fence
+	/// Submits the command buffers and signals a semaphore when they finish. Submissions that wait on the returned
+	/// future block at `wait_stage`.
+	pub fn flush(self, wait_stage: vk::PipelineStageFlags) -> (Fence, FlushFuture) {
+		let semaphore = self.queue.device.create_semaphore();
+		let fence = self.signal(semaphore.clone()).submit();
+		(fence, FlushFuture::new(semaphore, wait_stage))
 	}
 
-	pub fn flush(self) -> (Fence, FlushFuture) {
-		let (semaphores, stages) = self.prev.semaphores();
-		let mut resources = Vec::with_capacity(semaphores.len() + 1);
-		let mut semaphore_vks = Vec::with_capacity(semaphores.len());
-		for semaphore in semaphores {
-			semaphore_vks.push(semaphore.vk);
-			resources.push(Resource::Semaphore(semaphore));
-		}
+	fn submit(self) -> Fence {
+		let cmd_vks: Vec<_> = self.cmds.iter().map(|x| x.vk).collect();
+		let wait_vks: Vec<_> = self.waits.iter().map(|x| x.vk).collect();
+		let signal_vks: Vec<_> = self.signals.iter().map(|x| x.vk).collect();
 
-		let fence = self.queue.device.create_fence(false, vec![self.cmd.clone()]);
-		let semaphore = self.queue.device.create_semaphore();
+		// everything the submission uses stays alive until the fence is waited on
+		let cmds = self.cmds.into_iter().map(Resource::PrimaryCommandBuffer);
+		let semaphores = self.waits.into_iter().chain(self.signals).map(Resource::Semaphore);
+		let fence = self.queue.device.create_fence(false, cmds.chain(semaphores).collect());
 
 		let submits = [vk::SubmitInfo::builder()
-			.wait_semaphores(&semaphore_vks)
-			.wait_dst_stage_mask(&stages)
-			.command_buffers(&[self.cmd.vk])
-			.signal_semaphores(&[semaphore.vk])
+			.wait_semaphores(&wait_vks)
+			.wait_dst_stage_mask(&self.wait_stages)
+			.command_buffers(&cmd_vks)
+			.signal_semaphores(&signal_vks)
 			.build()];
-		unsafe { self.queue.device().vk.queue_submit(self.queue.vk, &submits, fence.vk) }.unwrap();
+		unsafe { self.queue.device.vk.queue_submit(self.queue.vk, &submits, fence.vk) }.unwrap();
 
-		(fence, FlushFuture { semaphore })
+		fence
 	}
 }
 
+/// A semaphore signalled by an earlier submission, and the stage later submissions should wait for it at.
 pub struct FlushFuture {
 	semaphore: Arc<Semaphore>,
+	stage: vk::PipelineStageFlags,
+}
+impl FlushFuture {
+	pub fn new(semaphore: Arc<Semaphore>, stage: vk::PipelineStageFlags) -> Self {
+		Self { semaphore, stage }
+	}
 }
 impl GpuFuture for FlushFuture {
 	fn semaphores(self) -> (Vec<Arc<Semaphore>>, Vec<vk::PipelineStageFlags>) {
-		// TODO: figure out what stages to block
-		(vec![self.semaphore], vec![vk::PipelineStageFlags::BOTTOM_OF_PIPE])
+		(vec![self.semaphore], vec![self.stage])
 	}
 }
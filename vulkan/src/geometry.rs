@@ -0,0 +1,166 @@
+//! Sizes and positions in pixels, which convert to and from both ash's structs and nalgebra's vectors.
+
+use ash::vk;
+use nalgebra::Vector2;
+use std::ops::{Add, Sub};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Extent {
+	pub width: u32,
+	pub height: u32,
+}
+impl Extent {
+	pub fn new(width: u32, height: u32) -> Self {
+		Self { width, height }
+	}
+
+	pub fn area(self) -> u64 {
+		self.width as u64 * self.height as u64
+	}
+
+	/// Whether it has no pixels, like a minimized window's surface.
+	pub fn is_empty(self) -> bool {
+		self.width == 0 || self.height == 0
+	}
+
+	/// Width over height. Empty extents have an aspect of 1, so projections built from it stay finite.
+	pub fn aspect(self) -> f32 {
+		if self.is_empty() {
+			return 1.0;
+		}
+		self.width as f32 / self.height as f32
+	}
+
+	/// Multiplies both sides by `factor`, rounding to the nearest pixel, like for converting logical sizes to physical.
+	pub fn scale(self, factor: f32) -> Self {
+		Self { width: (self.width as f32 * factor).round() as _, height: (self.height as f32 * factor).round() as _ }
+	}
+
+	/// Clamps each side separately, like fitting a window's size into a surface's supported range.
+	pub fn clamp(self, min: Self, max: Self) -> Self {
+		Self { width: self.width.max(min.width).min(max.width), height: self.height.max(min.height).min(max.height) }
+	}
+
+	pub fn to_vector(self) -> Vector2<u32> {
+		Vector2::new(self.width, self.height)
+	}
+}
+impl From<vk::Extent2D> for Extent {
+	fn from(extent: vk::Extent2D) -> Self {
+		Self { width: extent.width, height: extent.height }
+	}
+}
+impl From<Extent> for vk::Extent2D {
+	fn from(extent: Extent) -> Self {
+		Self { width: extent.width, height: extent.height }
+	}
+}
+impl From<Vector2<u32>> for Extent {
+	fn from(v: Vector2<u32>) -> Self {
+		Self { width: v.x, height: v.y }
+	}
+}
+impl From<(u32, u32)> for Extent {
+	fn from((width, height): (u32, u32)) -> Self {
+		Self { width, height }
+	}
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Offset {
+	pub x: i32,
+	pub y: i32,
+}
+impl Offset {
+	pub fn new(x: i32, y: i32) -> Self {
+		Self { x, y }
+	}
+
+	pub fn to_vector(self) -> Vector2<i32> {
+		Vector2::new(self.x, self.y)
+	}
+}
+impl Add for Offset {
+	type Output = Self;
+
+	fn add(self, other: Self) -> Self {
+		Self { x: self.x + other.x, y: self.y + other.y }
+	}
+}
+impl Sub for Offset {
+	type Output = Self;
+
+	fn sub(self, other: Self) -> Self {
+		Self { x: self.x - other.x, y: self.y - other.y }
+	}
+}
+impl From<vk::Offset2D> for Offset {
+	fn from(offset: vk::Offset2D) -> Self {
+		Self { x: offset.x, y: offset.y }
+	}
+}
+impl From<Offset> for vk::Offset2D {
+	fn from(offset: Offset) -> Self {
+		Self { x: offset.x, y: offset.y }
+	}
+}
+impl From<Vector2<i32>> for Offset {
+	fn from(v: Vector2<i32>) -> Self {
+		Self { x: v.x, y: v.y }
+	}
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Rect {
+	pub offset: Offset,
+	pub extent: Extent,
+}
+impl Rect {
+	pub fn new(offset: Offset, extent: Extent) -> Self {
+		Self { offset, extent }
+	}
+
+	/// The whole of something `extent` in size, like a framebuffer.
+	pub fn from_extent(extent: Extent) -> Self {
+		Self { offset: Offset::default(), extent }
+	}
+
+	/// The rect from `min` up to but not including `max`, or `None` if that's empty.
+	pub fn from_min_max(min: Vector2<i32>, max: Vector2<i32>) -> Option<Self> {
+		if min.x >= max.x || min.y >= max.y {
+			return None;
+		}
+		Some(Self { offset: min.into(), extent: Extent::new((max.x - min.x) as _, (max.y - min.y) as _) })
+	}
+
+	pub fn min(&self) -> Vector2<i32> {
+		self.offset.to_vector()
+	}
+
+	/// One past the last pixel on each axis.
+	pub fn max(&self) -> Vector2<i32> {
+		self.min() + self.extent.to_vector().map(|x| x as i32)
+	}
+
+	pub fn contains(&self, point: Vector2<i32>) -> bool {
+		let (min, max) = (self.min(), self.max());
+		point.x >= min.x && point.y >= min.y && point.x < max.x && point.y < max.y
+	}
+
+	/// The part both rects cover, or `None` if they don't overlap.
+	pub fn intersection(&self, other: &Self) -> Option<Self> {
+		let min = self.min().zip_map(&other.min(), |a, b| a.max(b));
+		let max = self.max().zip_map(&other.max(), |a, b| a.min(b));
+		Self::from_min_max(min, max)
+	}
+}
+impl From<vk::Rect2D> for Rect {
+	fn from(rect: vk::Rect2D) -> Self {
+		Self { offset: rect.offset.into(), extent: rect.extent.into() }
+	}
+}
+impl From<Rect> for vk::Rect2D {
+	fn from(rect: Rect) -> Self {
+		Self { offset: rect.offset.into(), extent: rect.extent.into() }
+	}
+}
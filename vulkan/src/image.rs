@@ -1,8 +1,354 @@
 pub use ash::vk::{Format, ImageSubresourceRange};
 
-use crate::{device::Device, render_pass::RenderPass};
+use crate::{
+	buffer::{Buffer, Readback},
+	command::{BufferBarrier, CommandBufferBuilder, CommandPool, ImageBarrier},
+	device::{BufferUsageFlags, Device, Queue, SubmitFuture},
+	render_pass::RenderPass,
+};
 use ash::{version::DeviceV1_0, vk};
 use std::sync::Arc;
+use typenum::{B0, B1};
+use vk_mem::Allocation;
+
+/// An image in device local memory, freed when the last reference is dropped.
+pub struct Image {
+	device: Arc<Device>,
+	pub vk: vk::Image,
+	allocation: Allocation,
+	pub image_type: vk::ImageType,
+	pub format: Format,
+	pub extent: vk::Extent3D,
+	pub mip_levels: u32,
+}
+impl Image {
+	pub(crate) unsafe fn from_vk(
+		device: Arc<Device>,
+		vk: vk::Image,
+		allocation: Allocation,
+		ci: &vk::ImageCreateInfo,
+	) -> Arc<Self> {
+		let (image_type, format, extent, mip_levels) = (ci.image_type, ci.format, ci.extent, ci.mip_levels);
+		Arc::new(Self { device, vk, allocation, image_type, format, extent, mip_levels })
+	}
+
+	/// Creates a view of every mip level and the first layer, with the aspect implied by the format.
+	pub fn create_view(self: &Arc<Self>) -> Arc<ImageView> {
+		self.device.create_image_view(self.clone(), self.view_type(), self.format, self.range())
+	}
+
+	/// Creates a view of a single mip level, like for binding one level as a storage image.
+	pub fn create_level_view(self: &Arc<Self>, level: u32) -> Arc<ImageView> {
+		let mut range = self.range();
+		range.base_mip_level = level;
+		range.level_count = 1;
+		self.device.create_image_view(self.clone(), self.view_type(), self.format, range)
+	}
+
+	fn view_type(&self) -> vk::ImageViewType {
+		match self.image_type {
+			vk::ImageType::TYPE_1D => vk::ImageViewType::TYPE_1D,
+			vk::ImageType::TYPE_3D => vk::ImageViewType::TYPE_3D,
+			_ => vk::ImageViewType::TYPE_2D,
+		}
+	}
+
+	/// Records the other half of a queue family transfer started by `ImageInit::record_copy_from_buffer`. The
+	/// submission must wait on the copy's submission at the `TRANSFER` stage.
+	pub fn record_acquire(
+		self: &Arc<Self>,
+		cmd: CommandBufferBuilder<B0>,
+		transfer: QueueTransfer,
+	) -> CommandBufferBuilder<B0> {
+		let to_shader = ImageBarrier::new(
+			self.clone(),
+			vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+			vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+		)
+		.access(vk::AccessFlags::empty(), vk::AccessFlags::SHADER_READ)
+		.transfer(transfer);
+		cmd.pipeline_barrier(
+			vk::PipelineStageFlags::TRANSFER,
+			vk::PipelineStageFlags::FRAGMENT_SHADER,
+			vec![to_shader],
+			vec![],
+		)
+	}
+
+	/// Fills every mip level after the first by repeatedly downsampling the one before it. The image must be in
+	/// `SHADER_READ_ONLY_OPTIMAL`, and is left that way. It needs `TRANSFER_SRC` usage, a format that supports linear
+	/// blits, and a queue with graphics.
+	pub fn record_generate_mips(self: &Arc<Self>, mut cmd: CommandBufferBuilder<B0>) -> CommandBufferBuilder<B0> {
+		let layers = vk::ImageSubresourceLayers::builder().aspect_mask(aspect(self.format)).layer_count(1).build();
+		let corner =
+			|extent: vk::Extent3D| vk::Offset3D { x: extent.width as _, y: extent.height as _, z: extent.depth as _ };
+
+		cmd = cmd.begin_label("generate mips");
+		for level in 1..self.mip_levels {
+			// the base level was uploaded, and the others were just written by the last blit
+			let src_layout = if level == 1 {
+				vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+			} else {
+				vk::ImageLayout::TRANSFER_DST_OPTIMAL
+			};
+			let src =
+				ImageBarrier::new(self.clone(), src_layout, vk::ImageLayout::TRANSFER_SRC_OPTIMAL).levels(level - 1, 1);
+			// the level's old contents are overwritten, so they can be discarded
+			let dst =
+				ImageBarrier::new(self.clone(), vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+					.levels(level, 1);
+			let region = vk::ImageBlit::builder()
+				.src_subresource(vk::ImageSubresourceLayers { mip_level: level - 1, ..layers })
+				.src_offsets([vk::Offset3D::default(), corner(self.level_extent(level - 1))])
+				.dst_subresource(vk::ImageSubresourceLayers { mip_level: level, ..layers })
+				.dst_offsets([vk::Offset3D::default(), corner(self.level_extent(level))])
+				.build();
+			let src_done = ImageBarrier::new(
+				self.clone(),
+				vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+				vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+			)
+			.levels(level - 1, 1);
+
+			cmd = cmd
+				.pipeline_barrier(
+					vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::FRAGMENT_SHADER,
+					vk::PipelineStageFlags::TRANSFER,
+					vec![src, dst],
+					vec![],
+				)
+				.blit_image(
+					self.clone(),
+					vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+					self.clone(),
+					vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+					&[region],
+					vk::Filter::LINEAR,
+				)
+				.pipeline_barrier(
+					vk::PipelineStageFlags::TRANSFER,
+					vk::PipelineStageFlags::FRAGMENT_SHADER,
+					vec![src_done],
+					vec![],
+				);
+		}
+
+		// the last level was only written
+		if self.mip_levels > 1 {
+			let last = ImageBarrier::new(
+				self.clone(),
+				vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+				vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+			)
+			.levels(self.mip_levels - 1, 1);
+			cmd = cmd.pipeline_barrier(
+				vk::PipelineStageFlags::TRANSFER,
+				vk::PipelineStageFlags::FRAGMENT_SHADER,
+				vec![last],
+				vec![],
+			);
+		}
+		cmd.end_label()
+	}
+
+	/// Copies `extent` texels starting at `offset` in mip level `level` into host visible memory on `queue`, after
+	/// everything submitted to it before, as tightly packed rows from the top. The image must be in `layout`, which
+	/// it's left in, and needs `TRANSFER_SRC` usage. The format must be in `block_size`, and the region must cover
+	/// whole blocks.
+	pub fn read_region(
+		self: &Arc<Self>,
+		queue: &Arc<Queue>,
+		pool: &Arc<CommandPool>,
+		layout: vk::ImageLayout,
+		level: u32,
+		offset: vk::Offset3D,
+		extent: vk::Extent3D,
+	) -> Readback<u8> {
+		let (block, bytes) = block_size(self.format).expect("unknown block size for a read back format");
+		let blocks = |x: u32| ((x + block - 1) / block) as usize;
+		let len = blocks(extent.width) * blocks(extent.height) * extent.depth as usize * bytes as usize;
+		let staging = self.device.create_buffer_slice(len, B1, BufferUsageFlags::TRANSFER_DST).uninitialized();
+		self.device.set_object_name(staging.vk, "readback");
+
+		let regions = [vk::BufferImageCopy::builder()
+			.image_subresource(
+				vk::ImageSubresourceLayers::builder()
+					.aspect_mask(aspect(self.format))
+					.mip_level(level)
+					.layer_count(1)
+					.build(),
+			)
+			.image_offset(offset)
+			.image_extent(extent)
+			.build()];
+		// earlier submissions may still be writing it
+		let to_src = ImageBarrier::new(self.clone(), layout, vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+			.access(vk::AccessFlags::MEMORY_WRITE, vk::AccessFlags::TRANSFER_READ)
+			.levels(level, 1);
+		let from_src = ImageBarrier::new(self.clone(), vk::ImageLayout::TRANSFER_SRC_OPTIMAL, layout).levels(level, 1);
+		let copied = BufferBarrier::new(staging.clone(), vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::HOST_READ);
+		let cmd = pool
+			.record(true, false)
+			.pipeline_barrier(
+				vk::PipelineStageFlags::ALL_COMMANDS,
+				vk::PipelineStageFlags::TRANSFER,
+				vec![to_src],
+				vec![],
+			)
+			.copy_image_to_buffer(self.clone(), vk::ImageLayout::TRANSFER_SRC_OPTIMAL, staging.clone(), &regions)
+			.pipeline_barrier(
+				vk::PipelineStageFlags::TRANSFER,
+				vk::PipelineStageFlags::ALL_COMMANDS | vk::PipelineStageFlags::HOST,
+				vec![from_src],
+				vec![copied],
+			)
+			.build();
+		Readback::new(queue.submit(cmd).end(), staging)
+	}
+
+	/// The size of a mip level. Each level is half the size of the last, rounded down, but at least 1.
+	pub fn level_extent(&self, level: u32) -> vk::Extent3D {
+		vk::Extent3D {
+			width: (self.extent.width >> level).max(1),
+			height: (self.extent.height >> level).max(1),
+			depth: (self.extent.depth >> level).max(1),
+		}
+	}
+}
+impl ImageAbstract for Image {
+	fn device(&self) -> &Arc<Device> {
+		&self.device
+	}
+
+	fn vk(&self) -> vk::Image {
+		self.vk
+	}
+
+	fn range(&self) -> vk::ImageSubresourceRange {
+		vk::ImageSubresourceRange::builder()
+			.aspect_mask(aspect(self.format))
+			.level_count(self.mip_levels)
+			.layer_count(1)
+			.build()
+	}
+}
+impl Drop for Image {
+	fn drop(&mut self) {
+		self.device.allocator.destroy_image(self.vk, &self.allocation).unwrap();
+	}
+}
+
+/// An image whose contents are still undefined. Filling it gives back the `Image`, ready for sampling from the
+/// fragment shader.
+pub struct ImageInit {
+	image: Arc<Image>,
+}
+impl ImageInit {
+	pub(crate) fn new(image: Arc<Image>) -> Self {
+		Self { image }
+	}
+
+	/// The image, for creating views before it's filled. Nothing may read it until the copy is complete.
+	pub fn image(&self) -> &Arc<Image> {
+		&self.image
+	}
+
+	/// Skips initialization, for attachments that a render pass clears or overwrites.
+	pub fn attachment(self) -> Arc<Image> {
+		self.image
+	}
+
+	pub fn copy_from_buffer(
+		self,
+		queue: &Arc<Queue>,
+		pool: &Arc<CommandPool>,
+		buffer: Arc<Buffer<[u8]>>,
+	) -> (Arc<Image>, SubmitFuture) {
+		let levels = self.image.mip_levels;
+		let (image, cmd) = self.record_copy_from_buffer(pool.record(true, false), buffer, 0, levels, None);
+		(image, queue.submit(cmd.build()))
+	}
+
+	/// Records the copy from `buffer`, starting `offset` bytes in, into the first `levels` mip levels, with layout
+	/// transitions on either side that cover the whole image. With more than one level, `buffer` holds them in order,
+	/// tightly packed, and the format must be in `block_size`. `offset` must be a multiple of 4 and of the format's
+	/// texel block size. The remaining levels can be filled in with `Image::record_generate_mips`.
+	///
+	/// With a `transfer`, the final barrier releases the image to another queue family, which must then record
+	/// `Image::record_acquire` before using it.
+	pub fn record_copy_from_buffer(
+		self,
+		cmd: CommandBufferBuilder<B0>,
+		buffer: Arc<Buffer<[u8]>>,
+		mut offset: u64,
+		levels: u32,
+		transfer: Option<QueueTransfer>,
+	) -> (Arc<Image>, CommandBufferBuilder<B0>) {
+		let image = self.image;
+		assert!(levels >= 1 && levels <= image.mip_levels);
+
+		let mut regions = Vec::with_capacity(levels as _);
+		for level in 0..levels {
+			let extent = image.level_extent(level);
+			regions.push(
+				vk::BufferImageCopy::builder()
+					.buffer_offset(offset)
+					.image_subresource(
+						vk::ImageSubresourceLayers::builder()
+							.aspect_mask(aspect(image.format))
+							.mip_level(level)
+							.layer_count(1)
+							.build(),
+					)
+					.image_extent(extent)
+					.build(),
+			);
+			if level + 1 < levels {
+				let (block, bytes) = block_size(image.format).expect("unknown block size for a mipmapped format");
+				let blocks = |x: u32| ((x + block - 1) / block) as vk::DeviceSize;
+				offset += blocks(extent.width) * blocks(extent.height) * extent.depth as vk::DeviceSize * bytes as u64;
+			}
+		}
+
+		let cmd = cmd
+			.transition_layout(image.clone(), vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+			.copy_buffer_to_image(buffer, image.clone(), vk::ImageLayout::TRANSFER_DST_OPTIMAL, &regions);
+		let cmd = match transfer {
+			// a release only needs the source half of the barrier. the access and stage are ignored on this side
+			Some(transfer) => {
+				let release = ImageBarrier::new(
+					image.clone(),
+					vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+					vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+				)
+				.access(vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::empty())
+				.transfer(transfer);
+				cmd.pipeline_barrier(
+					vk::PipelineStageFlags::TRANSFER,
+					vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+					vec![release],
+					vec![],
+				)
+			},
+			None => cmd.transition_layout(
+				image.clone(),
+				vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+				vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+			),
+		};
+		(image, cmd)
+	}
+}
+
+/// Moves ownership of an image between queue families.
+#[derive(Clone, Copy, Debug)]
+pub struct QueueTransfer {
+	pub src: u32,
+	pub dst: u32,
+}
+impl QueueTransfer {
+	pub(crate) const IGNORED: Self = Self { src: vk::QUEUE_FAMILY_IGNORED, dst: vk::QUEUE_FAMILY_IGNORED };
+}
 
 pub struct Framebuffer {
 	render_pass: Arc<RenderPass>,
@@ -39,7 +385,44 @@ impl Drop for ImageView {
 	}
 }
 
-pub trait ImageAbstract {
+/// `Send + Sync` so command buffers that hold images can be recorded on other threads.
+pub trait ImageAbstract: Send + Sync {
 	fn device(&self) -> &Arc<Device>;
 	fn vk(&self) -> vk::Image;
+	/// Every mip level and layer, for barriers on the whole image.
+	fn range(&self) -> vk::ImageSubresourceRange;
+}
+
+/// Returns `DEPTH` for depth formats and `COLOR` for everything else.
+/// The width and height in texels of one of the format's blocks, and its size in bytes. Uncompressed formats have 1x1
+/// blocks. Only covers formats whose blocks are a multiple of 4 bytes, so tightly packed mip levels stay aligned for
+/// copies.
+pub fn block_size(format: Format) -> Option<(u32, u32)> {
+	match format {
+		Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB | Format::B8G8R8A8_UNORM | Format::B8G8R8A8_SRGB => Some((1, 4)),
+		Format::BC1_RGB_UNORM_BLOCK
+		| Format::BC1_RGB_SRGB_BLOCK
+		| Format::BC1_RGBA_UNORM_BLOCK
+		| Format::BC1_RGBA_SRGB_BLOCK
+		| Format::BC4_UNORM_BLOCK
+		| Format::BC4_SNORM_BLOCK => Some((4, 8)),
+		Format::BC2_UNORM_BLOCK
+		| Format::BC2_SRGB_BLOCK
+		| Format::BC3_UNORM_BLOCK
+		| Format::BC3_SRGB_BLOCK
+		| Format::BC5_UNORM_BLOCK
+		| Format::BC5_SNORM_BLOCK
+		| Format::BC6H_UFLOAT_BLOCK
+		| Format::BC6H_SFLOAT_BLOCK
+		| Format::BC7_UNORM_BLOCK
+		| Format::BC7_SRGB_BLOCK => Some((4, 16)),
+		_ => None,
+	}
+}
+
+pub fn aspect(format: Format) -> vk::ImageAspectFlags {
+	match format {
+		Format::D16_UNORM | Format::X8_D24_UNORM_PACK32 | Format::D32_SFLOAT => vk::ImageAspectFlags::DEPTH,
+		_ => vk::ImageAspectFlags::COLOR,
+	}
 }
@@ -1,9 +1,17 @@
-use crate::{physical_device::PhysicalDevice, surface::Surface, Vulkan};
+#[cfg(feature = "window-system")]
+use crate::surface::Surface;
+use crate::{physical_device::PhysicalDevice, Vulkan};
+#[cfg(all(feature = "window-system", target_os = "macos"))]
+use ash::extensions::mvk;
+#[cfg(feature = "window-system")]
+use ash::prelude::VkResult;
 use ash::{
 	extensions::{ext, khr},
 	version::{EntryV1_0, InstanceV1_0},
 	vk, vk_make_version, Instance as VkInstance,
 };
+use log::LevelFilter;
+#[cfg(feature = "window-system")]
 use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
 use std::{
 	collections::HashSet,
@@ -15,108 +23,194 @@ pub struct Instance {
 	_vulkan: Arc<Vulkan>,
 	pub vk: VkInstance,
 	pub khr_surface: khr::Surface,
-	#[cfg(windows)]
+	#[cfg(all(feature = "window-system", windows))]
 	pub khr_win32_surface: khr::Win32Surface,
-	#[cfg(unix)]
-	pub khr_xlib_surface: khr::XlibSurface,
-	#[cfg(unix)]
-	pub khr_wayland_surface: khr::WaylandSurface,
-	#[cfg(debug_assertions)]
-	pub debug_utils: ext::DebugUtils,
-	#[cfg(debug_assertions)]
+	/// The window system extensions are each loaded if the driver supports them.
+	#[cfg(all(feature = "window-system", unix, not(target_os = "macos")))]
+	pub khr_xlib_surface: Option<khr::XlibSurface>,
+	#[cfg(all(feature = "window-system", unix, not(target_os = "macos")))]
+	pub khr_xcb_surface: Option<khr::XcbSurface>,
+	#[cfg(all(feature = "window-system", unix, not(target_os = "macos")))]
+	pub khr_wayland_surface: Option<khr::WaylandSurface>,
+	/// Creates surfaces through MoltenVK.
+	#[cfg(all(feature = "window-system", target_os = "macos"))]
+	pub mvk_macos_surface: mvk::MacOSSurface,
+	/// Loaded when validation is enabled and the extension is available.
+	pub debug_utils: Option<ext::DebugUtils>,
 	debug_messenger: vk::DebugUtilsMessengerEXT,
 }
 impl Instance {
-	pub fn new(vulkan: Arc<Vulkan>, application_name: &CStr, application_version: Version) -> Arc<Self> {
+	/// `validation` enables the validation layers, if they're installed, and routes their messages to the `log` crate.
+	/// Only severities that the current log level would show are reported.
+	pub fn new(
+		vulkan: Arc<Vulkan>,
+		application_name: &CStr,
+		application_version: Version,
+		validation: bool,
+	) -> Arc<Self> {
 		let app_info = vk::ApplicationInfo::builder()
 			.application_name(&application_name)
 			.application_version(application_version.vk);
 
-		let mut exts = vec![b"VK_KHR_surface\0".as_ptr() as _];
-		#[cfg(windows)]
-		exts.push(b"VK_KHR_win32_surface\0".as_ptr() as _);
-		#[cfg(unix)]
-		exts.push(b"VK_KHR_xlib_surface\0".as_ptr() as _);
-		#[cfg(debug_assertions)]
-		exts.push(b"VK_EXT_debug_utils\0".as_ptr() as _);
+		let available_exts = vulkan.vk.enumerate_instance_extension_properties().unwrap();
+		let available_exts = available_exts
+			.iter()
+			.map(|props| unsafe { CStr::from_ptr(props.extension_name.as_ptr()) })
+			.collect::<HashSet<_>>();
 
-		#[allow(unused_mut)]
-		let mut layers_pref = HashSet::new();
-		#[cfg(debug_assertions)]
-		layers_pref.insert(CStr::from_bytes_with_nul(b"VK_LAYER_LUNARG_standard_validation\0").unwrap());
+		let mut exts = vec![khr::Surface::name().as_ptr()];
+		#[cfg(all(feature = "window-system", windows))]
+		exts.push(khr::Win32Surface::name().as_ptr());
+		// drivers don't have to support every window system, so only the ones they do are enabled, and a window on any
+		// other fails when its surface is created
+		#[cfg(all(feature = "window-system", unix, not(target_os = "macos")))]
+		let (xlib, xcb, wayland) = {
+			let mut enable = |name: &'static CStr| {
+				let available = available_exts.contains(name);
+				if available {
+					exts.push(name.as_ptr());
+				}
+				available
+			};
+			let xlib = enable(khr::XlibSurface::name());
+			let xcb = enable(khr::XcbSurface::name());
+			let wayland = enable(khr::WaylandSurface::name());
+			if !xlib && !xcb && !wayland {
+				log::warn!("no X11 or Wayland surface extension is available, so windows can't be drawn to");
+			}
+			(xlib, xcb, wayland)
+		};
+		#[cfg(all(feature = "window-system", target_os = "macos"))]
+		exts.push(mvk::MacOSSurface::name().as_ptr());
+		// portability drivers like MoltenVK are only listed if the app says it can handle their missing features
+		let portability = available_exts.contains(portability_enumeration_name());
+		if portability {
+			exts.push(portability_enumeration_name().as_ptr());
+		}
+		let debug_utils_name = ext::DebugUtils::name();
+		let debug_utils_supported = available_exts.contains(debug_utils_name);
+		if validation && !debug_utils_supported {
+			log::warn!("validation was requested, but {:?} isn't available", debug_utils_name);
+		}
+		let debug = validation && debug_utils_supported;
+		if debug {
+			exts.push(debug_utils_name.as_ptr());
+		}
+
+		let available = vulkan.vk.enumerate_instance_layer_properties().unwrap();
+		let available =
+			available.iter().map(|props| unsafe { CStr::from_ptr(props.layer_name.as_ptr()) }).collect::<HashSet<_>>();
+		let mut layers = vec![];
+		if validation {
+			// the khronos layer replaced the lunarg one, and enabling both would report everything twice
+			let validation_layer = [&b"VK_LAYER_KHRONOS_validation\0"[..], b"VK_LAYER_LUNARG_standard_validation\0"]
+				.iter()
+				.map(|name| CStr::from_bytes_with_nul(name).unwrap())
+				.find(|name| available.contains(name));
+			match validation_layer {
+				Some(name) => layers.push(name.as_ptr()),
+				None => log::warn!("validation was requested, but no validation layer is installed"),
+			}
+		}
 		#[cfg(debug_assertions)]
-		layers_pref.insert(CStr::from_bytes_with_nul(b"VK_LAYER_LUNARG_monitor\0").unwrap());
-		let layers = vulkan.vk.enumerate_instance_layer_properties().unwrap();
-		let layers = layers
-			.iter()
-			.map(|props| unsafe { CStr::from_ptr(props.layer_name.as_ptr()) })
-			.collect::<HashSet<_>>()
-			.intersection(&layers_pref)
-			.map(|ext| ext.as_ptr())
-			.collect::<Vec<_>>();
+		{
+			let monitor = CStr::from_bytes_with_nul(b"VK_LAYER_LUNARG_monitor\0").unwrap();
+			if available.contains(monitor) {
+				layers.push(monitor.as_ptr());
+			}
+		}
 
+		// VK_INSTANCE_CREATE_ENUMERATE_PORTABILITY_BIT_KHR, which ash doesn't have yet
+		let flags = vk::InstanceCreateFlags::from_raw(if portability { 1 } else { 0 });
 		let ci = vk::InstanceCreateInfo::builder()
+			.flags(flags)
 			.application_info(&app_info)
 			.enabled_layer_names(&layers)
 			.enabled_extension_names(&exts);
 		let vk = unsafe { vulkan.vk.create_instance(&ci, None) }.unwrap();
 		let khr_surface = khr::Surface::new(&vulkan.vk, &vk);
-		#[cfg(windows)]
+		#[cfg(all(feature = "window-system", windows))]
 		let khr_win32_surface = khr::Win32Surface::new(&vulkan.vk, &vk);
-		#[cfg(unix)]
-		let khr_xlib_surface = khr::XlibSurface::new(&vulkan.vk, &vk);
-		#[cfg(unix)]
-		let khr_wayland_surface = khr::WaylandSurface::new(&vulkan.vk, &vk);
-		#[cfg(debug_assertions)]
-		let debug_utils = ext::DebugUtils::new(&vulkan.vk, &vk);
+		#[cfg(all(feature = "window-system", unix, not(target_os = "macos")))]
+		let khr_xlib_surface = if xlib { Some(khr::XlibSurface::new(&vulkan.vk, &vk)) } else { None };
+		#[cfg(all(feature = "window-system", unix, not(target_os = "macos")))]
+		let khr_xcb_surface = if xcb { Some(khr::XcbSurface::new(&vulkan.vk, &vk)) } else { None };
+		#[cfg(all(feature = "window-system", unix, not(target_os = "macos")))]
+		let khr_wayland_surface = if wayland { Some(khr::WaylandSurface::new(&vulkan.vk, &vk)) } else { None };
+		#[cfg(all(feature = "window-system", target_os = "macos"))]
+		let mvk_macos_surface = mvk::MacOSSurface::new(&vulkan.vk, &vk);
+		let debug_utils = if debug { Some(ext::DebugUtils::new(&vulkan.vk, &vk)) } else { None };
 
-		#[cfg(debug_assertions)]
-		let debug_messenger = {
-			let ci = vk::DebugUtilsMessengerCreateInfoEXT::builder()
-				.message_severity(vk::DebugUtilsMessageSeverityFlagsEXT::all())
-				.message_type(vk::DebugUtilsMessageTypeFlagsEXT::all())
-				.pfn_user_callback(Some(user_callback));
-			unsafe { debug_utils.create_debug_utils_messenger(&ci, None) }.unwrap()
+		let debug_messenger = match &debug_utils {
+			Some(debug_utils) => {
+				let ci = vk::DebugUtilsMessengerCreateInfoEXT::builder()
+					.message_severity(message_severities())
+					.message_type(vk::DebugUtilsMessageTypeFlagsEXT::all())
+					.pfn_user_callback(Some(user_callback));
+				unsafe { debug_utils.create_debug_utils_messenger(&ci, None) }.unwrap()
+			},
+			None => vk::DebugUtilsMessengerEXT::null(),
 		};
 
 		Arc::new(Self {
 			_vulkan: vulkan,
 			vk,
 			khr_surface,
-			#[cfg(windows)]
+			#[cfg(all(feature = "window-system", windows))]
 			khr_win32_surface,
-			#[cfg(unix)]
+			#[cfg(all(feature = "window-system", unix, not(target_os = "macos")))]
 			khr_xlib_surface,
-			#[cfg(unix)]
+			#[cfg(all(feature = "window-system", unix, not(target_os = "macos")))]
+			khr_xcb_surface,
+			#[cfg(all(feature = "window-system", unix, not(target_os = "macos")))]
 			khr_wayland_surface,
-			#[cfg(debug_assertions)]
+			#[cfg(all(feature = "window-system", target_os = "macos"))]
+			mvk_macos_surface,
 			debug_utils,
-			#[cfg(debug_assertions)]
 			debug_messenger,
 		})
 	}
 
-	pub fn create_surface<T: HasRawWindowHandle>(self: &Arc<Self>, window: T) -> Arc<Surface<T>> {
+	/// Fails with `ERROR_EXTENSION_NOT_PRESENT` if the driver doesn't support the window's window system.
+	#[cfg(feature = "window-system")]
+	pub fn create_surface<T: HasRawWindowHandle>(self: &Arc<Self>, window: T) -> VkResult<Arc<Surface<T>>> {
+		let missing = vk::Result::ERROR_EXTENSION_NOT_PRESENT;
 		let vk = match window.raw_window_handle() {
 			#[cfg(windows)]
 			RawWindowHandle::Windows(handle) => {
 				let ci = vk::Win32SurfaceCreateInfoKHR::builder().hinstance(handle.hinstance).hwnd(handle.hwnd);
-				unsafe { self.khr_win32_surface.create_win32_surface(&ci, None) }.unwrap()
+				unsafe { self.khr_win32_surface.create_win32_surface(&ci, None) }?
 			},
-			#[cfg(unix)]
+			#[cfg(all(unix, not(target_os = "macos")))]
 			RawWindowHandle::Xlib(handle) => {
+				let ext = self.khr_xlib_surface.as_ref().ok_or(missing)?;
 				let ci = vk::XlibSurfaceCreateInfoKHR::builder().dpy(handle.display as _).window(handle.window);
-				unsafe { self.khr_xlib_surface.create_xlib_surface(&ci, None) }.unwrap()
+				unsafe { ext.create_xlib_surface(&ci, None) }?
+			},
+			#[cfg(all(unix, not(target_os = "macos")))]
+			RawWindowHandle::Xcb(handle) => {
+				let ext = self.khr_xcb_surface.as_ref().ok_or(missing)?;
+				let ci =
+					vk::XcbSurfaceCreateInfoKHR::builder().connection(handle.connection as _).window(handle.window);
+				unsafe { ext.create_xcb_surface(&ci, None) }?
 			},
-			#[cfg(unix)]
+			#[cfg(all(unix, not(target_os = "macos")))]
 			RawWindowHandle::Wayland(handle) => {
+				let ext = self.khr_wayland_surface.as_ref().ok_or(missing)?;
 				let ci = vk::WaylandSurfaceCreateInfoKHR::builder().display(handle.display).surface(handle.surface);
-				unsafe { self.khr_wayland_surface.create_wayland_surface(&ci, None) }.unwrap()
+				unsafe { ext.create_wayland_surface(&ci, None) }?
 			},
-			_ => unimplemented!(),
+			#[cfg(target_os = "macos")]
+			RawWindowHandle::MacOS(handle) => {
+				// MoltenVK draws into the view's layer, which has to be a metal layer
+				unsafe { attach_metal_layer(handle.ns_view) };
+				let ci = vk::MacOSSurfaceCreateInfoMVK::builder().view(unsafe { &*(handle.ns_view as *const c_void) });
+				unsafe { self.mvk_macos_surface.create_mac_os_surface_mvk(&ci, None) }?
+			},
+			_ => return Err(missing),
 		};
 
-		unsafe { Surface::from_vk(self.clone(), window, vk) }
+		Ok(unsafe { Surface::from_vk(self.clone(), window, vk) })
 	}
 
 	pub fn enumerate_physical_devices<'a>(self: &'a Arc<Instance>) -> impl Iterator<Item = PhysicalDevice<'a>> {
@@ -129,13 +223,18 @@ impl Instance {
 impl Drop for Instance {
 	fn drop(&mut self) {
 		unsafe {
-			#[cfg(debug_assertions)]
-			self.debug_utils.destroy_debug_utils_messenger(self.debug_messenger, None);
+			if let Some(debug_utils) = &self.debug_utils {
+				debug_utils.destroy_debug_utils_messenger(self.debug_messenger, None);
+			}
 			self.vk.destroy_instance(None);
 		}
 	}
 }
 
+fn portability_enumeration_name() -> &'static CStr {
+	CStr::from_bytes_with_nul(b"VK_KHR_portability_enumeration\0").unwrap()
+}
+
 pub struct Version {
 	vk: u32,
 }
@@ -145,7 +244,55 @@ impl Version {
 	}
 }
 
-#[cfg(debug_assertions)]
+/// Gives `view` a `CAMetalLayer` to draw into, unless it already has one.
+#[cfg(all(feature = "window-system", target_os = "macos"))]
+unsafe fn attach_metal_layer(view: *mut c_void) {
+	use objc::{
+		class, msg_send,
+		runtime::{Object, BOOL, NO, YES},
+		sel, sel_impl,
+	};
+
+	let view = view as *mut Object;
+	let current: *mut Object = msg_send![view, layer];
+	let is_metal: BOOL = if current.is_null() { NO } else { msg_send![current, isKindOfClass: class!(CAMetalLayer)] };
+	if is_metal == YES {
+		return;
+	}
+
+	let layer: *mut Object = msg_send![class!(CAMetalLayer), new];
+	let _: () = msg_send![view, setWantsLayer: YES];
+	let _: () = msg_send![view, setLayer: layer];
+	// otherwise the layer renders at one pixel per point and looks blurry on retina displays
+	let window: *mut Object = msg_send![view, window];
+	if !window.is_null() {
+		let scale: f64 = msg_send![window, backingScaleFactor];
+		let _: () = msg_send![layer, setContentsScale: scale];
+	}
+}
+
+/// Where `CAMetalLayer` comes from.
+#[cfg(all(feature = "window-system", target_os = "macos"))]
+#[link(name = "QuartzCore", kind = "framework")]
+extern {}
+
+/// The severities the log crate would show at its current level, so the layers don't format messages just to have
+/// them thrown away.
+fn message_severities() -> vk::DebugUtilsMessageSeverityFlagsEXT {
+	let level = log::max_level();
+	let mut severities = vk::DebugUtilsMessageSeverityFlagsEXT::ERROR;
+	if level >= LevelFilter::Warn {
+		severities |= vk::DebugUtilsMessageSeverityFlagsEXT::WARNING;
+	}
+	if level >= LevelFilter::Info {
+		severities |= vk::DebugUtilsMessageSeverityFlagsEXT::INFO;
+	}
+	if level >= LevelFilter::Debug {
+		severities |= vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE;
+	}
+	severities
+}
+
 unsafe extern "system" fn user_callback(
 	message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
 	message_types: vk::DebugUtilsMessageTypeFlagsEXT,
@@ -1,11 +1,21 @@
+//! A thin wrapper over ash that keeps Vulkan objects alive with `Arc`s for as long as anything uses them, including
+//! command buffers in flight. `examples/triangle.rs` goes from an instance to presenting in a winit window.
+//!
+//! The `window-system` feature, on by default, adds `Instance::create_surface` for any window with a raw window handle.
+//! Without it, the crate is only for offscreen rendering and compute, and doesn't depend on the window system crates.
+
 pub mod buffer;
 pub mod command;
+pub mod descriptor;
 pub mod device;
+pub mod geometry;
 pub mod image;
 pub mod instance;
 pub mod physical_device;
 pub mod pipeline;
+pub mod query;
 pub mod render_pass;
+pub mod sampler;
 pub mod shader;
 pub mod surface;
 pub mod swapchain;
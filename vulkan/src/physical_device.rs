@@ -3,8 +3,8 @@ use crate::{
 	instance::Instance,
 	surface::{PresentMode, Surface, SurfaceCapabilities, SurfaceFormat},
 };
-use ash::{version::InstanceV1_0, vk};
-use std::sync::Arc;
+use ash::{prelude::VkResult, version::InstanceV1_0, vk};
+use std::{ffi::CStr, sync::Arc};
 
 #[derive(Clone, Copy)]
 pub struct PhysicalDevice<'a> {
@@ -15,7 +15,7 @@ impl<'a> PhysicalDevice<'a> {
 	pub fn create_device(
 		&self,
 		qfams: impl IntoIterator<Item = (QueueFamily<'a>, &'a [f32])>,
-	) -> (Arc<Device>, impl Iterator<Item = Arc<Queue>>) {
+	) -> VkResult<(Arc<Device>, impl Iterator<Item = Arc<Queue>>)> {
 		let qcis: Vec<_> = qfams
 			.into_iter()
 			.inspect(|(qfam, _)| assert!(&qfam.physical_device() == self))
@@ -24,11 +24,26 @@ impl<'a> PhysicalDevice<'a> {
 			})
 			.collect();
 
-		let exts = [b"VK_KHR_swapchain\0".as_ptr() as _];
-
-		let ci = vk::DeviceCreateInfo::builder().queue_create_infos(&qcis).enabled_extension_names(&exts);
-		let vk = unsafe { self.instance.vk.create_device(self.vk, &ci, None) }.unwrap();
-		let device = Device::from_vk(self.instance.clone(), self.vk, vk);
+		let mut exts = vec![b"VK_KHR_swapchain\0".as_ptr() as _];
+		// portability drivers like MoltenVK require it to be enabled whenever they have it
+		let portability_subset = CStr::from_bytes_with_nul(b"VK_KHR_portability_subset\0").unwrap();
+		if self.supports_extension(portability_subset) {
+			exts.push(portability_subset.as_ptr());
+		}
+
+		// enabled whenever they're supported, so users can check `Device::features` and fall back on their own
+		let supported = unsafe { self.instance.vk.get_physical_device_features(self.vk) };
+		let features = vk::PhysicalDeviceFeatures::builder()
+			.sampler_anisotropy(supported.sampler_anisotropy == vk::TRUE)
+			.fill_mode_non_solid(supported.fill_mode_non_solid == vk::TRUE)
+			.build();
+
+		let ci = vk::DeviceCreateInfo::builder()
+			.queue_create_infos(&qcis)
+			.enabled_extension_names(&exts)
+			.enabled_features(&features);
+		let vk = unsafe { self.instance.vk.create_device(self.vk, &ci, None) }?;
+		let device = Device::from_vk(self.instance.clone(), self.vk, vk, features);
 
 		let device2 = device.clone();
 		let queues = qcis
@@ -39,7 +54,26 @@ impl<'a> PhysicalDevice<'a> {
 			})
 			.flatten();
 
-		(device, queues)
+		Ok((device, queues))
+	}
+
+	pub fn get_properties(&self) -> vk::PhysicalDeviceProperties {
+		unsafe { self.instance.vk.get_physical_device_properties(self.vk) }
+	}
+
+	pub fn get_format_properties(&self, format: vk::Format) -> vk::FormatProperties {
+		unsafe { self.instance.vk.get_physical_device_format_properties(self.vk, format) }
+	}
+
+	pub fn get_memory_properties(&self) -> vk::PhysicalDeviceMemoryProperties {
+		unsafe { self.instance.vk.get_physical_device_memory_properties(self.vk) }
+	}
+
+	pub fn supports_extension(&self, name: &CStr) -> bool {
+		unsafe { self.instance.vk.enumerate_device_extension_properties(self.vk) }
+			.unwrap()
+			.iter()
+			.any(|props| unsafe { CStr::from_ptr(props.extension_name.as_ptr()) } == name)
 	}
 
 	pub fn get_queue_family_properties(self) -> impl Iterator<Item = QueueFamilyProperties<'a>> {
@@ -92,6 +126,11 @@ impl<'a> QueueFamilyProperties<'a> {
 	pub fn queue_flags(&self) -> QueueFlags {
 		QueueFlags { vk: self.vk.queue_flags }
 	}
+
+	/// How many bits of timestamp queries are meaningful, or 0 if the family doesn't support them.
+	pub fn timestamp_valid_bits(&self) -> u32 {
+		self.vk.timestamp_valid_bits
+	}
 }
 
 #[derive(Clone, Copy)]
@@ -117,4 +156,13 @@ impl QueueFlags {
 	pub fn graphics(self) -> bool {
 		self.vk.contains(vk::QueueFlags::GRAPHICS)
 	}
+
+	pub fn compute(self) -> bool {
+		self.vk.contains(vk::QueueFlags::COMPUTE)
+	}
+
+	/// Graphics and compute families always support transfers even if this returns false.
+	pub fn transfer(self) -> bool {
+		self.vk.contains(vk::QueueFlags::TRANSFER)
+	}
 }
@@ -1,6 +1,8 @@
-pub use ash::vk::Viewport;
+pub use ash::vk::{PushConstantRange, Viewport};
 
-use crate::{device::Device, render_pass::RenderPass, shader::ShaderModule, Extent2D, Offset2D};
+use crate::{
+	descriptor::DescriptorSetLayout, device::Device, render_pass::RenderPass, shader::ShaderModule, Extent2D, Offset2D,
+};
 use ash::{version::DeviceV1_0, vk};
 use std::{
 	ffi::CStr,
@@ -11,11 +13,27 @@ use std::{
 
 pub struct PipelineLayout {
 	device: Arc<Device>,
+	_set_layouts: Vec<Arc<DescriptorSetLayout>>,
+	push_constant_ranges: Vec<PushConstantRange>,
 	pub vk: vk::PipelineLayout,
 }
 impl PipelineLayout {
-	pub(crate) unsafe fn from_vk(device: Arc<Device>, vk: vk::PipelineLayout) -> Arc<Self> {
-		Arc::new(Self { device, vk })
+	pub(crate) unsafe fn from_vk(
+		device: Arc<Device>,
+		set_layouts: Vec<Arc<DescriptorSetLayout>>,
+		push_constant_ranges: Vec<PushConstantRange>,
+		vk: vk::PipelineLayout,
+	) -> Arc<Self> {
+		Arc::new(Self { device, _set_layouts: set_layouts, push_constant_ranges, vk })
+	}
+
+	/// Panics unless `size` bytes at `offset` fall within a single push constant range that covers all of `stages`.
+	pub(crate) fn check_push_constants(&self, stages: vk::ShaderStageFlags, offset: u32, size: u32) {
+		assert!(offset % 4 == 0 && size % 4 == 0, "push constant offset and size must be multiples of 4");
+		let fits = self.push_constant_ranges.iter().any(|range| {
+			range.stage_flags.contains(stages) && range.offset <= offset && offset + size <= range.offset + range.size
+		});
+		assert!(fits, "{} bytes at offset {} aren't in a push constant range for {:?}", size, offset, stages);
 	}
 }
 impl Drop for PipelineLayout {
@@ -38,6 +56,28 @@ impl Drop for Pipeline {
 	}
 }
 
+pub struct ComputePipeline {
+	device: Arc<Device>,
+	_layout: Arc<PipelineLayout>,
+	_shader: Arc<ShaderModule>,
+	pub vk: vk::Pipeline,
+}
+impl ComputePipeline {
+	pub(crate) unsafe fn from_vk(
+		device: Arc<Device>,
+		layout: Arc<PipelineLayout>,
+		shader: Arc<ShaderModule>,
+		vk: vk::Pipeline,
+	) -> Arc<Self> {
+		Arc::new(Self { device, _layout: layout, _shader: shader, vk })
+	}
+}
+impl Drop for ComputePipeline {
+	fn drop(&mut self) {
+		unsafe { self.device.vk.destroy_pipeline(self.vk, None) };
+	}
+}
+
 pub struct PipelineBuilder<'a, T: VertexDesc> {
 	device: Arc<Device>,
 	layout: Arc<PipelineLayout>,
@@ -45,7 +85,18 @@ pub struct PipelineBuilder<'a, T: VertexDesc> {
 	vertex_shader: Option<Arc<ShaderModule>>,
 	fragment_shader: Option<Arc<ShaderModule>>,
 	vertex_input: PhantomData<T>,
+	/// The stride and attributes of binding 1, which advances once per instance.
+	instance_input: Option<(u32, Vec<vk::VertexInputAttributeDescription>)>,
 	viewports: &'a [Viewport],
+	topology: vk::PrimitiveTopology,
+	polygon_mode: vk::PolygonMode,
+	cull_mode: vk::CullModeFlags,
+	dynamic_scissor: bool,
+	dynamic_viewport: bool,
+	samples: vk::SampleCountFlags,
+	blend_src_color: Option<vk::BlendFactor>,
+	depth_test: bool,
+	depth_write: bool,
 }
 impl<'a, T: VertexDesc> PipelineBuilder<'a, T> {
 	pub fn build(self) -> Arc<Pipeline> {
@@ -66,17 +117,26 @@ impl<'a, T: VertexDesc> PipelineBuilder<'a, T> {
 			);
 		}
 
-		let vertex_binding_descriptions = [vk::VertexInputBindingDescription::builder()
+		let mut vertex_binding_descriptions = vec![vk::VertexInputBindingDescription::builder()
 			.binding(0)
 			.stride(size_of::<T>() as _)
 			.input_rate(vk::VertexInputRate::VERTEX)
 			.build()];
-		let vertex_attribute_descriptions = T::attribute_descs();
+		let mut vertex_attribute_descriptions = T::attribute_descs();
+		if let Some((stride, attributes)) = &self.instance_input {
+			vertex_binding_descriptions.push(
+				vk::VertexInputBindingDescription::builder()
+					.binding(1)
+					.stride(*stride)
+					.input_rate(vk::VertexInputRate::INSTANCE)
+					.build(),
+			);
+			vertex_attribute_descriptions.extend_from_slice(attributes);
+		}
 		let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder()
 			.vertex_binding_descriptions(&vertex_binding_descriptions)
 			.vertex_attribute_descriptions(&vertex_attribute_descriptions);
-		let input_assembly_state =
-			vk::PipelineInputAssemblyStateCreateInfo::builder().topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+		let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder().topology(self.topology);
 		let scissors: Vec<_> = self
 			.viewports
 			.iter()
@@ -87,18 +147,44 @@ impl<'a, T: VertexDesc> PipelineBuilder<'a, T> {
 					.build()
 			})
 			.collect();
-		let viewport_state =
-			vk::PipelineViewportStateCreateInfo::builder().viewports(self.viewports).scissors(&scissors);
+		let viewport_state = if self.dynamic_viewport {
+			vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1)
+		} else {
+			vk::PipelineViewportStateCreateInfo::builder().viewports(self.viewports).scissors(&scissors)
+		};
 		let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
-			.polygon_mode(vk::PolygonMode::FILL)
-			.cull_mode(vk::CullModeFlags::BACK)
+			.polygon_mode(self.polygon_mode)
+			.cull_mode(self.cull_mode)
 			.front_face(vk::FrontFace::CLOCKWISE)
 			.line_width(1.0);
-		let multisample_state =
-			vk::PipelineMultisampleStateCreateInfo::builder().rasterization_samples(vk::SampleCountFlags::TYPE_1);
-		let attachments =
-			[vk::PipelineColorBlendAttachmentState::builder().color_write_mask(vk::ColorComponentFlags::all()).build()];
+		let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder().rasterization_samples(self.samples);
+		let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+			.depth_test_enable(self.depth_test)
+			.depth_write_enable(self.depth_write)
+			.depth_compare_op(vk::CompareOp::LESS)
+			.max_depth_bounds(1.0);
+		let mut attachment =
+			vk::PipelineColorBlendAttachmentState::builder().color_write_mask(vk::ColorComponentFlags::all());
+		if let Some(src_color_blend_factor) = self.blend_src_color {
+			attachment = attachment
+				.blend_enable(true)
+				.src_color_blend_factor(src_color_blend_factor)
+				.dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+				.color_blend_op(vk::BlendOp::ADD)
+				.src_alpha_blend_factor(vk::BlendFactor::ONE)
+				.dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+				.alpha_blend_op(vk::BlendOp::ADD);
+		}
+		let attachments = [attachment.build()];
 		let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder().attachments(&attachments);
+		let dynamic_states = if self.dynamic_viewport {
+			&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR][..]
+		} else if self.dynamic_scissor {
+			&[vk::DynamicState::SCISSOR][..]
+		} else {
+			&[]
+		};
+		let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
 		let cis = [vk::GraphicsPipelineCreateInfo::builder()
 			.stages(&stages)
 			.vertex_input_state(&vertex_input_state)
@@ -106,7 +192,9 @@ impl<'a, T: VertexDesc> PipelineBuilder<'a, T> {
 			.viewport_state(&viewport_state)
 			.rasterization_state(&rasterization_state)
 			.multisample_state(&multisample_state)
+			.depth_stencil_state(&depth_stencil_state)
 			.color_blend_state(&color_blend_state)
+			.dynamic_state(&dynamic_state)
 			.layout(self.layout.vk)
 			.render_pass(self.render_pass.vk)
 			.build()];
@@ -122,6 +210,62 @@ impl<'a, T: VertexDesc> PipelineBuilder<'a, T> {
 		})
 	}
 
+	pub fn alpha_blending(mut self) -> Self {
+		self.blend_src_color = Some(vk::BlendFactor::SRC_ALPHA);
+		self
+	}
+
+	/// Like `alpha_blending`, but for fragment colors that are already multiplied by their alpha.
+	pub fn premultiplied_alpha_blending(mut self) -> Self {
+		self.blend_src_color = Some(vk::BlendFactor::ONE);
+		self
+	}
+
+	/// Defaults to a triangle list.
+	pub fn topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+		self.topology = topology;
+		self
+	}
+
+	/// Defaults to filling triangles. Anything else needs the device's `fill_mode_non_solid` feature.
+	pub fn polygon_mode(mut self, polygon_mode: vk::PolygonMode) -> Self {
+		self.polygon_mode = polygon_mode;
+		self
+	}
+
+	/// Defaults to culling back faces, which are counter-clockwise.
+	pub fn cull_mode(mut self, cull_mode: vk::CullModeFlags) -> Self {
+		self.cull_mode = cull_mode;
+		self
+	}
+
+	/// Leaves the scissors to be set with `CommandBufferBuilder::set_scissor` instead of matching the viewports.
+	pub fn dynamic_scissor(mut self) -> Self {
+		self.dynamic_scissor = true;
+		self
+	}
+
+	/// Leaves a single viewport and scissor to be set with `CommandBufferBuilder::set_viewport` and `set_scissor`, so
+	/// the pipeline doesn't need to be rebuilt when the framebuffer is resized. Overrides `viewports`.
+	pub fn dynamic_viewport(mut self) -> Self {
+		self.dynamic_viewport = true;
+		self
+	}
+
+	/// Discards fragments behind what's already in the depth attachment, and if `write` is true, stores the depth of
+	/// the ones that pass. Disabled by default.
+	pub fn depth_test(mut self, write: bool) -> Self {
+		self.depth_test = true;
+		self.depth_write = write;
+		self
+	}
+
+	/// Must match the sample count of the subpass's attachments. Defaults to 1.
+	pub fn samples(mut self, samples: vk::SampleCountFlags) -> Self {
+		self.samples = samples;
+		self
+	}
+
 	pub fn vertex_shader(mut self, vertex_shader: Arc<ShaderModule>) -> Self {
 		self.vertex_shader = Some(vertex_shader);
 		self
@@ -136,6 +280,13 @@ impl<'a, T: VertexDesc> PipelineBuilder<'a, T> {
 		unsafe { transmute(self) }
 	}
 
+	/// Adds binding 1, which advances once per instance instead of once per vertex. `I`'s attributes must use binding
+	/// 1 and locations after the vertex attributes.
+	pub fn instance_input<I: VertexDesc>(mut self) -> Self {
+		self.instance_input = Some((size_of::<I>() as _, I::attribute_descs()));
+		self
+	}
+
 	pub fn viewports<'b>(self, viewports: &'b [Viewport]) -> PipelineBuilder<'b, T> {
 		let mut this: PipelineBuilder<'b, T> = unsafe { transmute(self) };
 		this.viewports = viewports;
@@ -150,7 +301,17 @@ impl<'a, T: VertexDesc> PipelineBuilder<'a, T> {
 			vertex_shader: None,
 			fragment_shader: None,
 			vertex_input: PhantomData,
+			instance_input: None,
 			viewports: &[],
+			topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+			polygon_mode: vk::PolygonMode::FILL,
+			cull_mode: vk::CullModeFlags::BACK,
+			dynamic_scissor: false,
+			dynamic_viewport: false,
+			samples: vk::SampleCountFlags::TYPE_1,
+			blend_src_color: None,
+			depth_test: false,
+			depth_write: false,
 		}
 	}
 }
@@ -0,0 +1,37 @@
+use crate::device::Device;
+use ash::{prelude::VkResult, version::DeviceV1_0, vk};
+use std::sync::Arc;
+
+pub struct QueryPool {
+	device: Arc<Device>,
+	pub vk: vk::QueryPool,
+}
+impl QueryPool {
+	pub(crate) unsafe fn from_vk(device: Arc<Device>, vk: vk::QueryPool) -> Arc<Self> {
+		Arc::new(Self { device, vk })
+	}
+
+	/// Reads the 64-bit results of the queries starting at `first`, one for each element of `results`. Returns
+	/// `false` without waiting if any of them aren't available yet.
+	pub fn get_results(&self, first: u32, results: &mut [u64]) -> VkResult<bool> {
+		let res = unsafe {
+			self.device.vk.get_query_pool_results(
+				self.vk,
+				first,
+				results.len() as _,
+				results,
+				vk::QueryResultFlags::TYPE_64,
+			)
+		};
+		match res {
+			Ok(()) => Ok(true),
+			Err(vk::Result::NOT_READY) => Ok(false),
+			Err(err) => Err(err),
+		}
+	}
+}
+impl Drop for QueryPool {
+	fn drop(&mut self) {
+		unsafe { self.device.vk.destroy_query_pool(self.vk, None) };
+	}
+}
@@ -1,5 +1,9 @@
-use crate::device::Device;
-use ash::{version::DeviceV1_0, vk};
+pub use ash::vk::{
+	AccessFlags, AttachmentLoadOp, AttachmentStoreOp, ImageLayout, PipelineStageFlags, SampleCountFlags,
+};
+
+use crate::{device::Device, image::Format};
+use ash::{prelude::VkResult, version::DeviceV1_0, vk};
 use std::sync::Arc;
 
 pub struct RenderPass {
@@ -21,63 +25,186 @@ impl Drop for RenderPass {
 	}
 }
 
-#[macro_export]
-#[rustfmt::skip]
-macro_rules! ordered_passes_renderpass {
-	(
-		$device:expr,
-		attachments: {
-			$(
-				$atch_name:ident : {
-					load: $load:ident,
-					store: $store:ident,
-					format: $format:expr,
-					samples: $samples:expr,
-					$(initial_layout: $init_layout:expr,)*
-					$(final_layout: $final_layout:expr,)*
+/// Attachments and subpasses are referred to by the order they were added in.
+pub struct RenderPassBuilder {
+	device: Arc<Device>,
+	attachments: Vec<Attachment>,
+	subpasses: Vec<Subpass>,
+	dependencies: Vec<vk::SubpassDependency>,
+}
+impl RenderPassBuilder {
+	pub(crate) fn new(device: Arc<Device>) -> Self {
+		Self { device, attachments: vec![], subpasses: vec![], dependencies: vec![] }
+	}
+
+	pub fn build(self) -> VkResult<Arc<RenderPass>> {
+		let attachments: Vec<_> = self.attachments.iter().map(Attachment::to_vk).collect();
+		let refs: Vec<_> = self.subpasses.iter().map(Subpass::refs).collect();
+		let subpasses: Vec<_> = refs
+			.iter()
+			.map(|(color, resolve, depth)| {
+				let mut subpass = vk::SubpassDescription::builder()
+					.pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+					.color_attachments(color);
+				if !resolve.is_empty() {
+					subpass = subpass.resolve_attachments(resolve);
 				}
-			),*
-		},
-		passes: [
-			$(
-				{
-					color: [$($color_atch:ident),*],
-					depth_stencil: { $($depth_atch:ident)* },
-					input: [$($input_atch:ident),*] $(,)*
-					$(resolve: [$($resolve_atch:ident),*])* $(,)*
+				if let Some(depth) = depth {
+					subpass = subpass.depth_stencil_attachment(depth);
 				}
-			),*
-		]
-	) => {{
-		let attachments = [$(
-			vk::AttachmentDescription::builder()
-				.format($format)
-				.samples(vk::SampleCountFlags::TYPE_1)
-				.load_op(vk::AttachmentLoadOp::CLEAR)
-				.store_op(vk::AttachmentStoreOp::STORE)
-				.stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-				.stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-				.initial_layout(vk::ImageLayout::UNDEFINED)
-				.final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
-				.build()
-		),*];
-		let color_attachments =
-			[vk::AttachmentReference::builder().layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL).build()];
-		let subpasses = [vk::SubpassDescription::builder()
-			.pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-			.color_attachments(&color_attachments)
-			.build()];
-		let dependencies = [vk::SubpassDependency::builder()
-			.src_subpass(vk::SUBPASS_EXTERNAL)
-			.src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-			.dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-			.dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
-			.build()];
+				subpass.build()
+			})
+			.collect();
+
 		let ci = vk::RenderPassCreateInfo::builder()
 			.attachments(&attachments)
 			.subpasses(&subpasses)
-			.dependencies(&dependencies);
-		let vk = unsafe { $device.vk.create_render_pass(&ci, None) }.unwrap();
-		unsafe { vulkan::render_pass::RenderPass::from_vk($device.clone(), vk) }
-	}};
+			.dependencies(&self.dependencies);
+		self.device.create_render_pass(&ci)
+	}
+
+	pub fn attachment(mut self, attachment: Attachment) -> Self {
+		self.attachments.push(attachment);
+		self
+	}
+
+	pub fn subpass(mut self, subpass: Subpass) -> Self {
+		self.subpasses.push(subpass);
+		self
+	}
+
+	pub fn dependency(mut self, dependency: vk::SubpassDependency) -> Self {
+		self.dependencies.push(dependency);
+		self
+	}
+
+	/// Makes the first subpass wait on work submitted before the render pass, like the previous frame's use of the
+	/// same attachments.
+	pub fn external_dependency(
+		self,
+		src_stage: PipelineStageFlags,
+		src_access: AccessFlags,
+		dst_stage: PipelineStageFlags,
+		dst_access: AccessFlags,
+	) -> Self {
+		self.dependency(
+			vk::SubpassDependency::builder()
+				.src_subpass(vk::SUBPASS_EXTERNAL)
+				.dst_subpass(0)
+				.src_stage_mask(src_stage)
+				.src_access_mask(src_access)
+				.dst_stage_mask(dst_stage)
+				.dst_access_mask(dst_access)
+				.build(),
+		)
+	}
+
+	/// A single subpass that clears and draws to one color attachment, which ends up in `final_layout`.
+	pub fn single_color(self, format: Format, final_layout: ImageLayout) -> Self {
+		self.attachment(Attachment::new(format, final_layout)).subpass(Subpass::new().color(0)).external_dependency(
+			PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+			AccessFlags::empty(),
+			PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+			AccessFlags::COLOR_ATTACHMENT_READ | AccessFlags::COLOR_ATTACHMENT_WRITE,
+		)
+	}
+}
+
+/// Defaults to a single sample that's cleared at the start of the pass and stored at the end, with its previous
+/// contents discarded. Stencil contents are always discarded.
+#[derive(Clone, Copy, Debug)]
+pub struct Attachment {
+	format: Format,
+	samples: SampleCountFlags,
+	load: AttachmentLoadOp,
+	store: AttachmentStoreOp,
+	initial_layout: ImageLayout,
+	final_layout: ImageLayout,
+}
+impl Attachment {
+	pub fn new(format: Format, final_layout: ImageLayout) -> Self {
+		Self {
+			format,
+			samples: SampleCountFlags::TYPE_1,
+			load: AttachmentLoadOp::CLEAR,
+			store: AttachmentStoreOp::STORE,
+			initial_layout: ImageLayout::UNDEFINED,
+			final_layout,
+		}
+	}
+
+	pub fn samples(mut self, samples: SampleCountFlags) -> Self {
+		self.samples = samples;
+		self
+	}
+
+	pub fn load(mut self, load: AttachmentLoadOp) -> Self {
+		self.load = load;
+		self
+	}
+
+	pub fn store(mut self, store: AttachmentStoreOp) -> Self {
+		self.store = store;
+		self
+	}
+
+	pub fn initial_layout(mut self, initial_layout: ImageLayout) -> Self {
+		self.initial_layout = initial_layout;
+		self
+	}
+
+	fn to_vk(&self) -> vk::AttachmentDescription {
+		vk::AttachmentDescription::builder()
+			.format(self.format)
+			.samples(self.samples)
+			.load_op(self.load)
+			.store_op(self.store)
+			.stencil_load_op(AttachmentLoadOp::DONT_CARE)
+			.stencil_store_op(AttachmentStoreOp::DONT_CARE)
+			.initial_layout(self.initial_layout)
+			.final_layout(self.final_layout)
+			.build()
+	}
+}
+
+/// A graphics subpass. Attachments are in `COLOR_ATTACHMENT_OPTIMAL` or `DEPTH_STENCIL_ATTACHMENT_OPTIMAL` while it
+/// runs.
+#[derive(Clone, Debug, Default)]
+pub struct Subpass {
+	color: Vec<u32>,
+	resolve: Vec<u32>,
+	depth: Option<u32>,
+}
+impl Subpass {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn color(mut self, attachment: u32) -> Self {
+		self.color.push(attachment);
+		self
+	}
+
+	/// Resolves the color attachment at the same position into `attachment`. Either every color attachment gets a
+	/// resolve attachment or none do.
+	pub fn resolve(mut self, attachment: u32) -> Self {
+		self.resolve.push(attachment);
+		self
+	}
+
+	pub fn depth(mut self, attachment: u32) -> Self {
+		self.depth = Some(attachment);
+		self
+	}
+
+	fn refs(&self) -> (Vec<vk::AttachmentReference>, Vec<vk::AttachmentReference>, Option<vk::AttachmentReference>) {
+		assert!(self.resolve.is_empty() || self.resolve.len() == self.color.len());
+		let color_ref =
+			|&attachment| vk::AttachmentReference { attachment, layout: ImageLayout::COLOR_ATTACHMENT_OPTIMAL };
+		let depth = self.depth.map(|attachment| vk::AttachmentReference {
+			attachment,
+			layout: ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+		});
+		(self.color.iter().map(color_ref).collect(), self.resolve.iter().map(color_ref).collect(), depth)
+	}
 }
@@ -0,0 +1,98 @@
+pub use ash::vk::{Filter, SamplerAddressMode, SamplerMipmapMode};
+
+use crate::device::Device;
+use ash::{version::DeviceV1_0, vk};
+use std::sync::Arc;
+
+pub struct Sampler {
+	device: Arc<Device>,
+	pub vk: vk::Sampler,
+}
+impl Sampler {
+	pub(crate) unsafe fn from_vk(device: Arc<Device>, vk: vk::Sampler) -> Arc<Self> {
+		Arc::new(Self { device, vk })
+	}
+}
+impl Drop for Sampler {
+	fn drop(&mut self) {
+		unsafe { self.device.vk.destroy_sampler(self.vk, None) };
+	}
+}
+
+/// Defaults to nearest filtering and repeating in every direction, like a zeroed `VkSamplerCreateInfo`, except that
+/// every mip level can be sampled.
+pub struct SamplerBuilder {
+	device: Arc<Device>,
+	mag_filter: Filter,
+	min_filter: Filter,
+	mipmap_mode: SamplerMipmapMode,
+	address_modes: [SamplerAddressMode; 3],
+	max_anisotropy: Option<f32>,
+}
+impl SamplerBuilder {
+	pub(crate) fn new(device: Arc<Device>) -> Self {
+		Self {
+			device,
+			mag_filter: Filter::NEAREST,
+			min_filter: Filter::NEAREST,
+			mipmap_mode: SamplerMipmapMode::NEAREST,
+			address_modes: [SamplerAddressMode::REPEAT; 3],
+			max_anisotropy: None,
+		}
+	}
+
+	/// Sets both the magnification and minification filters.
+	pub fn filter(self, filter: Filter) -> Self {
+		self.mag_filter(filter).min_filter(filter)
+	}
+
+	pub fn mag_filter(mut self, filter: Filter) -> Self {
+		self.mag_filter = filter;
+		self
+	}
+
+	pub fn min_filter(mut self, filter: Filter) -> Self {
+		self.min_filter = filter;
+		self
+	}
+
+	pub fn mipmap_mode(mut self, mode: SamplerMipmapMode) -> Self {
+		self.mipmap_mode = mode;
+		self
+	}
+
+	/// Sets the same mode for the u, v and w coordinates.
+	pub fn address_mode(self, mode: SamplerAddressMode) -> Self {
+		self.address_modes(mode, mode, mode)
+	}
+
+	pub fn address_modes(mut self, u: SamplerAddressMode, v: SamplerAddressMode, w: SamplerAddressMode) -> Self {
+		self.address_modes = [u, v, w];
+		self
+	}
+
+	/// Clamped to the device's limit. Ignored if the device doesn't support anisotropic filtering.
+	pub fn anisotropy(mut self, max: f32) -> Self {
+		self.max_anisotropy = Some(max);
+		self
+	}
+
+	pub fn build(self) -> Arc<Sampler> {
+		let max_anisotropy = self.max_anisotropy.filter(|_| self.device.features().sampler_anisotropy == vk::TRUE);
+		let max_anisotropy = max_anisotropy
+			.map(|max| max.min(self.device.physical_device().get_properties().limits.max_sampler_anisotropy));
+
+		let ci = vk::SamplerCreateInfo::builder()
+			.mag_filter(self.mag_filter)
+			.min_filter(self.min_filter)
+			.mipmap_mode(self.mipmap_mode)
+			.address_mode_u(self.address_modes[0])
+			.address_mode_v(self.address_modes[1])
+			.address_mode_w(self.address_modes[2])
+			.anisotropy_enable(max_anisotropy.is_some())
+			.max_anisotropy(max_anisotropy.unwrap_or(1.0))
+			.max_lod(vk::LOD_CLAMP_NONE);
+		let vk = unsafe { self.device.vk.create_sampler(&ci, None) }.unwrap();
+		unsafe { Sampler::from_vk(self.device, vk) }
+	}
+}
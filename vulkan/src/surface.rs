@@ -3,7 +3,7 @@ pub use ash::vk::{
 	SurfaceFormatKHR as SurfaceFormat, SurfaceTransformFlagsKHR as SurfaceTransformFlags,
 };
 
-use crate::instance::Instance;
+use crate::{geometry::Extent, image::Format, instance::Instance, physical_device::PhysicalDevice, Extent2D};
 use ash::vk;
 use std::sync::Arc;
 
@@ -17,6 +17,41 @@ impl<T> Surface<T> {
 		&self.window
 	}
 
+	pub fn capabilities(&self, physical: &PhysicalDevice) -> SurfaceCapabilities {
+		physical.get_surface_capabilities(self)
+	}
+
+	pub fn formats(&self, physical: &PhysicalDevice) -> Vec<SurfaceFormat> {
+		physical.get_surface_formats(self)
+	}
+
+	pub fn present_modes(&self, physical: &PhysicalDevice) -> Vec<PresentMode> {
+		physical.get_surface_present_modes(self)
+	}
+
+	/// The first of `preferred` the surface supports, or the first format it lists if it supports none of them.
+	/// Returns `None` if it lists no formats at all.
+	pub fn choose_format(&self, physical: &PhysicalDevice, preferred: &[SurfaceFormat]) -> Option<SurfaceFormat> {
+		let supported = self.formats(physical);
+		// a single undefined format means the surface takes anything
+		if let [only] = supported[..] {
+			if only.format == Format::UNDEFINED {
+				let fallback = SurfaceFormat { format: Format::B8G8R8A8_UNORM, color_space: only.color_space };
+				return Some(preferred.first().copied().unwrap_or(fallback));
+			}
+		}
+		let is_supported =
+			|a: &&SurfaceFormat| supported.iter().any(|b| a.format == b.format && a.color_space == b.color_space);
+		preferred.iter().find(is_supported).or_else(|| supported.first()).copied()
+	}
+
+	/// The first of `preferred` the surface supports, or FIFO, which every surface supports.
+	pub fn choose_present_mode(&self, physical: &PhysicalDevice, preferred: &[PresentMode]) -> PresentMode {
+		let supported = self.present_modes(physical);
+		preferred.iter().copied().find(|mode| supported.contains(mode)).unwrap_or(PresentMode::FIFO)
+	}
+
+	#[cfg(feature = "window-system")]
 	pub(crate) unsafe fn from_vk(instance: Arc<Instance>, window: T, vk: vk::SurfaceKHR) -> Arc<Self> {
 		Arc::new(Self { instance, window, vk })
 	}
@@ -26,3 +61,20 @@ impl<T> Drop for Surface<T> {
 		unsafe { self.instance.khr_surface.destroy_surface(self.vk, None) };
 	}
 }
+
+/// The size a swapchain for a surface with `caps` must be. Surfaces that let the swapchain decide report a current
+/// extent of `u32::MAX`, and get `wanted`, which is usually the window's size, clamped to what they support.
+pub fn image_extent(caps: &SurfaceCapabilities, wanted: impl Into<Extent>) -> Extent2D {
+	if caps.current_extent.width != u32::MAX {
+		return caps.current_extent;
+	}
+	wanted.into().clamp(caps.min_image_extent.into(), caps.max_image_extent.into()).into()
+}
+
+/// One more image than the minimum, so acquiring rarely waits on the driver, within the maximum if there is one.
+pub fn image_count(caps: &SurfaceCapabilities) -> u32 {
+	match caps.max_image_count {
+		0 => caps.min_image_count + 1,
+		max => (caps.min_image_count + 1).min(max),
+	}
+}
@@ -9,12 +9,16 @@ use std::sync::Mutex;
 
 use crate::{
 	device::Device,
+	geometry::Extent,
 	image::Format,
-	surface::{ColorSpace, PresentMode, Surface, SurfaceTransformFlags},
+	surface::{
+		image_count, image_extent, ColorSpace, PresentMode, Surface, SurfaceCapabilities, SurfaceFormat,
+		SurfaceTransformFlags,
+	},
 	Extent2D,
 };
 use ash::vk;
-use std::sync::Arc;
+use std::{iter::empty, sync::Arc};
 
 pub struct Swapchain<T> {
 	device: Arc<Device>,
@@ -60,7 +64,8 @@ impl<T> Swapchain<T> {
 		min_image_count: u32,
 		image_format: Format,
 		image_color_space: ColorSpace,
-		image_extent: Extent2D,
+		image_extent: impl Into<Extent>,
+		image_usage: vk::ImageUsageFlags,
 		queue_families: impl IntoIterator<Item = QueueFamily<'a>>,
 		pre_transform: SurfaceTransformFlags,
 		composite_alpha: CompositeAlphaFlags,
@@ -72,6 +77,7 @@ impl<T> Swapchain<T> {
 			.map(|qfam| qfam.idx)
 			.collect();
 
+		let image_extent: Extent = image_extent.into();
 		let image_sharing_mode =
 			if queue_family_indices.len() > 1 { vk::SharingMode::CONCURRENT } else { vk::SharingMode::EXCLUSIVE };
 
@@ -80,9 +86,9 @@ impl<T> Swapchain<T> {
 			.min_image_count(min_image_count)
 			.image_format(image_format)
 			.image_color_space(image_color_space)
-			.image_extent(image_extent)
+			.image_extent(image_extent.into())
 			.image_array_layers(1)
-			.image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+			.image_usage(image_usage)
 			.image_sharing_mode(image_sharing_mode)
 			.queue_family_indices(&queue_family_indices)
 			.pre_transform(pre_transform)
@@ -129,7 +135,7 @@ impl<T> SwapchainImage<T> {
 		Arc::new(Self { swapchain, vk })
 	}
 }
-impl<T> ImageAbstract for SwapchainImage<T> {
+impl<T: Send + Sync> ImageAbstract for SwapchainImage<T> {
 	fn device(&self) -> &Arc<Device> {
 		&self.swapchain.device
 	}
@@ -137,6 +143,186 @@ impl<T> ImageAbstract for SwapchainImage<T> {
 	fn vk(&self) -> vk::Image {
 		self.vk
 	}
+
+	fn range(&self) -> vk::ImageSubresourceRange {
+		vk::ImageSubresourceRange::builder()
+			.aspect_mask(vk::ImageAspectFlags::COLOR)
+			.level_count(1)
+			.layer_count(1)
+			.build()
+	}
+}
+
+/// Owns a swapchain and recreates it whenever it's out of date or suboptimal, so users only have to rebuild what they
+/// made from its images.
+pub struct SwapchainManager<T> {
+	device: Arc<Device>,
+	format: SurfaceFormat,
+	usage: vk::ImageUsageFlags,
+	present_mode: PresentMode,
+	/// The size to use when the surface lets the swapchain decide, which is usually the window's size.
+	window_extent: Box<dyn Fn(&T) -> Extent2D + Send + Sync>,
+	swapchain: Arc<Swapchain<T>>,
+	images: Vec<Arc<SwapchainImage<T>>>,
+	extent: Extent2D,
+	out_of_date: bool,
+}
+impl<T: Send + Sync + 'static> SwapchainManager<T> {
+	/// Uses the first of `formats` the surface supports, or the first one it lists if it supports none of them, and the
+	/// first of `present_modes` it supports, or FIFO since that's always supported. Returns `None` if the surface has
+	/// no formats at all, or doesn't support `usage` for its images.
+	pub fn new(
+		device: Arc<Device>,
+		surface: Arc<Surface<T>>,
+		formats: &[SurfaceFormat],
+		usage: vk::ImageUsageFlags,
+		present_modes: &[PresentMode],
+		window_extent: impl Fn(&T) -> Extent2D + Send + Sync + 'static,
+	) -> Option<Self> {
+		let physical = device.physical_device();
+		let format = surface.choose_format(&physical, formats)?;
+		let present_mode = surface.choose_present_mode(&physical, present_modes);
+
+		let caps = surface.capabilities(&physical);
+		if !caps.supported_usage_flags.contains(usage) {
+			return None;
+		}
+		let window_extent = Box::new(window_extent);
+		// a zero-sized swapchain can't be created, but the first acquire will recreate it at the right size anyway
+		let extent = image_extent(&caps, window_extent(surface.window()));
+		let extent = Extent2D { width: extent.width.max(1), height: extent.height.max(1) };
+		let (swapchain, images) = create(&device, surface, &caps, format, usage, extent, present_mode, None);
+
+		Some(Self { device, format, usage, present_mode, window_extent, swapchain, images, extent, out_of_date: false })
+	}
+
+	/// Acquires the next image, recreating the swapchain first if it's out of date. `recreated` is called with the new
+	/// images and extent whenever that happens, so anything made from them can be rebuilt. Returns `None` if there's
+	/// nothing to draw to, like while the window is minimized.
+	pub fn acquire(
+		&mut self,
+		timeout: u64,
+		mut recreated: impl FnMut(&[Arc<SwapchainImage<T>>], Extent2D),
+	) -> Option<(u32, AcquireFuture<T>)> {
+		// one retry, since the surface can change again between recreating and acquiring
+		for _ in 0..2 {
+			if self.out_of_date {
+				if !self.recreate() {
+					return None;
+				}
+				recreated(&self.images, self.extent);
+			}
+
+			match self.swapchain.acquire_next_image(timeout) {
+				Ok((idx, suboptimal, future)) => {
+					// the image can still be presented, so the swapchain is replaced before the next one instead
+					self.out_of_date = suboptimal;
+					return Some((idx, future));
+				},
+				Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.out_of_date = true,
+				Err(err) => panic!("{}", err),
+			}
+		}
+		None
+	}
+
+	/// Presents an image from `acquire` once `prev` is done.
+	pub fn present(&mut self, prev: impl GpuFuture, queue: Arc<Queue>, image_idx: u32) {
+		match Swapchain::present_after(prev, queue, &[self.swapchain.clone()], &[image_idx]) {
+			Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.out_of_date = true,
+			Ok(false) => (),
+			Err(err) => panic!("{}", err),
+		}
+	}
+
+	/// Recreates the swapchain before the next acquire, for changes the surface might not report, like switching to
+	/// fullscreen.
+	pub fn invalidate(&mut self) {
+		self.out_of_date = true;
+	}
+
+	/// Picks a new present mode the same way `new` does. The swapchain is only recreated if the mode actually changes.
+	pub fn set_present_modes(&mut self, present_modes: &[PresentMode]) {
+		let present_mode = self.surface().choose_present_mode(&self.device.physical_device(), present_modes);
+		if present_mode != self.present_mode {
+			self.present_mode = present_mode;
+			self.out_of_date = true;
+		}
+	}
+
+	pub fn surface(&self) -> &Arc<Surface<T>> {
+		self.swapchain.surface()
+	}
+
+	pub fn format(&self) -> SurfaceFormat {
+		self.format
+	}
+
+	pub fn present_mode(&self) -> PresentMode {
+		self.present_mode
+	}
+
+	pub fn images(&self) -> &[Arc<SwapchainImage<T>>] {
+		&self.images
+	}
+
+	pub fn extent(&self) -> Extent2D {
+		self.extent
+	}
+
+	/// Returns false without recreating anything if the surface has no area.
+	fn recreate(&mut self) -> bool {
+		let surface = self.swapchain.surface().clone();
+		let caps = surface.capabilities(&self.device.physical_device());
+		let extent = image_extent(&caps, (self.window_extent)(surface.window()));
+		if extent.width == 0 || extent.height == 0 {
+			return false;
+		}
+
+		// nothing can still be using the old images once they're replaced
+		self.device.wait_idle();
+		let (swapchain, images) = create(
+			&self.device,
+			surface,
+			&caps,
+			self.format,
+			self.usage,
+			extent,
+			self.present_mode,
+			Some(&self.swapchain),
+		);
+		self.swapchain = swapchain;
+		self.images = images;
+		self.extent = extent;
+		self.out_of_date = false;
+		true
+	}
+}
+
+fn create<T>(
+	device: &Arc<Device>,
+	surface: Arc<Surface<T>>,
+	caps: &SurfaceCapabilities,
+	format: SurfaceFormat,
+	usage: vk::ImageUsageFlags,
+	extent: Extent2D,
+	present_mode: PresentMode,
+	old_swapchain: Option<&Swapchain<T>>,
+) -> (Arc<Swapchain<T>>, Vec<Arc<SwapchainImage<T>>>) {
+	let (swapchain, images) = device.create_swapchain(
+		surface,
+		image_count(caps),
+		format.format,
+		format.color_space,
+		extent,
+		usage,
+		empty(),
+		caps.current_transform,
+		CompositeAlphaFlags::OPAQUE,
+		present_mode,
+		old_swapchain,
+	);
+	(swapchain, images.collect())
 }
 
 pub struct AcquireFuture<T> {
@@ -1,6 +1,13 @@
 use crate::{
-	buffer::BufferAbstract, command::CommandBuffer, device::Device, image::Framebuffer, pipeline::Pipeline,
+	buffer::BufferAbstract,
+	command::CommandBuffer,
+	descriptor::DescriptorSet,
+	device::Device,
+	image::{Framebuffer, ImageAbstract, ImageView},
+	pipeline::{ComputePipeline, Pipeline, PipelineLayout},
+	query::QueryPool,
 	render_pass::RenderPass,
+	sampler::Sampler,
 };
 use ash::{version::DeviceV1_0, vk};
 use std::sync::{Arc, Mutex};
@@ -9,7 +16,7 @@ use typenum::{B0, B1};
 pub struct Fence {
 	device: Arc<Device>,
 	pub vk: vk::Fence,
-	pub(crate) resources: Mutex<Vec<Arc<CommandBuffer<B0>>>>,
+	pub(crate) resources: Mutex<Vec<Resource>>,
 }
 impl Fence {
 	pub fn wait(&self) {
@@ -17,7 +24,35 @@ impl Fence {
 		self.resources.lock().unwrap().clear();
 	}
 
-	pub(crate) unsafe fn from_vk(device: Arc<Device>, vk: vk::Fence, resources: Vec<Arc<CommandBuffer<B0>>>) -> Self {
+	/// Blocks until any of `fences` is signalled or `timeout` nanoseconds pass, and returns whether one was. Fences
+	/// from a different device than the first one are skipped, since one call can only wait on one device.
+	pub fn wait_any(fences: &[&Fence], timeout: u64) -> bool {
+		let device = match fences.first() {
+			Some(fence) => &fence.device,
+			None => return false,
+		};
+		let vks =
+			fences.iter().filter(|fence| Arc::ptr_eq(&fence.device, device)).map(|fence| fence.vk).collect::<Vec<_>>();
+		match unsafe { device.vk.wait_for_fences(&vks, false, timeout) } {
+			Ok(()) => true,
+			Err(vk::Result::TIMEOUT) => false,
+			Err(err) => panic!("{}", err),
+		}
+	}
+
+	/// Checks whether the fence is signalled without blocking.
+	pub fn is_signalled(&self) -> bool {
+		match unsafe { self.device.vk.get_fence_status(self.vk) } {
+			Ok(()) => {
+				self.resources.lock().unwrap().clear();
+				true
+			},
+			Err(vk::Result::NOT_READY) => false,
+			Err(err) => panic!("{}", err),
+		}
+	}
+
+	pub(crate) unsafe fn from_vk(device: Arc<Device>, vk: vk::Fence, resources: Vec<Resource>) -> Self {
 		Self { device, vk, resources: Mutex::new(resources) }
 	}
 }
@@ -50,8 +85,16 @@ pub trait GpuFuture {
 pub(crate) enum Resource {
 	Buffer(Arc<dyn BufferAbstract>),
 	CommandBuffer(Arc<CommandBuffer<B1>>),
+	DescriptorSet(Arc<DescriptorSet>),
 	Framebuffer(Arc<Framebuffer>),
+	Image(Arc<dyn ImageAbstract>),
+	ImageView(Arc<ImageView>),
+	ComputePipeline(Arc<ComputePipeline>),
 	Pipeline(Arc<Pipeline>),
+	PipelineLayout(Arc<PipelineLayout>),
+	PrimaryCommandBuffer(Arc<CommandBuffer<B0>>),
+	QueryPool(Arc<QueryPool>),
 	RenderPass(Arc<RenderPass>),
+	Sampler(Arc<Sampler>),
 	Semaphore(Arc<Semaphore>),
 }